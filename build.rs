@@ -0,0 +1,18 @@
+/*
+This file is part of Timetrack Jr.
+Timetrack Jr. is free software: you can redistribute it and/or modify it under the terms of the GNU General Public License as published by the Free Software Foundation, either version 3 of the License, or (at your option) any later version.
+Timetrack Jr. is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU General Public License for more details.
+You should have received a copy of the GNU General Public License along with Timetrack Jr. If not, see <https://www.gnu.org/licenses/>.
+*/
+
+fn main() {
+    #[cfg(feature = "grpc")]
+    {
+        //use a bundled protoc so building doesn't need protobuf installed
+        std::env::set_var("PROTOC", protoc_bin_vendored::protoc_bin_path().unwrap());
+        tonic_prost_build::configure()
+            .build_client(false)
+            .compile_protos(&["proto/ttjr.proto"], &["proto"])
+            .unwrap();
+    }
+}
@@ -0,0 +1,75 @@
+# Timetrack Jr. auf Deutsch.  Fehlende Meldungen werden auf Englisch angezeigt (siehe en.ftl).
+
+## Benachrichtigungen
+
+started-notification = Gestartet: { $category }
+stopped-notification = Gestoppt: { $category }
+timing-notification =
+    Aktuell läuft „{ $category }“
+    Gestartet: { $start_local }
+    Dauer: { $elapsed }
+not-timing-notification = Gerade läuft keine Zeiterfassung
+long-running-notification =
+    Läuft { $category } noch?
+    { $category } läuft schon seit { $elapsed }, stimmt das noch?
+
+untracked-summary = Keine Zeiterfassung
+untracked-body =
+    Seit { $minutes ->
+        [one] einer Minute
+       *[other] { $minutes } Minuten
+    } wird nichts erfasst, woran arbeitest du gerade?
+break-summary = Zeit für eine Pause?
+break-body = Du arbeitest seit { $elapsed } ohne Pause
+break-start = { $category } starten
+pomodoro-break = Pomodoro { $count } geschafft
+pomodoro-break-body = Mach { $minutes } Minuten Pause
+pomodoro-stop = Stoppen
+pomodoro-resume = Die Pause ist vorbei
+pomodoro-resume-body = Weiter mit { $category }
+pomodoro-done = Pomodoro { $count } geschafft
+pomodoro-done-body = { $category } wurde gestoppt
+goal-reached = Ziel erreicht!
+meeting-summary = { $meeting } beginnt
+meeting-body = Als { $category } erfassen?  `ttjr start-timing { $category }`
+wrap-up-summary = Feierabend um { $end_of_day }
+welcome-back = Willkommen zurück
+idle-body = Du warst { $away } weg, während { $category } lief
+idle-keep = Behalten
+idle-stop = Um { $time } stoppen
+idle-break = Zu { $category } verschieben
+idle-stopped-body = { $category } wurde um { $time } gestoppt, du warst { $away } weg
+idle-resume = { $category } ab jetzt fortsetzen
+unlocked-body = { $category } wurde beim Sperren des Bildschirms gestoppt
+unlocked-resume = { $category } fortsetzen
+window-summary = Zu { $category } wechseln?
+window-body = „{ $title }“ passt zu { $pattern }
+window-body-timing = „{ $title }“ passt zu { $pattern }, aber gerade läuft { $category }
+action-stop = Stoppen
+action-keep = Weitermachen
+action-switch = Wechseln…
+switch-summary = Wechseln zu…
+
+## export --format summary
+
+summary-all-time = Auswertung über den gesamten Zeitraum
+summary-since = Auswertung ab { $start }
+summary-through = Auswertung bis { $end }
+summary-between = Auswertung von { $start } bis { $end }
+summary-logged = { $count ->
+        [one] { $count } Eintrag
+       *[other] { $count } Einträge
+    } mit insgesamt { $total }
+summary-billed = { $billed } abgerechnet, jeder Eintrag auf { $increment }-Minuten-Schritte gerundet ({ $rounding })
+summary-total = gesamt
+summary-nothing = Keine Zeiten zum Auswerten gefunden
+
+## Fehler
+
+error = Fehler!: { $message }
+error-category-not-found = Die Kategorie „{ $category }“ gibt es nicht, lege sie mit `ttjr add-category` an
+error-time-not-found = Keine Zeit mit der ID { $id }
+error-overlapping-time = Die Zeit würde sich mit anderen Zeiten überschneiden (IDs: { $ids })
+error-parse-time = „{ $input }“ konnte nicht gelesen werden: { $reason }
+error-unusual-time = { $time } liegt { $reason }, mit --allow-unusual-times geht es trotzdem
+error-database-busy = Die Datenbank ist von einem anderen Programm (etwa einem anderen ttjr) gesperrt, nach { $attempts } Versuchen aufgegeben
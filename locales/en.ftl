@@ -0,0 +1,79 @@
+# Timetrack Jr. in English.  Every message is here, other languages fall back to these.
+#
+# Notification templates (the *-notification ids) use the same placeholders as the started-notification option:
+# { $category }, { $elapsed }, { $start_local }, and { $today_total }.  The first line is the summary, the rest is the
+# body.
+
+## Notifications
+
+started-notification = Started: { $category }
+stopped-notification = Stopped: { $category }
+timing-notification =
+    Currently timing "{ $category }"
+    Started: { $start_local }
+    Duration: { $elapsed }
+not-timing-notification = Not currently timing
+long-running-notification =
+    Still timing { $category }?
+    You've been timing { $category } for { $elapsed }, still correct?
+
+untracked-summary = Not timing anything
+untracked-body =
+    Nothing's been timed for { $minutes ->
+        [one] a minute
+       *[other] { $minutes } minutes
+    }, what are you working on?
+break-summary = Time for a break?
+break-body = You've been working for { $elapsed } without one
+break-start = Start { $category }
+pomodoro-break = Pomodoro { $count } done
+pomodoro-break-body = Take a { $minutes } minute break
+pomodoro-stop = Stop
+pomodoro-resume = Break's over
+pomodoro-resume-body = Back to { $category }
+pomodoro-done = Pomodoro { $count } done
+pomodoro-done-body = Stopped timing { $category }
+goal-reached = Goal reached!
+meeting-summary = { $meeting } is starting
+meeting-body = Time it as { $category }?  `ttjr start-timing { $category }`
+wrap-up-summary = Wrapping up at { $end_of_day }
+welcome-back = Welcome back
+idle-body = You were away for { $away } while timing { $category }
+idle-keep = Keep it
+idle-stop = Stop at { $time }
+idle-break = Move to { $category }
+idle-stopped-body = Stopped timing { $category } at { $time }, you were away for { $away }
+idle-resume = Resume { $category } from now
+unlocked-body = Stopped timing { $category } when the screen was locked
+unlocked-resume = Resume { $category }
+window-summary = Switch to { $category }?
+window-body = "{ $title }" matches { $pattern }
+window-body-timing = "{ $title }" matches { $pattern }, but you're timing { $category }
+action-stop = Stop
+action-keep = Keep going
+action-switch = Switch…
+switch-summary = Switch to…
+
+## export --format summary
+
+summary-all-time = Tabulating results for all time
+summary-since = Tabulating results starting on/after { $start }
+summary-through = Tabulating results through { $end }
+summary-between = Tabulating results starting on/after { $start } through { $end }
+summary-logged = Logged { $count ->
+        [one] { $count } activity
+       *[other] { $count } activities
+    } for a total of { $total }
+summary-billed = Billed { $billed } after rounding each entry { $rounding } to { $increment } minute increments
+summary-total = total
+summary-nothing = Didn't find any times to summarize
+
+## Errors
+
+error = Error!: { $message }
+error-category-not-found = Category '{ $category }' does not exist in the timetrack jr database, use `ttjr add-category` to add it
+error-time-not-found = No time with id { $id }
+error-overlapping-time = Attempted to insert time that overlaps with other times! (overlapped IDs: { $ids })
+error-parse-time = Couldn't parse "{ $input }": { $reason }
+error-unusual-time = { $time } is { $reason }, add --allow-unusual-times if that's right
+error-database-busy = The database is locked by another program (like another ttjr), gave up after { $attempts } tries
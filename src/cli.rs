@@ -4,8 +4,11 @@ Timetrack Jr. is free software: you can redistribute it and/or modify it under t
 Timetrack Jr. is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU General Public License for more details.
 You should have received a copy of the GNU General Public License along with Timetrack Jr. If not, see <https://www.gnu.org/licenses/>.
 */
+use crate::zone::Zone;
 use chrono::{DateTime, Datelike};
-use clap::{Parser, Subcommand};
+use clap::{Args, Parser, Subcommand};
+use once_cell::sync::Lazy;
+use regex::Regex;
 
 #[derive(Parser, Debug)]
 #[command(author, version, about, long_about = None)]
@@ -14,10 +17,67 @@ pub struct Cli {
     #[arg(long, default_value = "ttjr.sqlite3")]
     pub db_path: Option<String>,
 
+    ///Print JSON instead of text, including errors (as {"error": ...})
+    #[arg(long, global = true)]
+    pub json: bool,
+
+    ///Log what ttjr is doing to stderr, like the SQL it runs and how it decided when to end a time
+    #[arg(long, global = true, conflicts_with = "quiet")]
+    pub verbose: bool,
+
+    ///Don't print anything but errors and warnings
+    #[arg(long, global = true)]
+    pub quiet: bool,
+
+    ///Append what --verbose would log (and how long DB queries, exports, and syncs take) to this file instead
+    #[arg(long, global = true)]
+    pub log_file: Option<String>,
+
+    ///Show what amend-time, delete-time, bulk-delete-times, or import-org would change, without changing anything
+    #[arg(long, global = true)]
+    pub dry_run: bool,
+
+    ///Don't ask before deleting times (or replacing git hooks).  Without a terminal to ask on (like in cron or a
+    /// script), deleting times is refused unless this is set
+    #[arg(long, global = true, visible_alias = "force")]
+    pub yes: bool,
+
     #[command(subcommand)]
     pub command: Commands,
 }
 
+impl Cli {
+    ///Make --json mean the same thing before the subcommand (`ttjr --json stats`) and after it (`ttjr stats --json`, for
+    /// commands with their own --json flag)
+    pub fn propagate_json(&mut self) {
+        let own = match &mut self.command {
+            Commands::Budget { json, .. }
+            | Commands::Invoice { json, .. }
+            | Commands::Goals { json, .. } => json,
+            //other formats are either already json or can't be
+            Commands::Export {
+                format: ExportFormat::Summary,
+                filter,
+                ..
+            }
+            | Commands::Stats { filter, .. }
+            | Commands::Earnings { filter, .. }
+            | Commands::Attendance { filter, .. }
+            | Commands::Schedule { filter, .. }
+            | Commands::Trend { filter, .. } => &mut filter.json,
+            //--json is global, so it lands in the filter too, where it'd be refused for formats that aren't summary
+            Commands::Export { filter, .. } => {
+                self.json |= filter.json;
+                filter.json = false;
+                return;
+            }
+            _ => return,
+        };
+        *own |= self.json;
+        self.json = *own;
+    }
+}
+
 fn roll_months<T: chrono::TimeZone>(date: &DateTime<T>, num_months: i32) -> DateTime<T> {
     let mut new_date = date.clone();
     if num_months == 0 {
@@ -54,16 +114,40 @@ fn roll_months<T: chrono::TimeZone>(date: &DateTime<T>, num_months: i32) -> Date
 }
 
 pub fn time_string_to_tstamp(tstring: &Option<String>) -> Option<i64> {
+    time_string_to_tstamp_from(tstring, chrono::Local::now())
+}
+
+///An exact time for scripts: unix seconds (bare or like `@1667307600`) or RFC 3339 (like 2022-11-01T13:00:00Z)
+fn exact_tstamp(tstring: &str) -> Option<i64> {
+    let tstring = tstring.trim();
+    let epoch = tstring.strip_prefix('@').unwrap_or(tstring);
+    if !epoch.is_empty() && epoch.chars().all(|c| c.is_ascii_digit()) {
+        return epoch.parse().ok();
+    }
+    DateTime::parse_from_rfc3339(tstring)
+        .ok()
+        .map(|parsed| parsed.timestamp())
+}
+
+///Parse a time string relative to `now`, so relative times like "today" or "last monday" use now's timezone.
+/// Exact times (unix seconds, `@<seconds>`, and RFC 3339) are taken as they are.
+pub fn time_string_to_tstamp_from<T: chrono::TimeZone>(
+    tstring: &Option<String>,
+    now: DateTime<T>,
+) -> Option<i64>
+where
+    T::Offset: Copy,
+{
     match tstring {
         Some(raw_time) => {
-            if let Ok(parsed) = chrono_english::parse_date_string(
-                raw_time,
-                chrono::Local::now(),
-                chrono_english::Dialect::Us,
-            ) {
+            if let Some(exact) = exact_tstamp(raw_time) {
+                Some(exact)
+            } else if let Ok(parsed) =
+                chrono_english::parse_date_string(raw_time, now, chrono_english::Dialect::Us)
+            {
                 Some(parsed.timestamp())
             } else if let Ok(parsed_duration) = chrono_english::parse_duration(raw_time) {
-                let mut parsed_time = chrono::Local::now();
+                let mut parsed_time = now;
                 match parsed_duration {
                     chrono_english::Interval::Seconds(n) => {
                         parsed_time += chrono::Duration::seconds(n as i64)
@@ -84,13 +168,90 @@ pub fn time_string_to_tstamp(tstring: &Option<String>) -> Option<i64> {
     }
 }
 
+static DURATION_PATTERN: Lazy<Regex> = Lazy::new(|| {
+    Regex::new("^(?:(?P<hours>\\d+(?:\\.\\d+)?)h)?(?:(?P<minutes>\\d+)m)?(?:(?P<seconds>\\d+)s)?$")
+        .unwrap()
+});
+
+///Parse a duration like "10h", "1.5h", "2h15m", "90m", or "30s" to a number of seconds
+pub fn duration_string_to_seconds(dstring: &str) -> Option<i64> {
+    if dstring.trim().is_empty() {
+        return None;
+    }
+    let capture = DURATION_PATTERN.captures(dstring.trim())?;
+    let hours = capture
+        .name("hours")
+        .map_or(Some(0.0), |h| h.as_str().parse::<f64>().ok())?;
+    let minutes = capture
+        .name("minutes")
+        .map_or(Some(0), |m| m.as_str().parse::<i64>().ok())?;
+    let seconds = capture
+        .name("seconds")
+        .map_or(Some(0), |s| s.as_str().parse::<i64>().ok())?;
+    let hours = (hours * 60.0 * 60.0).round();
+    //casting would saturate rather than fail
+    if hours >= i64::MAX as f64 {
+        return None;
+    }
+    (hours as i64)
+        .checked_add(minutes.checked_mul(60)?)?
+        .checked_add(seconds)
+}
+
+///Parse a weekly schedule like "mon-fri:4h,sat:1h30m" to the planned seconds for each day, starting with monday.
+/// Days can be listed more than once, in which case their times are added together.
+pub fn schedule_string_to_seconds(sstring: &str) -> Option<[u64; 7]> {
+    let mut planned: [u64; 7] = [0; 7];
+    for block in sstring.split(',') {
+        let (days, duration) = block.trim().split_once(':')?;
+        let duration = duration_string_to_seconds(duration)?.unsigned_abs();
+        for day in day_range(days)? {
+            let day = &mut planned[day.num_days_from_monday() as usize];
+            *day = day.checked_add(duration)?;
+        }
+    }
+    Some(planned)
+}
+
+///A day ("mon") or range of days ("mon-fri", which can wrap around like "fri-mon")
+fn day_range(days: &str) -> Option<Vec<chrono::Weekday>> {
+    let (first, last) = days.split_once('-').unwrap_or((days, days));
+    let first = first.trim().parse::<chrono::Weekday>().ok()?;
+    let last = last.trim().parse::<chrono::Weekday>().ok()?;
+    let mut range = vec![first];
+    while *range.last().unwrap() != last {
+        range.push(range.last().unwrap().succ());
+    }
+    Some(range)
+}
+
+///Parse days of the week like "weekdays", "weekends", "daily", or "mon-wed,fri" to whether each day is included,
+/// starting with monday
+pub fn days_string_to_days(dstring: &str) -> Option<[bool; 7]> {
+    let mut included = [false; 7];
+    for days in dstring.split(',') {
+        let days = match days.trim().to_lowercase().as_str() {
+            "weekdays" => day_range("mon-fri")?,
+            "weekends" => day_range("sat-sun")?,
+            "daily" => day_range("mon-sun")?,
+            days => day_range(days)?,
+        };
+        for day in days {
+            included[day.num_days_from_monday() as usize] = true;
+        }
+    }
+    Some(included)
+}
+
 #[derive(Subcommand, Debug)]
 pub enum Commands {
     ///Set up DB and configure options
     ///Show config options and currently-registered-categories
     ShowConfig,
     ///Create a new category that you can use for time tracking
-    AddCategory { category_name: String },
+    AddCategory {
+        category_name: String,
+    },
     ///Delete a category
     DeleteCategory {
         category_name: String,
@@ -103,10 +264,26 @@ pub enum Commands {
         option_value: String,
     },
     ///Remove an option
-    UnsetOption { option_name: OptionName },
+    UnsetOption {
+        option_name: OptionName,
+    },
+    ///Set an option for a single category
+    SetCategoryOption {
+        category_name: String,
+        option_name: CategoryOptionName,
+        option_value: String,
+    },
+    ///Remove an option from a category
+    UnsetCategoryOption {
+        category_name: String,
+        option_name: CategoryOptionName,
+    },
     ///Start timing an activity - stops timing any currently running activities
     StartTiming {
         category_name: String,
+        ///What you're working on, for more detail than the category (like a ticket or task for an invoice)
+        #[arg(long)]
+        note: Option<String>,
         #[arg(short, long)]
         notify: bool,
     },
@@ -115,14 +292,11 @@ pub enum Commands {
         #[arg(short, long)]
         notify: bool,
     },
+    ///Change when a time started or ended, or its category
     AmendTime {
         time_id: i64,
-        #[arg(short, long)]
-        start_time: Option<String>,
-        #[arg(short, long)]
-        end_time: Option<String>,
-        #[arg(short, long)]
-        category: Option<String>,
+        #[command(flatten)]
+        amendment: Amendment,
     },
     ///Rename a category - updates any corresponding time as well
     RenameCategory {
@@ -135,20 +309,36 @@ pub enum Commands {
     CurrentlyTiming {
         #[arg(short, long)]
         notify: bool,
+        ///Keep running, printing a line of JSON (the time being recorded, or null) whenever it changes
+        #[arg(long, conflicts_with = "notify")]
+        follow: bool,
+        ///Seconds between checks with --follow
+        #[arg(long, default_value_t = 5)]
+        interval: u64,
+    },
+    ///Print a single line describing what's being timed and exit, quick enough for a status bar to run every few seconds
+    Status {
+        #[arg(short, long, value_enum)]
+        format: StatusFormat,
+        ///Template for `--format plain`, {category}, {elapsed} (hours:minutes), and {start} are replaced
+        #[arg(long, default_value = "{category} {elapsed}")]
+        template: String,
+        ///What `--format plain` prints when nothing's being timed
+        #[arg(long, default_value = "")]
+        not_timing: String,
+        ///Truncate `--format plain`/`starship` output to this many characters (ending with "…")
+        #[arg(long)]
+        max_length: Option<usize>,
     },
     ///Delete any time records between a certain start and end time.
     BulkDeleteTimes {
-        ///By default, delete any time whose start OR end are between --start-time and --end-time.  
-        ///If --non-inclusive is set, do not delete a time unless its start AND end is between --start-time and --end-time
-        #[arg(short, long)]
-        non_inclusive: bool,
-        #[arg(short, long)]
-        start_time: String,
-        #[arg(short, long)]
-        end_time: String,
+        #[command(flatten)]
+        selection: BulkDelete,
     },
     ///Delete a given time record.
-    DeleteTime { time_id: i64 },
+    DeleteTime {
+        time_id: i64,
+    },
     ///Export the DB to a more friendly format for analysis
     Export {
         ///Format of export to generate
@@ -160,12 +350,466 @@ pub enum Commands {
         ///Filename to export to - use `-` for stdout
         #[arg(short, long, default_value = "-")]
         outfile: String,
-        ///Earliest entries to include in the extract (defaults to everything)
+        ///Instead of writing to --outfile, POST the export to an InfluxDB write URL (only for --format influx),
+        /// like http://localhost:8086/api/v2/write?org=me&bucket=ttjr.  Set INFLUX_TOKEN to authenticate
+        #[arg(long, conflicts_with = "outfile")]
+        push: Option<String>,
+        ///Instead of exporting, print the JSON Schema of --format json or jsonl exports (each time has the
+        /// schema_version it follows)
+        #[arg(long, conflicts_with_all = ["listen", "push"])]
+        schema: bool,
+        #[command(flatten)]
+        filter: ReportFilter,
+        #[command(flatten)]
+        summary: SummaryOptions,
+        #[command(flatten)]
+        page: Page,
+    },
+    ///Show per-category statistics (session counts, mean/median/longest sessions, sessions per day)
+    Stats {
+        ///Instead of stats, list entries that look like mistakes (much longer than usual for their category, or over 18 hours)
+        #[arg(long)]
+        anomalies: bool,
+        #[command(flatten)]
+        filter: ReportFilter,
+        #[command(flatten)]
+        table: TableOptions,
+    },
+    ///Show earnings for billable categories (categories with a rate set)
+    Earnings {
+        ///How to group earnings
+        #[arg(short, long, value_enum, default_value = "category")]
+        group_by: EarningsGroup,
+        ///Only pay for up to this many hours per day (across all categories), reporting any excess separately
+        #[arg(long)]
+        daily_cap: Option<f64>,
+        #[command(flatten)]
+        filter: ReportFilter,
+        #[command(flatten)]
+        table: TableOptions,
+    },
+    ///Show this week's time for each category with a weekly-cap, flagging any which are over budget
+    Budget {
+        ///Send a desktop notification for each category which is over its cap
+        #[arg(short, long)]
+        notify: bool,
+        ///Timezone used for day and week boundaries, e.g. America/New_York or UTC (defaults to the local timezone)
+        #[arg(long)]
+        timezone: Option<Zone>,
+        ///Print a JSON document instead of text
+        #[arg(long)]
+        json: bool,
+        #[command(flatten)]
+        table: TableOptions,
+    },
+    ///Show a month's hours and amounts for each category with a client, grouped by client, ready to put on invoices
+    Invoice {
+        ///Any date in the month to report on (defaults to this month)
+        #[arg(short, long)]
+        month: Option<String>,
+        ///Only show this client
+        #[arg(short, long)]
+        client: Option<String>,
+        ///Timezone used for month boundaries, e.g. America/New_York or UTC (defaults to the local timezone)
+        #[arg(long)]
+        timezone: Option<Zone>,
+        ///Print a JSON document instead of text
+        #[arg(long)]
+        json: bool,
+        #[command(flatten)]
+        table: TableOptions,
+    },
+    ///Show a week's time for each category with a weekly-goal, and how closely each goal was met
+    Goals {
+        ///Any date in the week to report on (defaults to this week)
+        #[arg(short, long)]
+        week_of: Option<String>,
+        ///Timezone used for week boundaries, e.g. America/New_York or UTC (defaults to the local timezone)
+        #[arg(long)]
+        timezone: Option<Zone>,
+        ///Print a JSON document instead of text
+        #[arg(long)]
+        json: bool,
+        #[command(flatten)]
+        table: TableOptions,
+    },
+    ///Show the first start, last end, total tracked time, and untracked gaps for each day
+    Attendance {
+        #[command(flatten)]
+        filter: ReportFilter,
+        #[command(flatten)]
+        table: TableOptions,
+    },
+    ///Compare time tracked each day to the time planned by each category's schedule, highlighting shortfalls.
+    ///Covers the current week unless --start-time/--end-time are given
+    Schedule {
+        #[command(flatten)]
+        filter: ReportFilter,
+        #[command(flatten)]
+        table: TableOptions,
+    },
+    ///Serve a JSON HTTP API for categories, times, starting/stopping timing, and summaries
+    Serve {
+        ///Address to listen on
+        #[arg(default_value = "127.0.0.1:8080")]
+        bind_addr: String,
+    },
+    ///Speak JSON-RPC 2.0 on stdin/stdout (one message per line) for editor plugins.
+    /// Methods are start {category}, stop, current, and amend {id, start_time, end_time, duration, category}
+    Rpc,
+    ///Run a Rhai script (https://rhai.rs) for custom reports and automations, see the README's section on scripting.
+    /// Scripts can read times, categories, and options, but only change them with --allow-writes
+    Script {
+        ///The script to run
+        file: String,
+        ///Let the script start/stop timing, amend and delete times, and add categories
+        #[arg(long)]
+        allow_writes: bool,
+        ///Arguments for the script, in its ARGS array
+        #[arg(trailing_var_arg = true, allow_hyphen_values = true)]
+        args: Vec<String>,
+    },
+    ///Two-way sync of finished times with Toggl Track.  Categories are matched to Toggl projects with the
+    /// toggl-project category option (or to entries whose description is the category name)
+    SyncToggl {
+        ///Only sync times which started on/after this time (defaults to 30 days ago)
         #[arg(short, long)]
         start_time: Option<String>,
-        ///Latest entries to include in the extract (defaults to everything)
+        ///What to do with times which changed both locally and in Toggl
+        #[arg(long, value_enum, default_value = "skip")]
+        conflicts: ConflictRule,
+    },
+    ///Two-way sync of finished times with Clockify.  Categories are matched to Clockify projects with the
+    /// clockify-workspace and clockify-project category options (or to entries whose description is the category name)
+    SyncClockify {
+        ///Only sync times which started on/after this time (defaults to a day before the last sync, or 30 days ago)
         #[arg(short, long)]
-        end_time: Option<String>,
+        start_time: Option<String>,
+        ///What to do with times which changed both locally and in Clockify
+        #[arg(long, value_enum, default_value = "skip")]
+        conflicts: ConflictRule,
+    },
+    ///Push finished times as events to a CalDAV calendar collection (i.e. in Nextcloud or Radicale).
+    /// Later pushes update events for times that changed and remove events for deleted times
+    PushCaldav {
+        ///URL of the calendar collection
+        url: String,
+    },
+    ///Mirror finished times to an Outlook calendar with Microsoft Graph (authenticate by putting an access token
+    /// in OUTLOOK_ACCESS_TOKEN).  Can also import meetings as pending times to confirm with `confirm-pending`
+    SyncOutlook {
+        ///Only sync times which started on/after this time (defaults to 30 days ago)
+        #[arg(short, long)]
+        start_time: Option<String>,
+        ///Import finished meetings from your calendar as pending times
+        #[arg(short, long)]
+        import_meetings: bool,
+    },
+    ///List imported times (like Outlook meetings) that haven't been confirmed or dismissed
+    ListPending,
+    ///Log a pending time
+    ConfirmPending {
+        pending_id: i64,
+        ///Category to log the time under, required if one wasn't guessed when it was imported
+        #[arg(short, long)]
+        category: Option<String>,
+    },
+    ///Throw away a pending time without logging it
+    DismissPending {
+        pending_id: i64,
+    },
+    ///Suggest categories based on recent activity (commits, checkouts) in the git repos listed in git-repo
+    /// category options.  Branch names containing a category's name suggest that category
+    Suggest {
+        ///Only consider git activity since this time
+        #[arg(short, long, default_value = "2 hours ago")]
+        since: String,
+        ///Keep running, reporting whenever the suggestion doesn't match what's being timed
+        #[arg(short, long)]
+        watch: bool,
+        ///Seconds between checks with --watch
+        #[arg(long, default_value_t = 60)]
+        interval: u64,
+        ///With --watch, also send a desktop notification
+        #[arg(short, long)]
+        notify: bool,
+    },
+    ///Note some context (like the git branch you're on) on the time being recorded
+    Annotate {
+        annotation: String,
+    },
+    ///Tag a time (like `ttjr tag 12 client-a urgent`), to filter exports and bulk deletes with --tag
+    Tag {
+        time_id: i64,
+        ///Tags to add, with or without a leading #
+        #[arg(required = true)]
+        tags: Vec<String>,
+    },
+    ///Take tags off a time
+    Untag {
+        time_id: i64,
+        #[arg(required = true)]
+        tags: Vec<String>,
+    },
+    ///Manage git hooks that annotate the running time with the repo and branch you're working on
+    Hook {
+        #[command(subcommand)]
+        action: HookAction,
+    },
+    ///Import CLOCK lines from an org-mode file.  Clocks are logged under the nearest heading whose title (or a tag)
+    /// is a category, and clocks that are already in ttjr are skipped.  Use `export --format org` to go the other way
+    ImportOrg {
+        path: String,
+        ///Timezone the org timestamps are in (defaults to the local timezone)
+        #[arg(long)]
+        timezone: Option<Zone>,
+        ///Add categories for clocks that aren't under a category's heading (named for the heading they're under)
+        #[arg(long)]
+        create_categories: bool,
+        ///Import clocks in the future, or from before the earliest-time option (which are otherwise skipped)
+        #[arg(long)]
+        allow_unusual_times: bool,
+    },
+    ///Keep running, showing the category being timed (and how long it's been going) as your Discord Rich Presence.
+    /// Needs the id of a Discord application to show the presence as, see the discord-client-id option
+    DiscordPresence {
+        ///Seconds between checks for a change in what's being timed
+        #[arg(long, default_value_t = 15)]
+        interval: u64,
+    },
+    ///Keep running, exposing what's being timed (and today's totals) to Home Assistant with MQTT discovery.
+    /// Home Assistant can start timing by picking a category and stop it with a button
+    HomeAssistant {
+        ///MQTT broker to connect to, as host:port
+        #[arg(default_value = "localhost:1883")]
+        broker: String,
+        ///Seconds between updates of today's totals
+        #[arg(long, default_value_t = 60)]
+        interval: u64,
+    },
+    ///Show what's being timed in the macOS menu bar, with a menu to start timing a category or stop
+    Menubar {
+        ///Seconds between updates of the elapsed time
+        #[arg(long, default_value_t = 30)]
+        interval: u64,
+    },
+    ///Time a category in work intervals of pomodoro-work-minutes (defaults to 25) separated by breaks of
+    /// pomodoro-break-minutes (defaults to 5), with a notification when each one's over.  Breaks are timed as the
+    /// break-category if it exists, otherwise timing stops for them.
+    Pomodoro {
+        category: String,
+        ///Stop timing after this many work intervals (otherwise keep going until interrupted)
+        #[arg(long)]
+        cycles: Option<u32>,
+    },
+    ///A terminal dashboard of what's being timed, today's times, and today's totals per category, with keys to
+    /// start, switch, and stop timing
+    Tui,
+    ///Show what's being timed in the system tray, with a menu to start timing a category or stop
+    Tray {
+        ///Seconds between updates of the elapsed time
+        #[arg(long, default_value_t = 30)]
+        interval: u64,
+    },
+    ///Serve org.ttjr.Tracker on the D-Bus session bus, with StartTiming/StopTiming/Current methods and properties
+    /// for what's being timed (which signal PropertiesChanged however timing is started or stopped)
+    ServeDbus {
+        ///Seconds between checks for timing started or stopped outside of D-Bus
+        #[arg(long, default_value_t = 5)]
+        interval: u64,
+    },
+    ///Serve a gRPC API for starting/stopping timing, listing times, and summaries (see proto/ttjr.proto)
+    ServeGrpc {
+        ///Address to listen on
+        #[arg(default_value = "127.0.0.1:50051")]
+        bind_addr: String,
+    },
+    ///Run the background features enabled by the daemon-features option (a REST server, webhook retries,
+    /// suggestions from git activity...) in one long-running process
+    Daemon {
+        ///Seconds between checks
+        #[arg(long, default_value_t = 60)]
+        interval: u64,
+        ///Instead of running the daemon, write a systemd user unit that runs it (with this DB and interval) and
+        /// enable it, so it starts at login
+        #[arg(long)]
+        install_systemd: bool,
+        ///With --install-systemd, also write a socket unit listening on the daemon-bind-addr option, so systemd
+        /// holds the REST API's port and hands it to the daemon
+        #[arg(long, requires = "install_systemd")]
+        socket: bool,
+        ///Instead of running the daemon, write a launchd agent that runs it (with this DB and interval) and load
+        /// it, so it starts at login
+        #[arg(long, conflicts_with = "install_systemd")]
+        install_launchd: bool,
+        ///Instead of running the daemon, register a scheduled task that runs it (with this DB and interval) at
+        /// logon and start it.  It runs in your session (rather than as a service) so it can show notifications.
+        #[arg(long, conflicts_with_all = ["install_systemd", "install_launchd"])]
+        install_winservice: bool,
+    },
+    ///Stop the daemon's reminders (and meeting suggestions) for a while, i.e. `ttjr snooze 30m`
+    Snooze {
+        ///How long, like 30m or 1h (0 to stop snoozing)
+        duration: String,
+    },
+    ///POST a JSON event to a URL whenever timing is started/stopped or a time is amended/deleted.  Events are
+    /// queued, and sent by `deliver-webhooks` or the daemon's webhooks feature
+    AddWebhook {
+        url: String,
+        ///Sign each event with this key, as an HMAC-SHA256 in the X-Ttjr-Signature header
+        #[arg(long)]
+        secret: Option<String>,
+    },
+    ///Stop sending events to a webhook (dropping any it hasn't been sent yet)
+    DeleteWebhook {
+        webhook_id: i64,
+    },
+    ///List webhooks and how many events each has waiting to be delivered
+    ListWebhooks,
+    ///Send queued webhook events, including any that failed to deliver and are due for another attempt
+    DeliverWebhooks,
+    ///Have the daemon's auto-start feature start timing a category at a time of day (i.e.
+    /// `ttjr add-auto-start weekdays 09:00 work`), unless something's already being timed
+    AddAutoStart {
+        ///Like weekdays, weekends, daily, or mon-wed,fri
+        days: String,
+        ///HH:MM
+        time: String,
+        category: String,
+    },
+    ///Delete an auto-start rule
+    DeleteAutoStart {
+        auto_start_id: i64,
+    },
+    ///List auto-start rules
+    ListAutoStarts,
+    ///Have the daemon's active-window feature suggest (or switch to) a category when the focused window's title
+    /// matches a regex (i.e. `ttjr add-window-rule "(?i)zoom meeting" meetings`).  The first matching rule wins.
+    AddWindowRule {
+        pattern: String,
+        category: String,
+    },
+    DeleteWindowRule {
+        rule_id: i64,
+    },
+    ///List window rules in the order they're checked
+    ListWindowRules,
+    ///Bind a global key combination (i.e. ctrl+alt+w) to an action, used when the daemon's hotkeys feature is enabled
+    SetHotkey {
+        keypress: String,
+        action: HotkeyAction,
+        ///The category to start, for the start action
+        category: Option<String>,
+    },
+    ///Press a key combination in the terminal to bind it to an action (like set-hotkey)
+    RecordHotkey {
+        action: HotkeyAction,
+        ///The category to start, for the start action
+        category: Option<String>,
+    },
+    ///Remove a global key combination
+    UnsetHotkey {
+        keypress: String,
+    },
+    ///List global key combinations and what they do, checking for any that conflict
+    ListHotkeys,
+    ///Show daily totals for a category alongside a moving average
+    Trend {
+        category: String,
+        ///Number of days to include in the moving average
+        #[arg(short, long, default_value_t = 7)]
+        window: usize,
+        #[command(flatten)]
+        filter: ReportFilter,
+        #[command(flatten)]
+        table: TableOptions,
+    },
+    ///Any other command runs `ttjr-<command>` from PATH, see the README's section on extensions
+    #[command(external_subcommand)]
+    External(Vec<String>),
+}
+
+///Changes to a time, for amend-time (and the RPC and script equivalents).  Anything left out stays as it is.
+#[derive(Args, Debug, Clone, Default)]
+pub struct Amendment {
+    #[arg(short, long)]
+    pub start_time: Option<String>,
+    #[arg(short, long)]
+    pub end_time: Option<String>,
+    ///Instead of --end-time, end the time this long (like "2h15m" or "90m") after its start (or the new --start-time)
+    #[arg(short, long, conflicts_with = "end_time")]
+    pub duration: Option<String>,
+    #[arg(short, long)]
+    pub category: Option<String>,
+    ///Replace the time's note (an empty note removes it)
+    #[arg(long)]
+    pub note: Option<String>,
+    ///Allow times in the future, or from before the earliest-time option
+    #[arg(long)]
+    pub allow_unusual_times: bool,
+}
+
+///Which times bulk-delete-times deletes
+#[derive(Args, Debug, Clone, Default)]
+pub struct BulkDelete {
+    ///By default, delete any time whose start OR end are between --start-time and --end-time.  
+    ///If --non-inclusive is set, do not delete a time unless its start AND end is between --start-time and --end-time
+    #[arg(short, long)]
+    pub non_inclusive: bool,
+    #[arg(short, long)]
+    pub start_time: String,
+    #[arg(short, long)]
+    pub end_time: String,
+    ///Only delete times with this tag (may be given more than once, to delete times with any of them)
+    #[arg(long)]
+    pub tag: Vec<String>,
+}
+
+///Options shared by commands which export or report on a range of logged times
+#[derive(Args, Debug, Clone, Default)]
+pub struct ReportFilter {
+    ///Earliest entries to include (defaults to everything)
+    #[arg(short, long)]
+    pub start_time: Option<String>,
+    ///Latest entries to include (defaults to everything)
+    #[arg(short, long)]
+    pub end_time: Option<String>,
+    ///How to decide which times fall between --start-time and --end-time.
+    ///Defaults to "overlap" (clipping times to the range) for summaries and reports, and "start" for json/csv/ical exports
+    #[arg(long, value_enum)]
+    pub filter_mode: Option<FilterMode>,
+    ///Only include categories matching a glob pattern like 'client-*' (may be given more than once)
+    #[arg(long)]
+    pub category_match: Vec<String>,
+    ///Only include categories matching a regular expression (may be given more than once)
+    #[arg(long)]
+    pub category_regex: Vec<String>,
+    ///Only include times with this tag (may be given more than once, to include times with any of them)
+    #[arg(long)]
+    pub tag: Vec<String>,
+    ///Roll hierarchical categories (like work/meetings) up to this many levels, i.e. --depth 1 reports work/meetings as work
+    #[arg(long)]
+    pub depth: Option<usize>,
+    ///Timezone used for day boundaries and displayed times, e.g. America/New_York or UTC (defaults to the local timezone)
+    #[arg(long)]
+    pub timezone: Option<Zone>,
+    ///Print a JSON document instead of text (for summaries and reports)
+    #[arg(long)]
+    pub json: bool,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum HookAction {
+    ///Install post-checkout and post-commit hooks that run `ttjr annotate` with the repo and branch
+    Install {
+        ///Repo to install the hooks in (existing hooks that weren't installed by ttjr are only replaced with --force)
+        #[arg(default_value = ".")]
+        repo: String,
+    },
+    ///Remove hooks installed by `hook install`
+    Uninstall {
+        #[arg(default_value = ".")]
+        repo: String,
     },
 }
 
@@ -175,9 +819,495 @@ pub enum ExportFormat {
     Csv,
     Ical,
     Summary,
+    ///One JSON object per line, like those in --format json
+    Jsonl,
+    ///InfluxDB line protocol, one point per time
+    Influx,
+    ///Emacs org-mode, a heading per category with CLOCK lines for its times
+    Org,
+}
+
+///Options which only apply to `export --format summary`
+#[derive(Args, Debug, Clone, Default)]
+pub struct SummaryOptions {
+    ///Round each entry to the billing-increment option (using the billing-rounding option) and show billed vs. raw totals
+    #[arg(long)]
+    pub billing: bool,
+    ///Only count up to this many hours per day (across all categories), reporting any excess separately
+    #[arg(long)]
+    pub daily_cap: Option<f64>,
+    ///Break the totals down by day, week (ISO weeks), or month instead of showing one row per category
+    #[arg(long, value_enum)]
+    pub group_by: Option<SummaryPeriod>,
+    #[command(flatten)]
+    pub table: TableOptions,
+}
+
+///Periods that `export --format summary --group-by` can total by
+#[derive(clap::ValueEnum, Clone, Debug)]
+pub enum SummaryPeriod {
+    Day,
+    Week,
+    Month,
+}
+
+///Options for reports printed as tables
+#[derive(Args, Debug, Clone, Default)]
+pub struct TableOptions {
+    ///Comma-separated list of columns to show, in order (defaults to all columns)
+    #[arg(long, value_delimiter = ',')]
+    pub columns: Vec<String>,
+    ///Add a row of totals to the end of the table
+    #[arg(long)]
+    pub totals: bool,
+}
+
+///Output formats for `status`
+#[derive(clap::ValueEnum, Clone, Debug)]
+pub enum StatusFormat {
+    ///JSON for a waybar custom module (`"return-type": "json"`), with a class of "timing" or "idle"
+    Waybar,
+    ///Plain text from --template, for tmux's status-right or a polybar/i3blocks script
+    Plain,
+    ///Short "category 1h23m" for a starship custom module, or nothing when not timing (which hides the module)
+    Starship,
+}
+
+///Features `daemon` can run, enabled with the daemon-features option
+#[derive(clap::ValueEnum, Clone, Debug, PartialEq, Eq)]
+pub enum DaemonFeature {
+    ///Serve the REST API (like `serve`) on the daemon-bind-addr option, or 127.0.0.1:8080
+    Serve,
+    ///Deliver webhook events (and retry ones which failed)
+    Webhooks,
+    ///Notify when git activity suggests you're working on something other than what's being timed
+    Suggest,
+    ///Desktop notifications reminding you to start timing, that you've been timing something for a long time, or to
+    /// wrap up before end-of-day (see the remind-untracked, long-running, and wrap-up options)
+    Reminders,
+    ///Ask whether to keep time spent away from the computer, stop timing when you left, or move it to a break (see
+    /// the idle-timeout and idle-action options)
+    Idle,
+    ///Stop timing at the end-of-day option, rather than whenever timing's next started or stopped
+    EndOfDay,
+    ///Start timing categories at the times set up with `add-auto-start`
+    AutoStart,
+    ///Suggest switching to a category when the focused window matches one of its window rules (see
+    /// `add-window-rule` and the active-window-mode option)
+    ActiveWindow,
+    ///Stop timing when the screen is locked, then resume (or offer to) when it's unlocked (see the lock-action
+    /// option).  Linux needs dbus-monitor, and it isn't supported on windows.
+    Lock,
+    ///Suggest a break after break-after of continuous timing, offering to time it as break-category
+    Breaks,
+    ///Congratulate you when a category reaches its daily-goal or weekly-goal (see the goal-notification category
+    /// option)
+    Goals,
+    ///Global key combinations to start and stop timing (see `set-hotkey`)
+    Hotkeys,
+}
+
+///What the daemon's active-window feature does when the focused window matches a rule for another category
+#[derive(clap::ValueEnum, Clone, Debug, PartialEq, Eq)]
+pub enum ActiveWindowMode {
+    ///Send a notification suggesting the category
+    Suggest,
+    ///Start timing the category
+    Switch,
+}
+
+///What the daemon's idle feature does when you've been away from the computer for idle-timeout
+#[derive(clap::ValueEnum, Clone, Debug, PartialEq, Eq)]
+pub enum IdleAction {
+    ///Keep timing, and ask what to do with the time away when you're back
+    Ask,
+    ///Stop timing as of when you went idle, and ask whether to keep the time away when you're back
+    Stop,
+}
+
+///What the daemon's lock feature does when the screen is locked and unlocked
+#[derive(clap::ValueEnum, Clone, Debug, PartialEq, Eq)]
+pub enum LockAction {
+    ///Stop timing when the screen was locked
+    Stop,
+    ///Stop timing when the screen was locked, and start timing the same category again when it's unlocked
+    Pause,
+    ///Stop timing when the screen was locked, and ask whether to start timing it again when it's unlocked
+    Ask,
+}
+
+///What a global key combination does
+#[derive(clap::ValueEnum, Clone, Debug, PartialEq, Eq)]
+pub enum HotkeyAction {
+    ///Start timing a category
+    Start,
+    ///Stop timing
+    Stop,
+    ///Start timing whatever was timed last
+    Resume,
+    ///Show a notification about what's being timed
+    CurrentlyTiming,
+}
+
+///Notifications ttjr sends, for the notification-urgency and notification-sound options
+#[derive(clap::ValueEnum, Clone, Debug, PartialEq, Eq)]
+pub enum NotificationEvent {
+    ///`start-timing --notify`
+    Started,
+    ///--notify stopping timing
+    Stopped,
+    ///`currently-timing --notify`
+    Timing,
+    ///`currently-timing --notify` when nothing's being timed
+    NotTiming,
+    ///The daemon's reminder to start timing
+    Untracked,
+    ///The daemon's warning that something's been timed a long time
+    LongRunning,
+    ///The daemon's summary before end-of-day
+    WrapUp,
+    ///The daemon asking what to do with time spent away from the computer
+    Idle,
+    ///The daemon suggesting a break
+    Break,
+    ///`pomodoro` work intervals and breaks ending
+    Pomodoro,
+    ///The daemon asking whether to start timing again after the screen's unlocked
+    Unlocked,
+    ///The daemon suggesting timing meeting-category when a meeting starts
+    Meeting,
+    ///A suggestion from git activity
+    Suggest,
+    ///The daemon congratulating you on reaching a goal
+    GoalReached,
+    ///`budget --notify`
+    OverBudget,
+    ///`export --listen` failing to export
+    ExportFailed,
+}
+
+#[derive(clap::ValueEnum, Clone, Debug)]
+pub enum NotificationUrgency {
+    Low,
+    Normal,
+    Critical,
+}
+
+///Which page of times an export (or API listing) includes.  Times are sorted by when they started.
+#[derive(Args, Debug, Clone, Default)]
+pub struct Page {
+    ///Oldest (asc) or newest (desc) times first
+    #[arg(long, value_enum, default_value_t)]
+    pub order: Order,
+    ///Only include times after (in --order) the time with this id, like the last one of the previous page
+    #[arg(long)]
+    pub after: Option<i64>,
+    ///Skip this many times
+    #[arg(long, default_value_t = 0)]
+    pub offset: usize,
+    ///Include at most this many times
+    #[arg(long)]
+    pub limit: Option<usize>,
+}
+
+impl Page {
+    ///Whether this is only part of the times
+    pub fn is_partial(&self) -> bool {
+        self.after.is_some() || self.offset > 0 || self.limit.is_some()
+    }
+
+    ///Apply --offset and --limit to (already sorted and filtered) times
+    pub fn slice<T>(&self, times: impl Iterator<Item = T>) -> impl Iterator<Item = T> {
+        times
+            .skip(self.offset)
+            .take(self.limit.unwrap_or(usize::MAX))
+    }
+}
+
+#[derive(clap::ValueEnum, Clone, Copy, Debug, Default, PartialEq)]
+pub enum Order {
+    #[default]
+    Asc,
+    Desc,
+}
+
+///How --start-time/--end-time select times
+#[derive(clap::ValueEnum, Clone, Debug)]
+pub enum FilterMode {
+    ///Only include times which start inside the range
+    Start,
+    ///Include any time which overlaps the range, counting only the part inside the range
+    Overlap,
 }
 
 #[derive(clap::ValueEnum, Clone, Debug)]
 pub enum OptionName {
     EndOfDay,
+    ///Start of business hours (defaults to 09:00), when the daemon reminds you to start timing
+    StartOfDay,
+    ///Prefix used when formatting amounts of money (i.e. "$" or "EUR ")
+    Currency,
+    ///Smallest increment of time you bill for (i.e. "15m"), used by `export --format summary --billing`
+    BillingIncrement,
+    ///How entries are rounded to the billing increment: up, down, or nearest (defaults to up)
+    BillingRounding,
+    ///API token for `sync-toggl` (from your Toggl profile page)
+    TogglApiToken,
+    ///Toggl workspace id to sync with (defaults to your default workspace)
+    TogglWorkspace,
+    ///API key for `sync-clockify` (from your Clockify profile settings)
+    ClockifyApiKey,
+    ///Clockify workspace id to sync with (defaults to your active workspace)
+    ClockifyWorkspace,
+    ///Username for `push-caldav`
+    CaldavUsername,
+    ///Password (or app password) for `push-caldav`
+    CaldavPassword,
+    ///Microsoft Graph access token for `sync-outlook` (OUTLOOK_ACCESS_TOKEN takes precedence, tokens expire quickly)
+    OutlookAccessToken,
+    ///Id of the Outlook calendar times are pushed to (defaults to your main calendar)
+    OutlookCalendar,
+    ///Slack user token (with the users.profile:write scope) used to set your status to what you're timing
+    SlackToken,
+    ///Id of the Discord application used by `discord-presence` (its name is what your status shows you "playing")
+    DiscordClientId,
+    ///Username for the MQTT broker used by `home-assistant`
+    MqttUsername,
+    ///Password for the MQTT broker (MQTT_PASSWORD takes precedence)
+    MqttPassword,
+    ///Comma-separated features for `daemon` to run (i.e. "serve,webhooks")
+    DaemonFeatures,
+    ///Address the daemon's REST API listens on (defaults to 127.0.0.1:8080)
+    DaemonBindAddr,
+    ///How often the daemon reminds you to start timing when nothing's being timed during business hours (i.e. "30m")
+    RemindUntracked,
+    ///How long something can be timed before the daemon asks if it's still right (i.e. "3h"), see the long-running category option
+    LongRunning,
+    ///How long before end-of-day the daemon sends a summary of the day and a reminder to stop timing (i.e. "15m")
+    WrapUp,
+    ///How long without keyboard or mouse input counts as being away from the computer (i.e. "10m")
+    IdleTimeout,
+    ///ask or stop, what the daemon does once you've been idle for idle-timeout (defaults to ask)
+    IdleAction,
+    ///Category time away from the computer can be moved to, and breaks are timed as (defaults to "break")
+    BreakCategory,
+    ///How long the daemon's breaks feature lets you work without a break before suggesting one (i.e. "90m")
+    BreakAfter,
+    ///Minutes in each of `pomodoro`'s work intervals (defaults to 25)
+    PomodoroWorkMinutes,
+    ///Minutes in each of `pomodoro`'s breaks (defaults to 5)
+    PomodoroBreakMinutes,
+    ///suggest or switch, what the daemon does when the focused window matches a window rule (defaults to suggest)
+    ActiveWindowMode,
+    ///stop, pause, or ask, what the daemon does when the screen is locked and unlocked (defaults to pause)
+    LockAction,
+    ///Unix timestamp the daemon's reminders are snoozed until (set by `snooze`)
+    SnoozedUntil,
+    ///ICS file or URL (like your calendar's secret address) of meetings, during which the daemon doesn't remind you
+    /// to start timing
+    MeetingCalendar,
+    ///Category the daemon suggests timing when a meeting from meeting-calendar starts
+    MeetingCategory,
+    ///"true" to add Stop, Keep going, and Switch… buttons to --notify notifications about what's being timed
+    /// (ttjr waits for a button to be clicked, or the notification to be dismissed)
+    NotificationActions,
+    ///Template for the notification from `start-timing --notify`.  The first line is the summary and the rest is
+    /// the body, with placeholders {category}, {elapsed}, {start_local}, and {today_total}
+    StartedNotification,
+    ///Template for the notification when --notify stops timing (see started-notification)
+    StoppedNotification,
+    ///Template for the notification from `currently-timing --notify` (see started-notification)
+    TimingNotification,
+    ///Template for the notification from `currently-timing --notify` when nothing's being timed (see started-notification)
+    NotTimingNotification,
+    ///Template for the daemon's long-running notification (see started-notification)
+    LongRunningNotification,
+    ///How often `export --listen` sends a notification while exports keep failing (i.e. "1h"), unset for none
+    ExportFailureNotification,
+    ///When notifications from the daemon (and other background notifications) are held back, as a comma-separated
+    /// list of times of day and days like "22:00-08:00,weekends".  Critical ones (see notification-urgency) still
+    /// show.
+    QuietHours,
+    ///Where notifications go when they can't be shown (on headless servers, WSL, or desktops without a notification
+    /// server): "terminal" to print them with a bell, or the path of a file or named pipe to write them to
+    NotifyFallback,
+    ///Urgency of each kind of notification, as a comma-separated list like "wrap-up:critical,started:low"
+    /// (ignored on macOS)
+    NotificationUrgency,
+    ///Sound for each kind of notification, as a comma-separated list like "wrap-up:alarm-clock-elapsed,started:none"
+    /// (a sound theme name on linux, a system sound on macOS/windows, "none" for silence)
+    NotificationSound,
+    ///Language for notifications, the summary export, and errors, like "de" (defaults to English)
+    Language,
+    ///Times from before this (defaults to 2000-01-01) are taken to be typos, unless --allow-unusual-times
+    EarliestTime,
+}
+
+#[derive(clap::ValueEnum, Clone, Debug)]
+pub enum BillingRounding {
+    Up,
+    Down,
+    Nearest,
+}
+
+#[derive(clap::ValueEnum, Clone, Debug)]
+pub enum CategoryOptionName {
+    ///Hourly rate for billable time logged under the category
+    Rate,
+    ///Maximum time you want to spend on the category each week (i.e. "10h" or "7h30m")
+    WeeklyCap,
+    ///Amount of time you'd like to spend on the category each week (i.e. "3h")
+    WeeklyGoal,
+    ///Amount of time you'd like to spend on the category each day (i.e. "30m")
+    DailyGoal,
+    ///"false" to stop the daemon's goals feature congratulating you when the category reaches its goals
+    GoalNotification,
+    ///Time planned for the category on each day of the week (i.e. "mon-fri:4h,sat:1h")
+    Schedule,
+    ///Client the category is billed to, used to group invoices
+    Client,
+    ///Id of the Toggl project that the category's times are synced to
+    TogglProject,
+    ///Id of the Clockify workspace that the category's times are synced to (if not the clockify-workspace option)
+    ClockifyWorkspace,
+    ///Id of the Clockify project that the category's times are synced to
+    ClockifyProject,
+    ///Outlook category of meetings that are imported under the category
+    OutlookCategory,
+    ///Git repos (comma-separated paths) whose activity suggests the category, see `suggest`
+    GitRepo,
+    ///Emoji for your Slack status while timing the category (i.e. ":headphones:", defaults to ":stopwatch:")
+    SlackEmoji,
+    ///How long the category can be timed before the daemon asks if it's still right (instead of the long-running option)
+    LongRunning,
+}
+
+///Which side wins when a time was changed both locally and remotely since the last sync
+#[derive(clap::ValueEnum, Clone, Debug)]
+pub enum ConflictRule {
+    ///Leave conflicting times alone and report them
+    Skip,
+    Local,
+    Remote,
+}
+
+#[derive(clap::ValueEnum, Clone, Debug)]
+pub enum EarningsGroup {
+    Week,
+    Month,
+    Category,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap::CommandFactory;
+
+    #[test]
+    fn test_json_flag() {
+        Cli::command().debug_assert();
+        for args in [
+            vec!["ttjr", "--json", "stats"],
+            vec!["ttjr", "stats", "--json"],
+            vec!["ttjr", "--json", "goals"],
+            vec!["ttjr", "goals", "--json"],
+        ] {
+            let mut cli = Cli::parse_from(&args);
+            cli.propagate_json();
+            assert!(cli.json, "{:?}", args);
+            assert!(
+                matches!(
+                    cli.command,
+                    Commands::Stats {
+                        filter: ReportFilter { json: true, .. },
+                        ..
+                    } | Commands::Goals { json: true, .. }
+                ),
+                "{:?}",
+                args
+            );
+        }
+        let mut cli = Cli::parse_from(["ttjr", "add-category", "work", "--json"]);
+        cli.propagate_json();
+        assert!(cli.json);
+        //json exports are already json, --json just has errors print as json too
+        for format in ["json", "jsonl"] {
+            let mut cli = Cli::parse_from(["ttjr", "--json", "export", "--format", format]);
+            cli.propagate_json();
+            assert!(cli.json, "{}", format);
+            assert!(
+                matches!(
+                    cli.command,
+                    Commands::Export {
+                        filter: ReportFilter { json: false, .. },
+                        ..
+                    }
+                ),
+                "{}",
+                format
+            );
+        }
+    }
+
+    #[test]
+    fn test_time_string_to_tstamp() {
+        let now = chrono::DateTime::parse_from_rfc3339("2022-11-01T12:00:00-04:00").unwrap();
+        let parse = |time: &str| time_string_to_tstamp_from(&Some(time.to_string()), now);
+        assert_eq!(Some(1667307600), parse("1667307600"));
+        assert_eq!(Some(1667307600), parse("@1667307600"));
+        assert_eq!(Some(1667307600), parse("2022-11-01T13:00:00Z"));
+        assert_eq!(Some(1667307600), parse("2022-11-01T09:00:00-04:00"));
+        assert_eq!(Some(1667307600), parse("2022-11-01 09:00"));
+        assert_eq!(Some(1667318400), parse("today 12:00"));
+        assert_eq!(None, parse("@"));
+        assert_eq!(None, parse("@yesterday"));
+    }
+
+    #[test]
+    fn test_duration_string_to_seconds() {
+        assert_eq!(Some(36000), duration_string_to_seconds("10h"));
+        assert_eq!(Some(5400), duration_string_to_seconds("1.5h"));
+        assert_eq!(Some(8100), duration_string_to_seconds("2h15m"));
+        assert_eq!(Some(5400), duration_string_to_seconds("90m"));
+        assert_eq!(Some(30), duration_string_to_seconds("30s"));
+        assert_eq!(None, duration_string_to_seconds(""));
+        assert_eq!(None, duration_string_to_seconds("10"));
+        assert_eq!(None, duration_string_to_seconds("h"));
+        assert_eq!(None, duration_string_to_seconds("ten hours"));
+        //too long for an i64, rather than panicking
+        assert_eq!(None, duration_string_to_seconds("99999999999999999999m"));
+        assert_eq!(None, duration_string_to_seconds("153722867280912931m"));
+        assert_eq!(None, duration_string_to_seconds("99999999999999999999h"));
+        assert_eq!(None, duration_string_to_seconds("1h9223372036854775807s"));
+    }
+
+    #[test]
+    fn test_schedule_string_to_seconds() {
+        assert_eq!(
+            Some([3600, 3600, 3600, 3600, 3600, 5400, 0]),
+            schedule_string_to_seconds("mon-fri:1h, sat:1h30m")
+        );
+        assert_eq!(
+            Some([3600, 0, 0, 0, 0, 1800, 1800]),
+            schedule_string_to_seconds("sat-mon:30m,monday:30m")
+        );
+        assert_eq!(None, schedule_string_to_seconds("mon-fri"));
+        assert_eq!(None, schedule_string_to_seconds("someday:1h"));
+        assert_eq!(None, schedule_string_to_seconds("mon:1 hour"));
+        assert_eq!(None, schedule_string_to_seconds(""));
+    }
+
+    #[test]
+    fn test_days_string_to_days() {
+        let t = true;
+        let f = false;
+        assert_eq!(Some([t, t, t, t, t, f, f]), days_string_to_days("Weekdays"));
+        assert_eq!(
+            Some([t, f, f, f, t, t, t]),
+            days_string_to_days("fri-sun, mon")
+        );
+        assert_eq!(Some([t; 7]), days_string_to_days("daily"));
+        assert_eq!(None, days_string_to_days("someday"));
+        assert_eq!(None, days_string_to_days(""));
+    }
 }
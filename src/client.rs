@@ -0,0 +1,171 @@
+/*
+This file is part of Timetrack Jr.
+Timetrack Jr. is free software: you can redistribute it and/or modify it under the terms of the GNU General Public License as published by the Free Software Foundation, either version 3 of the License, or (at your option) any later version.
+Timetrack Jr. is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU General Public License for more details.
+You should have received a copy of the GNU General Public License along with Timetrack Jr. If not, see <https://www.gnu.org/licenses/>.
+*/
+//The engine behind `ttjr`, for programs (like a GUI) embedding it instead of shelling out to the binary.  Methods
+// return data rather than printing it, and don't show notifications.
+use crate::{
+    cli::{Cli, FilterMode, Page},
+    commands::{self, log, report},
+    db::{self, Categories, TimeWindow},
+    TTError,
+};
+use chrono::Local;
+use rusqlite::Connection;
+use std::collections::BTreeMap;
+
+///A Timetrack Jr. database
+///
+///```
+///use timetrack_jr::TtjrClient;
+///
+///let mut client = TtjrClient::open_in_memory()?;
+///client.add_category("work")?;
+///client.start("work")?;
+///let stopped = client.stop()?.unwrap();
+///assert_eq!("work", stopped.category);
+///assert!(client.summarize(None, None)?.contains_key("work"));
+///# Ok::<(), timetrack_jr::TTError>(())
+///```
+pub struct TtjrClient {
+    conn: Connection,
+}
+
+impl TtjrClient {
+    ///Open the database at `db_path`, creating it if it doesn't exist
+    pub fn open(db_path: &str) -> Result<TtjrClient, TTError> {
+        TtjrClient::new(Connection::open(db_path)?)
+    }
+
+    ///A database which only lasts as long as the client (handy for tests)
+    pub fn open_in_memory() -> Result<TtjrClient, TTError> {
+        TtjrClient::new(Connection::open_in_memory()?)
+    }
+
+    ///Use an already open connection, setting up the database if needed
+    pub fn new(mut conn: Connection) -> Result<TtjrClient, TTError> {
+        db::initialize_db(&mut conn)?;
+        Ok(TtjrClient { conn })
+    }
+
+    ///The underlying connection, for anything the client doesn't cover
+    pub fn connection(&mut self) -> &mut Connection {
+        &mut self.conn
+    }
+
+    ///Run a command exactly as `ttjr` would (printing its output to stdout)
+    pub fn execute(&mut self, cli: &Cli) -> Result<(), TTError> {
+        commands::execute(cli, &mut self.conn)
+    }
+
+    ///Every category, in order
+    pub fn categories(&mut self) -> Result<Categories, TTError> {
+        db::get_categories(&self.conn.transaction()?)
+    }
+
+    ///Add a category to time things in
+    pub fn add_category(&mut self, category: &str) -> Result<(), TTError> {
+        db::write(&mut self.conn, |tx| {
+            db::add_category(tx, &category.to_string())
+        })
+    }
+
+    ///Start timing `category` (which must exist), stopping whatever was being timed.  Returns the new time.
+    pub fn start(&mut self, category: &str) -> Result<TimeWindow, TTError> {
+        log::start_timing(&mut self.conn, &category.to_string(), &None, &false)?;
+        self.current()?.ok_or_else(|| TTError::Network {
+            message: format!("Couldn't start timing {}", category),
+        })
+    }
+
+    ///Stop timing, returning the time that was stopped (if anything was being timed)
+    pub fn stop(&mut self) -> Result<Option<TimeWindow>, TTError> {
+        let open = self.current()?;
+        log::stop_timing(&mut self.conn, &false)?;
+        match open.and_then(|time| time.id) {
+            Some(id) => Ok(Some(db::get_time(&self.conn.transaction()?, id)?)),
+            None => Ok(None),
+        }
+    }
+
+    ///What's being timed right now
+    pub fn current(&mut self) -> Result<Option<TimeWindow>, TTError> {
+        db::get_last_open_time(&self.conn.transaction()?)
+    }
+
+    ///A page of the times which start between `start` and `end` (unix timestamps, either end may be left open)
+    pub fn list(
+        &mut self,
+        start: Option<i64>,
+        end: Option<i64>,
+        page: &Page,
+    ) -> Result<Vec<TimeWindow>, TTError> {
+        db::iter_times(
+            &self.conn.transaction()?,
+            start,
+            end,
+            &FilterMode::Start,
+            page,
+            |times| page.slice(times).collect(),
+        )
+    }
+
+    ///Seconds logged to each category between `start` and `end` (unix timestamps, either end may be left open), only
+    /// counting the part of each time inside the range, and counting running time up to now
+    pub fn summarize(
+        &mut self,
+        start: Option<i64>,
+        end: Option<i64>,
+    ) -> Result<BTreeMap<String, u64>, TTError> {
+        report::totals_between(
+            &mut self.conn.transaction()?,
+            start.unwrap_or(0),
+            end.unwrap_or(Local::now().timestamp()),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_client() {
+        let mut client = TtjrClient::open_in_memory().unwrap();
+        assert_eq!(3, client.start("work").unwrap_err().exit_code());
+        client.add_category("work").unwrap();
+        assert_eq!(None, client.stop().unwrap());
+
+        let work = client.start("work").unwrap();
+        assert_eq!("work", work.category);
+        assert_eq!(None, work.end_time);
+        assert_eq!(Some(work.clone()), client.current().unwrap());
+
+        let stopped = client.stop().unwrap().unwrap();
+        assert_eq!(work.id, stopped.id);
+        assert!(stopped.end_time.is_some());
+        assert_eq!(None, client.current().unwrap());
+
+        assert_eq!(
+            3,
+            log::delete_time(client.connection(), &9, &false)
+                .unwrap_err()
+                .exit_code()
+        );
+
+        assert_eq!(
+            vec![stopped],
+            client.list(None, None, &Page::default()).unwrap()
+        );
+        assert_eq!(
+            vec!["work"],
+            client
+                .summarize(None, None)
+                .unwrap()
+                .keys()
+                .collect::<Vec<_>>()
+        );
+    }
+}
@@ -0,0 +1,125 @@
+/*
+This file is part of Timetrack Jr.
+Timetrack Jr. is free software: you can redistribute it and/or modify it under the terms of the GNU General Public License as published by the Free Software Foundation, either version 3 of the License, or (at your option) any later version.
+Timetrack Jr. is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU General Public License for more details.
+You should have received a copy of the GNU General Public License along with Timetrack Jr. If not, see <https://www.gnu.org/licenses/>.
+*/
+use crate::{
+    cli,
+    commands::report,
+    db::{self, AutoStart},
+    zone::Zone,
+    TTError,
+};
+use chrono::{DateTime, Datelike, Local};
+use rusqlite::Connection;
+use serde_json::json;
+
+fn invalid_days(days: &str) -> TTError {
+    TTError::invalid(
+        "Days",
+        days,
+        "like weekdays, weekends, daily, or mon-wed,fri",
+    )
+}
+
+///The first rule scheduled for after `since` and no later than `now` (on the same day)
+pub(crate) fn due<'a>(
+    rules: &'a [AutoStart],
+    since: i64,
+    now: &DateTime<Local>,
+) -> Result<Option<&'a AutoStart>, TTError> {
+    let today = now.date_naive();
+    for rule in rules {
+        let days = cli::days_string_to_days(&rule.days).ok_or_else(|| invalid_days(&rule.days))?;
+        if !days[today.weekday().num_days_from_monday() as usize] {
+            continue;
+        }
+        let scheduled =
+            Zone::Local.midnight(today) + db::parse_time(&rule.time)?.seconds_since_midnight();
+        if since < scheduled && scheduled <= now.timestamp() {
+            return Ok(Some(rule));
+        }
+    }
+    Ok(None)
+}
+
+pub fn add_auto_start(
+    conn: &mut Connection,
+    days: &str,
+    time: &String,
+    category: &str,
+    json: &bool,
+) -> Result<(), TTError> {
+    cli::days_string_to_days(days).ok_or_else(|| invalid_days(days))?;
+    db::parse_time(time)?;
+    let id = db::write(conn, |tx| {
+        if !db::get_categories(tx)?.contains(category) {
+            return Err(TTError::CategoryNotFound {
+                category: category.to_string(),
+            });
+        }
+        db::add_auto_start(tx, days, time, category)
+    })?;
+    match json {
+        true => report::print_json(&json!({ "id": id }))?,
+        false => println!("Added auto-start {}", id),
+    }
+    Ok(())
+}
+
+pub fn delete_auto_start(conn: &mut Connection, id: &i64) -> Result<(), TTError> {
+    match db::write(conn, |tx| db::delete_auto_start(tx, *id))? {
+        true => Ok(()),
+        false => Err(TTError::NotFound {
+            kind: "auto-start",
+            id: *id,
+        }),
+    }
+}
+
+pub fn list_auto_starts(conn: &mut Connection, json: &bool) -> Result<(), TTError> {
+    let rules = db::get_auto_starts(&conn.transaction()?)?;
+    if *json {
+        return report::print_json(&json!(rules));
+    }
+    for rule in rules {
+        println!(
+            "{}: {} {} start {}",
+            rule.id, rule.days, rule.time, rule.category
+        );
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    #[test]
+    fn test_due() {
+        let rule = |id, days: &str, time: &str| AutoStart {
+            id,
+            days: days.to_string(),
+            time: time.to_string(),
+            category: "work".to_string(),
+        };
+        let rules = vec![rule(1, "weekdays", "09:00"), rule(2, "sat", "10:00")];
+        let at = |day, hour, minute| {
+            Local
+                .with_ymd_and_hms(2024, 6, day, hour, minute, 0)
+                .unwrap()
+        };
+        let id = |since: DateTime<Local>, now| {
+            due(&rules, since.timestamp(), &now).unwrap().map(|r| r.id)
+        };
+        //monday the 3rd
+        assert_eq!(Some(1), id(at(3, 8, 59), at(3, 9, 0)));
+        assert_eq!(None, id(at(3, 9, 0), at(3, 9, 1)));
+        assert_eq!(None, id(at(3, 8, 0), at(3, 8, 30)));
+        //saturday the 8th
+        assert_eq!(Some(2), id(at(8, 9, 59), at(8, 10, 1)));
+        assert_eq!(None, id(at(8, 8, 59), at(8, 9, 1)));
+    }
+}
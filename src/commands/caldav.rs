@@ -0,0 +1,270 @@
+/*
+This file is part of Timetrack Jr.
+Timetrack Jr. is free software: you can redistribute it and/or modify it under the terms of the GNU General Public License as published by the Free Software Foundation, either version 3 of the License, or (at your option) any later version.
+Timetrack Jr. is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU General Public License for more details.
+You should have received a copy of the GNU General Public License along with Timetrack Jr. If not, see <https://www.gnu.org/licenses/>.
+*/
+use crate::{
+    cli::FilterMode,
+    commands::{
+        export::unix_to_utc,
+        sync::{event_changes, EventChange},
+    },
+    db::{self, PushedEvent, TimeWindow},
+    TTError,
+};
+use base64::Engine;
+use icalendar::{Calendar, Component, Event};
+use rusqlite::Connection;
+use std::collections::BTreeSet;
+
+///What happened to a conditional request
+#[derive(Debug, PartialEq)]
+enum Outcome {
+    ///Written, with the new etag if the server sent one
+    Written(Option<String>),
+    ///The event changed (or was created) on the server since it was pushed
+    Changed,
+    Missing,
+}
+
+struct Caldav {
+    agent: ureq::Agent,
+    collection: String,
+    authorization: Option<String>,
+}
+
+///Stable UID for the event a time is pushed to, so re-pushing replaces it rather than duplicating it
+fn uid(time_id: i64) -> String {
+    format!("ttjr-{}@timetrack_jr", time_id)
+}
+
+fn event_body(time: &TimeWindow) -> String {
+    let id = time.id.unwrap_or(-1);
+    let mut calendar = Calendar::new();
+    calendar.push(
+        Event::new()
+            .uid(&uid(id))
+            .summary(&time.category)
+            .description(&format!("Timetrack Jr. time {}", id))
+            .starts(unix_to_utc(&time.start_time))
+            .ends(unix_to_utc(&time.end_time.unwrap_or(time.start_time)))
+            .done(),
+    );
+    calendar.to_string()
+}
+
+impl Caldav {
+    fn event_url(&self, time_id: i64) -> String {
+        format!("{}/ttjr-{}.ics", self.collection, time_id)
+    }
+
+    ///Make a request which only goes through if `condition` (an If-Match/If-None-Match header) holds
+    fn request(
+        &self,
+        method: &str,
+        url: &str,
+        condition: Option<(&str, &str)>,
+        body: Option<String>,
+    ) -> Result<Outcome, TTError> {
+        let error = |e: &dyn std::fmt::Display| TTError::Network {
+            message: format!("{} {} failed: {}", method, url, e),
+        };
+        let mut request = ureq::http::Request::builder().method(method).uri(url);
+        if let Some((name, value)) = condition {
+            request = request.header(name, value);
+        }
+        if let Some(authorization) = &self.authorization {
+            request = request.header("Authorization", authorization);
+        }
+        let response = match body {
+            Some(body) => self.agent.run(
+                request
+                    .header("Content-Type", "text/calendar; charset=utf-8")
+                    .body(body)
+                    .map_err(|e| error(&e))?,
+            ),
+            None => self.agent.run(request.body(()).map_err(|e| error(&e))?),
+        }
+        .map_err(|e| error(&e))?;
+        match response.status().as_u16() {
+            200..=299 => Ok(Outcome::Written(
+                response
+                    .headers()
+                    .get("ETag")
+                    .and_then(|e| e.to_str().ok())
+                    .map(|e| e.to_string()),
+            )),
+            404 | 410 => Ok(Outcome::Missing),
+            412 => Ok(Outcome::Changed),
+            status => Err(error(&format!("server returned {}", status))),
+        }
+    }
+
+    ///Create an event (if `etag` is None) or update it if it hasn't changed since `etag`
+    fn put(&self, time: &TimeWindow, etag: Option<Option<&str>>) -> Result<Outcome, TTError> {
+        let condition = match etag {
+            None => Some(("If-None-Match", "*")),
+            //without an etag the event is just overwritten
+            Some(etag) => etag.map(|e| ("If-Match", e)),
+        };
+        self.request(
+            "PUT",
+            &self.event_url(time.id.unwrap_or(-1)),
+            condition,
+            Some(event_body(time)),
+        )
+    }
+
+    fn delete(&self, event: &PushedEvent) -> Result<Outcome, TTError> {
+        self.request(
+            "DELETE",
+            &event.event_id,
+            event.etag.as_deref().map(|e| ("If-Match", e)),
+            None,
+        )
+    }
+}
+
+fn record(
+    conn: &mut Connection,
+    collection: &str,
+    url: String,
+    time: &TimeWindow,
+    etag: Option<String>,
+) -> Result<(), TTError> {
+    let event = PushedEvent {
+        time_id: time.id.unwrap_or(-1),
+        event_id: url,
+        etag,
+        category: time.category.clone(),
+        start_time: time.start_time,
+        end_time: time.end_time.unwrap_or(time.start_time),
+    };
+    db::write(conn, |tx| db::set_pushed_event(tx, collection, &event))
+}
+
+///Push finished times as events to a CalDAV collection (like a Nextcloud or Radicale calendar), updating events
+/// for times that changed and removing events for times that were deleted.
+/// Events edited on the server since they were pushed are left alone.
+pub fn push_caldav(conn: &mut Connection, url: &str) -> Result<(), TTError> {
+    let collection = url.trim_end_matches('/').to_string();
+    let (options, times, pushed) = {
+        let mut tx = conn.transaction()?;
+        (
+            db::get_options(&tx)?,
+            db::get_times(&mut tx, None, None, &FilterMode::Start)?,
+            db::get_pushed_events(&tx, &collection)?,
+        )
+    };
+    let username = std::env::var("CALDAV_USERNAME")
+        .ok()
+        .or(options.get("caldav-username").cloned());
+    let password = std::env::var("CALDAV_PASSWORD")
+        .ok()
+        .or(options.get("caldav-password").cloned());
+    let caldav = Caldav {
+        agent: ureq::Agent::new_with_config(
+            ureq::Agent::config_builder()
+                .http_status_as_error(false)
+                .build(),
+        ),
+        collection: collection.clone(),
+        authorization: username.map(|username| {
+            format!(
+                "Basic {}",
+                base64::engine::general_purpose::STANDARD.encode(format!(
+                    "{}:{}",
+                    username,
+                    password.unwrap_or_default()
+                ))
+            )
+        }),
+    };
+
+    let ids: BTreeSet<i64> = times.iter().filter_map(|t| t.id).collect();
+    let (mut created, mut updated, mut deleted) = (0, 0, 0);
+    let mut skipped = vec![];
+    for change in event_changes(&times, pushed, |id| !ids.contains(&id)) {
+        match change {
+            EventChange::Create(time) => match caldav.put(&time, None)? {
+                Outcome::Written(etag) => {
+                    record(
+                        conn,
+                        &collection,
+                        caldav.event_url(time.id.unwrap_or(-1)),
+                        &time,
+                        etag,
+                    )?;
+                    created += 1;
+                }
+                _ => skipped.push(format!(
+                    "time {} already has an event on the server",
+                    time.id.unwrap_or(-1)
+                )),
+            },
+            EventChange::Update(time, event) => {
+                let outcome = match caldav.put(&time, Some(event.etag.as_deref()))? {
+                    //deleted on the server, put it back
+                    Outcome::Missing => caldav.put(&time, None)?,
+                    outcome => outcome,
+                };
+                match outcome {
+                    Outcome::Written(etag) => {
+                        record(conn, &collection, event.event_id, &time, etag)?;
+                        updated += 1;
+                    }
+                    _ => skipped.push(format!(
+                        "the event for time {} was changed on the server",
+                        event.time_id
+                    )),
+                }
+            }
+            EventChange::Delete(event) => match caldav.delete(&event)? {
+                Outcome::Changed => skipped.push(format!(
+                    "the event for deleted time {} was changed on the server",
+                    event.time_id
+                )),
+                _ => {
+                    db::write(conn, |tx| {
+                        db::delete_pushed_event(tx, &collection, event.time_id)
+                    })?;
+                    deleted += 1;
+                }
+            },
+        }
+    }
+    println!(
+        "Created {} events, updated {} events, deleted {} events",
+        created, updated, deleted
+    );
+    for skipped in skipped {
+        println!("Skipped: {}", skipped);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_event_body() {
+        let body = event_body(&TimeWindow {
+            id: Some(7),
+            category: "work".to_string(),
+            start_time: 0,
+            end_time: Some(3600),
+            note: None,
+        });
+        let lines: Vec<&str> = body.lines().collect();
+        for expected in [
+            "UID:ttjr-7@timetrack_jr",
+            "SUMMARY:work",
+            "DTSTART:19700101T000000Z",
+            "DTEND:19700101T010000Z",
+        ] {
+            assert!(lines.contains(&expected), "missing {}", expected);
+        }
+    }
+}
@@ -0,0 +1,278 @@
+/*
+This file is part of Timetrack Jr.
+Timetrack Jr. is free software: you can redistribute it and/or modify it under the terms of the GNU General Public License as published by the Free Software Foundation, either version 3 of the License, or (at your option) any later version.
+Timetrack Jr. is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU General Public License for more details.
+You should have received a copy of the GNU General Public License along with Timetrack Jr. If not, see <https://www.gnu.org/licenses/>.
+*/
+use crate::{
+    cli::{self, ConflictRule},
+    commands::{
+        export::unix_to_utc,
+        sync::{self, api_request, Remote, RemoteTime},
+    },
+    db::{self, TimeWindow},
+    TTError,
+};
+use chrono::{DateTime, Duration, SecondsFormat, Utc};
+use rusqlite::Connection;
+use serde::Deserialize;
+use serde_json::{json, Value};
+use std::collections::{BTreeMap, BTreeSet};
+
+const API: &str = "https://api.clockify.me/api/v1";
+const PAGE_SIZE: usize = 200;
+///Later syncs re-check times from a day before the last sync, to catch times that were still running then
+const RESYNC_OVERLAP: i64 = 24 * 60 * 60;
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct TimeInterval {
+    start: String,
+    end: Option<String>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ClockifyEntry {
+    id: String,
+    project_id: Option<String>,
+    description: Option<String>,
+    time_interval: TimeInterval,
+}
+
+///Where a category's times go in Clockify
+#[derive(Clone, PartialEq)]
+struct Mapping {
+    workspace: String,
+    project: Option<String>,
+}
+
+struct Clockify {
+    api_key: String,
+    user_id: String,
+    default_workspace: String,
+    ///clockify-workspace/clockify-project category options
+    mappings: BTreeMap<String, Mapping>,
+    categories: db::Categories,
+}
+
+fn parse_timestamp(timestamp: &str) -> Result<i64, TTError> {
+    DateTime::parse_from_rfc3339(timestamp)
+        .map(|d| d.timestamp())
+        .map_err(|_| TTError::Network {
+            message: format!("Clockify returned an invalid timestamp \"{}\"", timestamp),
+        })
+}
+
+fn timestamp(tstamp: i64) -> String {
+    unix_to_utc(&tstamp).to_rfc3339_opts(SecondsFormat::Secs, true)
+}
+
+///Entries are identified by workspace and entry id (like "<workspace>/<entry>") since categories can sync to different workspaces
+fn split_id(id: &str) -> Result<(&str, &str), TTError> {
+    id.split_once('/').ok_or(TTError::Network {
+        message: format!("Invalid Clockify entry id \"{}\"", id),
+    })
+}
+
+impl Clockify {
+    fn request(&self, method: &str, path: &str, body: Option<&Value>) -> Result<Value, TTError> {
+        api_request(
+            method,
+            &format!("{}{}", API, path),
+            &[("X-Api-Key", &self.api_key)],
+            body,
+        )
+    }
+
+    fn mapping(&self, category: &str) -> Mapping {
+        self.mappings.get(category).cloned().unwrap_or(Mapping {
+            workspace: self.default_workspace.clone(),
+            project: None,
+        })
+    }
+
+    ///Entries are matched to categories by workspace and project, then by description
+    fn category(&self, workspace: &str, entry: &ClockifyEntry) -> Option<String> {
+        let by_project = self.mappings.iter().find_map(|(category, mapping)| {
+            match mapping.project.is_some()
+                && mapping.project == entry.project_id
+                && mapping.workspace == workspace
+            {
+                true => Some(category.clone()),
+                false => None,
+            }
+        });
+        by_project.or(entry
+            .description
+            .clone()
+            .filter(|d| self.categories.contains(d)))
+    }
+
+    fn entry_body(&self, time: &TimeWindow) -> Value {
+        json!({
+            "start": timestamp(time.start_time),
+            "end": timestamp(time.end_time.unwrap_or(time.start_time)),
+            "description": time.category,
+            "projectId": self.mapping(&time.category).project,
+        })
+    }
+}
+
+impl Remote for Clockify {
+    fn service(&self) -> &'static str {
+        "clockify"
+    }
+
+    fn list(&mut self, start: i64, end: i64) -> Result<Vec<RemoteTime>, TTError> {
+        let workspaces: BTreeSet<String> = self
+            .mappings
+            .values()
+            .map(|m| m.workspace.clone())
+            .chain([self.default_workspace.clone()])
+            .collect();
+        let mut times = vec![];
+        for workspace in workspaces {
+            for page in 1.. {
+                let entries: Vec<ClockifyEntry> = serde_json::from_value(self.request(
+                    "GET",
+                    &format!(
+                        "/workspaces/{}/user/{}/time-entries?start={}&end={}&page={}&page-size={}",
+                        workspace,
+                        self.user_id,
+                        timestamp(start),
+                        timestamp(end + 1),
+                        page,
+                        PAGE_SIZE
+                    ),
+                    None,
+                )?)?;
+                let count = entries.len();
+                for entry in entries {
+                    //running entries are synced once they're stopped
+                    if let Some(stop) = &entry.time_interval.end {
+                        times.push(RemoteTime {
+                            id: format!("{}/{}", workspace, entry.id),
+                            category: self.category(&workspace, &entry),
+                            start_time: parse_timestamp(&entry.time_interval.start)?,
+                            end_time: parse_timestamp(stop)?,
+                        });
+                    }
+                }
+                if count < PAGE_SIZE {
+                    break;
+                }
+            }
+        }
+        Ok(times)
+    }
+
+    fn create(&mut self, time: &TimeWindow) -> Result<String, TTError> {
+        let workspace = self.mapping(&time.category).workspace;
+        let created = self.request(
+            "POST",
+            &format!("/workspaces/{}/time-entries", workspace),
+            Some(&self.entry_body(time)),
+        )?;
+        match created["id"].as_str() {
+            Some(id) => Ok(format!("{}/{}", workspace, id)),
+            None => Err(TTError::Network {
+                message: "Clockify didn't return the id of the new time entry".to_string(),
+            }),
+        }
+    }
+
+    fn update(&mut self, id: &str, time: &TimeWindow) -> Result<(), TTError> {
+        let (workspace, entry) = split_id(id)?;
+        self.request(
+            "PUT",
+            &format!("/workspaces/{}/time-entries/{}", workspace, entry),
+            Some(&self.entry_body(time)),
+        )?;
+        Ok(())
+    }
+
+    fn delete(&mut self, id: &str) -> Result<(), TTError> {
+        let (workspace, entry) = split_id(id)?;
+        self.request(
+            "DELETE",
+            &format!("/workspaces/{}/time-entries/{}", workspace, entry),
+            None,
+        )?;
+        Ok(())
+    }
+}
+
+///Two-way sync of finished times with Clockify.  Without `start_time`, syncs times since the last sync (or the last 30 days).
+pub fn sync_clockify(
+    conn: &mut Connection,
+    start_time: &Option<String>,
+    conflicts: &ConflictRule,
+) -> Result<(), TTError> {
+    let (options, category_options, categories, last_synced) = {
+        let tx = conn.transaction()?;
+        (
+            db::get_options(&tx)?,
+            db::get_category_options(&tx)?,
+            db::get_categories(&tx)?,
+            db::get_last_synced(&tx, "clockify")?,
+        )
+    };
+    let api_key = std::env::var("CLOCKIFY_API_KEY")
+        .ok()
+        .or(options.get("clockify-api-key").cloned())
+        .ok_or(TTError::NotConfigured {
+            message: "Set your Clockify API key with `ttjr set-option clockify-api-key <key>` (or the CLOCKIFY_API_KEY environment variable)".to_string(),
+        })?;
+    let mut clockify = Clockify {
+        api_key,
+        user_id: String::new(),
+        default_workspace: String::new(),
+        mappings: BTreeMap::new(),
+        categories,
+    };
+    let user = clockify.request("GET", "/user", None)?;
+    clockify.user_id = user["id"].as_str().unwrap_or_default().to_string();
+    clockify.default_workspace = match options.get("clockify-workspace") {
+        Some(workspace) => workspace.clone(),
+        None => user["activeWorkspace"]
+            .as_str()
+            .or(user["defaultWorkspace"].as_str())
+            .ok_or(TTError::NotConfigured {
+                message: "Couldn't find your Clockify workspace, set one with `ttjr set-option clockify-workspace <id>`".to_string(),
+            })?
+            .to_string(),
+    };
+    clockify.mappings = category_options
+        .iter()
+        .filter(|(_, o)| o.contains_key("clockify-workspace") || o.contains_key("clockify-project"))
+        .map(|(category, o)| {
+            (
+                category.clone(),
+                Mapping {
+                    workspace: o
+                        .get("clockify-workspace")
+                        .cloned()
+                        .unwrap_or(clockify.default_workspace.clone()),
+                    project: o.get("clockify-project").cloned(),
+                },
+            )
+        })
+        .collect();
+
+    let start = match (start_time, last_synced) {
+        (Some(input), _) => cli::time_string_to_tstamp(start_time)
+            .ok_or_else(|| TTError::unparseable(input, "start-time"))?,
+        (None, Some(last_synced)) => last_synced - RESYNC_OVERLAP,
+        (None, None) => (Utc::now() - Duration::days(30)).timestamp(),
+    };
+    let report = sync::sync(
+        conn,
+        &mut clockify,
+        start,
+        Utc::now().timestamp(),
+        conflicts,
+    )?;
+    print!("{}", report);
+    Ok(())
+}
@@ -4,7 +4,12 @@ Timetrack Jr. is free software: you can redistribute it and/or modify it under t
 Timetrack Jr. is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU General Public License for more details.
 You should have received a copy of the GNU General Public License along with Timetrack Jr. If not, see <https://www.gnu.org/licenses/>.
 */
-use crate::{cli::OptionName, db, TTError};
+use crate::{
+    cli::{self, CategoryOptionName, OptionName},
+    commands::{self, daemon, notify},
+    db, l10n, TTError,
+};
+use clap::ValueEnum;
 use libsqlite3_sys;
 use rusqlite::Connection;
 
@@ -20,35 +25,68 @@ pub fn show(conn: &mut Connection) -> Result<(), TTError> {
 }
 
 pub fn add_category(conn: &mut Connection, category_name: &String) -> Result<(), TTError> {
-    let tx = conn.transaction()?;
-    db::add_category(&tx, &category_name)?;
-    tx.commit()?;
-    Ok(())
+    db::write(conn, |tx| db::add_category(tx, category_name))
 }
 
 pub fn delete_category(
     conn: &mut Connection,
     category_name: &String,
     delete_logged_times: &bool,
+    yes: &bool,
 ) -> Result<(), TTError> {
-    let tx = conn.transaction()?;
-    match db::delete_category(&tx, category_name, delete_logged_times) {
-        Err(TTError::SqlError(rusqlite::Error::SqliteFailure(
+    if *delete_logged_times {
+        let logged = db::write_or_dry_run(conn, &true, |tx| {
+            db::delete_category(tx, category_name, delete_logged_times)
+        })?;
+        if logged > 0 {
+            commands::proceed(
+                &format!(
+                    "Delete {} and the {} times logged to it?",
+                    category_name, logged
+                ),
+                yes,
+            )?;
+        }
+    }
+    match db::write(conn, |tx| {
+        db::delete_category(tx, category_name, delete_logged_times)
+    }) {
+        Err(TTError::Sql(rusqlite::Error::SqliteFailure(
             libsqlite3_sys::Error {
                 code: libsqlite3_sys::ErrorCode::ConstraintViolation,
                 extended_code: _,
             },
             _,
         ))) => {
-            return Err(TTError::TTError { message: "Unable to delete category because times have been logged with that category.  Add --delete-logged-times to delete the category AND any times logged with the category".to_string()});
-        }
-        Err(e) => {
-            return Err(e);
+            Err(TTError::InvalidArgument { message: "Unable to delete category because times have been logged with that category.  Add --delete-logged-times to delete the category AND any times logged with the category".to_string()})
         }
-        Ok(_) => {}
+        Err(e) => Err(e),
+        Ok(_) => Ok(()),
+    }
+}
+
+///Check that an option is a numeric id (like a Toggl workspace or project id)
+fn validate_id(option_name: &impl ValueEnum, option_value: &str) -> Result<(), TTError> {
+    match option_value.parse::<i64>() {
+        Ok(_) => Ok(()),
+        Err(_) => Err(TTError::invalid(
+            option_name.to_possible_value().unwrap().get_name(),
+            option_value,
+            "a numeric id",
+        )),
+    }
+}
+
+///Check that an option is a (positive) duration like 15m or 1h
+fn validate_duration(option_name: &impl ValueEnum, option_value: &str) -> Result<(), TTError> {
+    match cli::duration_string_to_seconds(option_value) {
+        Some(d) if d > 0 => Ok(()),
+        _ => Err(TTError::invalid(
+            option_name.to_possible_value().unwrap().get_name(),
+            option_value,
+            "a duration like 15m or 1h",
+        )),
     }
-    tx.commit()?;
-    Ok(())
 }
 
 pub fn set_option(
@@ -58,27 +96,174 @@ pub fn set_option(
 ) -> Result<(), TTError> {
     //validate option values if necessary
     match option_name {
-        OptionName::EndOfDay => {
+        OptionName::EndOfDay | OptionName::StartOfDay => {
             //check that end of day has correct format
             db::parse_time(option_value)?;
         }
+        OptionName::Currency => {}
+        OptionName::BillingIncrement => validate_duration(option_name, option_value)?,
+        OptionName::BillingRounding => {
+            cli::BillingRounding::from_str(option_value, true).map_err(|_| {
+                TTError::invalid(
+                    "billing-rounding",
+                    option_value,
+                    "one of up, down, or nearest",
+                )
+            })?;
+        }
+        OptionName::TogglApiToken => {}
+        OptionName::TogglWorkspace => validate_id(option_name, option_value)?,
+        //clockify ids aren't numeric
+        OptionName::ClockifyApiKey | OptionName::ClockifyWorkspace => {}
+        OptionName::CaldavUsername | OptionName::CaldavPassword => {}
+        OptionName::OutlookAccessToken | OptionName::OutlookCalendar => {}
+        OptionName::SlackToken | OptionName::DiscordClientId => {}
+        OptionName::MqttUsername | OptionName::MqttPassword => {}
+        OptionName::DaemonFeatures => {
+            daemon::parse_features(option_value)?;
+        }
+        OptionName::DaemonBindAddr | OptionName::BreakCategory => {}
+        OptionName::MeetingCalendar | OptionName::MeetingCategory => {}
+        OptionName::SnoozedUntil => {
+            option_value
+                .parse::<i64>()
+                .map_err(|_| TTError::invalid("snoozed-until", option_value, "a unix timestamp"))?;
+        }
+        OptionName::StartedNotification
+        | OptionName::StoppedNotification
+        | OptionName::TimingNotification
+        | OptionName::NotTimingNotification
+        | OptionName::LongRunningNotification => {}
+        OptionName::NotificationUrgency => {
+            for (_, urgency) in notify::event_settings(option_value)? {
+                cli::NotificationUrgency::from_str(&urgency, true).map_err(|_| {
+                    TTError::invalid("Notification urgency", &urgency, "low, normal, or critical")
+                })?;
+            }
+        }
+        OptionName::ActiveWindowMode => {
+            cli::ActiveWindowMode::from_str(option_value, true).map_err(|_| {
+                TTError::invalid("active-window-mode", option_value, "suggest or switch")
+            })?;
+        }
+        OptionName::IdleAction => {
+            cli::IdleAction::from_str(option_value, true)
+                .map_err(|_| TTError::invalid("idle-action", option_value, "ask or stop"))?;
+        }
+        OptionName::LockAction => {
+            cli::LockAction::from_str(option_value, true).map_err(|_| {
+                TTError::invalid("lock-action", option_value, "stop, pause, or ask")
+            })?;
+        }
+        OptionName::PomodoroWorkMinutes | OptionName::PomodoroBreakMinutes => {
+            if !matches!(option_value.parse::<u64>(), Ok(minutes) if minutes > 0) {
+                return Err(TTError::invalid(
+                    option_name.to_possible_value().unwrap().get_name(),
+                    option_value,
+                    "a whole number of minutes",
+                ));
+            }
+        }
+        OptionName::NotifyFallback => {}
+        OptionName::Language => l10n::validate(option_value)?,
+        OptionName::EarliestTime => {
+            cli::time_string_to_tstamp(&Some(option_value.clone()))
+                .ok_or(TTError::unparseable(option_value, "earliest-time"))?;
+        }
+        OptionName::QuietHours => {
+            notify::in_quiet_hours(option_value, &chrono::Local::now())?;
+        }
+        OptionName::NotificationSound => {
+            notify::event_settings(option_value)?;
+        }
+        OptionName::NotificationActions => {
+            option_value.parse::<bool>().map_err(|_| {
+                TTError::invalid("notification-actions", option_value, "true or false")
+            })?;
+        }
+        OptionName::RemindUntracked
+        | OptionName::LongRunning
+        | OptionName::WrapUp
+        | OptionName::IdleTimeout
+        | OptionName::BreakAfter
+        | OptionName::ExportFailureNotification => validate_duration(option_name, option_value)?,
     }
-    let tx = conn.transaction()?;
-    db::set_option(&tx, option_name, option_value)?;
-    tx.commit()?;
-    Ok(())
+    db::write(conn, |tx| db::set_option(tx, option_name, option_value))
 }
 
 pub fn unset_option(conn: &mut Connection, option_name: &OptionName) -> Result<(), TTError> {
-    let tx = conn.transaction()?;
-    db::unset_option(&tx, option_name)?;
-    tx.commit()?;
-    Ok(())
+    db::write(conn, |tx| db::unset_option(tx, option_name))
 }
 
 pub fn rename_category(conn: &mut Connection, old: &String, new: &String) -> Result<(), TTError> {
-    let mut tx = conn.transaction()?;
-    db::rename_category(&mut tx, old, new)?;
-    tx.commit()?;
-    Ok(())
+    db::write(conn, |tx| db::rename_category(tx, old, new))
+}
+
+pub fn set_category_option(
+    conn: &mut Connection,
+    category_name: &String,
+    option_name: &CategoryOptionName,
+    option_value: &String,
+) -> Result<(), TTError> {
+    //validate option values if necessary
+    match option_name {
+        CategoryOptionName::Rate => {
+            if !matches!(option_value.parse::<f64>(), Ok(rate) if rate >= 0.0) {
+                return Err(TTError::invalid(
+                    "Rate",
+                    option_value,
+                    "a non-negative number",
+                ));
+            }
+        }
+        CategoryOptionName::WeeklyCap
+        | CategoryOptionName::WeeklyGoal
+        | CategoryOptionName::DailyGoal => {
+            if cli::duration_string_to_seconds(option_value).is_none() {
+                return Err(TTError::invalid(
+                    option_name.to_possible_value().unwrap().get_name(),
+                    option_value,
+                    "a duration like 10h or 7h30m",
+                ));
+            }
+        }
+        CategoryOptionName::Client => {}
+        CategoryOptionName::TogglProject => validate_id(option_name, option_value)?,
+        CategoryOptionName::ClockifyWorkspace | CategoryOptionName::ClockifyProject => {}
+        CategoryOptionName::OutlookCategory | CategoryOptionName::GitRepo => {}
+        CategoryOptionName::SlackEmoji => {}
+        CategoryOptionName::GoalNotification => {
+            option_value.parse::<bool>().map_err(|_| {
+                TTError::invalid("goal-notification", option_value, "true or false")
+            })?;
+        }
+        CategoryOptionName::LongRunning => validate_duration(option_name, option_value)?,
+        CategoryOptionName::Schedule => {
+            if cli::schedule_string_to_seconds(option_value).is_none() {
+                return Err(TTError::invalid(
+                    "schedule",
+                    option_value,
+                    "a list of days and durations like mon-fri:4h,sat:1h",
+                ));
+            }
+        }
+    }
+    db::write(conn, |tx| {
+        if !db::get_categories(tx)?.contains(category_name) {
+            return Err(TTError::CategoryNotFound {
+                category: category_name.to_string(),
+            });
+        }
+        db::set_category_option(tx, category_name, option_name, option_value)
+    })
+}
+
+pub fn unset_category_option(
+    conn: &mut Connection,
+    category_name: &String,
+    option_name: &CategoryOptionName,
+) -> Result<(), TTError> {
+    db::write(conn, |tx| {
+        db::unset_category_option(tx, category_name, option_name)
+    })
 }
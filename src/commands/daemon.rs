@@ -0,0 +1,1192 @@
+/*
+This file is part of Timetrack Jr.
+Timetrack Jr. is free software: you can redistribute it and/or modify it under the terms of the GNU General Public License as published by the Free Software Foundation, either version 3 of the License, or (at your option) any later version.
+Timetrack Jr. is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU General Public License for more details.
+You should have received a copy of the GNU General Public License along with Timetrack Jr. If not, see <https://www.gnu.org/licenses/>.
+*/
+#[cfg(feature = "hotkeys")]
+use crate::commands::hotkey;
+#[cfg(feature = "async")]
+use crate::commands::runtime::Background;
+#[cfg(feature = "serve")]
+use crate::commands::serve;
+use crate::{
+    cli::{
+        self, ActiveWindowMode, DaemonFeature, FilterMode, IdleAction, LockAction,
+        NotificationEvent, OptionName,
+    },
+    commands::{
+        autostart, idle,
+        lock::{self, LockEvent},
+        log::{self, IdleChoice},
+        meetings::{self, Meeting},
+        notify::{self, Notification},
+        report,
+        suggest::{self, Suggestion},
+        webhook, window,
+    },
+    db::{self, HourMinute, TimeWindow},
+    l10n,
+    zone::Zone,
+    TTError,
+};
+use chrono::{DateTime, Datelike, Local, NaiveDate, Utc};
+use clap::ValueEnum;
+use rusqlite::{Connection, Transaction};
+use serde_json::json;
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    sync::mpsc::{Receiver, TryRecvError},
+    thread,
+    time::Duration,
+};
+
+///How far back git activity is considered by the suggest feature
+const SUGGEST_SINCE: i64 = 2 * 60 * 60;
+
+///How often the meeting-calendar is re-read
+const CALENDAR_REFRESH: i64 = 15 * 60;
+
+///Gaps between times shorter than this don't count as a break
+const SHORTEST_BREAK: i64 = 5 * 60;
+
+///Parse a comma-separated list of daemon features
+pub(crate) fn parse_features(listed: &str) -> Result<Vec<DaemonFeature>, TTError> {
+    let mut features = vec![];
+    for feature in listed.split(',') {
+        let feature = DaemonFeature::from_str(feature.trim(), true).map_err(|_| {
+            TTError::invalid(
+                "daemon-features",
+                listed,
+                &format!(
+                    "a comma-separated list of {}",
+                    DaemonFeature::value_variants()
+                        .iter()
+                        .map(feature_name)
+                        .collect::<Vec<_>>()
+                        .join(", ")
+                ),
+            )
+        })?;
+        check_compiled(&feature)?;
+        if !features.contains(&feature) {
+            features.push(feature);
+        }
+    }
+    Ok(features)
+}
+
+///Check that ttjr was built with the cargo feature a daemon feature needs
+fn check_compiled(feature: &DaemonFeature) -> Result<(), TTError> {
+    match feature {
+        #[cfg(not(feature = "serve"))]
+        DaemonFeature::Serve => Err(crate::commands::not_compiled("serve")),
+        #[cfg(not(feature = "webhooks"))]
+        DaemonFeature::Webhooks => Err(crate::commands::not_compiled("webhooks")),
+        #[cfg(not(feature = "hotkeys"))]
+        DaemonFeature::Hotkeys => Err(crate::commands::not_compiled("hotkeys")),
+        _ => Ok(()),
+    }
+}
+
+///Features listed in the daemon-features option
+fn enabled_features(options: &db::Options) -> Result<Vec<DaemonFeature>, TTError> {
+    match options.get("daemon-features") {
+        Some(listed) if !listed.trim().is_empty() => parse_features(listed),
+        _ => Err(TTError::NotConfigured {
+            message: "No daemon features are enabled, set daemon-features to a comma-separated list of them (i.e. `ttjr set-option daemon-features serve,webhooks`)".to_string(),
+        }),
+    }
+}
+
+///Whether `now` is during business hours: a weekday between the start-of-day and end-of-day options
+/// (9:00 to 17:00 if they aren't set)
+fn in_business_hours(now: &DateTime<Local>, options: &db::Options) -> Result<bool, TTError> {
+    let hours = |name: &str, default: &str| {
+        db::parse_time(options.get(name).unwrap_or(&default.to_string()))
+    };
+    let time = HourMinute::of(now);
+    Ok(now.weekday().num_days_from_monday() < 5
+        && hours("start-of-day", "09:00")? <= time
+        && time < hours("end-of-day", "17:00")?)
+}
+
+///Seconds a category can be timed before it's worth asking about, from its long-running category option or the
+/// long-running option
+fn long_running_threshold(
+    category: &str,
+    options: &db::Options,
+    category_options: &db::CategoryOptions,
+) -> Option<i64> {
+    category_options
+        .get(category)
+        .and_then(|o| o.get("long-running"))
+        .or(options.get("long-running"))
+        .and_then(|threshold| cli::duration_string_to_seconds(threshold))
+}
+
+///Today's totals, and a reminder about what's still being timed
+fn wrap_up_message(
+    totals: &BTreeMap<String, u64>,
+    open: Option<&TimeWindow>,
+    end_of_day: &HourMinute,
+) -> String {
+    let mut message = format!(
+        "Today: {} ({} total)",
+        totals
+            .iter()
+            .map(|(category, seconds)| format!("{} {}", category, report::hours_minutes(*seconds)))
+            .collect::<Vec<_>>()
+            .join(", "),
+        report::hours_minutes(totals.values().sum())
+    );
+    if let Some(open) = open {
+        message += &format!(
+            "\nStill timing {}, stop or amend it before it's ended at {}",
+            open.category, end_of_day
+        );
+    }
+    message
+}
+
+///Stop timing once end-of-day has passed since it was started (ending it at end-of-day, like stopping it would)
+fn end_of_day(conn: &mut Connection) -> Result<(), TTError> {
+    let tx = conn.transaction()?;
+    let end_of_day = match db::get_options(&tx)?.get("end-of-day") {
+        Some(end_of_day) => db::parse_time(end_of_day)?,
+        None => return Ok(()),
+    };
+    match db::get_last_open_time(&tx)? {
+        Some(open)
+            if db::end_of_business_after(open.start_time, &end_of_day, &Zone::Local)
+                <= Utc::now().timestamp() =>
+        {
+            tracing::debug!(
+                "stopping {}, end-of-day ({}) has passed since it started",
+                open.category,
+                end_of_day
+            );
+            drop(tx);
+            log::stop_timing(conn, &true)
+        }
+        _ => Ok(()),
+    }
+}
+
+///When the current stretch of work started: the start of the open time, or of the earliest time before it with no
+/// break (in `break_category`, or a gap of SHORTEST_BREAK or more) in between.  `times` are in order.
+fn working_since(times: &[TimeWindow], break_category: &str) -> Option<i64> {
+    let mut since: Option<i64> = None;
+    for time in times.iter().rev() {
+        match (since, time.end_time) {
+            (None, None) if time.category != break_category => since = Some(time.start_time),
+            (Some(start), Some(end))
+                if start - end < SHORTEST_BREAK && time.category != break_category =>
+            {
+                since = Some(time.start_time)
+            }
+            _ => break,
+        }
+    }
+    since
+}
+
+///A daily or weekly goal that's been reached
+#[derive(Debug, PartialEq)]
+struct GoalReached {
+    category: String,
+    ///"today" or "this week"
+    period: &'static str,
+    ///Start of the day or week
+    since: i64,
+    logged: u64,
+    goal: u64,
+}
+
+impl GoalReached {
+    fn message(&self) -> String {
+        format!(
+            "{}: {}/{} {}",
+            self.category,
+            report::hours_minutes(self.logged),
+            report::hours_minutes(self.goal),
+            self.period
+        )
+    }
+}
+
+///Daily and weekly goals reached as of `now`, for categories that haven't turned off goal-notification
+fn goals_reached(tx: &mut Transaction, now: &DateTime<Local>) -> Result<Vec<GoalReached>, TTError> {
+    let today = now.date_naive();
+    let category_options = db::get_category_options(tx)?;
+    let mut reached = vec![];
+    for (option, period, since) in [
+        ("daily-goal", "today", Zone::Local.midnight(today)),
+        (
+            "weekly-goal",
+            "this week",
+            report::start_of_week(today, &Zone::Local),
+        ),
+    ] {
+        let goals = report::category_durations(tx, option)?;
+        if goals.is_empty() {
+            continue;
+        }
+        let totals = report::totals_between(tx, since, now.timestamp())?;
+        for (category, goal) in goals {
+            let logged = totals.get(&category).copied().unwrap_or(0);
+            let notify = category_options
+                .get(&category)
+                .and_then(|options| options.get("goal-notification"))
+                .is_none_or(|notify| notify != "false");
+            if notify && goal > 0 && logged >= goal {
+                reached.push(GoalReached {
+                    category,
+                    period,
+                    since,
+                    logged,
+                    goal,
+                });
+            }
+        }
+    }
+    Ok(reached)
+}
+
+///Ways to snooze reminders from their notifications
+const SNOOZE_CHOICES: [&str; 3] = ["30m", "1h", "2h"];
+
+///Whether reminders are snoozed (see `snooze`)
+fn snoozed(options: &db::Options) -> bool {
+    options
+        .get("snoozed-until")
+        .and_then(|until| until.parse::<i64>().ok())
+        .is_some_and(|until| Utc::now().timestamp() < until)
+}
+
+///Snooze reminders for `duration` (or stop snoozing them, for 0)
+pub fn snooze(conn: &mut Connection, duration: &str, json: &bool) -> Result<(), TTError> {
+    let seconds = match duration.trim() {
+        "0" => Some(0),
+        duration => cli::duration_string_to_seconds(duration),
+    }
+    .ok_or_else(|| TTError::InvalidArgument {
+        message: format!(
+            "Snooze for a duration like 30m or 1h (or 0 to stop snoozing), got \"{}\"",
+            duration
+        ),
+    })?;
+    match seconds {
+        0 => {
+            db::write(conn, |tx| db::unset_option(tx, &OptionName::SnoozedUntil))?;
+            match json {
+                true => report::print_json(&json!({ "snoozed_until": null }))?,
+                false => println!("Reminders are back on"),
+            }
+        }
+        _ => {
+            let until = Utc::now().timestamp() + seconds;
+            db::write(conn, |tx| {
+                db::set_option(tx, &OptionName::SnoozedUntil, &until.to_string())
+            })?;
+            match json {
+                true => report::print_json(&json!({ "snoozed_until": until }))?,
+                false => println!(
+                    "Snoozed reminders until {}",
+                    Zone::Local.format(&until, "%H:%M")
+                ),
+            }
+        }
+    }
+    Ok(())
+}
+
+///Show a reminder.  With the notification-actions option set, it has buttons to snooze reminders, and this waits
+/// until one's clicked (or the notification's dismissed).
+fn show_reminder(
+    conn: &mut Connection,
+    event: &NotificationEvent,
+    reminder: &mut Notification,
+) -> Result<(), TTError> {
+    let options = db::get_options(&conn.transaction()?)?;
+    if options.get("notification-actions").map(|o| o.as_str()) != Some("true") {
+        return notify::show(conn, event, reminder);
+    }
+    let actions: notify::Actions = SNOOZE_CHOICES
+        .iter()
+        .map(|duration| (duration.to_string(), format!("Snooze {}", duration)))
+        .collect();
+    match notify::choose(conn, event, reminder, &actions)? {
+        Some(duration) => snooze(conn, &duration, &false),
+        None => Ok(()),
+    }
+}
+
+fn feature_name(feature: &DaemonFeature) -> String {
+    feature.to_possible_value().unwrap().get_name().to_string()
+}
+
+///What the daemon's features remember between checks
+struct Daemon {
+    features: Vec<DaemonFeature>,
+    ///Suggestion that was last notified about, so it's only reported once
+    last_suggestion: Option<Suggestion>,
+    ///When the daemon noticed nothing was being timed during business hours (or last reminded you about it)
+    untracked_since: Option<i64>,
+    ///Id of the time the daemon last warned had been running a long time, so it's only warned about once
+    warned_long_running: Option<i64>,
+    ///Day the wrap-up notification was last sent
+    wrapped_up: Option<NaiveDate>,
+    ///When you went idle while something was being timed
+    idle_since: Option<i64>,
+    ///What was being timed when idle-action stop stopped it
+    idle_stopped: Option<TimeWindow>,
+    ///Category the focused window last suggested (or switched to), so it's only acted on once
+    window_category: Option<String>,
+    ///When auto-starts were last checked
+    auto_started_through: Option<i64>,
+    ///Screen lock and unlock events, if the lock feature is enabled
+    lock_events: Option<Receiver<LockEvent>>,
+    ///Category that was being timed when the screen was locked
+    locked_category: Option<String>,
+    ///Meetings from the meeting-calendar option, and when they were read
+    meetings: Option<(i64, Vec<Meeting>)>,
+    ///Start of the meeting the daemon last suggested timing, so it's only suggested once
+    meeting_suggested: Option<i64>,
+    ///Start of the stretch of work the daemon last suggested a break from, so it's only suggested once
+    break_suggested: Option<i64>,
+    ///Goals that were already reached at the last check (by category, period, and start of the period)
+    goals_reached: Option<BTreeSet<(String, &'static str, i64)>>,
+    ///Webhook delivery in the background, if the webhooks feature is enabled
+    #[cfg(feature = "async")]
+    webhooks: Option<Background>,
+}
+
+impl Daemon {
+    fn run(&mut self, conn: &mut Connection, feature: &DaemonFeature) -> Result<(), TTError> {
+        match feature {
+            //runs on its own thread
+            DaemonFeature::Serve => {}
+            #[cfg(not(feature = "async"))]
+            DaemonFeature::Webhooks => {
+                webhook::deliver(conn)?;
+            }
+            //a slow endpoint shouldn't hold up reminders and idle checks
+            #[cfg(feature = "async")]
+            DaemonFeature::Webhooks => {
+                if let Some(webhooks) = &self.webhooks {
+                    webhooks.spawn("webhooks", webhook::deliver);
+                }
+            }
+            DaemonFeature::Suggest => {
+                let since = Utc::now().timestamp() - SUGGEST_SINCE;
+                if let Some((summary, message)) =
+                    suggest::mismatch(conn, since, &mut self.last_suggestion)?
+                {
+                    let mut notification = notify::notification(conn, &NotificationEvent::Suggest)?;
+                    notification.summary(&summary).body(&message);
+                    notify::show(conn, &NotificationEvent::Suggest, &notification)?;
+                }
+            }
+            DaemonFeature::Idle => self.check_idle(conn)?,
+            DaemonFeature::EndOfDay => end_of_day(conn)?,
+            DaemonFeature::AutoStart => self.auto_start(conn)?,
+            DaemonFeature::ActiveWindow => self.follow_window(conn)?,
+            DaemonFeature::Lock => self.handle_locks(conn)?,
+            DaemonFeature::Breaks => self.suggest_break(conn)?,
+            DaemonFeature::Goals => self.congratulate(conn)?,
+            //listens on the main thread
+            DaemonFeature::Hotkeys => {}
+            DaemonFeature::Reminders => {
+                let options = db::get_options(&conn.transaction()?)?;
+                match snoozed(&options) {
+                    //untracked time is counted from the end of the snooze
+                    true => self.untracked_since = None,
+                    false => {
+                        self.remind_untracked(conn)?;
+                        self.suggest_meeting(conn)?;
+                        self.warn_long_running(conn)?;
+                        self.wrap_up(conn)?;
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    ///Every remind-untracked, notify if nothing's been timed during business hours
+    fn remind_untracked(&mut self, conn: &mut Connection) -> Result<(), TTError> {
+        let (options, open) = {
+            let tx = conn.transaction()?;
+            (db::get_options(&tx)?, db::get_last_open_time(&tx)?)
+        };
+        let every = match options.get("remind-untracked") {
+            Some(every) => cli::duration_string_to_seconds(every).unwrap_or(0),
+            None => return Ok(()),
+        };
+        let now = Local::now();
+        if open.is_some()
+            || every <= 0
+            || !in_business_hours(&now, &options)?
+            || self.current_meeting(&options)?.is_some()
+        {
+            self.untracked_since = None;
+            return Ok(());
+        }
+        let since = *self.untracked_since.get_or_insert(now.timestamp());
+        if now.timestamp() - since >= every {
+            let mut reminder = notify::notification(conn, &NotificationEvent::Untracked)?;
+            reminder
+                .summary(&l10n::text("untracked-summary", &[]))
+                .body(&l10n::text(
+                    "untracked-body",
+                    &[("minutes", ((now.timestamp() - since) / 60).into())],
+                ));
+            self.untracked_since = Some(now.timestamp());
+            show_reminder(conn, &NotificationEvent::Untracked, &mut reminder)?;
+        }
+        Ok(())
+    }
+
+    ///Once a stretch of work has gone on for break-after, suggest a break (with a button to start timing it if the
+    /// break-category exists)
+    fn suggest_break(&mut self, conn: &mut Connection) -> Result<(), TTError> {
+        let mut tx = conn.transaction()?;
+        let options = db::get_options(&tx)?;
+        let after = match options.get("break-after") {
+            Some(after) => cli::duration_string_to_seconds(after).unwrap_or(0),
+            None => return Ok(()),
+        };
+        let break_category = options
+            .get("break-category")
+            .cloned()
+            .unwrap_or("break".to_string());
+        let now = Utc::now().timestamp();
+        let mut times = db::get_times(
+            &mut tx,
+            Some(now - 24 * 60 * 60),
+            None,
+            &FilterMode::Overlap,
+        )?;
+        times.sort_by_key(|time| time.start_time);
+        let since = match working_since(&times, &break_category) {
+            Some(since) if after > 0 && now - since >= after => since,
+            _ => return Ok(()),
+        };
+        if self.break_suggested == Some(since) {
+            return Ok(());
+        }
+        let can_start = db::get_categories(&tx)?.contains(&break_category);
+        drop(tx);
+        self.break_suggested = Some(since);
+        let mut suggestion = notify::notification(conn, &NotificationEvent::Break)?;
+        suggestion
+            .summary(&l10n::text("break-summary", &[]))
+            .body(&l10n::text(
+                "break-body",
+                &[(
+                    "elapsed",
+                    report::hours_minutes((now - since) as u64).into(),
+                )],
+            ));
+        if !can_start {
+            return notify::show(conn, &NotificationEvent::Break, &suggestion);
+        }
+        let actions = vec![(
+            "break".to_string(),
+            l10n::text(
+                "break-start",
+                &[("category", break_category.as_str().into())],
+            ),
+        )];
+        match notify::choose(conn, &NotificationEvent::Break, &mut suggestion, &actions)? {
+            Some(_) => log::start_timing(conn, &break_category, &None, &false),
+            None => Ok(()),
+        }
+    }
+
+    ///Notify when a category reaches a goal it hadn't at the last check (goals already reached when the daemon
+    /// starts aren't notified about)
+    fn congratulate(&mut self, conn: &mut Connection) -> Result<(), TTError> {
+        let reached = goals_reached(&mut conn.transaction()?, &Local::now())?;
+        if let Some(previously) = &self.goals_reached {
+            for goal in &reached {
+                if !previously.contains(&(goal.category.clone(), goal.period, goal.since)) {
+                    let mut notification =
+                        notify::notification(conn, &NotificationEvent::GoalReached)?;
+                    notification
+                        .summary(&l10n::text("goal-reached", &[]))
+                        .body(&goal.message());
+                    notify::show(conn, &NotificationEvent::GoalReached, &notification)?;
+                }
+            }
+        }
+        self.goals_reached = Some(
+            reached
+                .into_iter()
+                .map(|goal| (goal.category, goal.period, goal.since))
+                .collect(),
+        );
+        Ok(())
+    }
+
+    ///The meeting going on now (and when it started) from the meeting-calendar option
+    fn current_meeting(
+        &mut self,
+        options: &db::Options,
+    ) -> Result<Option<(Meeting, i64)>, TTError> {
+        let source = match options.get("meeting-calendar") {
+            Some(source) => source,
+            None => return Ok(None),
+        };
+        let now = Utc::now().timestamp();
+        if self
+            .meetings
+            .as_ref()
+            .is_none_or(|(read_at, _)| now - read_at >= CALENDAR_REFRESH)
+        {
+            //keep using what was last read (and don't retry until the next refresh) if the calendar can't be read
+            let previous = self.meetings.take().map(|(_, m)| m).unwrap_or_default();
+            match meetings::fetch(source) {
+                Ok(read) => self.meetings = Some((now, read)),
+                Err(e) => {
+                    self.meetings = Some((now, previous));
+                    return Err(e);
+                }
+            }
+        }
+        Ok(self
+            .meetings
+            .as_ref()
+            .and_then(|(_, read)| meetings::current(read, now))
+            .map(|(meeting, start)| (meeting.clone(), start)))
+    }
+
+    ///Notify (once per meeting) when a meeting starts while meeting-category isn't being timed
+    fn suggest_meeting(&mut self, conn: &mut Connection) -> Result<(), TTError> {
+        let (options, open) = {
+            let tx = conn.transaction()?;
+            (db::get_options(&tx)?, db::get_last_open_time(&tx)?)
+        };
+        let category = match options.get("meeting-category") {
+            Some(category) => category,
+            None => return Ok(()),
+        };
+        let (meeting, start) = match self.current_meeting(&options)? {
+            Some(current) => current,
+            None => return Ok(()),
+        };
+        if self.meeting_suggested == Some(start)
+            || open.is_some_and(|open| &open.category == category)
+        {
+            return Ok(());
+        }
+        let mut notification = notify::notification(conn, &NotificationEvent::Meeting)?;
+        notification
+            .summary(&l10n::text(
+                "meeting-summary",
+                &[("meeting", meeting.summary.as_str().into())],
+            ))
+            .body(&l10n::text(
+                "meeting-body",
+                &[("category", category.as_str().into())],
+            ));
+        notify::show(conn, &NotificationEvent::Meeting, &notification)?;
+        self.meeting_suggested = Some(start);
+        Ok(())
+    }
+
+    ///Notify (once) when the time being recorded has run longer than its long-running threshold
+    fn warn_long_running(&mut self, conn: &mut Connection) -> Result<(), TTError> {
+        let tx = conn.transaction()?;
+        let open = match db::get_last_open_time(&tx)? {
+            Some(open) => open,
+            None => return Ok(()),
+        };
+        let threshold = long_running_threshold(
+            &open.category,
+            &db::get_options(&tx)?,
+            &db::get_category_options(&tx)?,
+        );
+        let now = Utc::now().timestamp();
+        match threshold {
+            Some(threshold)
+                if now - open.start_time >= threshold && self.warned_long_running != open.id =>
+            {
+                drop(tx);
+                let mut reminder = notify::from_template(
+                    conn,
+                    &NotificationEvent::LongRunning,
+                    &l10n::template("long-running-notification"),
+                    Some(&open),
+                )?;
+                self.warned_long_running = open.id;
+                show_reminder(conn, &NotificationEvent::LongRunning, &mut reminder)?;
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+
+    ///Once a day, wrap-up before end-of-day, notify with today's totals and what's still being timed
+    fn wrap_up(&mut self, conn: &mut Connection) -> Result<(), TTError> {
+        let mut tx = conn.transaction()?;
+        let options = db::get_options(&tx)?;
+        let (lead, end_of_day) = match (options.get("wrap-up"), options.get("end-of-day")) {
+            (Some(lead), Some(end_of_day)) => (
+                cli::duration_string_to_seconds(lead).unwrap_or(0),
+                db::parse_time(end_of_day)?,
+            ),
+            _ => return Ok(()),
+        };
+        let now = Local::now();
+        let today = now.date_naive();
+        let midnight = Zone::Local.midnight(today);
+        //same end-of-day that stops timing, so they agree on days that change DST
+        let until_end_of_day =
+            db::end_of_business_after(midnight, &end_of_day, &Zone::Local) - now.timestamp();
+        if self.wrapped_up == Some(today) || until_end_of_day <= 0 || until_end_of_day > lead {
+            return Ok(());
+        }
+        let totals = report::totals_between(&mut tx, midnight, now.timestamp())?;
+        let open = db::get_last_open_time(&tx)?;
+        if totals.is_empty() && open.is_none() {
+            return Ok(());
+        }
+        let message = wrap_up_message(&totals, open.as_ref(), &end_of_day);
+        drop(tx);
+        let mut notification = notify::notification(conn, &NotificationEvent::WrapUp)?;
+        notification
+            .summary(&l10n::text(
+                "wrap-up-summary",
+                &[("end_of_day", end_of_day.to_string().into())],
+            ))
+            .body(&message);
+        notify::show(conn, &NotificationEvent::WrapUp, &notification)?;
+        self.wrapped_up = Some(today);
+        Ok(())
+    }
+
+    ///Notice going idle (for idle-timeout) while timing something, stopping timing then with idle-action stop, and on
+    /// returning ask what to do with the time away
+    fn check_idle(&mut self, conn: &mut Connection) -> Result<(), TTError> {
+        let tx = conn.transaction()?;
+        let options = db::get_options(&tx)?;
+        let timeout = match options.get("idle-timeout") {
+            Some(timeout) => cli::duration_string_to_seconds(timeout).unwrap_or(0),
+            None => return Ok(()),
+        };
+        let idle = idle::idle_seconds()?;
+        let now = Utc::now().timestamp();
+        match self.idle_since {
+            None if idle >= timeout => {
+                if db::get_last_open_time(&tx)?.is_none() {
+                    return Ok(());
+                }
+                let idle_start = now - idle;
+                self.idle_since = Some(idle_start);
+                let action = options
+                    .get("idle-action")
+                    .and_then(|a| IdleAction::from_str(a, true).ok())
+                    .unwrap_or(IdleAction::Ask);
+                if action == IdleAction::Stop {
+                    drop(tx);
+                    self.idle_stopped = log::stop_timing_at(conn, idle_start)?;
+                }
+                Ok(())
+            }
+            Some(idle_start) if idle < timeout => {
+                self.idle_since = None;
+                let returned = now - idle;
+                let stopped = self.idle_stopped.take();
+                let time = match (&stopped, db::get_last_open_time(&tx)?) {
+                    (Some(stopped), _) => stopped.clone(),
+                    (None, Some(open)) if open.start_time < idle_start => open,
+                    _ => return Ok(()),
+                };
+                let break_category = options
+                    .get("break-category")
+                    .cloned()
+                    .unwrap_or("break".to_string());
+                let idle_time = Zone::Local.format(&idle_start, "%H:%M");
+                let mut actions: notify::Actions =
+                    vec![("keep".to_string(), l10n::text("idle-keep", &[]))];
+                actions.push(match stopped {
+                    Some(_) => (
+                        "resume".to_string(),
+                        l10n::text(
+                            "idle-resume",
+                            &[("category", time.category.as_str().into())],
+                        ),
+                    ),
+                    None => (
+                        "stop".to_string(),
+                        l10n::text("idle-stop", &[("time", idle_time.as_str().into())]),
+                    ),
+                });
+                if db::get_categories(&tx)?.contains(&break_category) {
+                    actions.push((
+                        "break".to_string(),
+                        l10n::text(
+                            "idle-break",
+                            &[("category", break_category.as_str().into())],
+                        ),
+                    ));
+                }
+                drop(tx);
+                let away = report::hours_minutes((returned - idle_start) as u64);
+                let body = match stopped {
+                    Some(_) => l10n::text(
+                        "idle-stopped-body",
+                        &[
+                            ("category", time.category.as_str().into()),
+                            ("time", idle_time.as_str().into()),
+                            ("away", away.into()),
+                        ],
+                    ),
+                    None => l10n::text(
+                        "idle-body",
+                        &[
+                            ("away", away.into()),
+                            ("category", time.category.as_str().into()),
+                        ],
+                    ),
+                };
+                let mut welcome = notify::notification(conn, &NotificationEvent::Idle)?;
+                welcome
+                    .summary(&l10n::text("welcome-back", &[]))
+                    .body(&body);
+                let chosen =
+                    notify::choose(conn, &NotificationEvent::Idle, &mut welcome, &actions)?;
+                //without an answer things are left as they are
+                let choice = match (chosen.as_deref(), &stopped) {
+                    (Some("keep"), _) => IdleChoice::Keep,
+                    (Some("stop"), _) => IdleChoice::Stop,
+                    (Some("resume"), _) => IdleChoice::Resume,
+                    (Some("break"), _) => IdleChoice::Break(break_category),
+                    (_, Some(_)) => IdleChoice::Stop,
+                    (_, None) => IdleChoice::Keep,
+                };
+                match stopped {
+                    Some(stopped) => {
+                        log::resolve_idle_stopped(conn, &stopped, idle_start, returned, &choice)
+                    }
+                    None => log::resolve_idle(conn, idle_start, returned, &choice),
+                }
+            }
+            _ => Ok(()),
+        }
+    }
+
+    ///Start timing an auto-start rule's category when its time has come since the last check, unless something's
+    /// already being timed
+    fn auto_start(&mut self, conn: &mut Connection) -> Result<(), TTError> {
+        let now = Local::now();
+        let since = match self.auto_started_through.replace(now.timestamp()) {
+            Some(since) => since,
+            None => return Ok(()),
+        };
+        let tx = conn.transaction()?;
+        if db::get_last_open_time(&tx)?.is_some() {
+            return Ok(());
+        }
+        let rules = db::get_auto_starts(&tx)?;
+        drop(tx);
+        match autostart::due(&rules, since, &now)? {
+            Some(rule) => log::start_timing(conn, &rule.category, &None, &true),
+            None => Ok(()),
+        }
+    }
+
+    ///Suggest (or switch to) the category of the first window rule matching the focused window, if it isn't
+    /// what's being timed
+    fn follow_window(&mut self, conn: &mut Connection) -> Result<(), TTError> {
+        let tx = conn.transaction()?;
+        let rules = db::get_window_rules(&tx)?;
+        if rules.is_empty() {
+            return Ok(());
+        }
+        let title = window::active_window()?;
+        let rule = match window::matching_rule(&rules, &title)? {
+            Some(rule) => rule.clone(),
+            None => return Ok(()),
+        };
+        let open = db::get_last_open_time(&tx)?;
+        if open.as_ref().map(|o| &o.category) == Some(&rule.category) {
+            self.window_category = None;
+            return Ok(());
+        }
+        if self.window_category.as_ref() == Some(&rule.category) {
+            return Ok(());
+        }
+        let mode = db::get_options(&tx)?
+            .get("active-window-mode")
+            .and_then(|m| ActiveWindowMode::from_str(m, true).ok())
+            .unwrap_or(ActiveWindowMode::Suggest);
+        drop(tx);
+        self.window_category = Some(rule.category.clone());
+        match mode {
+            ActiveWindowMode::Switch => log::start_timing(conn, &rule.category, &None, &true),
+            ActiveWindowMode::Suggest => {
+                let mut args = vec![
+                    ("title", title.as_str().into()),
+                    ("pattern", rule.pattern.as_str().into()),
+                ];
+                let message = match open {
+                    Some(open) => {
+                        args.push(("category", open.category.into()));
+                        l10n::text("window-body-timing", &args)
+                    }
+                    None => l10n::text("window-body", &args),
+                };
+                let mut notification = notify::notification(conn, &NotificationEvent::Suggest)?;
+                notification
+                    .summary(&l10n::text(
+                        "window-summary",
+                        &[("category", rule.category.as_str().into())],
+                    ))
+                    .body(&message);
+                notify::show(conn, &NotificationEvent::Suggest, &notification)?;
+                Ok(())
+            }
+        }
+    }
+
+    ///Stop timing at each screen lock since the last check, and start timing again (or ask to) at each unlock
+    fn handle_locks(&mut self, conn: &mut Connection) -> Result<(), TTError> {
+        let events = match &self.lock_events {
+            Some(events) => events,
+            None => return Ok(()),
+        };
+        let mut pending = vec![];
+        loop {
+            match events.try_recv() {
+                Ok(event) => pending.push(event),
+                Err(TryRecvError::Empty) => break,
+                Err(TryRecvError::Disconnected) => {
+                    self.lock_events = None;
+                    return Err(TTError::System {
+                        message: "Stopped getting screen lock events".to_string(),
+                    });
+                }
+            }
+        }
+        let action = db::get_options(&conn.transaction()?)?
+            .get("lock-action")
+            .and_then(|a| LockAction::from_str(a, true).ok())
+            .unwrap_or(LockAction::Pause);
+        for event in pending {
+            match (event.locked, self.locked_category.take()) {
+                (true, _) => {
+                    self.locked_category = log::stop_timing_at(conn, event.at)?.map(|t| t.category)
+                }
+                (false, Some(category)) => match action {
+                    LockAction::Stop => {}
+                    LockAction::Pause => log::start_timing_at(conn, &category, event.at + 1)?,
+                    LockAction::Ask => {
+                        let resume = vec![(
+                            "resume".to_string(),
+                            l10n::text(
+                                "unlocked-resume",
+                                &[("category", category.as_str().into())],
+                            ),
+                        )];
+                        let mut welcome = notify::notification(conn, &NotificationEvent::Unlocked)?;
+                        welcome
+                            .summary(&l10n::text("welcome-back", &[]))
+                            .body(&l10n::text(
+                                "unlocked-body",
+                                &[("category", category.as_str().into())],
+                            ));
+                        let chosen = notify::choose(
+                            conn,
+                            &NotificationEvent::Unlocked,
+                            &mut welcome,
+                            &resume,
+                        )?;
+                        if chosen.is_some() {
+                            log::start_timing_at(conn, &category, event.at + 1)?;
+                        }
+                    }
+                },
+                (false, None) => {}
+            }
+        }
+        Ok(())
+    }
+
+    ///Run every feature once.  A feature's failures are only warnings, so one broken feature doesn't stop the others.
+    fn check(&mut self, conn: &mut Connection) -> Result<(), TTError> {
+        for feature in self.features.clone() {
+            match self.run(conn, &feature) {
+                Err(e) if e.recoverable() => {
+                    eprintln!("{} failed: {}", feature_name(&feature), e)
+                }
+                Err(e) => return Err(e),
+                Ok(_) => {}
+            }
+        }
+        Ok(())
+    }
+
+    fn check_every(&mut self, conn: &mut Connection, interval: &u64) -> Result<(), TTError> {
+        loop {
+            self.check(conn)?;
+            thread::sleep(Duration::from_secs(*interval));
+        }
+    }
+}
+
+///Run the features enabled in the daemon-features option until killed, checking every `interval` seconds
+#[cfg_attr(
+    not(any(feature = "serve", feature = "async")),
+    allow(unused_variables)
+)]
+pub fn daemon(conn: &mut Connection, db_path: &String, interval: &u64) -> Result<(), TTError> {
+    let options = db::get_options(&conn.transaction()?)?;
+    let mut daemon = Daemon {
+        features: enabled_features(&options)?,
+        last_suggestion: None,
+        untracked_since: None,
+        warned_long_running: None,
+        wrapped_up: None,
+        idle_since: None,
+        idle_stopped: None,
+        window_category: None,
+        auto_started_through: None,
+        lock_events: None,
+        locked_category: None,
+        meetings: None,
+        meeting_suggested: None,
+        break_suggested: None,
+        goals_reached: None,
+        #[cfg(feature = "async")]
+        webhooks: None,
+    };
+    if daemon.features.contains(&DaemonFeature::Lock) {
+        daemon.lock_events = Some(lock::watch()?);
+    }
+    #[cfg(feature = "async")]
+    if daemon.features.contains(&DaemonFeature::Webhooks) {
+        daemon.webhooks = Some(Background::new(db::open_pool(db_path, 1)?));
+    }
+    #[cfg(feature = "serve")]
+    if daemon.features.contains(&DaemonFeature::Serve) {
+        let db_path = db_path.clone();
+        let bind_addr = options
+            .get("daemon-bind-addr")
+            .cloned()
+            .unwrap_or("127.0.0.1:8080".to_string());
+        thread::spawn(move || {
+            if let Err(e) = serve::serve(&db_path, &bind_addr) {
+                eprintln!("serve failed: {}", e);
+            }
+        });
+    }
+    println!(
+        "Running {}",
+        daemon
+            .features
+            .iter()
+            .map(feature_name)
+            .collect::<Vec<_>>()
+            .join(", ")
+    );
+    #[cfg(feature = "hotkeys")]
+    if daemon.features.contains(&DaemonFeature::Hotkeys) {
+        //macOS and windows only deliver hotkeys to the main thread, so the checks get their own
+        let pool = db::open_pool(db_path, 1)?;
+        let interval = *interval;
+        thread::spawn(move || {
+            let result = pool
+                .get()
+                .map_err(TTError::from)
+                .and_then(|mut conn| daemon.check_every(&mut conn, &interval));
+            //recoverable failures are already warnings in check(), so this one would have ended the daemon anyway
+            if let Err(e) = result {
+                eprintln!("{}", l10n::error(&e));
+                std::process::exit(e.exit_code());
+            }
+        });
+        return hotkey::listen(conn);
+    }
+    daemon.check_every(conn, interval)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cli::CategoryOptionName;
+    use chrono::TimeZone;
+
+    #[test]
+    fn test_enabled_features() {
+        let options = |features: &str| {
+            db::Options::from([("daemon-features".to_string(), features.to_string())])
+        };
+        assert_eq!(
+            vec![DaemonFeature::Suggest, DaemonFeature::Reminders],
+            enabled_features(&options("suggest, Reminders,suggest")).unwrap()
+        );
+        assert!(enabled_features(&options("suggest,nope")).is_err());
+        //features ttjr was built without are refused up front
+        #[cfg(not(feature = "serve"))]
+        assert_eq!(
+            Err(crate::commands::not_compiled("serve")),
+            enabled_features(&options("suggest,serve"))
+        );
+        #[cfg(not(feature = "hotkeys"))]
+        assert_eq!(
+            Err(crate::commands::not_compiled("hotkeys")),
+            enabled_features(&options("hotkeys"))
+        );
+        assert!(enabled_features(&options("")).is_err());
+        assert!(enabled_features(&db::Options::new()).is_err());
+    }
+
+    #[test]
+    fn test_long_running_threshold() {
+        let options = db::Options::from([("long-running".to_string(), "3h".to_string())]);
+        let category_options = db::CategoryOptions::from([(
+            "deep work".to_string(),
+            db::Options::from([("long-running".to_string(), "5h".to_string())]),
+        )]);
+        assert_eq!(
+            Some(5 * 3600),
+            long_running_threshold("deep work", &options, &category_options)
+        );
+        assert_eq!(
+            Some(3 * 3600),
+            long_running_threshold("meetings", &options, &category_options)
+        );
+        assert_eq!(
+            None,
+            long_running_threshold("meetings", &db::Options::new(), &category_options)
+        );
+    }
+
+    #[test]
+    fn test_wrap_up_message() {
+        let totals = BTreeMap::from([
+            ("meetings".to_string(), 5400),
+            ("work".to_string(), 4 * 3600 + 720),
+        ]);
+        let end_of_day = db::parse_time(&"17:00".to_string()).unwrap();
+        assert_eq!(
+            "Today: meetings 01:30, work 04:12 (05:42 total)",
+            wrap_up_message(&totals, None, &end_of_day)
+        );
+        let open = TimeWindow {
+            id: Some(1),
+            category: "work".to_string(),
+            start_time: 0,
+            end_time: None,
+            note: None,
+        };
+        assert_eq!(
+            "Today: meetings 01:30, work 04:12 (05:42 total)\nStill timing work, stop or amend it before it's ended at 17:00",
+            wrap_up_message(&totals, Some(&open), &end_of_day)
+        );
+    }
+
+    #[test]
+    fn test_goals_reached() {
+        let mut conn = Connection::open_in_memory().unwrap();
+        db::initialize_db(&mut conn).unwrap();
+        //a wednesday afternoon
+        let now = Local.with_ymd_and_hms(2024, 6, 5, 14, 0, 0).unwrap();
+        let mut tx = conn.transaction().unwrap();
+        for (category, option, goal) in [
+            ("exercise", CategoryOptionName::WeeklyGoal, "1m"),
+            ("reading", CategoryOptionName::DailyGoal, "1m"),
+            ("writing", CategoryOptionName::DailyGoal, "1h"),
+        ] {
+            db::add_category(&tx, &category.to_string()).unwrap();
+            db::set_category_option(&tx, &category.to_string(), &option, &goal.to_string())
+                .unwrap();
+        }
+        db::set_category_option(
+            &tx,
+            &"reading".to_string(),
+            &CategoryOptionName::GoalNotification,
+            &"false".to_string(),
+        )
+        .unwrap();
+        for (i, category) in ["exercise", "reading", "writing"].iter().enumerate() {
+            let start = now.timestamp() - 600 * (i as i64 + 1);
+            db::upsert_time(
+                &mut tx,
+                TimeWindow {
+                    id: None,
+                    category: category.to_string(),
+                    start_time: start,
+                    end_time: Some(start + 300),
+                    note: None,
+                },
+                &true,
+            )
+            .unwrap();
+        }
+        let reached = goals_reached(&mut tx, &now).unwrap();
+        assert_eq!(1, reached.len());
+        assert_eq!("exercise: 00:05/00:01 this week", reached[0].message());
+    }
+
+    #[test]
+    fn test_working_since() {
+        let time = |category: &str, start_time, end_time| TimeWindow {
+            id: None,
+            category: category.to_string(),
+            start_time,
+            end_time,
+            note: None,
+        };
+        let times = vec![
+            time("work", 0, Some(3600)),
+            time("break", 3600, Some(4000)),
+            time("work", 4000, Some(5000)),
+            time("email", 5100, Some(6000)),
+            time("work", 6000, None),
+        ];
+        assert_eq!(Some(4000), working_since(&times, "break"));
+        assert_eq!(Some(0), working_since(&times, "rest"));
+        //a long enough gap is a break too
+        let times = vec![time("work", 0, Some(3600)), time("work", 4000, None)];
+        assert_eq!(Some(4000), working_since(&times, "break"));
+        //nothing's being timed, or it's a break
+        assert_eq!(None, working_since(&times[..1], "break"));
+        assert_eq!(None, working_since(&[time("break", 0, None)], "break"));
+    }
+
+    #[test]
+    fn test_snooze() {
+        let mut conn = Connection::open_in_memory().unwrap();
+        db::initialize_db(&mut conn).unwrap();
+        let is_snoozed = |conn: &mut Connection| {
+            snoozed(&db::get_options(&conn.transaction().unwrap()).unwrap())
+        };
+        assert!(!is_snoozed(&mut conn));
+        snooze(&mut conn, "30m", &false).unwrap();
+        assert!(is_snoozed(&mut conn));
+        snooze(&mut conn, "0", &false).unwrap();
+        assert!(!is_snoozed(&mut conn));
+        assert!(snooze(&mut conn, "a while", &false).is_err());
+    }
+
+    #[test]
+    fn test_in_business_hours() {
+        //2024-01-05 is a friday
+        let at = |day, hour| Local.with_ymd_and_hms(2024, 1, day, hour, 30, 0).unwrap();
+        let defaults = db::Options::new();
+        assert!(in_business_hours(&at(5, 9), &defaults).unwrap());
+        assert!(!in_business_hours(&at(5, 8), &defaults).unwrap());
+        assert!(!in_business_hours(&at(5, 17), &defaults).unwrap());
+        assert!(!in_business_hours(&at(6, 12), &defaults).unwrap());
+        let options = db::Options::from([
+            ("start-of-day".to_string(), "08:00".to_string()),
+            ("end-of-day".to_string(), "18:00".to_string()),
+        ]);
+        assert!(in_business_hours(&at(5, 8), &options).unwrap());
+        assert!(in_business_hours(&at(5, 17), &options).unwrap());
+    }
+}
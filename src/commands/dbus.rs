@@ -0,0 +1,162 @@
+/*
+This file is part of Timetrack Jr.
+Timetrack Jr. is free software: you can redistribute it and/or modify it under the terms of the GNU General Public License as published by the Free Software Foundation, either version 3 of the License, or (at your option) any later version.
+Timetrack Jr. is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU General Public License for more details.
+You should have received a copy of the GNU General Public License along with Timetrack Jr. If not, see <https://www.gnu.org/licenses/>.
+*/
+use crate::{
+    commands::log,
+    db::{self, Pool, TimeWindow},
+    TTError,
+};
+use std::{thread, time::Duration};
+use zbus::{blocking, fdo, interface, object_server::SignalEmitter};
+
+const NAME: &str = "org.ttjr.Tracker";
+const PATH: &str = "/org/ttjr/Tracker";
+
+fn fdo_error(error: TTError) -> fdo::Error {
+    fdo::Error::Failed(error.to_string())
+}
+
+fn dbus_error(error: zbus::Error) -> TTError {
+    TTError::System {
+        message: format!("D-Bus error: {}", error),
+    }
+}
+
+///The `(timing, category, start_time)` returned by Current, since D-Bus has no null
+fn current_tuple(current: Option<&TimeWindow>) -> (bool, String, i64) {
+    match current {
+        Some(time) => (true, time.category.clone(), time.start_time),
+        None => (false, String::new(), 0),
+    }
+}
+
+///org.ttjr.Tracker, which keeps the time being recorded so property changes can be signalled
+struct Tracker {
+    pool: Pool,
+    current: Option<TimeWindow>,
+}
+
+impl Tracker {
+    ///Re-read the time being recorded, returning whether it changed
+    fn reload(&mut self) -> Result<bool, TTError> {
+        let current = db::get_last_open_time(&self.pool.get()?.transaction()?)?;
+        let changed = current != self.current;
+        self.current = current;
+        Ok(changed)
+    }
+
+    async fn properties_changed(&self, emitter: &SignalEmitter<'_>) -> zbus::Result<()> {
+        self.timing_changed(emitter).await?;
+        self.category_changed(emitter).await?;
+        self.start_time_changed(emitter).await
+    }
+
+    async fn refresh(&mut self, emitter: &SignalEmitter<'_>) -> fdo::Result<()> {
+        if self.reload().map_err(fdo_error)? {
+            self.properties_changed(emitter).await?;
+        }
+        Ok(())
+    }
+}
+
+#[interface(name = "org.ttjr.Tracker")]
+impl Tracker {
+    ///Start timing a category, stopping whatever was being timed
+    async fn start_timing(
+        &mut self,
+        category: String,
+        #[zbus(signal_emitter)] emitter: SignalEmitter<'_>,
+    ) -> fdo::Result<()> {
+        log::start_timing(
+            &mut *self.pool.get().map_err(|e| fdo_error(e.into()))?,
+            &category,
+            &None,
+            &false,
+        )
+        .map_err(fdo_error)?;
+        self.refresh(&emitter).await
+    }
+
+    async fn stop_timing(
+        &mut self,
+        #[zbus(signal_emitter)] emitter: SignalEmitter<'_>,
+    ) -> fdo::Result<()> {
+        log::stop_timing(
+            &mut *self.pool.get().map_err(|e| fdo_error(e.into()))?,
+            &false,
+        )
+        .map_err(fdo_error)?;
+        self.refresh(&emitter).await
+    }
+
+    ///Whether anything's being timed, and if so its category and start time (unix seconds)
+    fn current(&self) -> (bool, String, i64) {
+        current_tuple(self.current.as_ref())
+    }
+
+    #[zbus(property)]
+    fn timing(&self) -> bool {
+        self.current.is_some()
+    }
+
+    ///The category being timed, empty when not timing
+    #[zbus(property)]
+    fn category(&self) -> String {
+        current_tuple(self.current.as_ref()).1
+    }
+
+    ///When timing started (unix seconds), 0 when not timing
+    #[zbus(property)]
+    fn start_time(&self) -> i64 {
+        current_tuple(self.current.as_ref()).2
+    }
+}
+
+///Serve org.ttjr.Tracker on the session bus.  Every `interval` seconds the DB is checked for timing started or
+/// stopped some other way, so PropertiesChanged is signalled no matter how timing changes.
+pub fn serve(db_path: &String, interval: &u64) -> Result<(), TTError> {
+    let mut tracker = Tracker {
+        pool: db::open_pool(db_path, 2)?,
+        current: None,
+    };
+    tracker.reload()?;
+    let connection = blocking::connection::Builder::session()
+        .and_then(|b| b.name(NAME))
+        .and_then(|b| b.serve_at(PATH, tracker))
+        .and_then(|b| b.build())
+        .map_err(dbus_error)?;
+    println!("Serving {} at {} on the session bus", NAME, PATH);
+    let tracker = connection
+        .object_server()
+        .interface::<_, Tracker>(PATH)
+        .map_err(dbus_error)?;
+    loop {
+        thread::sleep(Duration::from_secs(*interval));
+        let mut iface = tracker.get_mut();
+        if iface.reload()? {
+            zbus::block_on(iface.properties_changed(tracker.signal_emitter()))
+                .map_err(dbus_error)?;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_current_tuple() {
+        let time = TimeWindow {
+            id: Some(1),
+            category: "work".to_string(),
+            start_time: 100,
+            end_time: None,
+            note: None,
+        };
+        assert_eq!((true, "work".to_string(), 100), current_tuple(Some(&time)));
+        assert_eq!((false, String::new(), 0), current_tuple(None));
+    }
+}
@@ -0,0 +1,175 @@
+/*
+This file is part of Timetrack Jr.
+Timetrack Jr. is free software: you can redistribute it and/or modify it under the terms of the GNU General Public License as published by the Free Software Foundation, either version 3 of the License, or (at your option) any later version.
+Timetrack Jr. is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU General Public License for more details.
+You should have received a copy of the GNU General Public License along with Timetrack Jr. If not, see <https://www.gnu.org/licenses/>.
+*/
+use crate::{
+    db::{self, TimeWindow},
+    TTError,
+};
+use rusqlite::Connection;
+use serde_json::{json, Value};
+use std::{
+    io::{Read, Write},
+    thread,
+    time::Duration,
+};
+
+const HANDSHAKE: u32 = 0;
+const FRAME: u32 = 1;
+
+///Discord's local IPC socket (a unix socket, or a named pipe on windows)
+trait Ipc: Read + Write {}
+impl<T: Read + Write> Ipc for T {}
+
+///A message in Discord's IPC protocol: opcode and length (little-endian u32s) then JSON
+fn frame(op: u32, payload: &Value) -> Vec<u8> {
+    let json = payload.to_string();
+    let mut frame = Vec::with_capacity(8 + json.len());
+    frame.extend_from_slice(&op.to_le_bytes());
+    frame.extend_from_slice(&(json.len() as u32).to_le_bytes());
+    frame.extend_from_slice(json.as_bytes());
+    frame
+}
+
+fn read_frame(ipc: &mut dyn Ipc) -> Result<(u32, Value), TTError> {
+    let mut header = [0u8; 8];
+    ipc.read_exact(&mut header)?;
+    let op = u32::from_le_bytes(header[..4].try_into().unwrap());
+    let mut payload = vec![0u8; u32::from_le_bytes(header[4..].try_into().unwrap()) as usize];
+    ipc.read_exact(&mut payload)?;
+    Ok((op, serde_json::from_slice(&payload)?))
+}
+
+///Send a message and wait for Discord's reply
+fn send(ipc: &mut dyn Ipc, op: u32, payload: &Value) -> Result<Value, TTError> {
+    ipc.write_all(&frame(op, payload))?;
+    let (_, reply) = read_frame(ipc)?;
+    if reply["evt"] == "ERROR" {
+        return Err(TTError::Network {
+            message: format!(
+                "Discord returned an error: {}",
+                reply["data"]["message"].as_str().unwrap_or("unknown error")
+            ),
+        });
+    }
+    Ok(reply)
+}
+
+#[cfg(unix)]
+fn open_socket(n: u8) -> Option<Box<dyn Ipc>> {
+    let dir = ["XDG_RUNTIME_DIR", "TMPDIR", "TMP", "TEMP"]
+        .iter()
+        .find_map(|v| std::env::var(v).ok())
+        .unwrap_or("/tmp".to_string());
+    let stream = std::os::unix::net::UnixStream::connect(format!("{}/discord-ipc-{}", dir, n));
+    stream.ok().map(|s| Box::new(s) as Box<dyn Ipc>)
+}
+
+#[cfg(windows)]
+fn open_socket(n: u8) -> Option<Box<dyn Ipc>> {
+    let pipe = std::fs::OpenOptions::new()
+        .read(true)
+        .write(true)
+        .open(format!(r"\\?\pipe\discord-ipc-{}", n));
+    pipe.ok().map(|p| Box::new(p) as Box<dyn Ipc>)
+}
+
+///Connect to the running Discord client, as the application `client_id`
+fn connect(client_id: &str) -> Result<Box<dyn Ipc>, TTError> {
+    let mut ipc = (0..10).find_map(open_socket).ok_or(TTError::System {
+        message: "Couldn't find a running Discord client".to_string(),
+    })?;
+    send(
+        ipc.as_mut(),
+        HANDSHAKE,
+        &json!({ "v": 1, "client_id": client_id }),
+    )?;
+    Ok(ipc)
+}
+
+///The activity for the time being recorded, Discord shows the time elapsed since it started.  Null clears the presence.
+fn activity(time: Option<&TimeWindow>) -> Value {
+    match time {
+        Some(time) => json!({
+            "details": time.category,
+            "state": "Tracking time",
+            "timestamps": { "start": time.start_time },
+        }),
+        None => Value::Null,
+    }
+}
+
+fn set_activity(ipc: &mut dyn Ipc, time: Option<&TimeWindow>, nonce: u64) -> Result<(), TTError> {
+    send(
+        ipc,
+        FRAME,
+        &json!({
+            "cmd": "SET_ACTIVITY",
+            "args": { "pid": std::process::id(), "activity": activity(time) },
+            "nonce": nonce.to_string(),
+        }),
+    )?;
+    Ok(())
+}
+
+///Keep your Discord Rich Presence showing what's being timed, checking every `interval` seconds.
+/// Reconnects whenever Discord is restarted.
+pub fn discord_presence(conn: &mut Connection, interval: &u64) -> Result<(), TTError> {
+    let client_id = db::get_options(&conn.transaction()?)?
+        .get("discord-client-id")
+        .cloned()
+        .ok_or(TTError::NotConfigured {
+            message: "Set the id of your Discord application with `ttjr set-option discord-client-id <id>`".to_string(),
+        })?;
+    let mut ipc: Option<Box<dyn Ipc>> = None;
+    let mut shown: Option<Option<TimeWindow>> = None;
+    for nonce in 0.. {
+        let current = db::get_last_open_time(&conn.transaction()?)?;
+        if ipc.is_none() {
+            ipc = connect(&client_id).ok();
+            shown = None;
+        }
+        if let Some(connection) = ipc.as_mut() {
+            if shown.as_ref() != Some(&current) {
+                match set_activity(connection.as_mut(), current.as_ref(), nonce) {
+                    Ok(_) => shown = Some(current),
+                    //probably closed, try again next time
+                    Err(_) => ipc = None,
+                }
+            }
+        }
+        thread::sleep(Duration::from_secs(*interval));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_frame() {
+        let payload = json!({"v": 1});
+        let frame = frame(HANDSHAKE, &payload);
+        assert_eq!([0, 0, 0, 0, 7, 0, 0, 0], frame[..8]);
+        assert_eq!(b"{\"v\":1}", &frame[8..]);
+        let (op, read) = read_frame(&mut std::io::Cursor::new(frame)).unwrap();
+        assert_eq!((HANDSHAKE, payload), (op, read));
+    }
+
+    #[test]
+    fn test_activity() {
+        let time = TimeWindow {
+            id: Some(1),
+            category: "studying".to_string(),
+            start_time: 1000,
+            end_time: None,
+            note: None,
+        };
+        assert_eq!("studying", activity(Some(&time))["details"]);
+        assert_eq!(1000, activity(Some(&time))["timestamps"]["start"]);
+        assert_eq!(Value::Null, activity(None));
+    }
+}
@@ -7,99 +7,203 @@ You should have received a copy of the GNU General Public License along with Tim
 */
 use crate::{
     cli,
-    db::{self, TimeWindow},
+    commands::{
+        notify, org,
+        report::{self, hours_minutes},
+        table::{number, text, ReportTable},
+    },
+    db::{self, TimeIter, TimeWindow},
+    l10n,
+    zone::Zone,
     TTError,
 };
-use chrono::{DateTime, Datelike, Local, NaiveDateTime, Utc};
+use chrono::{DateTime, Datelike, Utc};
+use clap::ValueEnum;
+#[cfg(feature = "ical")]
 use icalendar::{Calendar, Component, Event};
-use notify_rust::{Notification, Timeout};
-use rusqlite::Connection;
-use serde::{Deserialize, Serialize};
+use regex::Regex;
+use rusqlite::{Connection, Transaction};
+use schemars::{schema_for, JsonSchema};
+use serde::{
+    ser::{SerializeSeq, Serializer},
+    Deserialize, Serialize,
+};
 use std::{
-    collections::BTreeMap,
-    io,
+    collections::{BTreeMap, BTreeSet},
+    io::{self, Write},
+    thread,
     time::{Duration, SystemTime},
 };
 
-#[derive(Serialize, Deserialize, PartialEq, Eq, Debug)]
+///Version of the JSON/JSONL export format, bumped whenever a change could break something reading exports
+pub(crate) const EXPORT_SCHEMA_VERSION: u32 = 1;
+
+///A time in a JSON or JSONL export
+#[derive(Serialize, Deserialize, JsonSchema, PartialEq, Eq, Debug)]
+#[schemars(title = "ttjr time")]
 struct TimeWindowExport {
+    ///Version of the export format (see `ttjr export --schema`)
+    #[schemars(extend("const" = EXPORT_SCHEMA_VERSION))]
+    pub schema_version: u32,
     pub id: Option<i64>,
     pub category: String,
+    ///Unix timestamp
     pub start_time: i64,
+    ///Unix timestamp, null while the time's still being recorded
     pub end_time: Option<i64>,
+    ///RFC 3339, in the export's timezone
     pub start_timestamp: String,
+    ///RFC 3339, in the export's timezone
     pub end_timestamp: Option<String>,
+    ///Set with `start-timing --note` or `amend-time --note`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub note: Option<String>,
+    ///Notes added with `ttjr annotate`
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub annotations: Vec<String>,
+    ///Added with `ttjr tag`, alphabetically
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tags: Vec<String>,
+}
+
+///What's kept about times outside of the times table, for json exports
+#[derive(Default)]
+struct Details {
+    annotations: BTreeMap<i64, Vec<String>>,
+    tags: BTreeMap<i64, Vec<String>>,
 }
 
-impl From<TimeWindow> for TimeWindowExport {
-    fn from(w: TimeWindow) -> Self {
+impl Details {
+    fn load(tx: &Transaction) -> Result<Details, TTError> {
+        Ok(Details {
+            annotations: db::get_annotations(tx)?,
+            tags: db::get_tags(tx)?,
+        })
+    }
+}
+
+impl TimeWindowExport {
+    fn new(w: TimeWindow, zone: &Zone, details: &Details) -> Self {
+        let detail = |details: &BTreeMap<i64, Vec<String>>| {
+            w.id.and_then(|id| details.get(&id).cloned())
+                .unwrap_or_default()
+        };
         TimeWindowExport {
+            schema_version: EXPORT_SCHEMA_VERSION,
+            annotations: detail(&details.annotations),
+            tags: detail(&details.tags),
             id: w.id,
             category: w.category,
-            start_time: w.start_time.clone(),
-            end_time: w.end_time.clone(),
-            start_timestamp: DateTime::<chrono::Local>::from(unix_to_utc(&w.start_time))
-                .to_rfc3339(),
-            end_timestamp: match w.end_time {
-                Some(t) => Some(DateTime::<chrono::Local>::from(unix_to_utc(&t)).to_rfc3339()),
-                None => None,
-            },
+            start_time: w.start_time,
+            end_time: w.end_time,
+            start_timestamp: zone.rfc3339(&w.start_time),
+            end_timestamp: w.end_time.map(|t| zone.rfc3339(&t)),
+            note: w.note,
         }
     }
 }
 
+///Print the JSON Schema of a json (an array of times) or jsonl (a line's time) export
+pub(crate) fn schema(format: &cli::ExportFormat) -> Result<(), TTError> {
+    let schema = match format {
+        cli::ExportFormat::Json => schema_for!(Vec<TimeWindowExport>),
+        cli::ExportFormat::Jsonl => schema_for!(TimeWindowExport),
+        _ => {
+            return Err(TTError::InvalidArgument {
+                message: "--schema only applies to --format json and jsonl".to_string(),
+            })
+        }
+    };
+    println!("{}", serde_json::to_string_pretty(&schema)?);
+    Ok(())
+}
+
+///Write times as a (pretty-printed) JSON array, a time at a time
 fn export_json(
-    outfile: &mut Box<dyn std::io::Write>,
-    times: Vec<TimeWindow>,
+    outfile: &mut dyn std::io::Write,
+    times: &mut TimeIter,
+    details: &Details,
+    zone: &Zone,
+) -> Result<(), TTError> {
+    let mut serializer = serde_json::Serializer::pretty(outfile);
+    let mut array = serializer.serialize_seq(None)?;
+    for time in times {
+        array.serialize_element(&TimeWindowExport::new(time?, zone, details))?;
+    }
+    array.end()?;
+    Ok(())
+}
+
+///Write times as JSON lines, one object (like those in a json export) per time
+fn export_jsonl(
+    outfile: &mut dyn std::io::Write,
+    times: &mut TimeIter,
+    details: &Details,
+    zone: &Zone,
 ) -> Result<(), TTError> {
-    let times_export: Vec<TimeWindowExport> = times.into_iter().map(|t| t.into()).collect();
-    outfile.write_all(serde_json::to_string_pretty(&times_export)?.as_bytes())?;
+    for time in times {
+        serde_json::to_writer(&mut *outfile, &TimeWindowExport::new(time?, zone, details))?;
+        outfile.write_all(b"\n")?;
+    }
     Ok(())
 }
 
-fn unix_to_utc(tstamp: &i64) -> DateTime<Utc> {
-    DateTime::<Utc>::from_utc(NaiveDateTime::from_timestamp(*tstamp, 0), Utc)
+pub(crate) fn unix_to_utc(tstamp: &i64) -> DateTime<Utc> {
+    DateTime::<Utc>::from_timestamp(*tstamp, 0).unwrap()
 }
 
-fn export_ical(
-    outfile: &mut Box<dyn std::io::Write>,
-    times: Vec<TimeWindow>,
-) -> Result<(), TTError> {
-    let mut calendar = Calendar::new();
+///Write finished times as events in a calendar, a time at a time
+#[cfg(feature = "ical")]
+fn export_ical(outfile: &mut dyn std::io::Write, times: &mut TimeIter) -> Result<(), TTError> {
+    //events go between the calendar's header and its END line
+    let calendar = Calendar::new().to_string();
+    let (header, footer) = calendar.split_at(calendar.rfind("END:VCALENDAR").unwrap_or(0));
+    outfile.write_all(header.as_bytes())?;
     for time in times {
-        if time.end_time.is_some() {
-            calendar.push(
-                Event::new()
-                    .summary(&time.category)
-                    .starts(unix_to_utc(&time.start_time))
-                    .ends(unix_to_utc(&time.end_time.unwrap()))
-                    .done(),
-            );
+        let time = time?;
+        if let Some(end_time) = time.end_time {
+            let mut event = Event::new();
+            event
+                .summary(&time.category)
+                .starts(unix_to_utc(&time.start_time))
+                .ends(unix_to_utc(&end_time));
+            if let Some(note) = &time.note {
+                event.description(note);
+            }
+            outfile.write_all(event.done().to_string().as_bytes())?;
         }
     }
-    outfile.write_all(calendar.to_string().as_bytes())?;
+    outfile.write_all(footer.as_bytes())?;
     Ok(())
 }
+
+#[cfg(not(feature = "ical"))]
+fn export_ical(_: &mut dyn std::io::Write, _: &mut TimeIter) -> Result<(), TTError> {
+    Err(crate::commands::not_compiled("ical"))
+}
+
 fn export_csv(
-    outfile: &mut Box<dyn std::io::Write>,
-    times: Vec<TimeWindow>,
+    outfile: &mut dyn std::io::Write,
+    times: &mut TimeIter,
+    zone: &Zone,
 ) -> Result<(), TTError> {
     outfile.write_all(
-        &"id,category,start,end,start_tstamp,end_tstamp,duration_hours,duration_seconds\n"
+        "id,category,start,end,start_tstamp,end_tstamp,duration_hours,duration_seconds,note\n"
             .as_bytes(),
     )?;
     for time in times {
+        let time = time?;
         outfile.write_all(
-            &format!(
-                "{},{},{},{},{},{},{},{}\n",
+            format!(
+                "{},{},{},{},{},{},{},{},{}\n",
                 time.id.unwrap_or(-1),
                 time.category
                     .replace(",", ".")
                     .replace("\n", "")
                     .replace("\r", ""),
-                DateTime::<chrono::Local>::from(unix_to_utc(&time.start_time)).to_rfc3339(),
+                zone.rfc3339(&time.start_time),
                 match time.end_time {
-                    Some(end) => DateTime::<chrono::Local>::from(unix_to_utc(&end)).to_rfc3339(),
+                    Some(end) => zone.rfc3339(&end),
                     None => "".to_string(),
                 },
                 time.start_time,
@@ -115,6 +219,7 @@ fn export_csv(
                     Some(end) => ((end - time.start_time) as f64).to_string(),
                     None => "".to_string(),
                 },
+                time.note.as_deref().map_or("".to_string(), csv_quote),
             )
             .as_bytes(),
         )?;
@@ -122,184 +227,1130 @@ fn export_csv(
     Ok(())
 }
 
-#[derive(Debug)]
-struct Summary {
-    total: u64,
-    count: u64,
+///Quote a free-text CSV field, so notes can have commas and quotes in them
+fn csv_quote(value: &str) -> String {
+    //a quoted field could have newlines, but not everything reading exports copes with them
+    format!(
+        "\"{}\"",
+        value.replace(['\r', '\n'], " ").replace('"', "\"\"")
+    )
 }
 
-fn export_summary(
-    outfile: &mut Box<dyn std::io::Write>,
-    times: Vec<TimeWindow>,
-    start: Option<i64>,
-    end: Option<i64>,
-) -> Result<(), TTError> {
-    match (start, end) {
-        (None, None) => outfile.write_all("Tabulating results for all time\n".as_bytes())?,
-        (Some(s), None) => outfile.write_all(
-            format!(
-                "Tabulating results starting on/after {}\n",
-                DateTime::<chrono::Local>::from(unix_to_utc(&s)).to_rfc2822()
-            )
-            .as_bytes(),
-        )?,
-        (None, Some(e)) => outfile.write_all(
-            format!(
-                "Tabulating results through {}\n",
-                DateTime::<chrono::Local>::from(unix_to_utc(&e)).to_rfc2822()
-            )
-            .as_bytes(),
-        )?,
-        (Some(s), Some(e)) => outfile.write_all(
+///Escape commas, spaces, and equals signs in a line protocol tag value
+fn influx_tag(value: &str) -> String {
+    //newlines can't be escaped, so they become spaces
+    value
+        .replace('\n', " ")
+        .replace('\\', "\\\\")
+        .replace(',', "\\,")
+        .replace('=', "\\=")
+        .replace(' ', "\\ ")
+}
+
+///Write times as InfluxDB line protocol points, timestamped (in nanoseconds) by when they started.
+/// Re-exporting a time overwrites its earlier point, so it's safe to push the same times repeatedly.
+fn export_influx(outfile: &mut dyn std::io::Write, times: &mut TimeIter) -> Result<(), TTError> {
+    for time in times {
+        let time = time?;
+        let mut fields = vec![
+            format!("id={}i", time.id.unwrap_or(-1)),
+            format!("running={}", time.end_time.is_none()),
+        ];
+        if let Some(end) = time.end_time {
+            fields.push(format!("end_time={}i", end));
+            fields.push(format!("duration_seconds={}i", end - time.start_time));
+        }
+        outfile.write_all(
             format!(
-                "Tabulating results starting on/after {} through {}\n",
-                DateTime::<chrono::Local>::from(unix_to_utc(&s)).to_rfc2822(),
-                DateTime::<chrono::Local>::from(unix_to_utc(&e)).to_rfc2822()
+                "ttjr_time,category={} {} {}\n",
+                influx_tag(&time.category),
+                fields.join(","),
+                time.start_time as i128 * 1_000_000_000
             )
             .as_bytes(),
-        )?,
+        )?;
     }
-    let mut category_totals = BTreeMap::<String, Summary>::new();
-    for time in times {
-        let summary = match category_totals.get_mut(&time.category) {
-            Some(s) => s,
-            None => {
-                category_totals.insert(time.category.clone(), Summary { total: 0, count: 0 });
-                category_totals.get_mut(&time.category).unwrap()
-            }
-        };
-        summary.count += 1;
+    Ok(())
+}
+
+#[derive(Debug, Default)]
+struct Summary {
+    total: u64,
+    count: u64,
+    ///total after rounding each entry to the billing increment
+    billed: u64,
+    ///ids of entries shorter than the billing increment
+    short_entries: Vec<i64>,
+}
+
+impl Summary {
+    ///Add a closed time's duration (and billed duration if billing) to the totals
+    fn add(&mut self, time: &TimeWindow, billing: &Option<Billing>) {
         if let Some(end) = time.end_time {
-            summary.total += (unix_to_utc(&end) - unix_to_utc(&time.start_time))
+            let duration = (unix_to_utc(&end) - unix_to_utc(&time.start_time))
                 .num_seconds()
-                .abs() as u64;
+                .unsigned_abs();
+            self.total += duration;
+            if let Some(billing) = billing {
+                self.billed += billing.round(duration);
+                if duration < billing.increment {
+                    self.short_entries.push(time.id.unwrap_or(-1));
+                }
+            }
+        }
+    }
+}
+
+///Label of the period (in `zone`) which `tstamp` falls in
+fn period_label(tstamp: &i64, period: &cli::SummaryPeriod, zone: &Zone) -> String {
+    let date = zone.date(tstamp);
+    match period {
+        cli::SummaryPeriod::Day => date.format("%Y-%m-%d").to_string(),
+        cli::SummaryPeriod::Week => {
+            let week = date.iso_week();
+            format!("{}-W{:02}", week.year(), week.week())
         }
+        cli::SummaryPeriod::Month => date.format("%Y-%m").to_string(),
+    }
+}
+
+///Totals for each period and category, times crossing midnight are split so each period is credited with
+/// the time actually spent in it
+fn period_totals(
+    times: &[TimeWindow],
+    period: &cli::SummaryPeriod,
+    billing: &Option<Billing>,
+    zone: &Zone,
+) -> BTreeMap<String, BTreeMap<String, Summary>> {
+    let mut totals = BTreeMap::<String, BTreeMap<String, Summary>>::new();
+    for time in report::split_by_day(times, zone) {
+        let summary = totals
+            .entry(period_label(&time.start_time, period, zone))
+            .or_default()
+            .entry(time.category.clone())
+            .or_default();
+        summary.count += 1;
+        summary.add(&time, billing);
+    }
+    totals
+}
+
+#[derive(Debug)]
+struct Billing {
+    increment: u64,
+    rounding: cli::BillingRounding,
+}
+
+impl Billing {
+    ///Read billing settings from options, these are required for --billing
+    fn from_options(options: &db::Options) -> Result<Billing, TTError> {
+        let increment = options
+            .get("billing-increment")
+            .and_then(|i| cli::duration_string_to_seconds(i))
+            .filter(|i| *i > 0)
+            .ok_or(TTError::NotConfigured {
+                message: "--billing requires a billing increment, use `ttjr set-option billing-increment <duration>` to set one".to_string(),
+            })?;
+        let rounding = options
+            .get("billing-rounding")
+            .and_then(|r| cli::BillingRounding::from_str(r, true).ok())
+            .unwrap_or(cli::BillingRounding::Up);
+        Ok(Billing {
+            increment: increment.unsigned_abs(),
+            rounding,
+        })
+    }
+
+    fn round(&self, seconds: u64) -> u64 {
+        let increments = match self.rounding {
+            cli::BillingRounding::Up => seconds.div_ceil(self.increment),
+            cli::BillingRounding::Down => seconds / self.increment,
+            cli::BillingRounding::Nearest => (seconds + self.increment / 2) / self.increment,
+        };
+        increments * self.increment
+    }
+}
+
+///Write a line describing the time range that a summary/report covers
+pub(crate) fn write_range_header(
+    outfile: &mut dyn std::io::Write,
+    start: Option<i64>,
+    end: Option<i64>,
+    zone: &Zone,
+) -> Result<(), TTError> {
+    let header = match (start, end) {
+        (None, None) => l10n::text("summary-all-time", &[]),
+        (Some(s), None) => l10n::text("summary-since", &[("start", zone.rfc2822(&s).into())]),
+        (None, Some(e)) => l10n::text("summary-through", &[("end", zone.rfc2822(&e).into())]),
+        (Some(s), Some(e)) => l10n::text(
+            "summary-between",
+            &[
+                ("start", zone.rfc2822(&s).into()),
+                ("end", zone.rfc2822(&e).into()),
+            ],
+        ),
+    };
+    outfile.write_all(format!("{}\n", header).as_bytes())?;
+    Ok(())
+}
+
+///Everything about a summary besides the times being summarized
+struct SummarySettings {
+    start: Option<i64>,
+    end: Option<i64>,
+    json: bool,
+    billing: Option<Billing>,
+    daily_cap: Option<u64>,
+    zone: Zone,
+    ///daily totals for each category over the last few days of the range
+    recent: BTreeMap<String, Vec<u64>>,
+    group_by: Option<cli::SummaryPeriod>,
+    table_options: cli::TableOptions,
+}
+
+fn export_summary(
+    outfile: &mut dyn std::io::Write,
+    times: Vec<TimeWindow>,
+    settings: SummarySettings,
+) -> Result<(), TTError> {
+    let SummarySettings {
+        start,
+        end,
+        json,
+        billing,
+        daily_cap,
+        zone,
+        recent,
+        group_by,
+        table_options,
+    } = settings;
+    let mut category_totals = BTreeMap::<String, Summary>::new();
+    for time in &times {
+        category_totals
+            .entry(time.category.clone())
+            .or_default()
+            .count += 1;
+    }
+    //capping splits times at midnight, so only use the capped times for durations
+    let (counted, excess) = match daily_cap {
+        Some(cap) => report::apply_daily_cap(&times, cap, &zone),
+        None => (times, report::Excess::new()),
+    };
+    let periods = group_by.map(|period| period_totals(&counted, &period, &billing, &zone));
+    for time in counted {
+        category_totals
+            .entry(time.category.clone())
+            .or_default()
+            .add(&time, &billing);
     }
     if let Some((total_duration, total_count)) = category_totals
         .values()
-        .map(|foo| (foo.total, foo.count))
+        .map(|summary| (summary.total, summary.count))
         .reduce(|accum, item| (accum.0 + item.0, accum.1 + item.1))
     {
-        outfile.write_all(
-            format!(
-                "Logged {} activites for a total of {:02}:{:02}\n",
-                total_count,
-                total_duration / 60 / 60,
-                total_duration / 60 % 60
-            )
-            .as_bytes(),
-        )?;
+        let total_billed: u64 = category_totals.values().map(|s| s.billed).sum();
+        if json {
+            let mut document = serde_json::json!({
+                "start": start,
+                "end": end,
+                "count": total_count,
+                "total_seconds": total_duration,
+                "categories": category_totals
+                    .iter()
+                    .map(|(category, summary)| {
+                        let mut category_document = serde_json::json!({
+                            "count": summary.count,
+                            "total_seconds": summary.total,
+                            "percent": summary.total as f64 / total_duration as f64 * 100_f64,
+                        });
+                        if billing.is_some() {
+                            category_document["billed_seconds"] = serde_json::json!(summary.billed);
+                            category_document["below_increment"] = serde_json::json!(summary.short_entries);
+                        }
+                        if let Some(daily) = recent.get(category) {
+                            category_document["recent_daily_seconds"] = serde_json::json!(daily);
+                        }
+                        (category.clone(), category_document)
+                    })
+                    .collect::<serde_json::Map<_, _>>(),
+            });
+            if billing.is_some() {
+                document["billed_seconds"] = serde_json::json!(total_billed);
+            }
+            if daily_cap.is_some() {
+                document["excess_seconds"] = serde_json::json!(excess);
+            }
+            if let Some(periods) = &periods {
+                document["periods"] = periods
+                    .iter()
+                    .map(|(period, categories)| {
+                        let categories = categories
+                            .iter()
+                            .map(|(category, summary)| {
+                                let mut category_document = serde_json::json!({
+                                    "count": summary.count,
+                                    "total_seconds": summary.total,
+                                });
+                                if billing.is_some() {
+                                    category_document["billed_seconds"] =
+                                        serde_json::json!(summary.billed);
+                                }
+                                (category.clone(), category_document)
+                            })
+                            .collect::<serde_json::Map<_, _>>();
+                        (period.clone(), serde_json::Value::Object(categories))
+                    })
+                    .collect::<serde_json::Map<_, _>>()
+                    .into();
+            }
+            outfile.write_all(serde_json::to_string_pretty(&document)?.as_bytes())?;
+            return Ok(());
+        }
+        write_range_header(outfile, start, end, &zone)?;
+        let logged = l10n::text(
+            "summary-logged",
+            &[
+                ("count", total_count.into()),
+                (
+                    "total",
+                    format!(
+                        "{:02}:{:02}",
+                        total_duration / 60 / 60,
+                        total_duration / 60 % 60
+                    )
+                    .into(),
+                ),
+            ],
+        );
+        outfile.write_all(format!("{}\n", logged).as_bytes())?;
+
+        if let Some(billing) = &billing {
+            let billed = l10n::text(
+                "summary-billed",
+                &[
+                    (
+                        "billed",
+                        format!(
+                            "{:02}:{:02}",
+                            total_billed / 60 / 60,
+                            total_billed / 60 % 60
+                        )
+                        .into(),
+                    ),
+                    (
+                        "rounding",
+                        billing
+                            .rounding
+                            .to_possible_value()
+                            .unwrap()
+                            .get_name()
+                            .into(),
+                    ),
+                    ("increment", (billing.increment / 60).into()),
+                ],
+            );
+            outfile.write_all(format!("{}\n", billed).as_bytes())?;
+        }
 
-        for (category, summary) in category_totals {
-            outfile.write_all(format!("{}:\n", category).as_bytes())?;
-            outfile.write_all(
+        if let Some(periods) = periods {
+            let mut columns = vec![
+                text("period"),
+                text("category"),
+                number("logs"),
+                number("total"),
+            ];
+            if billing.is_some() {
+                columns.push(number("billed"));
+            }
+            let pieces: u64 = periods
+                .values()
+                .flat_map(|c| c.values())
+                .map(|s| s.count)
+                .sum();
+            let mut table = ReportTable::new(columns);
+            for (period, categories) in periods {
+                for (category, summary) in categories {
+                    let mut row = vec![
+                        period.clone(),
+                        category,
+                        summary.count.to_string(),
+                        hours_minutes(summary.total),
+                    ];
+                    if billing.is_some() {
+                        row.push(hours_minutes(summary.billed));
+                    }
+                    table.row(row);
+                }
+            }
+            let mut totals = vec![
+                l10n::text("summary-total", &[]),
+                "".to_string(),
+                pieces.to_string(),
+                hours_minutes(total_duration),
+            ];
+            if billing.is_some() {
+                totals.push(hours_minutes(total_billed));
+            }
+            table.totals(totals);
+            outfile.write_all(table.render(&table_options)?.as_bytes())?;
+            return Ok(());
+        }
+
+        let mut columns = vec![
+            text("category"),
+            number("logs"),
+            number("total"),
+            number("percent"),
+        ];
+        if billing.is_some() {
+            columns.extend([number("billed"), text("short-entries")]);
+        }
+        if daily_cap.is_some() {
+            columns.push(number("over-cap"));
+        }
+        columns.push(text("recent"));
+        let mut table = ReportTable::new(columns);
+        for (category, summary) in &category_totals {
+            let mut row = vec![
+                category.clone(),
+                summary.count.to_string(),
+                hours_minutes(summary.total),
                 format!(
-                    "  {} logs, {:02}:{:02} cumulative, {:.2}% of total\n",
-                    summary.count,
-                    summary.total / 60 / 60,
-                    summary.total / 60 % 60,
-                    (summary.total as f64 / total_duration as f64) * 100 as f64
-                )
-                .as_bytes(),
-            )?;
+                    "{:.2}%",
+                    (summary.total as f64 / total_duration as f64) * 100_f64
+                ),
+            ];
+            if billing.is_some() {
+                row.extend([
+                    hours_minutes(summary.billed),
+                    summary
+                        .short_entries
+                        .iter()
+                        .map(|id| id.to_string())
+                        .collect::<Vec<String>>()
+                        .join(", "),
+                ]);
+            }
+            if daily_cap.is_some() {
+                row.push(hours_minutes(excess.get(category).copied().unwrap_or(0)));
+            }
+            row.push(
+                recent
+                    .get(category)
+                    .map_or("".to_string(), |d| report::sparkline(d)),
+            );
+            table.row(row);
+        }
+        let mut totals = vec![
+            l10n::text("summary-total", &[]),
+            total_count.to_string(),
+            hours_minutes(total_duration),
+            "100.00%".to_string(),
+        ];
+        if billing.is_some() {
+            totals.extend([hours_minutes(total_billed), "".to_string()]);
+        }
+        if daily_cap.is_some() {
+            totals.push(hours_minutes(excess.values().sum()));
         }
+        totals.push("".to_string());
+        table.totals(totals);
+        outfile.write_all(table.render(&table_options)?.as_bytes())?;
     } else {
-        return Err(TTError::TTError {
-            message: "Didn't find any times to summarize".to_string(),
+        return Err(TTError::NothingFound {
+            message: l10n::text("summary-nothing", &[]),
         });
     }
     Ok(())
 }
 
-fn gen_export(
-    conn: &mut Connection,
-    format: &cli::ExportFormat,
-    outfile: &String,
+///Parse optional --start-time/--end-time arguments, erroring if either was given but couldn't be parsed
+pub(crate) fn parse_time_range(
     start_time: &Option<String>,
     end_time: &Option<String>,
-) -> Result<(), TTError> {
-    let mut handle: Box<dyn std::io::Write> = Box::new(io::stdout());
-    if outfile != "-" {
-        handle = Box::new(std::fs::File::create(outfile)?)
+    zone: &Zone,
+) -> Result<(Option<i64>, Option<i64>), TTError> {
+    let start = zone.parse_time(start_time);
+    if let (Some(input), None) = (start_time, start) {
+        return Err(TTError::unparseable(input, "start-time"));
     }
-    let mut tx = conn.transaction()?;
-    //parse and check options
-    let start = cli::time_string_to_tstamp(start_time);
-    if start_time.is_some() && start.is_none() {
-        return Err(TTError::TTError {
-            message: "Was unable to parse start-time".to_string(),
-        });
+    let end = zone.parse_time(end_time);
+    if let (Some(input), None) = (end_time, end) {
+        return Err(TTError::unparseable(input, "end-time"));
+    }
+    Ok((start, end))
+}
+
+///Convert a glob pattern (supporting `*` and `?`) to an anchored regex
+fn glob_to_regex(glob: &str) -> String {
+    let mut pattern = String::from("^");
+    for c in glob.chars() {
+        match c {
+            '*' => pattern.push_str(".*"),
+            '?' => pattern.push('.'),
+            c => pattern.push_str(&regex::escape(&c.to_string())),
+        }
     }
-    let end = cli::time_string_to_tstamp(end_time);
-    if end_time.is_some() && end.is_none() {
-        return Err(TTError::TTError {
-            message: "was unable to parse end-time".to_string(),
+    pattern.push('$');
+    pattern
+}
+
+///The category and tag filters and --depth roll-up from a report filter, for selecting times one at a time
+pub(crate) struct CategorySelector {
+    patterns: Vec<Regex>,
+    ///Ids of the times with one of the --tags, if there are any
+    tagged: Option<BTreeSet<i64>>,
+    depth: Option<usize>,
+}
+
+impl CategorySelector {
+    pub(crate) fn new(
+        tx: &Transaction,
+        filter: &cli::ReportFilter,
+    ) -> Result<CategorySelector, TTError> {
+        let patterns = filter
+            .category_match
+            .iter()
+            .map(|glob| glob_to_regex(glob))
+            .chain(filter.category_regex.iter().cloned())
+            .map(|pattern| {
+                Regex::new(&pattern).map_err(|e| TTError::InvalidArgument {
+                    message: format!("Invalid category pattern \"{}\": {}", pattern, e),
+                })
+            })
+            .collect::<Result<Vec<Regex>, TTError>>()?;
+        let tagged = match filter.tag.is_empty() {
+            true => None,
+            false => Some(db::get_tagged(
+                tx,
+                &filter
+                    .tag
+                    .iter()
+                    .map(|tag| db::parse_tag(tag))
+                    .collect::<Result<Vec<String>, TTError>>()?,
+            )?),
+        };
+        Ok(CategorySelector {
+            patterns,
+            tagged,
+            depth: filter.depth,
+        })
+    }
+
+    ///Whether times in `category` are selected (every category is without any patterns)
+    pub(crate) fn matches(&self, category: &str) -> bool {
+        self.patterns.is_empty() || self.patterns.iter().any(|p| p.is_match(category))
+    }
+
+    ///The time (with its category rolled up) if it's selected
+    pub(crate) fn select(&self, mut time: TimeWindow) -> Option<TimeWindow> {
+        if !self.matches(&time.category) {
+            return None;
+        }
+        if let Some(tagged) = &self.tagged {
+            if !time.id.is_some_and(|id| tagged.contains(&id)) {
+                return None;
+            }
+        }
+        if let Some(depth) = self.depth {
+            time.category = roll_up_category(&time.category, depth);
+        }
+        Some(time)
+    }
+}
+
+///Truncate hierarchical categories (separated by `/`) to at most `depth` levels
+pub(crate) fn roll_up_category(category: &str, depth: usize) -> String {
+    category
+        .split('/')
+        .take(depth.max(1))
+        .collect::<Vec<&str>>()
+        .join("/")
+}
+
+///Apply the category filters and --depth roll-up from a report filter
+pub(crate) fn select_categories(
+    tx: &Transaction,
+    times: Vec<TimeWindow>,
+    filter: &cli::ReportFilter,
+) -> Result<Vec<TimeWindow>, TTError> {
+    let selector = CategorySelector::new(tx, filter)?;
+    Ok(times
+        .into_iter()
+        .filter_map(|time| selector.select(time))
+        .collect())
+}
+
+///A page of the times selected by `filter` (by when they start, unless it says otherwise), like the APIs list them
+#[cfg_attr(not(any(feature = "serve", feature = "grpc")), allow(dead_code))]
+pub(crate) fn list_times(
+    conn: &mut Connection,
+    filter: &cli::ReportFilter,
+    page: &cli::Page,
+) -> Result<Vec<TimeWindow>, TTError> {
+    let zone = filter.timezone.unwrap_or_default();
+    let (start, end) = parse_time_range(&filter.start_time, &filter.end_time, &zone)?;
+    let mode = filter.filter_mode.clone().unwrap_or(cli::FilterMode::Start);
+    let tx = conn.transaction()?;
+    let selector = CategorySelector::new(&tx, filter)?;
+    db::iter_times(&tx, start, end, &mode, page, |times| {
+        page.slice(times.filter_map(|time| match time {
+            Ok(time) => selector.select(time).map(Ok),
+            Err(e) => Some(Err(e)),
+        }))
+        .collect()
+    })
+}
+
+///Write an export of times selected by `filter` to `handle`
+#[tracing::instrument(level = "debug", skip_all, fields(format = ?format, rows))]
+pub(crate) fn write_export(
+    conn: &mut Connection,
+    format: &cli::ExportFormat,
+    handle: &mut dyn std::io::Write,
+    filter: &cli::ReportFilter,
+    summary: &cli::SummaryOptions,
+    page: &cli::Page,
+) -> Result<(), TTError> {
+    if filter.json && !matches!(format, cli::ExportFormat::Summary) {
+        return Err(TTError::InvalidArgument {
+            message: "--json only applies to --format summary (use --format json to export times as json)".to_string(),
         });
     }
-    //fetch times from database
-    let times = db::get_times(&mut tx, start, end)?;
-    match format {
-        cli::ExportFormat::Json => export_json(&mut handle, times)?,
-        cli::ExportFormat::Csv => export_csv(&mut handle, times)?,
-        cli::ExportFormat::Ical => export_ical(&mut handle, times)?,
-        cli::ExportFormat::Summary => export_summary(&mut handle, times, start, end)?,
+    if page.is_partial() && matches!(format, cli::ExportFormat::Summary) {
+        return Err(TTError::InvalidArgument {
+            message: "--after, --offset, and --limit don't apply to --format summary".to_string(),
+        });
     }
+    let mut tx = conn.transaction()?;
+    let billing = match summary.billing {
+        true => Some(Billing::from_options(&db::get_options(&tx)?)?),
+        false => None,
+    };
+    let daily_cap = match &summary.daily_cap {
+        Some(hours) => Some(report::daily_cap_seconds(hours)?),
+        None => None,
+    };
+    let zone = filter.timezone.unwrap_or_default();
+    let (start, end) = parse_time_range(&filter.start_time, &filter.end_time, &zone)?;
+    let mode = filter.filter_mode.clone().unwrap_or(match format {
+        cli::ExportFormat::Summary => cli::FilterMode::Overlap,
+        _ => cli::FilterMode::Start,
+    });
+    let recent = match format {
+        cli::ExportFormat::Summary => {
+            let last_day = end.map_or(zone.today(), |e| zone.date(&e));
+            report::recent_activity(&mut tx, filter, last_day, &zone)?
+        }
+        _ => BTreeMap::new(),
+    };
+    let details = match format {
+        cli::ExportFormat::Json | cli::ExportFormat::Jsonl => Details::load(&tx)?,
+        _ => Details::default(),
+    };
+    let selector = CategorySelector::new(&tx, filter)?;
+    let mut rows = 0;
+    //times are streamed from the database to the exporters which don't need them all at once
+    db::iter_times(&tx, start, end, &mode, page, |times| {
+        let times = times
+            .filter_map(|time| match time {
+                Ok(time) => selector.select(time).map(Ok),
+                Err(e) => Some(Err(e)),
+            })
+            .map(|time| match mode {
+                cli::FilterMode::Overlap => time.map(|t| t.clipped(start, end)),
+                cli::FilterMode::Start => time,
+            });
+        let mut times = page.slice(times).inspect(|_| rows += 1);
+        match format {
+            cli::ExportFormat::Json => export_json(handle, &mut times, &details, &zone),
+            cli::ExportFormat::Jsonl => export_jsonl(handle, &mut times, &details, &zone),
+            cli::ExportFormat::Csv => export_csv(handle, &mut times, &zone),
+            cli::ExportFormat::Ical => export_ical(handle, &mut times),
+            cli::ExportFormat::Influx => export_influx(handle, &mut times),
+            cli::ExportFormat::Org => {
+                org::export_org(handle, times.collect::<Result<_, _>>()?, &zone)
+            }
+            cli::ExportFormat::Summary => export_summary(
+                handle,
+                times.collect::<Result<_, _>>()?,
+                SummarySettings {
+                    start,
+                    end,
+                    json: filter.json,
+                    billing,
+                    daily_cap,
+                    zone,
+                    recent,
+                    group_by: summary.group_by.clone(),
+                    table_options: summary.table.clone(),
+                },
+            ),
+        }
+    })?;
+    tracing::Span::current().record("rows", rows);
     handle.flush()?;
     Ok(())
 }
 
+///Where an export goes
+pub(crate) enum Destination {
+    ///A filename, or `-` for stdout
+    File(String),
+    ///An InfluxDB write URL to POST line protocol to
+    Push(String),
+}
+
+///POST line protocol to an InfluxDB write endpoint
+#[cfg(feature = "sync")]
+fn push_influx(url: &str, body: Vec<u8>) -> Result<(), TTError> {
+    let mut request = ureq::post(url).header("Content-Type", "text/plain; charset=utf-8");
+    if let Ok(token) = std::env::var("INFLUX_TOKEN") {
+        request = request.header("Authorization", &format!("Token {}", token));
+    }
+    request.send(&body).map_err(|e| TTError::Network {
+        message: format!("Unable to push to {}: {}", url, e),
+    })?;
+    Ok(())
+}
+
+#[cfg(not(feature = "sync"))]
+fn push_influx(_: &str, _: Vec<u8>) -> Result<(), TTError> {
+    Err(crate::commands::not_compiled("sync"))
+}
+
+fn gen_export(
+    conn: &mut Connection,
+    format: &cli::ExportFormat,
+    destination: &Destination,
+    filter: &cli::ReportFilter,
+    summary: &cli::SummaryOptions,
+    page: &cli::Page,
+) -> Result<(), TTError> {
+    match destination {
+        Destination::Push(url) => {
+            if !matches!(format, cli::ExportFormat::Influx) {
+                return Err(TTError::InvalidArgument {
+                    message: "--push only works with --format influx".to_string(),
+                });
+            }
+            let mut body = vec![];
+            write_export(conn, format, &mut body, filter, summary, page)?;
+            push_influx(url, body)
+        }
+        Destination::File(outfile) => {
+            let mut handle: Box<dyn std::io::Write> = Box::new(io::stdout());
+            if outfile != "-" {
+                handle = Box::new(std::fs::File::create(outfile)?)
+            }
+            write_export(conn, format, &mut handle, filter, summary, page)
+        }
+    }
+}
+
+///Exports that have been failing in listen mode, for the export-failure-notification option
+#[derive(Default)]
+struct ExportFailures {
+    ///When exports started failing
+    since: Option<i64>,
+    ///When a notification was last sent about it
+    notified: Option<i64>,
+}
+
+impl ExportFailures {
+    ///Whether it's been long enough since the last notification to send another
+    fn due(&self, every: i64, now: i64) -> bool {
+        self.notified.is_none_or(|notified| now - notified >= every)
+    }
+
+    ///Note a failed export, and notify about it if export-failure-notification is set and it's due
+    fn failed(&mut self, conn: &mut Connection, error: &TTError) -> Result<(), TTError> {
+        let now = Utc::now().timestamp();
+        let since = *self.since.get_or_insert(now);
+        let every = match db::get_options(&conn.transaction()?)?.get("export-failure-notification")
+        {
+            Some(every) => cli::duration_string_to_seconds(every).unwrap_or(0),
+            None => return Ok(()),
+        };
+        if every <= 0 || !self.due(every, now) {
+            return Ok(());
+        }
+        let mut notification = notify::notification(conn, &cli::NotificationEvent::ExportFailed)?;
+        notification
+            .summary(&format!(
+                "Export failing since {}",
+                Zone::Local.format(&since, "%Y-%m-%d %H:%M")
+            ))
+            .body(&error.to_string());
+        notify::show(conn, &cli::NotificationEvent::ExportFailed, &notification)?;
+        self.notified = Some(now);
+        Ok(())
+    }
+}
+
+///Write an export, or with `listen` (the DB file, for --listen), keep re-writing it whenever the DB changes
 pub fn export(
     conn: &mut Connection,
     format: &cli::ExportFormat,
-    listen: &bool,
-    db_path: &String,
-    outfile: &String,
-    start_time: &Option<String>,
-    end_time: &Option<String>,
+    listen: Option<&String>,
+    destination: &Destination,
+    filter: &cli::ReportFilter,
+    summary: &cli::SummaryOptions,
+    page: &cli::Page,
 ) -> Result<(), TTError> {
-    if *listen {
+    if let Some(db_path) = listen {
         let mut last_mod: Option<SystemTime> = None;
+        let mut failures = ExportFailures::default();
         loop {
             let current_mod = std::fs::metadata(db_path)?.modified()?;
             if last_mod.is_none() || last_mod.unwrap() != current_mod {
-                match gen_export(conn, format, outfile, start_time, end_time) {
-                    Err(e) => println!("Could not generate export! Error: {:?}", e),
-                    _ => {}
+                match gen_export(conn, format, destination, filter, summary, page) {
+                    Ok(()) => failures = ExportFailures::default(),
+                    Err(e) => {
+                        println!("Could not generate export! Error: {:?}", e);
+                        if let Err(e) = failures.failed(conn, &e) {
+                            println!("Could not notify about the failed export! Error: {:?}", e)
+                        }
+                    }
                 }
                 last_mod = Some(current_mod);
             }
             std::thread::sleep(Duration::from_secs(1));
         }
     } else {
-        return gen_export(conn, format, outfile, start_time, end_time);
+        return gen_export(conn, format, destination, filter, summary, page);
     }
 }
 
-pub(crate) fn currently_timing(conn: &mut Connection, notify: &bool) -> Result<(), TTError> {
-    let tx = conn.transaction()?;
-    if let Some(open_time) = db::get_last_open_time(&tx)? {
+pub(crate) fn currently_timing(
+    conn: &mut Connection,
+    notify: &bool,
+    json: &bool,
+) -> Result<(), TTError> {
+    let open_time = db::get_last_open_time(&conn.transaction()?)?;
+    //scripts get null rather than nothing at all
+    if open_time.is_none() && *json {
+        println!("null");
+    }
+    if let Some(open_time) = open_time {
+        println!("{}", serde_json::to_string_pretty(&open_time)?);
         if *notify {
-            let start_tstamp = unix_to_utc(&open_time.start_time);
-            let duration_sec = (chrono::Utc::now() - start_tstamp).num_seconds();
-            Notification::new()
-                .appname("Timetrack Jr.")
-                .summary(&format!("Currently timing \"{}\"", open_time.category))
-                .body(&format!(
-                    "Started: {}\nDuration: {:02}:{:02}:{:02}",
-                    DateTime::<Local>::from(start_tstamp).to_rfc2822(),
-                    duration_sec / 60 / 60,
-                    duration_sec / 60 % 60,
-                    duration_sec % 60,
-                ))
-                .show()?;
+            let mut notification = notify::from_template(
+                conn,
+                &cli::NotificationEvent::Timing,
+                &l10n::template("timing-notification"),
+                Some(&open_time),
+            )?;
+            notify::show_timing(conn, &cli::NotificationEvent::Timing, &mut notification)?;
         }
-        println!("{}", serde_json::to_string_pretty(&open_time)?)
     } else if *notify {
-        Notification::new()
-            .appname("Timetrack Jr.")
-            .summary("Not currently timing")
-            .timeout(Timeout::Milliseconds(5000))
-            .show()?;
+        let mut notification = notify::from_template(
+            conn,
+            &cli::NotificationEvent::NotTiming,
+            &l10n::template("not-timing-notification"),
+            None,
+        )?;
+        notification.timeout(notify::Timeout::Milliseconds(5000));
+        notify::show(conn, &cli::NotificationEvent::NotTiming, &notification)?;
     }
     Ok(())
 }
+
+///A line of JSON for --follow: when it was checked, and the time being recorded (null if nothing is)
+fn follow_line(time: Option<&TimeWindow>, now: i64) -> String {
+    serde_json::json!({ "timestamp": now, "time": time }).to_string()
+}
+
+///Print a line of JSON about what's being timed, then another every time that changes, checking every `interval`
+/// seconds.  Stops quietly when whatever's reading the output goes away.
+pub(crate) fn follow_timing(conn: &mut Connection, interval: &u64) -> Result<(), TTError> {
+    let mut last: Option<Option<TimeWindow>> = None;
+    loop {
+        let open = db::get_last_open_time(&conn.transaction()?)?;
+        if last.as_ref() != Some(&open) {
+            let line = follow_line(open.as_ref(), Utc::now().timestamp());
+            match writeln!(io::stdout(), "{}", line) {
+                Err(e) if e.kind() == io::ErrorKind::BrokenPipe => return Ok(()),
+                result => result?,
+            }
+            last = Some(open);
+        }
+        thread::sleep(Duration::from_secs(*interval));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_export_failures_due() {
+        let mut failures = ExportFailures::default();
+        assert!(failures.due(3600, 1000));
+        failures.notified = Some(1000);
+        assert!(!failures.due(3600, 4599));
+        assert!(failures.due(3600, 4600));
+    }
+
+    #[test]
+    fn test_follow_line() {
+        let time = TimeWindow {
+            id: Some(3),
+            category: "work".to_string(),
+            start_time: 1000,
+            end_time: None,
+            note: None,
+        };
+        assert_eq!(
+            r#"{"timestamp":2000,"time":{"id":3,"category":"work","start_time":1000,"end_time":null}}"#,
+            follow_line(Some(&time), 2000)
+        );
+        assert_eq!(r#"{"timestamp":2000,"time":null}"#, follow_line(None, 2000));
+    }
+
+    #[test]
+    fn test_glob_to_regex() {
+        let pattern = Regex::new(&glob_to_regex("client-*")).unwrap();
+        assert!(pattern.is_match("client-a"));
+        assert!(pattern.is_match("client-"));
+        assert!(!pattern.is_match("my-client-a"));
+        let pattern = Regex::new(&glob_to_regex("a?c.d")).unwrap();
+        assert!(pattern.is_match("abc.d"));
+        assert!(!pattern.is_match("abcxd"));
+    }
+
+    #[test]
+    fn test_export_influx() {
+        let mut out = vec![];
+        export_influx(
+            &mut out,
+            &mut vec![
+                TimeWindow {
+                    id: Some(1),
+                    category: "client a,b=c".to_string(),
+                    start_time: 1667307600,
+                    end_time: Some(1667311200),
+                    note: None,
+                },
+                TimeWindow {
+                    id: Some(2),
+                    category: "work".to_string(),
+                    start_time: 1667311200,
+                    end_time: None,
+                    note: None,
+                },
+            ]
+            .into_iter()
+            .map(Ok),
+        )
+        .unwrap();
+        assert_eq!(
+            concat!(
+                "ttjr_time,category=client\\ a\\,b\\=c id=1i,running=false,end_time=1667311200i,duration_seconds=3600i 1667307600000000000\n",
+                "ttjr_time,category=work id=2i,running=true 1667311200000000000\n",
+            ),
+            String::from_utf8(out).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_write_export() {
+        let mut conn = Connection::open_in_memory().unwrap();
+        db::initialize_db(&mut conn).unwrap();
+        let mut tx = conn.transaction().unwrap();
+        for (category, start, end, note) in [
+            (
+                "client-a/meetings",
+                1667307600,
+                Some(1667311200),
+                Some("planning, \"v2\""),
+            ),
+            ("client-b", 1667311260, Some(1667318400), None),
+            ("client-a/coding", 1667322000, None, None),
+        ] {
+            db::add_category(&tx, &category.to_string()).unwrap();
+            db::upsert_time(
+                &mut tx,
+                TimeWindow {
+                    id: None,
+                    category: category.to_string(),
+                    start_time: start,
+                    end_time: end,
+                    note: note.map(String::from),
+                },
+                &true,
+            )
+            .unwrap();
+        }
+        tx.commit().unwrap();
+        let filter = cli::ReportFilter {
+            category_match: vec!["client-a/*".to_string()],
+            depth: Some(1),
+            timezone: Some(Zone::Named(chrono_tz::UTC)),
+            ..Default::default()
+        };
+        let export = |conn: &mut Connection, format| {
+            let mut out = vec![];
+            write_export(
+                conn,
+                &format,
+                &mut out,
+                &filter,
+                &Default::default(),
+                &Default::default(),
+            )
+            .unwrap();
+            String::from_utf8(out).unwrap()
+        };
+
+        assert_eq!(
+            concat!(
+                r#"{"schema_version":1,"id":1,"category":"client-a","start_time":1667307600,"end_time":1667311200,"start_timestamp":"2022-11-01T13:00:00+00:00","end_timestamp":"2022-11-01T14:00:00+00:00","note":"planning, \"v2\""}"#,
+                "\n",
+                r#"{"schema_version":1,"id":3,"category":"client-a","start_time":1667322000,"end_time":null,"start_timestamp":"2022-11-01T17:00:00+00:00","end_timestamp":null}"#,
+                "\n"
+            ),
+            export(&mut conn, cli::ExportFormat::Jsonl)
+        );
+        let json: Vec<TimeWindowExport> =
+            serde_json::from_str(&export(&mut conn, cli::ExportFormat::Json)).unwrap();
+        assert_eq!(
+            vec![Some(1), Some(3)],
+            json.iter().map(|t| t.id).collect::<Vec<_>>()
+        );
+        //tagged times can be picked out with --tag
+        let tx = conn.transaction().unwrap();
+        db::tag_time(&tx, 3, &["urgent".to_string()]).unwrap();
+        tx.commit().unwrap();
+        let mut out = vec![];
+        write_export(
+            &mut conn,
+            &cli::ExportFormat::Json,
+            &mut out,
+            &cli::ReportFilter {
+                tag: vec!["#urgent".to_string()],
+                ..Default::default()
+            },
+            &Default::default(),
+            &Default::default(),
+        )
+        .unwrap();
+        let tagged: Vec<TimeWindowExport> = serde_json::from_slice(&out).unwrap();
+        assert_eq!(
+            vec![(Some(3), vec!["urgent".to_string()])],
+            tagged
+                .into_iter()
+                .map(|t| (t.id, t.tags))
+                .collect::<Vec<_>>()
+        );
+        assert!(export(&mut conn, cli::ExportFormat::Csv).contains(
+            ",3600,\"planning, \"\"v2\"\"\"\n3,client-a,2022-11-01T17:00:00+00:00,,1667322000,,,,\n"
+        ));
+        //the schema promises every time has the version it's at
+        let schema = serde_json::to_value(schema_for!(TimeWindowExport)).unwrap();
+        assert_eq!(
+            serde_json::json!(EXPORT_SCHEMA_VERSION),
+            schema["properties"]["schema_version"]["const"]
+        );
+        assert!(schema["required"]
+            .as_array()
+            .unwrap()
+            .contains(&serde_json::json!("schema_version")));
+        //the open time isn't an event
+        #[cfg(feature = "ical")]
+        {
+            let ical = export(&mut conn, cli::ExportFormat::Ical);
+            assert!(ical.starts_with("BEGIN:VCALENDAR\r\n"));
+            assert!(ical.ends_with("END:VEVENT\r\nEND:VCALENDAR\r\n"));
+            assert_eq!(1, ical.matches("BEGIN:VEVENT").count());
+            assert!(ical.contains("SUMMARY:client-a\r\n"));
+            assert!(ical.contains("DESCRIPTION:planning, \"v2\"\r\n"));
+        }
+    }
+
+    #[test]
+    fn test_billing_round() {
+        let mut billing = Billing {
+            increment: 15 * 60,
+            rounding: cli::BillingRounding::Up,
+        };
+        assert_eq!(15 * 60, billing.round(60));
+        assert_eq!(15 * 60, billing.round(15 * 60));
+        assert_eq!(30 * 60, billing.round(16 * 60));
+        billing.rounding = cli::BillingRounding::Down;
+        assert_eq!(0, billing.round(14 * 60));
+        assert_eq!(15 * 60, billing.round(29 * 60));
+        billing.rounding = cli::BillingRounding::Nearest;
+        assert_eq!(0, billing.round(7 * 60));
+        assert_eq!(15 * 60, billing.round(8 * 60));
+    }
+
+    #[test]
+    fn test_period_totals() {
+        let zone = Zone::Named(chrono_tz::UTC);
+        let time = |category: &str, start, end| TimeWindow {
+            id: None,
+            category: category.to_string(),
+            start_time: start,
+            end_time: Some(end),
+            note: None,
+        };
+        //2022-10-31 23:00 through 2022-11-01 01:00 counts an hour towards each day/month
+        let times = [
+            time("a", 1667257200, 1667264400),
+            time("b", 1667307600, 1667311200),
+        ];
+        let totals = |period| {
+            period_totals(&times, &period, &None, &zone)
+                .into_iter()
+                .map(|(period, categories)| {
+                    (
+                        period,
+                        categories
+                            .into_iter()
+                            .map(|(category, s)| (category, s.total))
+                            .collect::<Vec<_>>(),
+                    )
+                })
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            vec![
+                ("2022-10-31".to_string(), vec![("a".to_string(), 3600)]),
+                (
+                    "2022-11-01".to_string(),
+                    vec![("a".to_string(), 3600), ("b".to_string(), 3600)]
+                ),
+            ],
+            totals(cli::SummaryPeriod::Day)
+        );
+        assert_eq!(
+            vec![(
+                "2022-W44".to_string(),
+                vec![("a".to_string(), 7200), ("b".to_string(), 3600)]
+            )],
+            totals(cli::SummaryPeriod::Week)
+        );
+        assert_eq!(
+            vec!["2022-10".to_string(), "2022-11".to_string()],
+            totals(cli::SummaryPeriod::Month)
+                .into_iter()
+                .map(|(period, _)| period)
+                .collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn test_roll_up_category() {
+        assert_eq!("work", roll_up_category("work/meetings/standup", 1));
+        assert_eq!(
+            "work/meetings",
+            roll_up_category("work/meetings/standup", 2)
+        );
+        assert_eq!(
+            "work/meetings/standup",
+            roll_up_category("work/meetings/standup", 5)
+        );
+        assert_eq!("work", roll_up_category("work", 0));
+    }
+}
@@ -0,0 +1,127 @@
+/*
+This file is part of Timetrack Jr.
+Timetrack Jr. is free software: you can redistribute it and/or modify it under the terms of the GNU General Public License as published by the Free Software Foundation, either version 3 of the License, or (at your option) any later version.
+Timetrack Jr. is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU General Public License for more details.
+You should have received a copy of the GNU General Public License along with Timetrack Jr. If not, see <https://www.gnu.org/licenses/>.
+*/
+//Extensions: like git and cargo, `ttjr jira sync` runs `ttjr-jira sync` from PATH, so commands can be shipped
+// separately from ttjr.  The database path and global flags are passed in TTJR_* environment variables.
+use crate::{cli::Cli, TTError};
+use std::{env, ffi::OsStr, path::PathBuf, process::Command};
+
+///Find the program for extension `name` in `path` (formatted like PATH)
+fn find_in(name: &str, path: &OsStr) -> Option<PathBuf> {
+    let program = format!("ttjr-{}", name);
+    //windows finds programs by extension
+    let candidates: Vec<String> = match cfg!(windows) {
+        true => env::var("PATHEXT")
+            .unwrap_or(".EXE;.CMD;.BAT".to_string())
+            .split(';')
+            .map(|extension| format!("{}{}", program, extension.to_lowercase()))
+            .collect(),
+        false => vec![program],
+    };
+    env::split_paths(path)
+        .flat_map(|dir| candidates.iter().map(move |c| dir.join(c)))
+        .find(executable)
+}
+
+#[cfg(unix)]
+fn executable(path: &PathBuf) -> bool {
+    use std::os::unix::fs::PermissionsExt;
+    path.metadata()
+        .is_ok_and(|m| m.is_file() && m.permissions().mode() & 0o111 != 0)
+}
+
+#[cfg(not(unix))]
+fn executable(path: &PathBuf) -> bool {
+    path.is_file()
+}
+
+fn flag(set: bool) -> String {
+    match set {
+        true => "1".to_string(),
+        false => "0".to_string(),
+    }
+}
+
+///What an extension is told about how ttjr was run
+fn environment(cli: &Cli) -> Vec<(&'static str, String)> {
+    let mut environment = vec![
+        ("TTJR_DB_PATH", cli.db_path.clone().unwrap_or_default()),
+        ("TTJR_JSON", flag(cli.json)),
+        ("TTJR_VERBOSE", flag(cli.verbose)),
+        ("TTJR_QUIET", flag(cli.quiet)),
+        ("TTJR_DRY_RUN", flag(cli.dry_run)),
+        ("TTJR_YES", flag(cli.yes)),
+    ];
+    if let Some(log_file) = &cli.log_file {
+        environment.push(("TTJR_LOG_FILE", log_file.clone()));
+    }
+    //so extensions can run ttjr commands themselves
+    if let Ok(ttjr) = env::current_exe() {
+        environment.push(("TTJR", ttjr.to_string_lossy().to_string()));
+    }
+    environment
+}
+
+///Run the extension `args[0]` with the rest of `args`, returning its exit code
+pub fn run(cli: &Cli, args: &[String]) -> Result<i32, TTError> {
+    let (name, args) = args.split_first().ok_or(TTError::InvalidArgument {
+        message: "No command given".to_string(),
+    })?;
+    let program = find_in(name, &env::var_os("PATH").unwrap_or_default()).ok_or(
+        TTError::InvalidArgument {
+            message: format!(
+                "\"{}\" isn't a ttjr command, and there's no ttjr-{} extension on your PATH",
+                name, name
+            ),
+        },
+    )?;
+    tracing::debug!("running {}", program.display());
+    let status = Command::new(&program)
+        .args(args)
+        .envs(environment(cli))
+        .status()
+        .map_err(|e| TTError::System {
+            message: format!("Couldn't run {}: {}", program.display(), e),
+        })?;
+    Ok(status.code().unwrap_or(1))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap::Parser;
+
+    #[test]
+    fn test_external() {
+        let cli = Cli::parse_from(["ttjr", "--json", "--dry-run", "jira", "sync", "--all"]);
+        let args = match &cli.command {
+            crate::cli::Commands::External(args) => args.clone(),
+            other => panic!("{:?}", other),
+        };
+        assert_eq!(vec!["jira", "sync", "--all"], args);
+        let environment = environment(&cli);
+        assert!(environment.contains(&("TTJR_DB_PATH", "ttjr.sqlite3".to_string())));
+        assert!(environment.contains(&("TTJR_JSON", "1".to_string())));
+        assert!(environment.contains(&("TTJR_DRY_RUN", "1".to_string())));
+        assert!(environment.contains(&("TTJR_YES", "0".to_string())));
+
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let dir = env::temp_dir().join(format!("ttjr-external-{}", std::process::id()));
+            std::fs::create_dir_all(&dir).unwrap();
+            let program = dir.join("ttjr-jira");
+            std::fs::write(&program, "#!/bin/sh\nexit 0\n").unwrap();
+            let path = env::join_paths([dir.join("missing"), dir.clone()]).unwrap();
+            //not executable yet
+            assert_eq!(None, find_in("jira", &path));
+            std::fs::set_permissions(&program, std::fs::Permissions::from_mode(0o755)).unwrap();
+            assert_eq!(Some(program), find_in("jira", &path));
+            assert_eq!(None, find_in("asana", &path));
+            let _ = std::fs::remove_dir_all(&dir);
+        }
+    }
+}
@@ -0,0 +1,153 @@
+/*
+This file is part of Timetrack Jr.
+Timetrack Jr. is free software: you can redistribute it and/or modify it under the terms of the GNU General Public License as published by the Free Software Foundation, either version 3 of the License, or (at your option) any later version.
+Timetrack Jr. is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU General Public License for more details.
+You should have received a copy of the GNU General Public License along with Timetrack Jr. If not, see <https://www.gnu.org/licenses/>.
+*/
+use crate::{cli::FilterMode, db, db::TimeWindow, TTError};
+use chrono::{DateTime, Utc};
+use rusqlite::Connection;
+use serde::Deserialize;
+use serde_json::{json, Value};
+
+#[derive(Deserialize)]
+struct Range {
+    from: String,
+    to: String,
+}
+
+#[derive(Deserialize)]
+struct Target {
+    target: Option<String>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Query {
+    range: Range,
+    interval_ms: Option<i64>,
+    max_data_points: Option<i64>,
+    #[serde(default)]
+    targets: Vec<Target>,
+}
+
+fn parse_timestamp(timestamp: &str) -> Result<i64, TTError> {
+    DateTime::parse_from_rfc3339(timestamp)
+        .map(|d| d.timestamp())
+        .map_err(|_| TTError::InvalidArgument {
+            message: format!("Invalid timestamp \"{}\"", timestamp),
+        })
+}
+
+///Seconds tracked in each `interval`-second bucket from `from` to `to`, buckets are aligned to the unix epoch.
+/// Times that are still open count up to `now`.
+fn bucket_seconds(
+    times: &[TimeWindow],
+    from: i64,
+    to: i64,
+    interval: i64,
+    now: i64,
+) -> Vec<(i64, i64)> {
+    let first = from.div_euclid(interval) * interval;
+    let mut buckets: Vec<(i64, i64)> = (first..to)
+        .step_by(interval as usize)
+        .map(|b| (b, 0))
+        .collect();
+    for time in times {
+        let start = time.start_time.max(first);
+        let end = time.end_time.unwrap_or(now).min(to);
+        let mut bucket_start = start.div_euclid(interval) * interval;
+        while bucket_start < end {
+            let overlap = end.min(bucket_start + interval) - start.max(bucket_start);
+            if let Some(bucket) = buckets.get_mut(((bucket_start - first) / interval) as usize) {
+                bucket.1 += overlap;
+            }
+            bucket_start += interval;
+        }
+    }
+    buckets
+}
+
+fn query(conn: &mut Connection, query: Query, now: i64) -> Result<Value, TTError> {
+    let from = parse_timestamp(&query.range.from)?;
+    let to = parse_timestamp(&query.range.to)?;
+    let mut interval = query.interval_ms.unwrap_or(3_600_000).max(1000) / 1000;
+    //never send more points than the panel asked for
+    if let Some(max) = query.max_data_points.filter(|m| *m > 0) {
+        interval = interval.max((to - from) / max + 1);
+    }
+    let mut tx = conn.transaction()?;
+    let times = db::get_times(&mut tx, Some(from), Some(to), &FilterMode::Overlap)?;
+    Ok(query
+        .targets
+        .iter()
+        .filter_map(|t| t.target.as_ref())
+        .map(|target| {
+            let category_times: Vec<TimeWindow> = times
+                .iter()
+                .filter(|t| &t.category == target)
+                .cloned()
+                .collect();
+            json!({
+                "target": target,
+                "datapoints": bucket_seconds(&category_times, from, to, interval, now)
+                    .into_iter()
+                    .map(|(bucket, seconds)| json!([seconds, bucket * 1000]))
+                    .collect::<Vec<Value>>(),
+            })
+        })
+        .collect())
+}
+
+///Handle a request to /grafana/<path> using the query contract of Grafana's simple-json/JSON datasources.
+/// Series are the seconds tracked for a category in each time bucket.
+pub(crate) fn route(
+    conn: &mut Connection,
+    path: &[&str],
+    body: &str,
+    now: DateTime<Utc>,
+) -> Result<Option<Value>, TTError> {
+    Ok(Some(match path {
+        //the datasource's connection test
+        [] => json!({}),
+        ["search"] => json!(db::get_categories(&conn.transaction()?)?),
+        ["metrics"] => json!(db::get_categories(&conn.transaction()?)?
+            .into_iter()
+            .map(|c| json!({ "label": c, "value": c }))
+            .collect::<Vec<Value>>()),
+        ["query"] => {
+            let parsed: Query =
+                serde_json::from_str(body).map_err(|e| TTError::InvalidArgument {
+                    message: format!("Invalid query: {}", e),
+                })?;
+            query(conn, parsed, now.timestamp())?
+        }
+        ["annotations"] | ["tag-keys"] | ["tag-values"] => json!([]),
+        _ => return Ok(None),
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_bucket_seconds() {
+        let time = |start, end| TimeWindow {
+            id: None,
+            category: "work".to_string(),
+            start_time: start,
+            end_time: end,
+            note: None,
+        };
+        assert_eq!(
+            vec![(0, 50), (100, 100), (200, 30), (300, 0)],
+            bucket_seconds(&[time(50, Some(230))], 0, 400, 100, 1000)
+        );
+        //clipped to the range, open times run until now
+        assert_eq!(
+            vec![(100, 70), (200, 50)],
+            bucket_seconds(&[time(0, Some(170)), time(220, None)], 130, 300, 100, 270)
+        );
+    }
+}
@@ -0,0 +1,276 @@
+/*
+This file is part of Timetrack Jr.
+Timetrack Jr. is free software: you can redistribute it and/or modify it under the terms of the GNU General Public License as published by the Free Software Foundation, either version 3 of the License, or (at your option) any later version.
+Timetrack Jr. is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU General Public License for more details.
+You should have received a copy of the GNU General Public License along with Timetrack Jr. If not, see <https://www.gnu.org/licenses/>.
+*/
+use crate::{
+    cli::{ExportFormat, FilterMode, Order, Page, ReportFilter, SummaryOptions},
+    commands::{
+        export::{self, write_export},
+        log, runtime,
+    },
+    db::{self, Pool, TimeWindow},
+    zone::Zone,
+    TTError,
+};
+use clap::ValueEnum;
+use rusqlite::Connection;
+use tonic::{transport::Server, Request, Response, Status};
+
+///Types and service traits generated from proto/ttjr.proto
+pub mod proto {
+    tonic::include_proto!("ttjr.v1");
+}
+
+use proto::{
+    timetrack_server::{Timetrack, TimetrackServer},
+    CategorySummary, ListTimesResponse, StartTimingRequest, StopTimingRequest, StopTimingResponse,
+    SummaryResponse, Time, TimeFilter,
+};
+
+impl From<TimeWindow> for Time {
+    fn from(time: TimeWindow) -> Self {
+        Time {
+            id: time.id.unwrap_or(-1),
+            category: time.category,
+            start_time: time.start_time,
+            end_time: time.end_time,
+        }
+    }
+}
+
+///Same status codes the HTTP API uses for the same errors
+fn status(error: TTError) -> Status {
+    match error {
+        TTError::Sql(rusqlite::Error::QueryReturnedNoRows) => Status::not_found("Not found"),
+        e @ (TTError::CategoryNotFound { .. }
+        | TTError::TimeNotFound { .. }
+        | TTError::NotFound { .. }) => Status::not_found(e.to_string()),
+        e @ TTError::OverlappingTime { .. } => Status::already_exists(e.to_string()),
+        e @ (TTError::ParseTime { .. }
+        | TTError::UnusualTime { .. }
+        | TTError::InvalidValue { .. }
+        | TTError::InvalidArgument { .. }) => Status::invalid_argument(e.to_string()),
+        e @ TTError::DatabaseBusy { .. } => Status::unavailable(e.to_string()),
+        e => Status::internal(e.to_string()),
+    }
+}
+
+fn page(filter: &TimeFilter) -> Result<Page, TTError> {
+    Ok(Page {
+        order: match &filter.order {
+            Some(order) => Order::from_str(order, true)
+                .map_err(|_| TTError::invalid("order", order, "asc or desc"))?,
+            None => Order::Asc,
+        },
+        after: filter.after,
+        offset: filter.offset as usize,
+        limit: filter.limit.map(|l| l as usize),
+    })
+}
+
+fn report_filter(filter: TimeFilter) -> Result<ReportFilter, TTError> {
+    Ok(ReportFilter {
+        start_time: filter.start,
+        end_time: filter.end,
+        filter_mode: match filter.filter_mode {
+            Some(mode) => Some(
+                FilterMode::from_str(&mode, true)
+                    .map_err(|_| TTError::invalid("filter_mode", &mode, "start or overlap"))?,
+            ),
+            None => None,
+        },
+        category_match: filter.category_match,
+        category_regex: filter.category_regex,
+        tag: filter.tag,
+        depth: None,
+        timezone: filter
+            .timezone
+            .map(|z| z.parse::<Zone>())
+            .transpose()
+            .map_err(|message| TTError::InvalidArgument { message })?,
+        json: true,
+    })
+}
+
+fn start_timing(conn: &mut Connection, request: StartTimingRequest) -> Result<Time, TTError> {
+    log::start_timing(conn, &request.category, &None, &false)?;
+    match db::get_last_open_time(&conn.transaction()?)? {
+        Some(time) => Ok(time.into()),
+        None => Err(TTError::System {
+            message: "Timing didn't start".to_string(),
+        }),
+    }
+}
+
+fn stop_timing(conn: &mut Connection) -> Result<StopTimingResponse, TTError> {
+    let open = db::get_last_open_time(&conn.transaction()?)?;
+    log::stop_timing(conn, &false)?;
+    let stopped = match open.and_then(|time| time.id) {
+        Some(id) => Some(db::get_time(&conn.transaction()?, id)?.into()),
+        None => None,
+    };
+    Ok(StopTimingResponse { stopped })
+}
+
+fn list_times(conn: &mut Connection, filter: TimeFilter) -> Result<ListTimesResponse, TTError> {
+    let page = page(&filter)?;
+    let times = export::list_times(conn, &report_filter(filter)?, &page)?;
+    Ok(ListTimesResponse {
+        times: times.into_iter().map(Time::from).collect(),
+    })
+}
+
+fn summary(conn: &mut Connection, filter: TimeFilter) -> Result<SummaryResponse, TTError> {
+    //build the summary the same way `export --format summary --json` does so the numbers always agree
+    let mut document = vec![];
+    write_export(
+        conn,
+        &ExportFormat::Summary,
+        &mut document,
+        &report_filter(filter)?,
+        &SummaryOptions::default(),
+        &Page::default(),
+    )?;
+    let document: serde_json::Value = serde_json::from_slice(&document)?;
+    let number = |value: &serde_json::Value| value.as_u64().unwrap_or_default();
+    Ok(SummaryResponse {
+        start: document["start"].as_i64(),
+        end: document["end"].as_i64(),
+        count: number(&document["count"]),
+        total_seconds: number(&document["total_seconds"]),
+        categories: document["categories"]
+            .as_object()
+            .into_iter()
+            .flatten()
+            .map(|(category, summary)| {
+                (
+                    category.clone(),
+                    CategorySummary {
+                        count: number(&summary["count"]),
+                        total_seconds: number(&summary["total_seconds"]),
+                        percent: summary["percent"].as_f64().unwrap_or_default(),
+                    },
+                )
+            })
+            .collect(),
+    })
+}
+
+struct Service {
+    pool: Pool,
+}
+
+impl Service {
+    ///Run a blocking DB operation on a pooled connection without holding up the async runtime
+    async fn with_conn<T: Send + 'static>(
+        &self,
+        operation: impl FnOnce(&mut Connection) -> Result<T, TTError> + Send + 'static,
+    ) -> Result<Response<T>, Status> {
+        runtime::with_conn(&self.pool, operation)
+            .await
+            .map(Response::new)
+            .map_err(status)
+    }
+}
+
+#[tonic::async_trait]
+impl Timetrack for Service {
+    async fn start_timing(
+        &self,
+        request: Request<StartTimingRequest>,
+    ) -> Result<Response<Time>, Status> {
+        let request = request.into_inner();
+        self.with_conn(move |conn| start_timing(conn, request))
+            .await
+    }
+
+    async fn stop_timing(
+        &self,
+        _: Request<StopTimingRequest>,
+    ) -> Result<Response<StopTimingResponse>, Status> {
+        self.with_conn(stop_timing).await
+    }
+
+    async fn list_times(
+        &self,
+        request: Request<TimeFilter>,
+    ) -> Result<Response<ListTimesResponse>, Status> {
+        let filter = request.into_inner();
+        self.with_conn(move |conn| list_times(conn, filter)).await
+    }
+
+    async fn summary(
+        &self,
+        request: Request<TimeFilter>,
+    ) -> Result<Response<SummaryResponse>, Status> {
+        let filter = request.into_inner();
+        self.with_conn(move |conn| summary(conn, filter)).await
+    }
+}
+
+pub fn serve(db_path: &String, bind_addr: &String) -> Result<(), TTError> {
+    let addr = bind_addr.parse().map_err(|_| TTError::InvalidArgument {
+        message: format!("Invalid address to listen on: {}", bind_addr),
+    })?;
+    let service = Service {
+        pool: db::open_pool(db_path, 4)?,
+    };
+    println!("Listening for gRPC on {}", bind_addr);
+    runtime::runtime()
+        .block_on(
+            Server::builder()
+                .add_service(TimetrackServer::new(service))
+                .serve(addr),
+        )
+        .map_err(|e| TTError::Network {
+            message: format!("gRPC server failed: {}", e),
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::commands::config;
+
+    #[test]
+    fn test_grpc_calls() {
+        let mut conn = Connection::open_in_memory().unwrap();
+        db::initialize_db(&mut conn).unwrap();
+        config::add_category(&mut conn, &"work".to_string()).unwrap();
+
+        let started = start_timing(
+            &mut conn,
+            StartTimingRequest {
+                category: "work".to_string(),
+            },
+        )
+        .unwrap();
+        assert_eq!("work", started.category);
+        assert_eq!(None, started.end_time);
+
+        let stopped = stop_timing(&mut conn).unwrap().stopped.unwrap();
+        assert_eq!(started.id, stopped.id);
+        assert!(stopped.end_time.is_some());
+        assert_eq!(None, stop_timing(&mut conn).unwrap().stopped);
+
+        let times = list_times(&mut conn, TimeFilter::default()).unwrap().times;
+        assert_eq!(vec![stopped], times);
+
+        let totals = summary(&mut conn, TimeFilter::default()).unwrap();
+        assert_eq!(1, totals.count);
+        assert_eq!(1, totals.categories["work"].count);
+
+        assert!(matches!(
+            list_times(
+                &mut conn,
+                TimeFilter {
+                    filter_mode: Some("sideways".to_string()),
+                    ..Default::default()
+                }
+            ),
+            Err(TTError::InvalidValue { .. })
+        ));
+    }
+}
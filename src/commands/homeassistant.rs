@@ -0,0 +1,302 @@
+/*
+This file is part of Timetrack Jr.
+Timetrack Jr. is free software: you can redistribute it and/or modify it under the terms of the GNU General Public License as published by the Free Software Foundation, either version 3 of the License, or (at your option) any later version.
+Timetrack Jr. is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU General Public License for more details.
+You should have received a copy of the GNU General Public License along with Timetrack Jr. If not, see <https://www.gnu.org/licenses/>.
+*/
+use crate::{
+    cli::FilterMode,
+    commands::log,
+    db::{self, TimeWindow},
+    zone::Zone,
+    TTError,
+};
+use chrono::Utc;
+use rumqttc::{Client, Event, LastWill, MqttOptions, Packet, QoS};
+use rusqlite::Connection;
+use serde_json::{json, Value};
+use std::{
+    collections::BTreeMap,
+    thread,
+    time::{Duration, Instant},
+};
+
+const DISCOVERY_PREFIX: &str = "homeassistant";
+const AVAILABILITY: &str = "ttjr/availability";
+const CATEGORY: &str = "ttjr/category";
+const CATEGORY_SET: &str = "ttjr/category/set";
+const STOP: &str = "ttjr/stop";
+///Option of the category select for when nothing is being timed
+const NOT_TIMING: &str = "Not timing";
+
+///Something Home Assistant asked ttjr to do
+#[derive(Debug, PartialEq)]
+enum Command {
+    Start(String),
+    Stop,
+}
+
+///Categories can be anything, but entity ids and topics can't
+fn slug(category: &str) -> String {
+    category
+        .to_lowercase()
+        .chars()
+        .map(|c| match c.is_ascii_alphanumeric() {
+            true => c,
+            false => '_',
+        })
+        .collect()
+}
+
+fn today_topic(category: &str) -> String {
+    format!("ttjr/today/{}", slug(category))
+}
+
+///MQTT discovery configs (topic and payload) for the entities ttjr exposes: a select showing (and setting) the
+/// category being timed, a stop button, and a sensor for each category's time today
+fn discovery(categories: &db::Categories) -> Vec<(String, Value)> {
+    let device = json!({
+        "identifiers": ["timetrack_jr"],
+        "name": "Timetrack Jr.",
+    });
+    let mut options = vec![NOT_TIMING.to_string()];
+    options.extend(categories.iter().cloned());
+    let mut configs = vec![
+        (
+            format!("{}/select/ttjr/category/config", DISCOVERY_PREFIX),
+            json!({
+                "name": "Category",
+                "unique_id": "ttjr_category",
+                "icon": "mdi:timer-outline",
+                "state_topic": CATEGORY,
+                "command_topic": CATEGORY_SET,
+                "options": options,
+                "availability_topic": AVAILABILITY,
+                "device": device,
+            }),
+        ),
+        (
+            format!("{}/button/ttjr/stop/config", DISCOVERY_PREFIX),
+            json!({
+                "name": "Stop timing",
+                "unique_id": "ttjr_stop",
+                "icon": "mdi:timer-off-outline",
+                "command_topic": STOP,
+                "availability_topic": AVAILABILITY,
+                "device": device,
+            }),
+        ),
+    ];
+    for category in categories {
+        configs.push((
+            format!(
+                "{}/sensor/ttjr/today_{}/config",
+                DISCOVERY_PREFIX,
+                slug(category)
+            ),
+            json!({
+                "name": format!("{} today", category),
+                "unique_id": format!("ttjr_today_{}", slug(category)),
+                "state_topic": today_topic(category),
+                "unit_of_measurement": "min",
+                "device_class": "duration",
+                "state_class": "total_increasing",
+                "availability_topic": AVAILABILITY,
+                "device": device,
+            }),
+        ));
+    }
+    configs
+}
+
+///State topics and their values: the category being timed and minutes logged for each category today (up to `now`)
+fn state(
+    categories: &db::Categories,
+    current: Option<&TimeWindow>,
+    today: &[TimeWindow],
+    now: i64,
+) -> Vec<(String, String)> {
+    let mut minutes: BTreeMap<&String, i64> = categories.iter().map(|c| (c, 0)).collect();
+    for time in today {
+        let seconds = time.end_time.unwrap_or(now) - time.start_time;
+        *minutes.entry(&time.category).or_default() += seconds.max(0);
+    }
+    let mut state = vec![(
+        CATEGORY.to_string(),
+        current
+            .map(|t| t.category.clone())
+            .unwrap_or(NOT_TIMING.to_string()),
+    )];
+    for (category, seconds) in minutes {
+        state.push((today_topic(category), (seconds / 60).to_string()));
+    }
+    state
+}
+
+fn command(topic: &str, payload: &[u8]) -> Option<Command> {
+    match (topic, String::from_utf8_lossy(payload).trim()) {
+        (STOP, _) | (CATEGORY_SET, NOT_TIMING) => Some(Command::Stop),
+        (CATEGORY_SET, category) => Some(Command::Start(category.to_string())),
+        _ => None,
+    }
+}
+
+fn publish(client: &Client, topic: String, payload: String) -> Result<(), TTError> {
+    client
+        .publish(topic, QoS::AtLeastOnce, true, payload)
+        .map_err(|e| TTError::Network {
+            message: format!("Couldn't publish to MQTT: {}", e),
+        })
+}
+
+fn publish_state(client: &Client, conn: &mut Connection) -> Result<db::Categories, TTError> {
+    let now = Utc::now().timestamp();
+    let midnight = Zone::Local.midnight(Zone::Local.today());
+    let mut tx = conn.transaction()?;
+    let categories = db::get_categories(&tx)?;
+    let current = db::get_last_open_time(&tx)?;
+    let today: Vec<TimeWindow> =
+        db::get_times(&mut tx, Some(midnight), None, &FilterMode::Overlap)?
+            .iter()
+            .map(|t| t.clipped(Some(midnight), None))
+            .collect();
+    for (topic, payload) in state(&categories, current.as_ref(), &today, now) {
+        publish(client, topic, payload)?;
+    }
+    Ok(categories)
+}
+
+fn announce(client: &Client, categories: &db::Categories) -> Result<(), TTError> {
+    for (topic, config) in discovery(categories) {
+        publish(client, topic, config.to_string())?;
+    }
+    publish(client, AVAILABILITY.to_string(), "online".to_string())
+}
+
+///Expose what's being timed to Home Assistant through an MQTT broker (using MQTT discovery), and start/stop timing
+/// when asked to by Home Assistant.  State is republished every `interval` seconds so daily totals keep counting.
+pub fn home_assistant(conn: &mut Connection, broker: &str, interval: &u64) -> Result<(), TTError> {
+    let options = db::get_options(&conn.transaction()?)?;
+    let (host, port) = match broker.rsplit_once(':') {
+        Some((host, port)) => (host, port.parse()?),
+        None => (broker, 1883),
+    };
+    let mut mqtt_options = MqttOptions::new(format!("ttjr-{}", std::process::id()), host, port);
+    mqtt_options.set_keep_alive(Duration::from_secs(30));
+    mqtt_options.set_last_will(LastWill::new(
+        AVAILABILITY,
+        "offline",
+        QoS::AtLeastOnce,
+        true,
+    ));
+    if let Some(username) = options.get("mqtt-username") {
+        let password = std::env::var("MQTT_PASSWORD")
+            .ok()
+            .or(options.get("mqtt-password").cloned())
+            .unwrap_or_default();
+        mqtt_options.set_credentials(username, password);
+    }
+    let (client, mut connection) = Client::new(mqtt_options, 100);
+    let subscribe = |topic: &str| {
+        client
+            .subscribe(topic, QoS::AtLeastOnce)
+            .map_err(|e| TTError::Network {
+                message: format!("Couldn't subscribe to {}: {}", topic, e),
+            })
+    };
+
+    let mut announced = db::Categories::new();
+    let mut published = Instant::now();
+    loop {
+        let mut changed = false;
+        match connection.recv_timeout(Duration::from_secs(1)) {
+            Ok(Ok(Event::Incoming(Packet::ConnAck(_)))) => {
+                println!("Connected to {}", broker);
+                announced = db::get_categories(&conn.transaction()?)?;
+                announce(&client, &announced)?;
+                subscribe(CATEGORY_SET)?;
+                subscribe(STOP)?;
+                changed = true;
+            }
+            Ok(Ok(Event::Incoming(Packet::Publish(message)))) => {
+                let result = match command(&message.topic, &message.payload) {
+                    Some(Command::Start(category)) => {
+                        log::start_timing(conn, &category, &None, &false)
+                    }
+                    Some(Command::Stop) => log::stop_timing(conn, &false),
+                    None => Ok(()),
+                };
+                match result {
+                    Err(e) if e.recoverable() => eprintln!("{}", e),
+                    result => result?,
+                }
+                changed = true;
+            }
+            Ok(Err(e)) => {
+                //the connection is retried the next time it's polled
+                eprintln!("MQTT connection error: {}", e);
+                thread::sleep(Duration::from_secs(5));
+            }
+            _ => {}
+        }
+        if changed || published.elapsed() >= Duration::from_secs(*interval) {
+            let categories = publish_state(&client, conn)?;
+            if categories != announced {
+                announce(&client, &categories)?;
+                announced = categories;
+            }
+            published = Instant::now();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_discovery() {
+        let categories: db::Categories = ["Deep Work", "email"].map(String::from).into();
+        let configs = discovery(&categories);
+        assert_eq!(4, configs.len());
+        assert_eq!(
+            json!(["Not timing", "Deep Work", "email"]),
+            configs[0].1["options"]
+        );
+        assert_eq!(
+            "homeassistant/sensor/ttjr/today_deep_work/config",
+            configs[2].0
+        );
+        assert_eq!("ttjr/today/deep_work", configs[2].1["state_topic"]);
+    }
+
+    #[test]
+    fn test_state_and_commands() {
+        let categories: db::Categories = ["work", "email"].map(String::from).into();
+        let time = |category: &str, start, end| TimeWindow {
+            id: None,
+            category: category.to_string(),
+            start_time: start,
+            end_time: end,
+            note: None,
+        };
+        let today = [time("work", 0, Some(600)), time("work", 1000, None)];
+        assert_eq!(
+            vec![
+                ("ttjr/category".to_string(), "work".to_string()),
+                ("ttjr/today/email".to_string(), "0".to_string()),
+                ("ttjr/today/work".to_string(), "20".to_string()),
+            ],
+            state(&categories, Some(&today[1]), &today, 1600)
+        );
+        assert_eq!("Not timing", state(&categories, None, &today, 1600)[0].1);
+
+        assert_eq!(
+            Some(Command::Start("email".to_string())),
+            command(CATEGORY_SET, b"email")
+        );
+        assert_eq!(Some(Command::Stop), command(CATEGORY_SET, b"Not timing"));
+        assert_eq!(Some(Command::Stop), command(STOP, b"PRESS"));
+        assert_eq!(None, command("ttjr/other", b""));
+    }
+}
@@ -0,0 +1,107 @@
+/*
+This file is part of Timetrack Jr.
+Timetrack Jr. is free software: you can redistribute it and/or modify it under the terms of the GNU General Public License as published by the Free Software Foundation, either version 3 of the License, or (at your option) any later version.
+Timetrack Jr. is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU General Public License for more details.
+You should have received a copy of the GNU General Public License along with Timetrack Jr. If not, see <https://www.gnu.org/licenses/>.
+*/
+use crate::{commands::suggest::git, TTError};
+use std::{fs, path::Path};
+
+///Marks hooks written by ttjr, so they can be replaced or removed without touching anyone else's hooks
+const MARKER: &str = "# Installed by `ttjr hook install`";
+const HOOKS: [&str; 2] = ["post-checkout", "post-commit"];
+
+///Quote a string for sh
+fn quote(s: &str) -> String {
+    format!("'{}'", s.replace('\'', "'\\''"))
+}
+
+///A hook which annotates the running time with the repo and branch.  Paths to ttjr and the DB are baked in since
+/// hooks run in the repo with whatever PATH git has.
+fn hook_script(hook: &str, ttjr: &str, db_path: &str) -> String {
+    //post-checkout also runs for file checkouts, which don't change the branch
+    let guard = match hook {
+        "post-checkout" => "[ \"$3\" = \"1\" ] || exit 0\n",
+        _ => "",
+    };
+    format!(
+        "#!/bin/sh\n{}\n{}{} --db-path {} annotate \"git: $(basename \"$(git rev-parse --show-toplevel)\") on $(git rev-parse --abbrev-ref HEAD)\" >/dev/null 2>&1 || true\n",
+        MARKER,
+        guard,
+        quote(ttjr),
+        quote(db_path)
+    )
+}
+
+fn hooks_dir(repo: &str) -> Result<String, TTError> {
+    let hooks =
+        git(repo, &["rev-parse", "--git-path", "hooks"]).ok_or(TTError::InvalidArgument {
+            message: format!("{} isn't a git repo", repo),
+        })?;
+    Ok(Path::new(repo).join(hooks).to_string_lossy().to_string())
+}
+
+fn is_ours(path: &Path) -> bool {
+    fs::read_to_string(path).is_ok_and(|s| s.contains(MARKER))
+}
+
+///Install post-checkout and post-commit hooks in `repo` that annotate the running time with the repo and branch.
+/// Existing hooks that weren't installed by ttjr are only replaced with `force`.
+pub fn install(repo: &str, db_path: &str, force: &bool) -> Result<(), TTError> {
+    let dir = hooks_dir(repo)?;
+    fs::create_dir_all(&dir)?;
+    let ttjr = std::env::current_exe()?;
+    let db_path = fs::canonicalize(db_path)?;
+    for hook in HOOKS {
+        let path = Path::new(&dir).join(hook);
+        if path.exists() && !is_ours(&path) && !force {
+            return Err(TTError::InvalidArgument {
+                message: format!(
+                    "{} already exists, use --force to replace it",
+                    path.display()
+                ),
+            });
+        }
+    }
+    for hook in HOOKS {
+        let path = Path::new(&dir).join(hook);
+        fs::write(
+            &path,
+            hook_script(hook, &ttjr.to_string_lossy(), &db_path.to_string_lossy()),
+        )?;
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            fs::set_permissions(&path, fs::Permissions::from_mode(0o755))?;
+        }
+        println!("Installed {}", path.display());
+    }
+    Ok(())
+}
+
+///Remove hooks installed by `install`
+pub fn uninstall(repo: &str) -> Result<(), TTError> {
+    let dir = hooks_dir(repo)?;
+    for hook in HOOKS {
+        let path = Path::new(&dir).join(hook);
+        if is_ours(&path) {
+            fs::remove_file(&path)?;
+            println!("Removed {}", path.display());
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_hook_script() {
+        let script = hook_script("post-checkout", "/opt/ttjr", "/home/me/it's.sqlite3");
+        assert!(script.starts_with("#!/bin/sh\n"));
+        assert!(script.contains("[ \"$3\" = \"1\" ] || exit 0"));
+        assert!(script.contains("'/opt/ttjr' --db-path '/home/me/it'\\''s.sqlite3' annotate"));
+        assert!(!hook_script("post-commit", "/opt/ttjr", "/db").contains("exit 0"));
+    }
+}
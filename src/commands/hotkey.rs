@@ -0,0 +1,287 @@
+/*
+This file is part of Timetrack Jr.
+Timetrack Jr. is free software: you can redistribute it and/or modify it under the terms of the GNU General Public License as published by the Free Software Foundation, either version 3 of the License, or (at your option) any later version.
+Timetrack Jr. is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU General Public License for more details.
+You should have received a copy of the GNU General Public License along with Timetrack Jr. If not, see <https://www.gnu.org/licenses/>.
+*/
+use crate::{
+    cli::HotkeyAction,
+    commands::{export, log},
+    db::{self, Hotkey},
+    keyboard::{self, Keypress},
+    TTError,
+};
+use clap::ValueEnum;
+use rusqlite::{Connection, Transaction};
+
+fn action_name(action: &HotkeyAction) -> String {
+    action.to_possible_value().unwrap().get_name().to_string()
+}
+
+///Check that `category` is given for (and only for) the start action, and exists
+fn check_category(
+    tx: &Transaction,
+    action: &HotkeyAction,
+    category: &Option<String>,
+) -> Result<(), TTError> {
+    match (action, category) {
+        (HotkeyAction::Start, None) => {
+            return Err(TTError::InvalidArgument {
+                message: "The start action needs a category to start (i.e. `ttjr set-hotkey ctrl+alt+w start work`)".to_string(),
+            })
+        }
+        (HotkeyAction::Start, Some(category)) if !db::get_categories(tx)?.contains(category) => {
+            return Err(TTError::CategoryNotFound {
+ category: category.to_string(),
+ })
+        }
+        (HotkeyAction::Start, Some(_)) => {}
+        (_, Some(_)) => {
+            return Err(TTError::InvalidArgument {
+                message: format!(
+                    "Only the start action takes a category, not {}",
+                    action_name(action)
+                ),
+            })
+        }
+        (_, None) => {}
+    }
+    Ok(())
+}
+
+pub fn set_hotkey(
+    conn: &mut Connection,
+    keypress: &str,
+    action: &HotkeyAction,
+    category: &Option<String>,
+) -> Result<(), TTError> {
+    let keypress: Keypress = keypress.parse()?;
+    db::write(conn, |tx| {
+        check_category(tx, action, category)?;
+        db::set_hotkey(
+            tx,
+            &Hotkey {
+                keypress: keypress.to_string(),
+                action: action_name(action),
+                category: category.clone(),
+            },
+        )
+    })
+}
+
+///Bind whatever key combination is pressed next in the terminal
+pub fn record_hotkey(
+    conn: &mut Connection,
+    action: &HotkeyAction,
+    category: &Option<String>,
+) -> Result<(), TTError> {
+    check_category(&conn.transaction()?, action, category)?;
+    println!(
+        "Press a key combination to {} (or escape to cancel)",
+        action_name(action)
+    );
+    match keyboard::get_keypress()? {
+        Some(keypress) => {
+            set_hotkey(conn, &keypress.to_string(), action, category)?;
+            let hotkey = Hotkey {
+                keypress: keypress.to_string(),
+                action: action_name(action),
+                category: category.clone(),
+            };
+            println!("Bound {} to {}", keypress, hotkey.description());
+        }
+        None => println!("Cancelled"),
+    }
+    Ok(())
+}
+
+pub fn unset_hotkey(conn: &mut Connection, keypress: &str) -> Result<(), TTError> {
+    let keypress: Keypress = keypress.parse()?;
+    match db::write(conn, |tx| db::delete_hotkey(tx, &keypress.to_string()))? {
+        true => Ok(()),
+        false => Err(TTError::HotkeyNotBound {
+            keypress: keypress.to_string(),
+        }),
+    }
+}
+
+///Do what a hotkey's bound to, with a notification to show it happened
+fn perform(conn: &mut Connection, hotkey: &Hotkey) -> Result<(), TTError> {
+    match (
+        HotkeyAction::from_str(&hotkey.action, true),
+        &hotkey.category,
+    ) {
+        (Ok(HotkeyAction::Start), Some(category)) => {
+            log::start_timing(conn, category, &None, &true)
+        }
+        (Ok(HotkeyAction::Stop), _) => log::stop_timing(conn, &true),
+        (Ok(HotkeyAction::Resume), _) => {
+            let tx = conn.transaction()?;
+            let last = match db::get_last_open_time(&tx)? {
+                Some(_) => None,
+                None => db::get_last_closed_time(&tx)?,
+            };
+            tx.commit()?;
+            match last {
+                Some(last) => log::start_timing(conn, &last.category, &None, &true),
+                //already timing something (or nothing's been timed yet)
+                None => export::currently_timing(conn, &true, &false),
+            }
+        }
+        (Ok(HotkeyAction::CurrentlyTiming), _) => export::currently_timing(conn, &true, &false),
+        _ => Err(TTError::InvalidArgument {
+            message: format!("{} is bound to an unknown action", hotkey.keypress),
+        }),
+    }
+}
+
+///Parse every binding's key combination, failing with everything that's wrong: combinations that don't parse, bad
+/// actions, and conflicts (combinations written differently that are really the same)
+fn validate(hotkeys: &[Hotkey]) -> Result<Vec<Keypress>, TTError> {
+    let mut problems = vec![];
+    let mut keypresses: Vec<Keypress> = vec![];
+    for hotkey in hotkeys {
+        match (
+            HotkeyAction::from_str(&hotkey.action, true),
+            &hotkey.category,
+        ) {
+            (Ok(HotkeyAction::Start), None) => problems.push(format!(
+                "{} starts timing without a category",
+                hotkey.keypress
+            )),
+            (Ok(_), _) => {}
+            (Err(_), _) => problems.push(format!(
+                "{} is bound to an unknown action, {}",
+                hotkey.keypress, hotkey.action
+            )),
+        }
+        match hotkey.keypress.parse::<Keypress>() {
+            Ok(keypress) => {
+                if let Some(i) = keypresses.iter().position(|k| *k == keypress) {
+                    problems.push(format!(
+                        "{} and {} are the same key combination",
+                        hotkeys[i].keypress, hotkey.keypress
+                    ));
+                }
+                keypresses.push(keypress);
+            }
+            Err(e @ TTError::InvalidArgument { .. }) => problems.push(e.to_string()),
+            Err(e) => return Err(e),
+        }
+    }
+    match problems.is_empty() {
+        true => Ok(keypresses),
+        false => Err(TTError::InvalidArgument {
+            message: format!(
+                "Some hotkeys need fixing (with `ttjr set-hotkey` or `ttjr unset-hotkey`):\n{}",
+                problems.join("\n")
+            ),
+        }),
+    }
+}
+
+///Print each key combination and what it does, failing if any of them need fixing
+pub fn list_hotkeys(conn: &mut Connection) -> Result<(), TTError> {
+    let hotkeys = db::get_hotkeys(&conn.transaction()?)?;
+    for hotkey in &hotkeys {
+        println!("{}: {}", hotkey.keypress, hotkey.description());
+    }
+    validate(&hotkeys)?;
+    Ok(())
+}
+
+///Listen for the hotkeys from `set-hotkey` until something fails.  Failed actions are reported and listening
+/// carries on.
+pub(crate) fn listen(conn: &mut Connection) -> Result<(), TTError> {
+    let hotkeys = db::get_hotkeys(&conn.transaction()?)?;
+    if hotkeys.is_empty() {
+        return Err(TTError::NotConfigured {
+            message: "No hotkeys are set, add one with `ttjr set-hotkey`".to_string(),
+        });
+    }
+    let keypresses = validate(&hotkeys)?;
+    keyboard::listen(&keypresses, |pressed| {
+        match perform(conn, &hotkeys[pressed]) {
+            Err(e) if e.recoverable() => {
+                eprintln!("{} failed: {}", hotkeys[pressed].keypress, e)
+            }
+            result => result?,
+        }
+        Ok(())
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn test_db() -> Connection {
+        let mut conn = Connection::open_in_memory().unwrap();
+        db::initialize_db(&mut conn).unwrap();
+        let tx = conn.transaction().unwrap();
+        db::add_category(&tx, &"work".to_string()).unwrap();
+        tx.commit().unwrap();
+        conn
+    }
+
+    #[test]
+    fn test_set_hotkey() {
+        let mut conn = test_db();
+        let work = Some("work".to_string());
+        set_hotkey(&mut conn, "Alt+Ctrl+W", &HotkeyAction::Start, &work).unwrap();
+        set_hotkey(&mut conn, "ctrl+alt+s", &HotkeyAction::Stop, &None).unwrap();
+        assert!(set_hotkey(&mut conn, "ctrl+alt+x", &HotkeyAction::Start, &None).is_err());
+        assert!(set_hotkey(&mut conn, "ctrl+alt+x", &HotkeyAction::Stop, &work).is_err());
+        let nope = Some("nope".to_string());
+        assert!(set_hotkey(&mut conn, "ctrl+alt+x", &HotkeyAction::Start, &nope).is_err());
+        assert_eq!(
+            vec![
+                Hotkey {
+                    keypress: "ctrl+alt+s".to_string(),
+                    action: "stop".to_string(),
+                    category: None
+                },
+                Hotkey {
+                    keypress: "ctrl+alt+w".to_string(),
+                    action: "start".to_string(),
+                    category: work
+                },
+            ],
+            db::get_hotkeys(&conn.transaction().unwrap()).unwrap()
+        );
+        unset_hotkey(&mut conn, "ctrl+ALT+w").unwrap();
+        assert!(unset_hotkey(&mut conn, "ctrl+alt+w").is_err());
+        assert_eq!(
+            1,
+            db::get_hotkeys(&conn.transaction().unwrap()).unwrap().len()
+        );
+    }
+
+    #[test]
+    fn test_validate() {
+        let hotkey = |keypress: &str, action: &str, category: Option<&str>| Hotkey {
+            keypress: keypress.to_string(),
+            action: action.to_string(),
+            category: category.map(|c| c.to_string()),
+        };
+        let hotkeys = vec![
+            hotkey("ctrl+alt+w", "start", Some("work")),
+            hotkey("ctrl+alt+s", "stop", None),
+        ];
+        assert_eq!(2, validate(&hotkeys).unwrap().len());
+        let hotkeys = vec![
+            hotkey("ctrl+alt+w", "start", Some("work")),
+            hotkey("Alt+Control+KeyW", "stop", None),
+            hotkey("ctrl+alt+x", "start", None),
+            hotkey("ctrl+alt+y", "dance", None),
+            hotkey("ctrl+alt+", "stop", None),
+        ];
+        match validate(&hotkeys) {
+            Err(TTError::InvalidArgument { message }) => {
+                assert_eq!(5, message.lines().count(), "{}", message);
+                assert!(message.contains("ctrl+alt+w and Alt+Control+KeyW are the same"));
+            }
+            result => panic!("expected problems, got {:?}", result),
+        }
+    }
+}
@@ -0,0 +1,129 @@
+/*
+This file is part of Timetrack Jr.
+Timetrack Jr. is free software: you can redistribute it and/or modify it under the terms of the GNU General Public License as published by the Free Software Foundation, either version 3 of the License, or (at your option) any later version.
+Timetrack Jr. is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU General Public License for more details.
+You should have received a copy of the GNU General Public License along with Timetrack Jr. If not, see <https://www.gnu.org/licenses/>.
+*/
+//How long since the last keyboard or mouse input.  Linux asks xprintidle (X11) or GNOME's idle monitor (wayland),
+// macOS reads HIDIdleTime from ioreg, and windows calls GetLastInputInfo.
+use crate::TTError;
+#[cfg(not(windows))]
+use std::process::Command;
+
+#[cfg(not(windows))]
+fn run(program: &str, args: &[&str]) -> Option<String> {
+    let output = Command::new(program).args(args).output().ok()?;
+    match output.status.success() {
+        true => Some(String::from_utf8_lossy(&output.stdout).trim().to_string()),
+        false => None,
+    }
+}
+
+///Milliseconds from `gdbus call ... GetIdletime`, which looks like "(uint64 12345,)"
+#[cfg_attr(not(all(unix, not(target_os = "macos"))), allow(dead_code))]
+fn parse_mutter_idletime(output: &str) -> Option<u64> {
+    output
+        .trim_start_matches('(')
+        .trim_end_matches(')')
+        .trim_end_matches(',')
+        .trim_start_matches("uint64")
+        .trim()
+        .parse()
+        .ok()
+}
+
+///Nanoseconds from the `"HIDIdleTime" = 123456789` line of `ioreg -c IOHIDSystem`
+#[cfg_attr(not(target_os = "macos"), allow(dead_code))]
+fn parse_hid_idle_time(output: &str) -> Option<u64> {
+    output
+        .lines()
+        .find(|line| line.contains("\"HIDIdleTime\""))?
+        .split('=')
+        .nth(1)?
+        .trim()
+        .parse()
+        .ok()
+}
+
+fn unknown() -> TTError {
+    TTError::System {
+        message: "Couldn't tell how long you've been idle (on linux, install xprintidle)"
+            .to_string(),
+    }
+}
+
+///Seconds since the last keyboard or mouse input
+#[cfg(all(unix, not(target_os = "macos")))]
+pub(crate) fn idle_seconds() -> Result<i64, TTError> {
+    let millis = run("xprintidle", &[])
+        .and_then(|ms| ms.parse::<u64>().ok())
+        .or_else(|| {
+            run(
+                "gdbus",
+                &[
+                    "call",
+                    "--session",
+                    "--dest",
+                    "org.gnome.Mutter.IdleMonitor",
+                    "--object-path",
+                    "/org/gnome/Mutter/IdleMonitor/Core",
+                    "--method",
+                    "org.gnome.Mutter.IdleMonitor.GetIdletime",
+                ],
+            )
+            .and_then(|output| parse_mutter_idletime(&output))
+        });
+    millis.map(|ms| (ms / 1000) as i64).ok_or_else(unknown)
+}
+
+///Seconds since the last keyboard or mouse input
+#[cfg(target_os = "macos")]
+pub(crate) fn idle_seconds() -> Result<i64, TTError> {
+    run("ioreg", &["-c", "IOHIDSystem", "-d", "4"])
+        .and_then(|output| parse_hid_idle_time(&output))
+        .map(|ns| (ns / 1_000_000_000) as i64)
+        .ok_or_else(unknown)
+}
+
+///Seconds since the last keyboard or mouse input
+#[cfg(windows)]
+pub(crate) fn idle_seconds() -> Result<i64, TTError> {
+    #[repr(C)]
+    struct LastInputInfo {
+        cb_size: u32,
+        dw_time: u32,
+    }
+    #[link(name = "user32")]
+    extern "system" {
+        fn GetLastInputInfo(plii: *mut LastInputInfo) -> i32;
+    }
+    #[link(name = "kernel32")]
+    extern "system" {
+        fn GetTickCount() -> u32;
+    }
+    let mut info = LastInputInfo {
+        cb_size: std::mem::size_of::<LastInputInfo>() as u32,
+        dw_time: 0,
+    };
+    //both are plain calls filling in (or returning) tick counts
+    let (ok, now) = unsafe { (GetLastInputInfo(&mut info), GetTickCount()) };
+    match ok {
+        0 => Err(unknown()),
+        //tick counts wrap after ~49 days
+        _ => Ok((now.wrapping_sub(info.dw_time) / 1000) as i64),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_idle_times() {
+        assert_eq!(Some(12345), parse_mutter_idletime("(uint64 12345,)"));
+        assert_eq!(None, parse_mutter_idletime("Error: nope"));
+        let ioreg = "  | |   \"HIDIdleLoadTime\" = 1\n  | |   \"HIDIdleTime\" = 61000000000\n";
+        assert_eq!(Some(61_000_000_000), parse_hid_idle_time(ioreg));
+        assert_eq!(None, parse_hid_idle_time("nothing here"));
+    }
+}
@@ -0,0 +1,177 @@
+/*
+This file is part of Timetrack Jr.
+Timetrack Jr. is free software: you can redistribute it and/or modify it under the terms of the GNU General Public License as published by the Free Software Foundation, either version 3 of the License, or (at your option) any later version.
+Timetrack Jr. is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU General Public License for more details.
+You should have received a copy of the GNU General Public License along with Timetrack Jr. If not, see <https://www.gnu.org/licenses/>.
+*/
+//Noticing the session being locked and unlocked.  Linux listens for the screensaver's ActiveChanged signal on the
+// session bus (through dbus-monitor, so it works without the dbus feature), macOS checks whether the screen is
+// locked every few seconds.  Either way, events are timestamped when they happen, so the daemon can deal with them
+// on its next check.
+use crate::TTError;
+use chrono::Utc;
+#[cfg(all(unix, not(target_os = "macos")))]
+use std::io::{BufRead, BufReader};
+use std::sync::mpsc::Receiver;
+#[cfg(not(windows))]
+use std::{
+    process::{Command, Stdio},
+    sync::mpsc,
+    thread,
+};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct LockEvent {
+    pub locked: bool,
+    pub at: i64,
+}
+
+///Read a line of `dbus-monitor` output.  A signal line sets `signal_at` (to the time it was sent), and the boolean
+/// argument on the line after it is the event.
+#[cfg_attr(not(all(unix, not(target_os = "macos"))), allow(dead_code))]
+fn parse_monitor_line(line: &str, signal_at: &mut Option<i64>) -> Option<LockEvent> {
+    if line.starts_with("signal ") {
+        *signal_at = match line.contains("member=ActiveChanged") {
+            true => Some(
+                line.split_whitespace()
+                    .find_map(|field| field.strip_prefix("time="))
+                    .and_then(|time| time.parse::<f64>().ok())
+                    .map(|time| time as i64)
+                    .unwrap_or(Utc::now().timestamp()),
+            ),
+            false => None,
+        };
+        return None;
+    }
+    let at = signal_at.take()?;
+    match line.trim() {
+        "boolean true" => Some(LockEvent { locked: true, at }),
+        "boolean false" => Some(LockEvent { locked: false, at }),
+        _ => None,
+    }
+}
+
+///Whether `ioreg -n Root -d 1` says the screen is locked
+#[cfg_attr(not(target_os = "macos"), allow(dead_code))]
+fn parse_screen_locked(output: &str) -> bool {
+    output
+        .lines()
+        .any(|line| line.contains("CGSSessionScreenIsLocked") && line.contains("Yes"))
+}
+
+fn unsupported(e: impl std::fmt::Display) -> TTError {
+    TTError::System {
+        message: format!("Couldn't watch for the screen locking: {}", e),
+    }
+}
+
+///Lock and unlock events, as they happen
+#[cfg(all(unix, not(target_os = "macos")))]
+pub(crate) fn watch() -> Result<Receiver<LockEvent>, TTError> {
+    let mut monitor = Command::new("dbus-monitor")
+        .args(["--session", "type='signal',member='ActiveChanged'"])
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn()
+        .map_err(unsupported)?;
+    let stdout = monitor.stdout.take().unwrap();
+    let (sender, receiver) = mpsc::channel();
+    thread::spawn(move || {
+        let mut signal_at = None;
+        for line in BufReader::new(stdout).lines().map_while(Result::ok) {
+            if let Some(event) = parse_monitor_line(&line, &mut signal_at) {
+                if sender.send(event).is_err() {
+                    break;
+                }
+            }
+        }
+        let _ = monitor.kill();
+    });
+    Ok(receiver)
+}
+
+///Lock and unlock events, as they happen (to within a few seconds)
+#[cfg(target_os = "macos")]
+pub(crate) fn watch() -> Result<Receiver<LockEvent>, TTError> {
+    use std::time::Duration;
+    let locked = || -> Result<bool, TTError> {
+        let output = Command::new("ioreg")
+            .args(["-n", "Root", "-d", "1"])
+            .stderr(Stdio::null())
+            .output()
+            .map_err(unsupported)?;
+        Ok(parse_screen_locked(&String::from_utf8_lossy(
+            &output.stdout,
+        )))
+    };
+    let mut was_locked = locked()?;
+    let (sender, receiver) = mpsc::channel();
+    thread::spawn(move || loop {
+        thread::sleep(Duration::from_secs(5));
+        let is_locked = match locked() {
+            Ok(is_locked) => is_locked,
+            Err(_) => break,
+        };
+        if is_locked != was_locked {
+            was_locked = is_locked;
+            let event = LockEvent {
+                locked: is_locked,
+                at: Utc::now().timestamp(),
+            };
+            if sender.send(event).is_err() {
+                break;
+            }
+        }
+    });
+    Ok(receiver)
+}
+
+///Lock and unlock events (not supported on windows yet)
+#[cfg(windows)]
+pub(crate) fn watch() -> Result<Receiver<LockEvent>, TTError> {
+    Err(unsupported("not supported on windows"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_monitor_line() {
+        let output = "signal time=1700000000.123456 sender=org.freedesktop.DBus -> destination=:1.99 serial=2 path=/org/freedesktop/DBus; interface=org.freedesktop.DBus; member=NameAcquired
+   string \":1.99\"
+signal time=1700000100.5 sender=:1.23 -> destination=(null destination) serial=7 path=/org/gnome/ScreenSaver; interface=org.gnome.ScreenSaver; member=ActiveChanged
+   boolean true
+signal time=1700000200.5 sender=:1.23 -> destination=(null destination) serial=8 path=/org/gnome/ScreenSaver; interface=org.gnome.ScreenSaver; member=ActiveChanged
+   boolean false
+   boolean true";
+        let mut signal_at = None;
+        let events: Vec<LockEvent> = output
+            .lines()
+            .filter_map(|line| parse_monitor_line(line, &mut signal_at))
+            .collect();
+        assert_eq!(
+            vec![
+                LockEvent {
+                    locked: true,
+                    at: 1700000100
+                },
+                LockEvent {
+                    locked: false,
+                    at: 1700000200
+                }
+            ],
+            events
+        );
+    }
+
+    #[test]
+    fn test_parse_screen_locked() {
+        assert!(parse_screen_locked(
+            "  | {\n  |   \"CGSSessionScreenIsLocked\" = Yes\n  | }"
+        ));
+        assert!(!parse_screen_locked(
+            "  | {\n  |   \"IOKitBuildVersion\" = \"Darwin\"\n  | }"
+        ));
+    }
+}
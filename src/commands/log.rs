@@ -6,152 +6,934 @@ Timetrack Jr. is distributed in the hope that it will be useful, but WITHOUT ANY
 You should have received a copy of the GNU General Public License along with Timetrack Jr. If not, see <https://www.gnu.org/licenses/>.
 */
 use crate::{
-    cli,
+    cli::{self, NotificationEvent},
+    commands::{self, notify, report, webhook},
     db::{self, TimeWindow},
+    l10n,
+    zone::Zone,
     TTError,
 };
-use notify_rust::{Notification, Timeout};
 use rusqlite::{Connection, Transaction};
+use serde_json::json;
 
-fn stop_timing_private(tx: &mut Transaction, notify: &bool) -> Result<(), TTError> {
-    let opts = db::get_options(&tx)?;
+///How many of the times a --dry-run would change are shown
+pub(crate) const DRY_RUN_SAMPLE: usize = 5;
+
+///One line about a time, for --dry-run output
+pub(crate) fn describe(time: &TimeWindow) -> String {
+    let zone = Zone::Local;
+    format!(
+        "{}: {} - {} {}{}",
+        time.id.unwrap_or(-1),
+        zone.format(&time.start_time, "%Y-%m-%d %H:%M"),
+        time.end_time
+            .map(|end| zone.format(&end, "%Y-%m-%d %H:%M"))
+            .unwrap_or("now".to_string()),
+        time.category,
+        time.note
+            .as_ref()
+            .map_or("".to_string(), |note| format!(" ({})", note))
+    )
+}
+
+///Print a few of the times a --dry-run would change
+pub(crate) fn print_sample(times: &[TimeWindow]) {
+    for time in times.iter().take(DRY_RUN_SAMPLE) {
+        println!("  {}", describe(time));
+    }
+    if times.len() > DRY_RUN_SAMPLE {
+        println!("  ...and {} more", times.len() - DRY_RUN_SAMPLE);
+    }
+}
+
+fn stop_timing_private(tx: &mut Transaction, _notify: &bool) -> Result<(), TTError> {
+    let open = db::get_last_open_time(tx)?;
+    let opts = db::get_options(tx)?;
     let mut done = false;
     if let Some(end) = opts.get("end-of-day") {
-        if let Ok(end) = db::parse_time(end) {
-            db::end_open_times(tx, end)?;
-            done = true;
+        match db::parse_time(end) {
+            Ok(end) => {
+                db::end_open_times(tx, end)?;
+                done = true;
+            }
+            Err(e) => tracing::debug!("ignoring the end-of-day option: {}", e),
         }
     }
     if !done {
+        tracing::debug!("ending open times now");
         db::end_open_times_immediately(tx)?;
     }
+    if let Some(TimeWindow { id: Some(id), .. }) = open {
+        webhook::enqueue(tx, "stop", json!({ "time": db::get_time(tx, id)? }))?;
+    }
+    Ok(())
+}
+
+///Tell integrations (like Slack) what's being timed now.  Their failures are only warnings, since timing has already changed.
+#[cfg_attr(not(feature = "sync"), allow(unused_variables))]
+fn update_integrations(conn: &mut Connection, category: Option<&str>) -> Result<(), TTError> {
+    #[cfg(feature = "sync")]
+    match commands::slack::update_status(conn, category) {
+        Err(e) if e.recoverable() => eprintln!("Couldn't update your Slack status: {}", e),
+        Err(e) => return Err(e),
+        Ok(_) => {}
+    }
+    Ok(())
+}
+
+fn notify_stopped(conn: &mut Connection, time: &TimeWindow) -> Result<(), TTError> {
+    //the time as it was stopped
+    let time = match time.id {
+        Some(id) => db::get_time(&conn.transaction()?, id)?,
+        None => time.clone(),
+    };
+    let notification = notify::from_template(
+        conn,
+        &NotificationEvent::Stopped,
+        &l10n::template("stopped-notification"),
+        Some(&time),
+    )?;
+    notify::show(conn, &NotificationEvent::Stopped, &notification)?;
     Ok(())
 }
 
 pub fn start_timing(
     conn: &mut Connection,
     category_name: &String,
+    note: &Option<String>,
     notify: &bool,
 ) -> Result<(), TTError> {
-    let mut tx = conn.transaction()?;
-    let categories = db::get_categories(&mut tx)?;
-    if !categories.contains(category_name) {
-        return Err(TTError::TTError { message: format!("Category '{}' does not exist in the timetrack jr database, use `ttjr add-category` to add it", category_name) });
-    }
-    let mut last_open: Option<TimeWindow> = None;
-    if *notify {
-        last_open = db::get_last_open_time(&mut tx)?;
-    }
-    stop_timing_private(&mut tx, notify)?;
-    db::start_timing(&mut tx, category_name)?;
-    tx.commit()?;
+    let last_open = db::write(conn, |tx| {
+        let categories = db::get_categories(tx)?;
+        if !categories.contains(category_name) {
+            return Err(TTError::CategoryNotFound {
+                category: category_name.to_string(),
+            });
+        }
+        let last_open = db::get_last_open_time(tx)?;
+        stop_timing_private(tx, notify)?;
+        db::start_timing(tx, category_name, note)?;
+        webhook::enqueue(tx, "start", json!({ "time": db::get_last_open_time(tx)? }))?;
+        Ok(last_open)
+    })?;
+    update_integrations(conn, Some(category_name))?;
 
     if *notify {
         if let Some(time) = &last_open {
-            Notification::new()
-                .summary(&format!("Stopped: {}", time.category))
-                .appname("Timetrack Jr.")
-                .show()?;
+            notify_stopped(conn, time)?;
         }
-        Notification::new()
-            .summary(&format!("Started: {}", category_name))
-            .appname("Timetrack Jr.")
-            .show()?;
+        let started = db::get_last_open_time(&conn.transaction()?)?;
+        let mut notification = notify::from_template(
+            conn,
+            &NotificationEvent::Started,
+            &l10n::template("started-notification"),
+            started.as_ref(),
+        )?;
+        notify::show_timing(conn, &NotificationEvent::Started, &mut notification)?;
     }
 
     return Ok(());
 }
 
-pub fn stop_timing(conn: &mut Connection, notify: &bool) -> Result<(), TTError> {
-    let mut tx = conn.transaction()?;
-    let mut last_open: Option<TimeWindow> = None;
-    if *notify {
-        last_open = db::get_last_open_time(&mut tx)?;
+///`ttjr start-timing`, printing the started time (and the time it stopped, if any) with --json
+pub fn start(
+    conn: &mut Connection,
+    category_name: &String,
+    note: &Option<String>,
+    notify: &bool,
+    json: &bool,
+) -> Result<(), TTError> {
+    let open = db::get_last_open_time(&conn.transaction()?)?;
+    start_timing(conn, category_name, note, notify)?;
+    if *json {
+        let tx = conn.transaction()?;
+        let stopped = match open.and_then(|time| time.id) {
+            Some(id) => Some(db::get_time(&tx, id)?),
+            None => None,
+        };
+        report::print_json(&json!({
+            "started": db::get_last_open_time(&tx)?,
+            "stopped": stopped,
+        }))?;
+    }
+    Ok(())
+}
+
+///`ttjr stop-timing`, printing the stopped time (or null) with --json
+pub fn stop(conn: &mut Connection, notify: &bool, json: &bool) -> Result<(), TTError> {
+    let open = db::get_last_open_time(&conn.transaction()?)?;
+    stop_timing(conn, notify)?;
+    if *json {
+        let stopped = match open.and_then(|time| time.id) {
+            Some(id) => Some(db::get_time(&conn.transaction()?, id)?),
+            None => None,
+        };
+        report::print_json(&json!({ "stopped": stopped }))?;
     }
-    stop_timing_private(&mut tx, notify)?;
-    tx.commit()?;
+    Ok(())
+}
+
+pub fn stop_timing(conn: &mut Connection, notify: &bool) -> Result<(), TTError> {
+    let last_open = db::write(conn, |tx| {
+        let last_open = db::get_last_open_time(tx)?;
+        stop_timing_private(tx, notify)?;
+        Ok(last_open)
+    })?;
+    update_integrations(conn, None)?;
     if *notify {
         if let Some(time) = &last_open {
-            Notification::new()
-                .summary(&format!("Stopped: {}", time.category))
-                .appname("Timetrack Jr.")
-                .show()?;
+            notify_stopped(conn, time)?;
         }
     }
     return Ok(());
 }
 
+///What to do with time spent away from the computer while timing something
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum IdleChoice {
+    Keep,
+    ///End the time when idleness began
+    Stop,
+    ///Log the time away to this category, and carry on timing what was being timed
+    Break(String),
+    ///Leave the time away out, and carry on timing what was being timed (when timing was already stopped)
+    Resume,
+}
+
+///End what's being timed at `at` if it was started before then, returning it as it was
+fn end_open_time_at(tx: &mut Transaction, at: i64) -> Result<Option<TimeWindow>, TTError> {
+    let open = match db::get_last_open_time(tx)? {
+        Some(open) if open.start_time < at => open,
+        _ => return Ok(None),
+    };
+    db::upsert_time(
+        tx,
+        TimeWindow {
+            end_time: Some(at),
+            ..open.clone()
+        },
+        &false,
+    )?;
+    webhook::enqueue(
+        tx,
+        "stop",
+        json!({ "time": db::get_time(tx, open.id.unwrap())? }),
+    )?;
+    Ok(Some(open))
+}
+
+fn start_timing_at_private(tx: &mut Transaction, category: &str, at: i64) -> Result<(), TTError> {
+    db::upsert_time(
+        tx,
+        TimeWindow {
+            id: None,
+            category: category.to_string(),
+            start_time: at,
+            end_time: None,
+            note: None,
+        },
+        &false,
+    )?;
+    webhook::enqueue(tx, "start", json!({ "time": db::get_last_open_time(tx)? }))
+}
+
+///Stop timing as of `at` (rather than now), returning what was being timed
+pub(crate) fn stop_timing_at(
+    conn: &mut Connection,
+    at: i64,
+) -> Result<Option<TimeWindow>, TTError> {
+    let stopped = db::write(conn, |tx| end_open_time_at(tx, at))?;
+    if stopped.is_some() {
+        update_integrations(conn, None)?;
+    }
+    Ok(stopped)
+}
+
+///Start timing as of `at` (rather than now), if nothing's being timed
+pub(crate) fn start_timing_at(
+    conn: &mut Connection,
+    category: &str,
+    at: i64,
+) -> Result<(), TTError> {
+    let started = db::write(conn, |tx| {
+        if db::get_last_open_time(tx)?.is_some() {
+            return Ok(false);
+        }
+        start_timing_at_private(tx, category, at)?;
+        Ok(true)
+    })?;
+    match started {
+        true => update_integrations(conn, Some(category)),
+        false => Ok(()),
+    }
+}
+
+///Apply `choice` to whatever was being timed since before `idle_start`, for time away from `idle_start` to `returned`
+pub(crate) fn resolve_idle(
+    conn: &mut Connection,
+    idle_start: i64,
+    returned: i64,
+    choice: &IdleChoice,
+) -> Result<(), TTError> {
+    if *choice == IdleChoice::Keep {
+        return Ok(());
+    }
+    let stopped = db::write(conn, |tx| {
+        let open = match end_open_time_at(tx, idle_start)? {
+            Some(open) => open,
+            None => return Ok(None),
+        };
+        Ok(Some(match choice {
+            IdleChoice::Break(category) => {
+                log_break(tx, category, &open.category, idle_start, returned)?;
+                Some(open.category)
+            }
+            _ => None,
+        }))
+    })?;
+    match stopped {
+        Some(timing) => update_integrations(conn, timing.as_deref()),
+        None => Ok(()),
+    }
+}
+
+///Log the time away from `idle_start` to `returned` as a break, then start timing `category` again
+fn log_break(
+    tx: &mut Transaction,
+    break_category: &str,
+    category: &str,
+    idle_start: i64,
+    returned: i64,
+) -> Result<(), TTError> {
+    //times can't share an endpoint, hence the extra seconds
+    let returned = returned.max(idle_start + 1);
+    db::upsert_time(
+        tx,
+        TimeWindow {
+            id: None,
+            category: break_category.to_string(),
+            start_time: idle_start + 1,
+            end_time: Some(returned),
+            note: None,
+        },
+        &false,
+    )?;
+    start_timing_at_private(tx, category, returned + 1)
+}
+
+///Do what was chosen with time away from the computer after `stopped` was stopped when you went idle.  Nothing
+/// changes if something's been started since.
+pub(crate) fn resolve_idle_stopped(
+    conn: &mut Connection,
+    stopped: &TimeWindow,
+    idle_start: i64,
+    returned: i64,
+    choice: &IdleChoice,
+) -> Result<(), TTError> {
+    let id = match stopped.id {
+        Some(id) => id,
+        None => return Ok(()),
+    };
+    let restarted = db::write(conn, |tx| {
+        if db::get_last_open_time(tx)?.is_some() {
+            return Ok(false);
+        }
+        match choice {
+            //it's as if timing never stopped
+            IdleChoice::Keep => {
+                let time = db::get_time(tx, id)?;
+                db::upsert_time(
+                    tx,
+                    TimeWindow {
+                        end_time: None,
+                        ..time
+                    },
+                    &false,
+                )?;
+                webhook::enqueue(tx, "start", json!({ "time": db::get_time(tx, id)? }))?;
+            }
+            IdleChoice::Stop => return Ok(false),
+            IdleChoice::Break(category) => {
+                log_break(tx, category, &stopped.category, idle_start, returned)?
+            }
+            IdleChoice::Resume => start_timing_at_private(tx, &stopped.category, returned)?,
+        }
+        Ok(true)
+    })?;
+    match restarted {
+        true => update_integrations(conn, Some(&stopped.category)),
+        false => Ok(()),
+    }
+}
+
+///Change a time, returning it as it was and as it is now (or would be, with `dry_run`).  A duration ends the time
+/// that long after its (possibly also amended) start.
 pub fn amend_time(
     conn: &mut Connection,
     time_id: &i64,
-    start_time: &Option<String>,
-    end_time: &Option<String>,
-    category_name: &Option<String>,
+    amendment: &cli::Amendment,
+    dry_run: &bool,
+) -> Result<(TimeWindow, TimeWindow), TTError> {
+    let duration = match &amendment.duration {
+        Some(duration) => Some(
+            cli::duration_string_to_seconds(duration).ok_or(TTError::ParseTime {
+                input: duration.clone(),
+                reason: "--duration should be a duration like \"2h15m\", \"90m\", or \"1.5h\""
+                    .to_string(),
+            })?,
+        ),
+        None => None,
+    };
+    let parse = |time: &Option<String>, argument: &str| match time {
+        Some(input) => cli::time_string_to_tstamp(time)
+            .map(Some)
+            .ok_or_else(|| TTError::unparseable(input, argument)),
+        None => Ok(None),
+    };
+    let start = parse(&amendment.start_time, "--start-time")?;
+    let end = parse(&amendment.end_time, "--end-time")?;
+    let amended = db::write_or_dry_run(conn, dry_run, |tx| {
+        let before = db::get_time(tx, *time_id)?;
+        let mut time = before.clone();
+        if let Some(start) = start {
+            time.start_time = start;
+        }
+        if let Some(end) = end {
+            time.end_time = Some(end);
+        }
+        if let Some(duration) = duration {
+            time.end_time = Some(time.start_time + duration);
+        }
+        if let Some(category) = &amendment.category {
+            time.category = category.clone();
+        }
+        if let Some(note) = &amendment.note {
+            time.note = Some(note.clone()).filter(|note| !note.is_empty());
+        }
+        db::upsert_time(tx, time, &amendment.allow_unusual_times)?;
+        let after = db::get_time(tx, *time_id)?;
+        webhook::enqueue(tx, "amend", json!({ "time": after }))?;
+        Ok((before, after))
+    })?;
+    Ok(amended)
+}
+
+///`ttjr amend-time`, printing the amended time with --json
+pub fn amend(
+    conn: &mut Connection,
+    time_id: &i64,
+    amendment: &cli::Amendment,
+    json: &bool,
+    dry_run: &bool,
 ) -> Result<(), TTError> {
-    let mut tx = conn.transaction()?;
-    let mut time = db::get_time(&tx, time_id.clone())?;
-    if let Some(start) = cli::time_string_to_tstamp(start_time) {
-        time.start_time = start;
+    let (before, after) = amend_time(conn, time_id, amendment, dry_run)?;
+    match (json, dry_run) {
+        (true, true) => {
+            report::print_json(&json!({ "dry_run": true, "before": before, "after": after }))
+        }
+        (true, false) => report::print_json(&json!(after)),
+        (false, true) => {
+            println!("Would change {}", describe(&before));
+            println!("         to {}", describe(&after));
+            Ok(())
+        }
+        (false, false) => Ok(()),
     }
-    if let Some(end) = cli::time_string_to_tstamp(end_time) {
-        time.end_time = Some(end);
+}
+
+///Delete a time, returning it as it was
+pub fn delete_time(
+    conn: &mut Connection,
+    time_id: &i64,
+    dry_run: &bool,
+) -> Result<TimeWindow, TTError> {
+    let deleted = db::write_or_dry_run(conn, dry_run, |tx| {
+        let time = db::get_time(tx, *time_id).ok();
+        let did_delete = db::delete_time(tx, time_id)?;
+        match time {
+            Some(time) if did_delete > 0 => {
+                webhook::enqueue(tx, "delete", json!({ "id": time_id, "time": time }))?;
+                Ok(time)
+            }
+            _ => Err(TTError::TimeNotFound { id: *time_id }),
+        }
+    })?;
+    Ok(deleted)
+}
+
+///`ttjr delete-time`
+pub fn delete(
+    conn: &mut Connection,
+    time_id: &i64,
+    json: &bool,
+    dry_run: &bool,
+    yes: &bool,
+) -> Result<(), TTError> {
+    if !dry_run {
+        let time = db::get_time(&conn.transaction()?, *time_id)
+            .map_err(|_| TTError::TimeNotFound { id: *time_id })?;
+        commands::proceed(&format!("Delete time {}?", describe(&time)), yes)?;
     }
-    if let Some(category) = category_name {
-        time.category = category.clone();
+    let deleted = delete_time(conn, time_id, dry_run)?;
+    match (json, dry_run) {
+        (true, true) => {
+            report::print_json(&json!({ "dry_run": true, "deleted": 1, "times": [deleted] }))
+        }
+        (true, false) => report::print_json(&json!({ "deleted": 1 })),
+        (false, true) => {
+            println!("Would delete {}", describe(&deleted));
+            Ok(())
+        }
+        (false, false) => Ok(()),
     }
+}
+
+pub fn bulk_delete_times(
+    conn: &mut Connection,
+    selection: &cli::BulkDelete,
+    json: &bool,
+    dry_run: &bool,
+    yes: &bool,
+) -> Result<(), TTError> {
+    let start = cli::time_string_to_tstamp(&Some(selection.start_time.clone()));
+    let end = cli::time_string_to_tstamp(&Some(selection.end_time.clone()));
+    let (start, end) = match (start, end) {
+        (Some(s), Some(e)) => (s, e),
+        (None, _) => return Err(TTError::unparseable(&selection.start_time, "--start-time")),
+        (_, None) => return Err(TTError::unparseable(&selection.end_time, "--end-time")),
+    };
 
-    db::upsert_time(&mut tx, time)?;
-    tx.commit()?;
+    let tags = parse_tags(&selection.tag)?;
+    let delete = |tx: &mut Transaction| {
+        db::bulk_delete_times(tx, &start, &end, &selection.non_inclusive, &tags)
+    };
+    if !dry_run {
+        //see how many times would go, to ask about them
+        let deleting = db::write_or_dry_run(conn, &true, delete)?;
+        if !deleting.is_empty() {
+            commands::proceed(&format!("Delete {} time records?", deleting.len()), yes)?;
+        }
+    }
+    let deleted = db::write_or_dry_run(conn, dry_run, |tx| {
+        let deleted = delete(tx)?;
+        for time in &deleted {
+            webhook::enqueue(tx, "delete", json!({ "id": time.id, "time": time }))?;
+        }
+        Ok(deleted)
+    })?;
+    match (json, dry_run) {
+        (true, true) => report::print_json(&json!({
+            "dry_run": true,
+            "deleted": deleted.len(),
+            "times": deleted.iter().take(DRY_RUN_SAMPLE).collect::<Vec<_>>(),
+        }))?,
+        (true, false) => report::print_json(&json!({ "deleted": deleted.len() }))?,
+        (false, true) => {
+            println!("Would delete {} time records", deleted.len());
+            print_sample(&deleted);
+        }
+        (false, false) => println!("Deleted {} time records", deleted.len()),
+    }
     Ok(())
 }
 
-pub fn delete_time(conn: &mut Connection, time_id: &i64) -> Result<(), TTError> {
-    let mut tx = conn.transaction()?;
-    let did_delete = db::delete_time(&mut tx, &time_id)?;
-    tx.commit()?;
-    if did_delete == 0 {
-        Err(TTError::TTError {
-            message: "Invalid time ID".to_string(),
-        })
-    } else {
-        Ok(())
+pub fn list_pending(conn: &mut Connection, json: &bool) -> Result<(), TTError> {
+    let tx = conn.transaction()?;
+    let zone = Zone::Local;
+    let pending_times = db::get_pending_times(&tx)?;
+    if *json {
+        return report::print_json(&json!(pending_times));
+    }
+    for pending in pending_times {
+        println!(
+            "{}: {} - {} {} ({})",
+            pending.id.unwrap_or(-1),
+            zone.format(&pending.start_time, "%Y-%m-%d %H:%M"),
+            zone.format(&pending.end_time, "%H:%M"),
+            pending.title,
+            pending
+                .category
+                .unwrap_or("no category, confirm with --category".to_string())
+        );
     }
+    Ok(())
 }
 
-pub fn bulk_delete_times(
+///Log a pending time, under `category_name` or the category that was guessed when it was imported
+pub fn confirm_pending(
     conn: &mut Connection,
-    start_time: &String,
-    end_time: &String,
-    non_inclusive: &bool,
+    pending_id: &i64,
+    category_name: &Option<String>,
 ) -> Result<(), TTError> {
-    let mut tx = conn.transaction()?;
-
-    let start = cli::time_string_to_tstamp(&Some(start_time.clone()));
-    let end = cli::time_string_to_tstamp(&Some(end_time.clone()));
-
-    let rows_deleted = match (start, end) {
-        (Some(s), Some(e)) => db::bulk_delete_times(&mut tx, &s, &e, non_inclusive)?,
-        (Some(_), None) => {
-            return Err(TTError::TTError {
-                message: format!("Could not parse --end-time, got \"{}\"", end_time),
-            })
-        }
-        (None, Some(_)) => {
-            return Err(TTError::TTError {
-                message: format!("Could not parse --start-time, got \"{}\"", end_time),
-            })
-        }
-        (None, None) => {
-            return Err(TTError::TTError {
-                message: format!(
-                    "Could not parse --start-time, got \"{}\" or --end-time, got({})",
-                    start_time, end_time
-                ),
-            })
-        }
-    };
+    db::write(conn, |tx| {
+        let pending = db::get_pending_time(tx, *pending_id)?;
+        let category =
+            category_name
+                .clone()
+                .or(pending.category)
+                .ok_or(TTError::InvalidArgument {
+                    message: format!(
+                        "Pending time {} doesn't have a category, pick one with --category",
+                        pending_id
+                    ),
+                })?;
+        db::upsert_time(
+            tx,
+            TimeWindow {
+                id: None,
+                category,
+                start_time: pending.start_time,
+                end_time: Some(pending.end_time),
+                note: None,
+            },
+            &false,
+        )?;
+        db::resolve_pending_time(tx, *pending_id)
+    })
+}
+
+pub fn dismiss_pending(conn: &mut Connection, pending_id: &i64) -> Result<(), TTError> {
+    db::write(conn, |tx| {
+        db::get_pending_time(tx, *pending_id)?;
+        db::resolve_pending_time(tx, *pending_id)
+    })
+}
+
+fn parse_tags(tags: &[String]) -> Result<Vec<String>, TTError> {
+    tags.iter().map(|tag| db::parse_tag(tag)).collect()
+}
 
-    tx.commit()?;
-    println!("Deleted {} time records", rows_deleted);
+///`ttjr tag`, printing the time's tags with --json
+pub fn tag(
+    conn: &mut Connection,
+    time_id: &i64,
+    tags: &[String],
+    json: &bool,
+) -> Result<(), TTError> {
+    let tags = parse_tags(tags)?;
+    db::write(conn, |tx| db::tag_time(tx, *time_id, &tags))?;
+    print_tags(conn, time_id, json)
+}
+
+///`ttjr untag`, printing the time's remaining tags with --json
+pub fn untag(
+    conn: &mut Connection,
+    time_id: &i64,
+    tags: &[String],
+    json: &bool,
+) -> Result<(), TTError> {
+    let tags = parse_tags(tags)?;
+    db::write(conn, |tx| db::untag_time(tx, *time_id, &tags))?;
+    print_tags(conn, time_id, json)
+}
+
+fn print_tags(conn: &mut Connection, time_id: &i64, json: &bool) -> Result<(), TTError> {
+    if *json {
+        let tags = db::get_tags(&conn.transaction()?)?.remove(time_id);
+        report::print_json(&json!({ "id": time_id, "tags": tags.unwrap_or_default() }))?;
+    }
     Ok(())
 }
+
+///Note some context (like the git branch being worked on) on the time being recorded
+pub fn annotate(conn: &mut Connection, annotation: &str) -> Result<(), TTError> {
+    db::write(conn, |tx| match db::get_last_open_time(tx)? {
+        Some(TimeWindow { id: Some(id), .. }) => db::add_annotation(tx, id, annotation),
+        _ => Err(TTError::NothingFound {
+            message: "Not currently timing, there's nothing to annotate".to_string(),
+        }),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rusqlite::Connection;
+
+    fn timing_since(start_time: i64) -> Connection {
+        timing_in(Connection::open_in_memory().unwrap(), start_time)
+    }
+
+    fn timing_in(mut conn: Connection, start_time: i64) -> Connection {
+        db::initialize_db(&mut conn).unwrap();
+        let mut tx = conn.transaction().unwrap();
+        for category in ["work", "break"] {
+            db::add_category(&tx, &category.to_string()).unwrap();
+        }
+        //the made-up times in these tests are plausible
+        let earliest = "1970-01-01".to_string();
+        db::set_option(&tx, &cli::OptionName::EarliestTime, &earliest).unwrap();
+        db::upsert_time(
+            &mut tx,
+            TimeWindow {
+                id: None,
+                category: "work".to_string(),
+                start_time,
+                end_time: None,
+                note: None,
+            },
+            &true,
+        )
+        .unwrap();
+        tx.commit().unwrap();
+        conn
+    }
+
+    fn times(conn: &mut Connection) -> Vec<(String, i64, Option<i64>)> {
+        db::get_times(
+            &mut conn.transaction().unwrap(),
+            None,
+            None,
+            &cli::FilterMode::Start,
+        )
+        .unwrap()
+        .into_iter()
+        .map(|t| (t.category, t.start_time, t.end_time))
+        .collect()
+    }
+
+    #[test]
+    fn test_resolve_idle() {
+        let mut conn = timing_since(1000);
+        resolve_idle(&mut conn, 2000, 3000, &IdleChoice::Keep).unwrap();
+        assert_eq!(vec![("work".to_string(), 1000, None)], times(&mut conn));
+        resolve_idle(&mut conn, 2000, 3000, &IdleChoice::Stop).unwrap();
+        assert_eq!(
+            vec![("work".to_string(), 1000, Some(2000))],
+            times(&mut conn)
+        );
+
+        let mut conn = timing_since(1000);
+        resolve_idle(
+            &mut conn,
+            2000,
+            3000,
+            &IdleChoice::Break("break".to_string()),
+        )
+        .unwrap();
+        assert_eq!(
+            vec![
+                ("work".to_string(), 1000, Some(2000)),
+                ("break".to_string(), 2001, Some(3000)),
+                ("work".to_string(), 3001, None)
+            ],
+            times(&mut conn)
+        );
+
+        //with idle-action stop, timing was stopped when going idle
+        let stopped_at_idle = |choice: IdleChoice| {
+            let mut conn = timing_since(1000);
+            let stopped = stop_timing_at(&mut conn, 2000).unwrap().unwrap();
+            resolve_idle_stopped(&mut conn, &stopped, 2000, 3000, &choice).unwrap();
+            times(&mut conn)
+        };
+        assert_eq!(
+            vec![("work".to_string(), 1000, None)],
+            stopped_at_idle(IdleChoice::Keep)
+        );
+        assert_eq!(
+            vec![("work".to_string(), 1000, Some(2000))],
+            stopped_at_idle(IdleChoice::Stop)
+        );
+        assert_eq!(
+            vec![
+                ("work".to_string(), 1000, Some(2000)),
+                ("work".to_string(), 3000, None)
+            ],
+            stopped_at_idle(IdleChoice::Resume)
+        );
+        assert_eq!(
+            vec![
+                ("work".to_string(), 1000, Some(2000)),
+                ("break".to_string(), 2001, Some(3000)),
+                ("work".to_string(), 3001, None)
+            ],
+            stopped_at_idle(IdleChoice::Break("break".to_string()))
+        );
+
+        //started after going idle, so it's left alone
+        let mut conn = timing_since(2500);
+        resolve_idle(&mut conn, 2000, 3000, &IdleChoice::Stop).unwrap();
+        assert_eq!(vec![("work".to_string(), 2500, None)], times(&mut conn));
+    }
+
+    #[test]
+    fn test_stop_while_locked() {
+        let path = std::env::temp_dir()
+            .join(format!("ttjr-stop-locked-{}.sqlite3", std::process::id()))
+            .to_string_lossy()
+            .to_string();
+        let mut conn = timing_in(Connection::open(&path).unwrap(), 1000);
+        //another writer (like the daemon) holding the lock is waited out, rather than failing the stop
+        let locked = std::thread::spawn({
+            let path = path.clone();
+            move || {
+                let other = Connection::open(&path).unwrap();
+                other.execute_batch("BEGIN IMMEDIATE").unwrap();
+                std::thread::sleep(std::time::Duration::from_millis(100));
+                other.execute_batch("COMMIT").unwrap();
+            }
+        });
+        std::thread::sleep(std::time::Duration::from_millis(20));
+        let stopped = stop_timing_at(&mut conn, 2000).unwrap().unwrap();
+        locked.join().unwrap();
+        assert_eq!("work", stopped.category);
+        assert_eq!(
+            vec![("work".to_string(), 1000, Some(2000))],
+            times(&mut conn)
+        );
+        drop(conn);
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[test]
+    fn test_amend_unparseable() {
+        let mut conn = timing_since(1000);
+        for amendment in [
+            cli::Amendment {
+                start_time: Some("yesterdy 9am".to_string()),
+                ..Default::default()
+            },
+            cli::Amendment {
+                end_time: Some("yesterdy 5pm".to_string()),
+                ..Default::default()
+            },
+        ] {
+            assert!(matches!(
+                amend_time(&mut conn, &1, &amendment, &false),
+                Err(TTError::ParseTime { .. })
+            ));
+        }
+        assert_eq!(vec![("work".to_string(), 1000, None)], times(&mut conn));
+    }
+
+    #[test]
+    fn test_amend_duration() {
+        let mut conn = timing_since(1000);
+        let amend = |conn: &mut Connection, start_time: Option<&str>, duration: &str| {
+            let amendment = cli::Amendment {
+                start_time: start_time.map(|s| s.to_string()),
+                duration: Some(duration.to_string()),
+                allow_unusual_times: true,
+                ..Default::default()
+            };
+            amend_time(conn, &1, &amendment, &false).map(|(_, after)| after.end_time)
+        };
+        assert_eq!(
+            Some(Some(1000 + 8100)),
+            amend(&mut conn, None, "2h15m").ok()
+        );
+        //from the new start
+        assert_eq!(
+            Some(Some(5000 + 5400)),
+            amend(&mut conn, Some("@5000"), "90m").ok()
+        );
+        assert!(matches!(
+            amend(&mut conn, None, "a while"),
+            Err(TTError::ParseTime { .. })
+        ));
+        assert_eq!(
+            vec![("work".to_string(), 5000, Some(10400))],
+            times(&mut conn)
+        );
+    }
+
+    #[test]
+    fn test_unusual_times() {
+        let mut conn = timing_since(1667307600);
+        let mut amendment = cli::Amendment {
+            end_time: Some("2092-11-01 10:00".to_string()),
+            ..Default::default()
+        };
+        assert!(matches!(
+            amend_time(&mut conn, &1, &amendment, &false),
+            Err(TTError::UnusualTime { .. })
+        ));
+        assert_eq!(
+            vec![("work".to_string(), 1667307600, None)],
+            times(&mut conn)
+        );
+        amendment.allow_unusual_times = true;
+        assert!(amend_time(&mut conn, &1, &amendment, &false).is_ok());
+        //times that were already unusual can still have their category changed
+        let amendment = cli::Amendment {
+            category: Some("break".to_string()),
+            ..Default::default()
+        };
+        assert!(amend_time(&mut conn, &1, &amendment, &false).is_ok());
+    }
+
+    #[test]
+    fn test_notes() {
+        let mut conn = timing_since(1000);
+        let note = |conn: &mut Connection, id| {
+            db::get_time(&conn.transaction().unwrap(), id).unwrap().note
+        };
+        assert_eq!(None, note(&mut conn, 1));
+        let mut amendment = cli::Amendment {
+            note: Some("TICKET-12 login redirect".to_string()),
+            ..Default::default()
+        };
+        amend_time(&mut conn, &1, &amendment, &false).unwrap();
+        assert_eq!(
+            Some("TICKET-12 login redirect".to_string()),
+            note(&mut conn, 1)
+        );
+        //other changes leave the note alone, and an empty one removes it
+        amendment.note = None;
+        amendment.category = Some("break".to_string());
+        amend_time(&mut conn, &1, &amendment, &false).unwrap();
+        assert_eq!(
+            Some("TICKET-12 login redirect".to_string()),
+            note(&mut conn, 1)
+        );
+        amendment.note = Some("".to_string());
+        amend_time(&mut conn, &1, &amendment, &false).unwrap();
+        assert_eq!(None, note(&mut conn, 1));
+
+        amend_time(
+            &mut conn,
+            &1,
+            &cli::Amendment {
+                end_time: Some("@2000".to_string()),
+                allow_unusual_times: true,
+                ..Default::default()
+            },
+            &false,
+        )
+        .unwrap();
+        start_timing(
+            &mut conn,
+            &"work".to_string(),
+            &Some("coffee".to_string()),
+            &false,
+        )
+        .unwrap();
+        assert_eq!(Some("coffee".to_string()), note(&mut conn, 2));
+    }
+
+    #[test]
+    fn test_dry_run() {
+        let mut conn = timing_since(1000);
+        let (before, after) = amend_time(
+            &mut conn,
+            &1,
+            &cli::Amendment {
+                category: Some("break".to_string()),
+                ..Default::default()
+            },
+            &true,
+        )
+        .unwrap();
+        assert_eq!(
+            ("work", "break"),
+            (&before.category[..], &after.category[..])
+        );
+        assert_eq!(before, delete_time(&mut conn, &1, &true).unwrap());
+        assert_eq!(vec![("work".to_string(), 1000, None)], times(&mut conn));
+        assert_eq!(
+            Err(TTError::TimeNotFound { id: 2 }),
+            delete_time(&mut conn, &2, &true)
+        );
+    }
+}
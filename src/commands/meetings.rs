@@ -0,0 +1,410 @@
+/*
+This file is part of Timetrack Jr.
+Timetrack Jr. is free software: you can redistribute it and/or modify it under the terms of the GNU General Public License as published by the Free Software Foundation, either version 3 of the License, or (at your option) any later version.
+Timetrack Jr. is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU General Public License for more details.
+You should have received a copy of the GNU General Public License along with Timetrack Jr. If not, see <https://www.gnu.org/licenses/>.
+*/
+//Meetings from an ICS calendar (a file, or a URL like a calendar's secret address), so the daemon knows when you're
+// in one.  Only as much of iCalendar as that needs is read: timed events, daily and weekly recurrences, and excluded
+// dates.  All-day and cancelled events aren't meetings.
+use crate::{zone::Zone, TTError};
+use chrono::{Datelike, Duration, NaiveDate, NaiveDateTime, Weekday};
+use once_cell::sync::Lazy;
+use regex::Regex;
+use std::fs;
+
+static DURATION: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(r"^\+?P(?:(\d+)W)?(?:(\d+)D)?(?:T(?:(\d+)H)?(?:(\d+)M)?(?:(\d+)S)?)?$").unwrap()
+});
+
+///A content line's parameters, like TZID=America/New_York
+type Params = Vec<(String, String)>;
+///A content line's name, parameters, and value
+type Property = (String, Params, String);
+
+#[derive(Debug, Clone, PartialEq)]
+enum Frequency {
+    Daily,
+    Weekly,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct Recurrence {
+    frequency: Frequency,
+    interval: i64,
+    ///Days of the week a weekly meeting happens on
+    days: Vec<Weekday>,
+    count: Option<u32>,
+    until: Option<i64>,
+}
+
+///A (possibly recurring) meeting
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct Meeting {
+    pub summary: String,
+    ///Wall-clock start of the first occurrence, in `zone`
+    start: NaiveDateTime,
+    zone: Zone,
+    ///Seconds
+    duration: i64,
+    recurrence: Option<Recurrence>,
+    ///Starts of occurrences that were removed
+    exceptions: Vec<i64>,
+}
+
+impl Meeting {
+    ///Start of the last occurrence starting no later than `now`
+    fn last_start(&self, now: i64) -> Option<i64> {
+        let rule = match &self.recurrence {
+            None => {
+                return self
+                    .zone
+                    .timestamp(&self.start)
+                    .filter(|start| *start <= now && !self.exceptions.contains(start))
+            }
+            Some(rule) => rule,
+        };
+        let first = self.start.date();
+        let week = |day: NaiveDate| day.week(Weekday::Mon).first_day();
+        let mut last = None;
+        let mut count = 0;
+        let mut day = first;
+        while self.zone.midnight(day) <= now {
+            let matches = match rule.frequency {
+                Frequency::Daily => (day - first).num_days() % rule.interval == 0,
+                Frequency::Weekly => {
+                    (week(day) - week(first)).num_days() / 7 % rule.interval == 0
+                        && rule.days.contains(&day.weekday())
+                }
+            };
+            //occurrences skipped by DST don't happen
+            if let Some(start) = matches
+                .then(|| self.zone.timestamp(&day.and_time(self.start.time())))
+                .flatten()
+            {
+                count += 1;
+                if start > now
+                    || rule.until.is_some_and(|until| start > until)
+                    || rule.count.is_some_and(|limit| count > limit)
+                {
+                    break;
+                }
+                if !self.exceptions.contains(&start) {
+                    last = Some(start);
+                }
+            }
+            day = day.succ_opt()?;
+        }
+        last
+    }
+
+    ///Start of the occurrence happening at `now`, if one is
+    pub(crate) fn in_progress(&self, now: i64) -> Option<i64> {
+        self.last_start(now)
+            .filter(|start| now < start + self.duration)
+    }
+}
+
+///The meeting happening at `now` and when it started, if there is one
+pub(crate) fn current(meetings: &[Meeting], now: i64) -> Option<(&Meeting, i64)> {
+    meetings
+        .iter()
+        .filter_map(|meeting| meeting.in_progress(now).map(|start| (meeting, start)))
+        .max_by_key(|(_, start)| *start)
+}
+
+///Split a content line into its name, parameters, and value
+fn property(line: &str) -> Option<(String, Params, &str)> {
+    let mut quoted = false;
+    let colon = line.char_indices().find_map(|(i, c)| match c {
+        '"' => {
+            quoted = !quoted;
+            None
+        }
+        ':' if !quoted => Some(i),
+        _ => None,
+    })?;
+    let mut parts = line[..colon].split(';');
+    let name = parts.next()?.to_uppercase();
+    let params = parts
+        .filter_map(|param| param.split_once('='))
+        .map(|(key, value)| (key.to_uppercase(), value.trim_matches('"').to_string()))
+        .collect();
+    Some((name, params, &line[colon + 1..]))
+}
+
+fn param<'a>(params: &'a [(String, String)], name: &str) -> Option<&'a str> {
+    params
+        .iter()
+        .find(|(key, _)| key == name)
+        .map(|(_, value)| value.as_str())
+}
+
+///A DATE-TIME value and the zone it's in (None for DATE values, or anything unreadable)
+fn datetime(value: &str, params: &[(String, String)]) -> Option<(NaiveDateTime, Zone)> {
+    let (value, zone) = match value.strip_suffix('Z') {
+        Some(utc) => (utc, Zone::Named(chrono_tz::UTC)),
+        //zones that aren't IANA names (like Outlook's "Eastern Standard Time") are assumed to be local
+        None => (
+            value,
+            param(params, "TZID")
+                .and_then(|tzid| tzid.parse().ok())
+                .unwrap_or(Zone::Local),
+        ),
+    };
+    NaiveDateTime::parse_from_str(value, "%Y%m%dT%H%M%S")
+        .ok()
+        .map(|datetime| (datetime, zone))
+}
+
+fn timestamp(value: &str, params: &[(String, String)]) -> Option<i64> {
+    datetime(value, params).and_then(|(datetime, zone)| zone.timestamp(&datetime))
+}
+
+///Seconds in a DURATION value like PT1H30M
+fn duration(value: &str) -> Option<i64> {
+    let captures = DURATION.captures(value)?;
+    let part = |i: usize, seconds: i64| {
+        captures
+            .get(i)
+            .and_then(|m| m.as_str().parse::<i64>().ok())
+            .unwrap_or(0)
+            * seconds
+    };
+    Some(part(1, 604800) + part(2, 86400) + part(3, 3600) + part(4, 60) + part(5, 1))
+}
+
+fn weekday(day: &str) -> Option<Weekday> {
+    //monthly rules put an ordinal before the day (like 1MO), which isn't supported anyway
+    match day.trim_start_matches(|c: char| c.is_ascii_digit() || c == '-' || c == '+') {
+        "MO" => Some(Weekday::Mon),
+        "TU" => Some(Weekday::Tue),
+        "WE" => Some(Weekday::Wed),
+        "TH" => Some(Weekday::Thu),
+        "FR" => Some(Weekday::Fri),
+        "SA" => Some(Weekday::Sat),
+        "SU" => Some(Weekday::Sun),
+        _ => None,
+    }
+}
+
+///An RRULE, None for frequencies that aren't supported (the meeting is then only seen on its first occurrence)
+fn recurrence(value: &str, start: &NaiveDateTime) -> Option<Recurrence> {
+    let parts: Vec<(&str, &str)> = value
+        .split(';')
+        .filter_map(|part| part.split_once('='))
+        .collect();
+    let get = |name: &str| parts.iter().find(|(key, _)| *key == name).map(|(_, v)| *v);
+    let frequency = match get("FREQ")? {
+        "DAILY" => Frequency::Daily,
+        "WEEKLY" => Frequency::Weekly,
+        _ => return None,
+    };
+    let days = get("BYDAY")
+        .map(|days| days.split(',').filter_map(weekday).collect::<Vec<_>>())
+        .filter(|days| !days.is_empty())
+        .unwrap_or(vec![start.weekday()]);
+    Some(Recurrence {
+        frequency,
+        interval: get("INTERVAL")
+            .and_then(|i| i.parse().ok())
+            .filter(|i| *i > 0)
+            .unwrap_or(1),
+        days,
+        count: get("COUNT").and_then(|c| c.parse().ok()),
+        until: get("UNTIL").and_then(|until| match until.len() {
+            //a date includes the whole day
+            8 => NaiveDate::parse_from_str(until, "%Y%m%d")
+                .ok()
+                .map(|day| Zone::Local.midnight(day + Duration::days(1)) - 1),
+            _ => timestamp(until, &[]),
+        }),
+    })
+}
+
+///The meetings in a calendar's VEVENTs
+pub(crate) fn parse_calendar(ics: &str) -> Vec<Meeting> {
+    let unfolded = ics
+        .replace("\r\n", "\n")
+        .replace("\n ", "")
+        .replace("\n\t", "");
+    let mut meetings = vec![];
+    let mut event: Option<Vec<Property>> = None;
+    //components inside events, like alarms
+    let mut nested = 0;
+    for line in unfolded.lines() {
+        let (name, params, value) = match property(line) {
+            Some(property) => property,
+            None => continue,
+        };
+        match (name.as_str(), value, event.as_mut()) {
+            ("BEGIN", "VEVENT", None) => event = Some(vec![]),
+            ("BEGIN", _, Some(_)) => nested += 1,
+            ("END", "VEVENT", Some(_)) if nested == 0 => {
+                meetings.extend(event.take().and_then(|properties| meeting(&properties)));
+            }
+            ("END", _, Some(_)) => nested -= 1,
+            (_, _, Some(properties)) if nested == 0 => {
+                properties.push((name, params, value.to_string()))
+            }
+            _ => {}
+        }
+    }
+    meetings
+}
+
+fn meeting(properties: &[Property]) -> Option<Meeting> {
+    let get = |name: &str| properties.iter().find(|(key, _, _)| key == name);
+    if get("STATUS").is_some_and(|(_, _, status)| status == "CANCELLED") {
+        return None;
+    }
+    let (_, params, value) = get("DTSTART")?;
+    let (start, zone) = datetime(value, params)?;
+    let duration = match (get("DTEND"), get("DURATION")) {
+        (Some((_, params, value)), _) => timestamp(value, params)? - zone.timestamp(&start)?,
+        (None, Some((_, _, value))) => duration(value)?,
+        (None, None) => return None,
+    };
+    let summary = get("SUMMARY")
+        .map(|(_, _, summary)| {
+            summary
+                .replace("\\n", " ")
+                .replace("\\N", " ")
+                .replace("\\,", ",")
+                .replace("\\;", ";")
+                .replace("\\\\", "\\")
+        })
+        .unwrap_or("Meeting".to_string());
+    let exceptions = properties
+        .iter()
+        .filter(|(key, _, _)| key == "EXDATE")
+        .flat_map(|(_, params, value)| value.split(',').filter_map(|v| timestamp(v, params)))
+        .collect();
+    Some(Meeting {
+        summary,
+        start,
+        zone,
+        duration,
+        recurrence: get("RRULE").and_then(|(_, _, rule)| recurrence(rule, &start)),
+        exceptions,
+    })
+}
+
+///Read the meetings from an ICS file or URL (webcal:// URLs are fetched over https)
+pub(crate) fn fetch(source: &str) -> Result<Vec<Meeting>, TTError> {
+    let source = match source.strip_prefix("webcal://") {
+        Some(rest) => format!("https://{}", rest),
+        None => source.to_string(),
+    };
+    let error = |e: &dyn std::fmt::Display| TTError::Network {
+        message: format!("Couldn't read meeting-calendar {}: {}", source, e),
+    };
+    let ics = match source.starts_with("http://") || source.starts_with("https://") {
+        #[cfg(feature = "sync")]
+        true => ureq::get(&source)
+            .call()
+            .map_err(|e| error(&e))?
+            .body_mut()
+            .read_to_string()
+            .map_err(|e| error(&e))?,
+        #[cfg(not(feature = "sync"))]
+        true => return Err(crate::commands::not_compiled("sync")),
+        false => fs::read_to_string(&source).map_err(|e| error(&e))?,
+    };
+    Ok(parse_calendar(&ics))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CALENDAR: &str = "BEGIN:VCALENDAR\r
+VERSION:2.0\r
+BEGIN:VEVENT\r
+UID:1\r
+SUMMARY:Standup\\, daily\r
+DTSTART;TZID=America/New_York:20240603T093000\r
+DURATION:PT15M\r
+RRULE:FREQ=WEEKLY;BYDAY=MO,TU,WE,TH,FR\r
+EXDATE;TZID=America/New_York:20240605T093000\r
+BEGIN:VALARM\r
+ACTION:DISPLAY\r
+DESCRIPTION:Reminder\r
+END:VALARM\r
+END:VEVENT\r
+BEGIN:VEVENT\r
+UID:2\r
+SUMMARY:Planning with a very long name that gets\r
+  folded\r
+DTSTART:20240604T150000Z\r
+DTEND:20240604T160000Z\r
+END:VEVENT\r
+BEGIN:VEVENT\r
+UID:3\r
+SUMMARY:Holiday\r
+DTSTART;VALUE=DATE:20240604\r
+END:VEVENT\r
+BEGIN:VEVENT\r
+UID:4\r
+SUMMARY:Cancelled\r
+STATUS:CANCELLED\r
+DTSTART:20240604T150000Z\r
+DTEND:20240604T160000Z\r
+END:VEVENT\r
+BEGIN:VEVENT\r
+UID:5\r
+SUMMARY:Biweekly 1:1\r
+DTSTART:20240603T180000Z\r
+DTEND:20240603T183000Z\r
+RRULE:FREQ=WEEKLY;INTERVAL=2;COUNT=3\r
+END:VEVENT\r
+END:VCALENDAR\r
+";
+
+    #[test]
+    fn test_parse_calendar() {
+        let meetings = parse_calendar(CALENDAR);
+        assert_eq!(
+            vec![
+                "Standup, daily",
+                "Planning with a very long name that gets folded",
+                "Biweekly 1:1"
+            ],
+            meetings
+                .iter()
+                .map(|m| m.summary.as_str())
+                .collect::<Vec<_>>()
+        );
+        assert_eq!(15 * 60, meetings[0].duration);
+        assert_eq!(Some(3600), duration("PT1H"));
+        assert_eq!(Some(86400 + 1800), duration("P1DT30M"));
+        assert_eq!(None, duration("1H"));
+    }
+
+    #[test]
+    fn test_current() {
+        let meetings = parse_calendar(CALENDAR);
+        let at = |datetime: &str| {
+            Zone::Named(chrono_tz::UTC)
+                .timestamp(&NaiveDateTime::parse_from_str(datetime, "%Y-%m-%d %H:%M").unwrap())
+                .unwrap()
+        };
+        let summary = |datetime| current(&meetings, at(datetime)).map(|(m, _)| m.summary.as_str());
+        //standup is 13:30 UTC during daylight time, but not on wednesday the 5th or weekends
+        assert_eq!(Some("Standup, daily"), summary("2024-06-03 13:30"));
+        assert_eq!(Some("Standup, daily"), summary("2024-06-04 13:44"));
+        assert_eq!(None, summary("2024-06-04 13:45"));
+        assert_eq!(None, summary("2024-06-05 13:35"));
+        assert_eq!(None, summary("2024-06-08 13:35"));
+        assert_eq!(Some("Standup, daily"), summary("2024-12-02 14:35"));
+        assert_eq!(
+            Some(at("2024-06-04 15:00")),
+            current(&meetings, at("2024-06-04 15:59")).map(|(_, start)| start)
+        );
+        //every other monday, three times
+        assert_eq!(Some("Biweekly 1:1"), summary("2024-06-17 18:10"));
+        assert_eq!(None, summary("2024-06-10 18:10"));
+        assert_eq!(Some("Biweekly 1:1"), summary("2024-07-01 18:10"));
+        assert_eq!(None, summary("2024-07-15 18:10"));
+    }
+}
@@ -0,0 +1,191 @@
+/*
+This file is part of Timetrack Jr.
+Timetrack Jr. is free software: you can redistribute it and/or modify it under the terms of the GNU General Public License as published by the Free Software Foundation, either version 3 of the License, or (at your option) any later version.
+Timetrack Jr. is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU General Public License for more details.
+You should have received a copy of the GNU General Public License along with Timetrack Jr. If not, see <https://www.gnu.org/licenses/>.
+*/
+use crate::{cli::FilterMode, db, TTError};
+use rusqlite::Connection;
+use std::{collections::BTreeMap, fmt::Write};
+
+///Escape a label value for the Prometheus text format
+fn label(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+///Accumulates metrics in the Prometheus text exposition format
+#[derive(Default)]
+struct Metrics {
+    text: String,
+}
+
+impl Metrics {
+    fn family(&mut self, name: &str, kind: &str, help: &str) {
+        //writing to a String can't fail
+        let _ = writeln!(self.text, "# HELP {} {}", name, help);
+        let _ = writeln!(self.text, "# TYPE {} {}", name, kind);
+    }
+
+    fn sample(&mut self, name: &str, labels: &[(&str, &str)], value: f64) {
+        let labels = labels
+            .iter()
+            .map(|(k, v)| format!("{}=\"{}\"", k, label(v)))
+            .collect::<Vec<String>>();
+        let _ = match labels.is_empty() {
+            true => writeln!(self.text, "{} {}", name, value),
+            false => writeln!(self.text, "{}{{{}}} {}", name, labels.join(","), value),
+        };
+    }
+}
+
+///Resident memory of this process, from /proc (so only on linux)
+fn resident_memory_bytes() -> Option<f64> {
+    let status = std::fs::read_to_string("/proc/self/status").ok()?;
+    let kilobytes: f64 = status
+        .lines()
+        .find_map(|l| l.strip_prefix("VmRSS:"))?
+        .trim()
+        .trim_end_matches("kB")
+        .trim()
+        .parse()
+        .ok()?;
+    Some(kilobytes * 1024.0)
+}
+
+///Metrics for everything in the DB as of `now`, plus stats for a process started at `started`
+pub(crate) fn render(conn: &mut Connection, now: i64, started: i64) -> Result<String, TTError> {
+    let mut tx = conn.transaction()?;
+    let categories = db::get_categories(&tx)?;
+    let times = db::get_times(&mut tx, None, None, &FilterMode::Start)?;
+    let current = db::get_last_open_time(&tx)?;
+
+    let mut tracked: BTreeMap<&String, i64> = categories.iter().map(|c| (c, 0)).collect();
+    for time in &times {
+        //count the running timer up to now so the counter climbs while timing
+        let end = time.end_time.unwrap_or(now);
+        *tracked.entry(&time.category).or_default() += (end - time.start_time).max(0);
+    }
+
+    let mut metrics = Metrics::default();
+    metrics.family(
+        "ttjr_tracked_seconds",
+        "gauge",
+        "Seconds logged for each category, including the running timer (can go down when times are amended or deleted)",
+    );
+    for (category, seconds) in &tracked {
+        metrics.sample(
+            "ttjr_tracked_seconds",
+            &[("category", category)],
+            *seconds as f64,
+        );
+    }
+    metrics.family(
+        "ttjr_logged_times",
+        "gauge",
+        "Number of times logged for each category",
+    );
+    for category in &categories {
+        let count = times.iter().filter(|t| &t.category == category).count();
+        metrics.sample("ttjr_logged_times", &[("category", category)], count as f64);
+    }
+    metrics.family(
+        "ttjr_timing",
+        "gauge",
+        "1 for the category currently being timed, 0 for the others",
+    );
+    for category in &categories {
+        let timing = current.as_ref().map(|c| &c.category) == Some(category);
+        metrics.sample(
+            "ttjr_timing",
+            &[("category", category)],
+            timing as u8 as f64,
+        );
+    }
+    metrics.family(
+        "ttjr_current_timer_start_time_seconds",
+        "gauge",
+        "Unix time the current timer started, absent when nothing is being timed",
+    );
+    metrics.family(
+        "ttjr_current_timer_elapsed_seconds",
+        "gauge",
+        "Seconds the current timer has been running, absent when nothing is being timed",
+    );
+    if let Some(time) = &current {
+        let labels = [("category", time.category.as_str())];
+        metrics.sample(
+            "ttjr_current_timer_start_time_seconds",
+            &labels,
+            time.start_time as f64,
+        );
+        metrics.sample(
+            "ttjr_current_timer_elapsed_seconds",
+            &labels,
+            (now - time.start_time).max(0) as f64,
+        );
+    }
+
+    metrics.family(
+        "process_start_time_seconds",
+        "gauge",
+        "Start time of the process since unix epoch in seconds",
+    );
+    metrics.sample("process_start_time_seconds", &[], started as f64);
+    if let Some(bytes) = resident_memory_bytes() {
+        metrics.family(
+            "process_resident_memory_bytes",
+            "gauge",
+            "Resident memory size in bytes",
+        );
+        metrics.sample("process_resident_memory_bytes", &[], bytes);
+    }
+    Ok(metrics.text)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{commands::config, db::TimeWindow};
+
+    #[test]
+    fn test_render() {
+        let mut conn = Connection::open_in_memory().unwrap();
+        db::initialize_db(&mut conn).unwrap();
+        config::add_category(&mut conn, &"work".to_string()).unwrap();
+        config::add_category(&mut conn, &"say \"hi\"".to_string()).unwrap();
+        let mut tx = conn.transaction().unwrap();
+        for (start, end) in [(0, Some(100)), (200, None)] {
+            db::upsert_time(
+                &mut tx,
+                TimeWindow {
+                    id: None,
+                    category: "work".to_string(),
+                    start_time: start,
+                    end_time: end,
+                    note: None,
+                },
+                &true,
+            )
+            .unwrap();
+        }
+        tx.commit().unwrap();
+
+        let text = render(&mut conn, 250, 10).unwrap();
+        let lines: Vec<&str> = text.lines().collect();
+        for expected in [
+            "ttjr_tracked_seconds{category=\"work\"} 150",
+            "ttjr_tracked_seconds{category=\"say \\\"hi\\\"\"} 0",
+            "ttjr_logged_times{category=\"work\"} 2",
+            "ttjr_timing{category=\"work\"} 1",
+            "ttjr_timing{category=\"say \\\"hi\\\"\"} 0",
+            "ttjr_current_timer_start_time_seconds{category=\"work\"} 200",
+            "ttjr_current_timer_elapsed_seconds{category=\"work\"} 50",
+            "process_start_time_seconds 10",
+        ] {
+            assert!(lines.contains(&expected), "missing {}", expected);
+        }
+    }
+}
@@ -4,62 +4,529 @@ Timetrack Jr. is free software: you can redistribute it and/or modify it under t
 Timetrack Jr. is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU General Public License for more details.
 You should have received a copy of the GNU General Public License along with Timetrack Jr. If not, see <https://www.gnu.org/licenses/>.
 */
-use crate::cli::{Cli, Commands};
-use crate::TTError;
+use crate::cli::{Cli, Commands, ExportFormat, HookAction};
+use crate::{db, l10n, TTError};
 use rusqlite::Connection;
+use serde_json::json;
+use std::io::{self, BufRead, IsTerminal, Write};
 
 use self::config::unset_option;
 
+mod autostart;
+#[cfg(all(feature = "ical", feature = "sync"))]
+mod caldav;
+#[cfg(feature = "sync")]
+mod clockify;
 mod config;
+mod daemon;
+#[cfg(all(feature = "dbus", unix, not(target_os = "macos")))]
+mod dbus;
+mod discord;
 mod export;
-mod log;
+pub mod external;
+#[cfg(feature = "serve")]
+mod grafana;
+#[cfg(feature = "grpc")]
+mod grpc;
+#[cfg(feature = "mqtt")]
+mod homeassistant;
+mod hook;
+#[cfg(feature = "hotkeys")]
+mod hotkey;
+mod idle;
+mod lock;
+pub(crate) mod log;
+mod meetings;
+#[cfg(feature = "serve")]
+mod metrics;
+mod notify;
+mod org;
+#[cfg(feature = "sync")]
+mod outlook;
+mod pomodoro;
+pub(crate) mod report;
+mod rpc;
+#[cfg(feature = "async")]
+mod runtime;
+#[cfg(feature = "script")]
+mod script;
+#[cfg(feature = "serve")]
+mod serve;
+mod service;
+#[cfg(not(feature = "notifications"))]
+mod silent;
+#[cfg(feature = "sync")]
+mod slack;
+mod status;
+mod suggest;
+#[cfg(feature = "sync")]
+mod sync;
+mod table;
+#[cfg(feature = "sync")]
+mod toggl;
+#[cfg(any(all(feature = "menubar", target_os = "macos"), feature = "tray"))]
+mod tray;
+#[cfg(feature = "tui")]
+mod tui;
+mod webhook;
+mod window;
+
+///Whether a command can print JSON with --json (long-running commands and those printing other formats can't)
+fn supports_json(command: &Commands) -> bool {
+    match command {
+        Commands::Export { format, .. } => {
+            matches!(
+                format,
+                ExportFormat::Json | ExportFormat::Jsonl | ExportFormat::Summary
+            )
+        }
+        Commands::CurrentlyTiming { follow, .. } => !follow,
+        Commands::ShowConfig
+        | Commands::AddCategory { .. }
+        | Commands::DeleteCategory { .. }
+        | Commands::RenameCategory { .. }
+        | Commands::SetOption { .. }
+        | Commands::UnsetOption { .. }
+        | Commands::SetCategoryOption { .. }
+        | Commands::UnsetCategoryOption { .. }
+        | Commands::StartTiming { .. }
+        | Commands::StopTiming { .. }
+        | Commands::AmendTime { .. }
+        | Commands::DeleteTime { .. }
+        | Commands::BulkDeleteTimes { .. }
+        | Commands::Stats { .. }
+        | Commands::Earnings { .. }
+        | Commands::Budget { .. }
+        | Commands::Invoice { .. }
+        | Commands::Goals { .. }
+        | Commands::Attendance { .. }
+        | Commands::Schedule { .. }
+        | Commands::Trend { .. }
+        | Commands::ListPending
+        | Commands::ConfirmPending { .. }
+        | Commands::DismissPending { .. }
+        | Commands::Annotate { .. }
+        | Commands::Tag { .. }
+        | Commands::Untag { .. }
+        | Commands::Snooze { .. }
+        | Commands::AddWebhook { .. }
+        | Commands::DeleteWebhook { .. }
+        | Commands::ListWebhooks
+        | Commands::AddAutoStart { .. }
+        | Commands::DeleteAutoStart { .. }
+        | Commands::ListAutoStarts
+        | Commands::AddWindowRule { .. }
+        | Commands::DeleteWindowRule { .. }
+        | Commands::ListWindowRules
+        | Commands::DeliverWebhooks
+        | Commands::External(_) => true,
+        _ => false,
+    }
+}
+
+///The error for something needing a cargo feature ttjr was built without
+pub(crate) fn not_compiled(feature: &str) -> TTError {
+    TTError::MissingFeature {
+        feature: feature.to_string(),
+    }
+}
+
+///Commands which can show what they'd change without changing it
+fn supports_dry_run(command: &Commands) -> bool {
+    matches!(
+        command,
+        Commands::AmendTime { .. }
+            | Commands::DeleteTime { .. }
+            | Commands::BulkDeleteTimes { .. }
+            | Commands::ImportOrg { .. }
+    )
+}
+
+///Ask before doing something destructive, unless --yes.  Without a terminal to ask on it's refused, rather than
+/// waiting on an answer that won't come.
+pub(crate) fn proceed(question: &str, yes: &bool) -> Result<(), TTError> {
+    let stdin = io::stdin();
+    proceed_with(question, yes, stdin.is_terminal(), &mut stdin.lock())
+}
+
+fn proceed_with(
+    question: &str,
+    yes: &bool,
+    interactive: bool,
+    input: &mut impl BufRead,
+) -> Result<(), TTError> {
+    if *yes {
+        return Ok(());
+    }
+    if !interactive {
+        return Err(TTError::NeedsConfirmation {
+            question: question.to_string(),
+        });
+    }
+    eprint!("{} [y/N] ", question);
+    io::stderr().flush()?;
+    let mut answer = String::new();
+    input.read_line(&mut answer)?;
+    match answer.trim().to_lowercase().as_str() {
+        "y" | "yes" => Ok(()),
+        _ => Err(TTError::Cancelled),
+    }
+}
+
+///With --json, commands which otherwise print nothing say that they worked
+fn confirm(json: bool) -> Result<(), TTError> {
+    match json {
+        true => report::print_json(&json!({ "ok": true })),
+        false => Ok(()),
+    }
+}
 
 pub fn execute(cli: &Cli, conn: &mut Connection) -> Result<(), TTError> {
+    let json = &cli.json;
+    if *json && !supports_json(&cli.command) {
+        return Err(TTError::InvalidArgument {
+            message: "This command can't print JSON, run it without --json".to_string(),
+        });
+    }
+    if let Some(language) = db::get_options(&conn.transaction()?)?.get("language") {
+        l10n::set_language(language)?;
+    }
+    let dry_run = &cli.dry_run;
+    if *dry_run && !supports_dry_run(&cli.command) {
+        return Err(TTError::InvalidArgument {
+            message: "This command can't do a dry run, --dry-run works with amend-time, delete-time, bulk-delete-times, and import-org".to_string(),
+        });
+    }
     match &cli.command {
         Commands::ShowConfig => config::show(conn),
-        Commands::AddCategory { category_name } => config::add_category(conn, category_name),
+        Commands::AddCategory { category_name } => {
+            config::add_category(conn, category_name).and_then(|_| confirm(*json))
+        }
         Commands::DeleteCategory {
             category_name,
             delete_logged_times,
-        } => config::delete_category(conn, category_name, delete_logged_times),
+        } => config::delete_category(conn, category_name, delete_logged_times, &cli.yes)
+            .and_then(|_| confirm(*json)),
         Commands::SetOption {
             option_name,
             option_value,
-        } => config::set_option(conn, option_name, option_value),
-        Commands::UnsetOption { option_name } => unset_option(conn, option_name),
+        } => config::set_option(conn, option_name, option_value).and_then(|_| confirm(*json)),
+        Commands::UnsetOption { option_name } => {
+            unset_option(conn, option_name).and_then(|_| confirm(*json))
+        }
+        Commands::SetCategoryOption {
+            category_name,
+            option_name,
+            option_value,
+        } => config::set_category_option(conn, category_name, option_name, option_value)
+            .and_then(|_| confirm(*json)),
+        Commands::UnsetCategoryOption {
+            category_name,
+            option_name,
+        } => config::unset_category_option(conn, category_name, option_name)
+            .and_then(|_| confirm(*json)),
         Commands::StartTiming {
             category_name,
+            note,
             notify,
-        } => log::start_timing(conn, category_name, notify),
-        Commands::StopTiming { notify } => log::stop_timing(conn, notify),
-        Commands::AmendTime {
-            time_id,
-            start_time,
-            end_time,
-            category,
-        } => log::amend_time(conn, time_id, start_time, end_time, category),
-        Commands::DeleteTime { time_id } => log::delete_time(conn, time_id),
+        } => log::start(conn, category_name, note, notify, json),
+        Commands::StopTiming { notify } => log::stop(conn, notify, json),
+        Commands::AmendTime { time_id, amendment } => {
+            log::amend(conn, time_id, amendment, json, dry_run)
+        }
+        Commands::DeleteTime { time_id } => log::delete(conn, time_id, json, dry_run, &cli.yes),
+        Commands::Export {
+            format,
+            schema: true,
+            ..
+        } => export::schema(format),
         Commands::Export {
             format,
             listen,
             outfile,
-            start_time,
-            end_time,
+            push,
+            filter,
+            summary,
+            page,
+            schema: false,
         } => export::export(
             conn,
             format,
-            listen,
-            &(cli.db_path.clone()).unwrap(),
-            outfile,
-            start_time,
-            end_time,
+            listen.then(|| cli.db_path.as_ref().unwrap()),
+            &match push {
+                Some(url) => export::Destination::Push(url.clone()),
+                None => export::Destination::File(outfile.clone()),
+            },
+            filter,
+            summary,
+            page,
+        ),
+        Commands::CurrentlyTiming {
+            notify,
+            follow,
+            interval,
+        } => match follow {
+            true => export::follow_timing(conn, interval),
+            false => export::currently_timing(conn, notify, json),
+        },
+        Commands::Status {
+            format,
+            template,
+            not_timing,
+            max_length,
+        } => status::status(conn, format, template, not_timing, max_length),
+        Commands::RenameCategory { old, new } => {
+            config::rename_category(conn, old, new).and_then(|_| confirm(*json))
+        }
+        Commands::BulkDeleteTimes { selection } => {
+            log::bulk_delete_times(conn, selection, json, dry_run, &cli.yes)
+        }
+        Commands::Stats {
+            anomalies,
+            filter,
+            table,
+        } => match anomalies {
+            true => report::anomalies(conn, filter),
+            false => report::stats(conn, filter, table),
+        },
+        Commands::Earnings {
+            group_by,
+            daily_cap,
+            filter,
+            table,
+        } => report::earnings(conn, group_by, daily_cap, filter, table),
+        Commands::Budget {
+            notify,
+            timezone,
+            json,
+            table,
+        } => report::budget(conn, notify, &timezone.unwrap_or_default(), json, table),
+        Commands::Invoice {
+            month,
+            client,
+            timezone,
+            json,
+            table,
+        } => report::invoice(
+            conn,
+            month,
+            client,
+            &timezone.unwrap_or_default(),
+            json,
+            table,
         ),
-        Commands::CurrentlyTiming { notify } => export::currently_timing(conn, notify),
-        Commands::RenameCategory { old, new } => config::rename_category(conn, old, new),
-        Commands::BulkDeleteTimes {
-            non_inclusive,
+        Commands::Goals {
+            week_of,
+            timezone,
+            json,
+            table,
+        } => report::goals(conn, week_of, &timezone.unwrap_or_default(), json, table),
+        Commands::Attendance { filter, table } => report::attendance(conn, filter, table),
+        Commands::Schedule { filter, table } => report::schedule(conn, filter, table),
+        #[cfg(feature = "sync")]
+        Commands::SyncToggl {
             start_time,
-            end_time,
-        } => log::bulk_delete_times(conn, start_time, end_time, non_inclusive),
+            conflicts,
+        } => toggl::sync_toggl(conn, start_time, conflicts),
+        #[cfg(feature = "sync")]
+        Commands::SyncClockify {
+            start_time,
+            conflicts,
+        } => clockify::sync_clockify(conn, start_time, conflicts),
+        #[cfg(all(feature = "ical", feature = "sync"))]
+        Commands::PushCaldav { url } => caldav::push_caldav(conn, url),
+        #[cfg(not(feature = "ical"))]
+        Commands::PushCaldav { .. } => Err(not_compiled("ical")),
+        #[cfg(feature = "sync")]
+        Commands::SyncOutlook {
+            start_time,
+            import_meetings,
+        } => outlook::sync_outlook(conn, start_time, import_meetings),
+        #[cfg(not(feature = "sync"))]
+        Commands::SyncToggl { .. }
+        | Commands::SyncClockify { .. }
+        | Commands::SyncOutlook { .. } => Err(not_compiled("sync")),
+        #[cfg(all(feature = "ical", not(feature = "sync")))]
+        Commands::PushCaldav { .. } => Err(not_compiled("sync")),
+        Commands::ListPending => log::list_pending(conn, json),
+        Commands::ConfirmPending {
+            pending_id,
+            category,
+        } => log::confirm_pending(conn, pending_id, category).and_then(|_| confirm(*json)),
+        Commands::DismissPending { pending_id } => {
+            log::dismiss_pending(conn, pending_id).and_then(|_| confirm(*json))
+        }
+        Commands::ImportOrg {
+            path,
+            timezone,
+            create_categories,
+            allow_unusual_times,
+        } => org::import_org(
+            conn,
+            path,
+            timezone,
+            create_categories,
+            allow_unusual_times,
+            dry_run,
+        ),
+        Commands::Annotate { annotation } => {
+            log::annotate(conn, annotation).and_then(|_| confirm(*json))
+        }
+        Commands::Tag { time_id, tags } => log::tag(conn, time_id, tags, json),
+        Commands::Untag { time_id, tags } => log::untag(conn, time_id, tags, json),
+        Commands::Hook { action } => match action {
+            HookAction::Install { repo } => {
+                hook::install(repo, cli.db_path.as_ref().unwrap(), &cli.yes)
+            }
+            HookAction::Uninstall { repo } => hook::uninstall(repo),
+        },
+        #[cfg(feature = "mqtt")]
+        Commands::HomeAssistant { broker, interval } => {
+            homeassistant::home_assistant(conn, broker, interval)
+        }
+        #[cfg(not(feature = "mqtt"))]
+        Commands::HomeAssistant { .. } => Err(not_compiled("mqtt")),
+        Commands::DiscordPresence { interval } => discord::discord_presence(conn, interval),
+        Commands::Suggest {
+            since,
+            watch,
+            interval,
+            notify,
+        } => suggest::suggest(conn, since, watch, interval, notify),
+        Commands::Rpc => rpc::rpc(conn, &mut io::stdin().lock(), &mut io::stdout()),
+        #[cfg(feature = "script")]
+        Commands::Script {
+            file,
+            allow_writes,
+            args,
+        } => script::script(cli.db_path.as_ref().unwrap(), file, args, allow_writes),
+        #[cfg(not(feature = "script"))]
+        Commands::Script { .. } => Err(not_compiled("script")),
+        #[cfg(feature = "serve")]
+        Commands::Serve { bind_addr } => serve::serve(&(cli.db_path.clone()).unwrap(), bind_addr),
+        #[cfg(not(feature = "serve"))]
+        Commands::Serve { .. } => Err(not_compiled("serve")),
+        #[cfg(all(feature = "menubar", target_os = "macos"))]
+        Commands::Menubar { interval } => tray::menubar(conn, interval),
+        #[cfg(not(all(feature = "menubar", target_os = "macos")))]
+        Commands::Menubar { .. } => Err(not_compiled("menubar")),
+        #[cfg(feature = "tray")]
+        Commands::Tray { interval } => tray::tray(conn, interval),
+        #[cfg(not(feature = "tray"))]
+        Commands::Tray { .. } => Err(not_compiled("tray")),
+        Commands::Pomodoro { category, cycles } => pomodoro::pomodoro(conn, category, cycles),
+        #[cfg(feature = "tui")]
+        Commands::Tui => tui::tui(conn),
+        #[cfg(not(feature = "tui"))]
+        Commands::Tui => Err(not_compiled("tui")),
+        #[cfg(all(feature = "dbus", unix, not(target_os = "macos")))]
+        Commands::ServeDbus { interval } => dbus::serve(&(cli.db_path.clone()).unwrap(), interval),
+        #[cfg(not(all(feature = "dbus", unix, not(target_os = "macos"))))]
+        Commands::ServeDbus { .. } => Err(not_compiled("dbus")),
+        #[cfg(feature = "grpc")]
+        Commands::ServeGrpc { bind_addr } => {
+            grpc::serve(&(cli.db_path.clone()).unwrap(), bind_addr)
+        }
+        #[cfg(not(feature = "grpc"))]
+        Commands::ServeGrpc { .. } => Err(not_compiled("grpc")),
+        Commands::Daemon {
+            interval,
+            install_systemd,
+            socket,
+            install_launchd,
+            install_winservice,
+        } => match (install_systemd, install_launchd, install_winservice) {
+            (true, _, _) => {
+                service::install_systemd(conn, cli.db_path.as_ref().unwrap(), interval, socket)
+            }
+            (_, true, _) => service::install_launchd(cli.db_path.as_ref().unwrap(), interval),
+            (_, _, true) => service::install_winservice(cli.db_path.as_ref().unwrap(), interval),
+            _ => daemon::daemon(conn, &(cli.db_path.clone()).unwrap(), interval),
+        },
+        Commands::Snooze { duration } => daemon::snooze(conn, duration, json),
+        #[cfg(feature = "webhooks")]
+        Commands::AddWebhook { url, secret } => webhook::add_webhook(conn, url, secret, json),
+        #[cfg(not(feature = "webhooks"))]
+        Commands::AddWebhook { .. } | Commands::DeliverWebhooks => Err(not_compiled("webhooks")),
+        Commands::DeleteWebhook { webhook_id } => {
+            webhook::delete_webhook(conn, webhook_id).and_then(|_| confirm(*json))
+        }
+        Commands::ListWebhooks => webhook::list_webhooks(conn, json),
+        Commands::AddAutoStart {
+            days,
+            time,
+            category,
+        } => autostart::add_auto_start(conn, days, time, category, json),
+        Commands::DeleteAutoStart { auto_start_id } => {
+            autostart::delete_auto_start(conn, auto_start_id).and_then(|_| confirm(*json))
+        }
+        Commands::ListAutoStarts => autostart::list_auto_starts(conn, json),
+        Commands::AddWindowRule { pattern, category } => {
+            window::add_window_rule(conn, pattern, category, json)
+        }
+        Commands::DeleteWindowRule { rule_id } => {
+            window::delete_window_rule(conn, rule_id).and_then(|_| confirm(*json))
+        }
+        Commands::ListWindowRules => window::list_window_rules(conn, json),
+        #[cfg(feature = "webhooks")]
+        Commands::DeliverWebhooks => webhook::deliver_webhooks(conn, json),
+        #[cfg(feature = "hotkeys")]
+        Commands::SetHotkey {
+            keypress,
+            action,
+            category,
+        } => hotkey::set_hotkey(conn, keypress, action, category),
+        #[cfg(feature = "hotkeys")]
+        Commands::RecordHotkey { action, category } => {
+            hotkey::record_hotkey(conn, action, category)
+        }
+        #[cfg(feature = "hotkeys")]
+        Commands::UnsetHotkey { keypress } => hotkey::unset_hotkey(conn, keypress),
+        #[cfg(feature = "hotkeys")]
+        Commands::ListHotkeys => hotkey::list_hotkeys(conn),
+        #[cfg(not(feature = "hotkeys"))]
+        Commands::SetHotkey { .. }
+        | Commands::RecordHotkey { .. }
+        | Commands::UnsetHotkey { .. }
+        | Commands::ListHotkeys => Err(not_compiled("hotkeys")),
+        Commands::Trend {
+            category,
+            window,
+            filter,
+            table,
+        } => report::trend(conn, category, window, filter, table),
+        Commands::External(args) => match external::run(cli, args)? {
+            0 => Ok(()),
+            code => Err(TTError::ExtensionFailed {
+                name: args[0].clone(),
+                code,
+            }),
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_proceed() {
+        let answer = |yes, interactive, input: &str| {
+            proceed_with("Delete?", &yes, interactive, &mut input.as_bytes()).is_ok()
+        };
+        assert!(answer(true, false, ""));
+        assert!(!answer(false, false, "y\n"));
+        assert!(answer(false, true, "y\n"));
+        assert!(answer(false, true, "Yes\n"));
+        assert!(!answer(false, true, "\n"));
+        assert!(!answer(false, true, ""));
+        assert!(matches!(
+            proceed_with("Delete?", &false, false, &mut "".as_bytes()),
+            Err(TTError::NeedsConfirmation { .. })
+        ));
+        assert!(matches!(
+            proceed_with("Delete?", &false, true, &mut "n\n".as_bytes()),
+            Err(TTError::Cancelled)
+        ));
     }
 }
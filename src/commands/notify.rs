@@ -0,0 +1,503 @@
+/*
+This file is part of Timetrack Jr.
+Timetrack Jr. is free software: you can redistribute it and/or modify it under the terms of the GNU General Public License as published by the Free Software Foundation, either version 3 of the License, or (at your option) any later version.
+Timetrack Jr. is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU General Public License for more details.
+You should have received a copy of the GNU General Public License along with Timetrack Jr. If not, see <https://www.gnu.org/licenses/>.
+*/
+#[cfg(not(target_os = "macos"))]
+use crate::cli::NotificationUrgency;
+#[cfg(all(not(feature = "notifications"), unix, not(target_os = "macos")))]
+use crate::commands::silent::Hint;
+#[cfg(all(not(feature = "notifications"), not(target_os = "macos")))]
+use crate::commands::silent::Urgency;
+#[cfg(not(feature = "notifications"))]
+pub(crate) use crate::commands::silent::{Notification, Timeout};
+use crate::{
+    cli::{self, FilterMode, NotificationEvent},
+    commands::{log, report, status},
+    db::{self, HourMinute, TimeWindow},
+    l10n,
+    zone::Zone,
+    TTError,
+};
+use chrono::{DateTime, Datelike, Local, Utc};
+use clap::ValueEnum;
+#[cfg(all(feature = "notifications", unix, not(target_os = "macos")))]
+use notify_rust::Hint;
+#[cfg(all(feature = "notifications", not(target_os = "macos")))]
+use notify_rust::Urgency;
+#[cfg(feature = "notifications")]
+pub(crate) use notify_rust::{Notification, Timeout};
+use rusqlite::{Connection, Transaction};
+use std::{
+    collections::BTreeMap,
+    fs::OpenOptions,
+    io::{self, Write},
+};
+
+///How many categories the "Switch to…" notification offers (notification servers only show a few buttons)
+const SWITCH_CATEGORIES: usize = 4;
+
+fn event_name(event: &NotificationEvent) -> String {
+    event.to_possible_value().unwrap().get_name().to_string()
+}
+
+///Parse a per-event option like "wrap-up:critical,started:low"
+pub(crate) fn event_settings(value: &str) -> Result<Vec<(NotificationEvent, String)>, TTError> {
+    let mut settings = vec![];
+    for setting in value.split(',').filter(|s| !s.trim().is_empty()) {
+        let parsed = setting.split_once(':').and_then(|(event, value)| {
+            let event = NotificationEvent::from_str(event.trim(), true).ok()?;
+            Some((event, value.trim().to_string()))
+        });
+        match parsed {
+            Some(parsed) => settings.push(parsed),
+            None => {
+                return Err(TTError::InvalidArgument {
+                    message: format!(
+                        "Expected <notification>:<value> with a notification of {}, got \"{}\"",
+                        NotificationEvent::value_variants()
+                            .iter()
+                            .map(event_name)
+                            .collect::<Vec<_>>()
+                            .join(", "),
+                        setting
+                    ),
+                })
+            }
+        }
+    }
+    Ok(settings)
+}
+
+///The setting for `event` in a per-event option
+fn event_setting(options: &db::Options, option: &str, event: &NotificationEvent) -> Option<String> {
+    event_settings(options.get(option)?)
+        .ok()?
+        .into_iter()
+        .find(|(e, _)| e == event)
+        .map(|(_, value)| value)
+}
+
+///A notification for `event`, with its urgency and sound from the notification-urgency and notification-sound options
+pub(crate) fn notification(
+    conn: &mut Connection,
+    event: &NotificationEvent,
+) -> Result<Notification, TTError> {
+    let options = db::get_options(&conn.transaction()?)?;
+    let mut notification = Notification::new();
+    notification.appname("Timetrack Jr.");
+    #[cfg(not(target_os = "macos"))]
+    if let Some(urgency) = event_setting(&options, "notification-urgency", event)
+        .and_then(|u| NotificationUrgency::from_str(&u, true).ok())
+    {
+        notification.urgency(match urgency {
+            NotificationUrgency::Low => Urgency::Low,
+            NotificationUrgency::Normal => Urgency::Normal,
+            NotificationUrgency::Critical => Urgency::Critical,
+        });
+    }
+    match event_setting(&options, "notification-sound", event) {
+        Some(sound) if sound == "none" => {
+            #[cfg(all(unix, not(target_os = "macos")))]
+            notification.hint(Hint::SuppressSound(true));
+        }
+        Some(sound) => {
+            notification.sound_name(&sound);
+        }
+        None => {}
+    }
+    Ok(notification)
+}
+
+///Values for the {category}, {elapsed}, {start_local}, and {today_total} placeholders in notification templates
+fn template_values(
+    tx: &mut Transaction,
+    time: Option<&TimeWindow>,
+) -> Result<BTreeMap<&'static str, String>, TTError> {
+    let now = Utc::now().timestamp();
+    let today = report::totals_between(tx, Zone::Local.midnight(Zone::Local.today()), now)?;
+    Ok(BTreeMap::from([
+        (
+            "category",
+            time.map(|t| t.category.clone()).unwrap_or_default(),
+        ),
+        (
+            "elapsed",
+            time.map(|t| status::elapsed(t.start_time, t.end_time.unwrap_or(now)))
+                .unwrap_or_default(),
+        ),
+        (
+            "start_local",
+            time.map(|t| Zone::Local.format(&t.start_time, "%Y-%m-%d %H:%M"))
+                .unwrap_or_default(),
+        ),
+        ("today_total", report::hours_minutes(today.values().sum())),
+    ]))
+}
+
+fn fill(template: &str, values: &BTreeMap<&'static str, String>) -> String {
+    values
+        .iter()
+        .fold(template.to_string(), |filled, (name, value)| {
+            filled.replace(&format!("{{{}}}", name), value)
+        })
+}
+
+///A notification about `time` for `event`, from the template in its <event>-notification option or `default`.  The
+/// template's first line is the summary and the rest is the body (`\n` can be used for a line break).
+pub(crate) fn from_template(
+    conn: &mut Connection,
+    event: &NotificationEvent,
+    default: &str,
+    time: Option<&TimeWindow>,
+) -> Result<Notification, TTError> {
+    let mut notification = notification(conn, event)?;
+    let mut tx = conn.transaction()?;
+    let template = db::get_options(&tx)?
+        .get(&format!("{}-notification", event_name(event)))
+        .cloned()
+        .unwrap_or(default.to_string())
+        .replace("\\n", "\n");
+    let filled = fill(&template, &template_values(&mut tx, time)?);
+    let (summary, body) = filled.split_once('\n').unwrap_or((&filled, ""));
+    notification.summary(summary);
+    if !body.is_empty() {
+        notification.body(body);
+    }
+    Ok(notification)
+}
+
+///(identifier, label) of a notification's buttons
+pub(crate) type Actions = Vec<(String, String)>;
+
+fn timing_actions() -> Actions {
+    [
+        ("stop", "action-stop"),
+        ("keep", "action-keep"),
+        ("switch", "action-switch"),
+    ]
+    .map(|(id, label)| (id.to_string(), l10n::text(label, &[])))
+    .into()
+}
+
+///Categories to offer switching to: the most recently timed first, then the rest alphabetically
+fn recent_categories(tx: &mut Transaction, limit: usize) -> Result<Vec<String>, TTError> {
+    let since = Utc::now().timestamp() - 14 * 24 * 60 * 60;
+    let mut times = db::get_times(tx, Some(since), None, &FilterMode::Start)?;
+    times.sort_by_key(|t| -t.start_time);
+    let mut categories: Vec<String> = vec![];
+    for category in times
+        .into_iter()
+        .map(|t| t.category)
+        .chain(db::get_categories(tx)?)
+    {
+        if !categories.contains(&category) {
+            categories.push(category);
+        }
+    }
+    categories.truncate(limit);
+    Ok(categories)
+}
+
+///A notification as a single line for the terminal, starting with a bell
+fn fallback_line(notification: &Notification) -> String {
+    let mut line = format!("\x07[ttjr] {}", notification.summary);
+    if !notification.body.is_empty() {
+        line += &format!(": {}", notification.body.replace('\n', " "));
+    }
+    line + "\n"
+}
+
+///Write a notification that couldn't be shown where the notify-fallback option says to, or return `error` if it
+/// isn't set
+fn fall_back(
+    conn: &mut Connection,
+    notification: &Notification,
+    error: TTError,
+) -> Result<(), TTError> {
+    let fallback = match db::get_options(&conn.transaction()?)?.get("notify-fallback") {
+        Some(fallback) => fallback.clone(),
+        None => return Err(error),
+    };
+    let line = fallback_line(notification);
+    match fallback.as_str() {
+        "terminal" => {
+            //the daemon's stderr may be a log, so the controlling terminal is preferred
+            #[cfg(unix)]
+            if let Ok(mut tty) = OpenOptions::new().write(true).open("/dev/tty") {
+                return Ok(tty.write_all(line.as_bytes())?);
+            }
+            Ok(io::stderr().write_all(line.as_bytes())?)
+        }
+        //opening for reading too keeps a named pipe from blocking until something reads from it
+        path => Ok(OpenOptions::new()
+            .read(true)
+            .append(true)
+            .create(true)
+            .open(path)?
+            .write_all(line.as_bytes())?),
+    }
+}
+
+///Notifications asked for by a command (like --notify), which quiet-hours doesn't hold back
+fn requested(event: &NotificationEvent) -> bool {
+    matches!(
+        event,
+        NotificationEvent::Started
+            | NotificationEvent::Stopped
+            | NotificationEvent::Timing
+            | NotificationEvent::NotTiming
+            | NotificationEvent::OverBudget
+    )
+}
+
+///Whether `now` is during `quiet_hours`, a comma-separated list of times of day (like 22:00-08:00) and days (like
+/// weekends or sat-sun)
+pub(crate) fn in_quiet_hours(quiet_hours: &str, now: &DateTime<Local>) -> Result<bool, TTError> {
+    let time = HourMinute::of(now);
+    let mut quiet = false;
+    for period in quiet_hours.split(',').map(|p| p.trim()) {
+        let invalid = || {
+            TTError::invalid("quiet-hours", period, "a comma-separated list of times like 22:00-08:00 and days like weekends or sat-sun")
+        };
+        quiet |= match period.split_once('-') {
+            Some((start, end)) if period.contains(':') => {
+                let start = db::parse_time(&start.trim().to_string()).map_err(|_| invalid())?;
+                let end = db::parse_time(&end.trim().to_string()).map_err(|_| invalid())?;
+                match start <= end {
+                    true => start <= time && time < end,
+                    //overnight
+                    false => start <= time || time < end,
+                }
+            }
+            _ => cli::days_string_to_days(period).ok_or_else(invalid)?
+                [now.weekday().num_days_from_monday() as usize],
+        };
+    }
+    Ok(quiet)
+}
+
+///Whether `event` should be held back for the quiet-hours option (critical notifications never are, see
+/// notification-urgency)
+fn held_back(conn: &mut Connection, event: &NotificationEvent) -> Result<bool, TTError> {
+    let options = db::get_options(&conn.transaction()?)?;
+    let quiet_hours = match options.get("quiet-hours") {
+        Some(quiet_hours) if !requested(event) => quiet_hours,
+        _ => return Ok(false),
+    };
+    let critical = event_setting(&options, "notification-urgency", event)
+        .is_some_and(|urgency| urgency.eq_ignore_ascii_case("critical"));
+    Ok(!critical && in_quiet_hours(quiet_hours, &Local::now())?)
+}
+
+///Show `notification` (unless it's held back for quiet-hours), falling back to the notify-fallback option if there's
+/// no notification server to show it
+pub(crate) fn show(
+    conn: &mut Connection,
+    event: &NotificationEvent,
+    notification: &Notification,
+) -> Result<(), TTError> {
+    if held_back(conn, event)? {
+        return Ok(());
+    }
+    match notification.show() {
+        Ok(_) => Ok(()),
+        Err(e) => fall_back(conn, notification, e.into()),
+    }
+}
+
+///Show `notification` with buttons, waiting for one to be clicked, and return its identifier (None if the
+/// notification was dismissed, held back for quiet-hours, or couldn't be shown and fell back to notify-fallback).
+/// Some backends report the button's label rather than its identifier, so either works.
+pub(crate) fn choose(
+    conn: &mut Connection,
+    event: &NotificationEvent,
+    notification: &mut Notification,
+    actions: &Actions,
+) -> Result<Option<String>, TTError> {
+    if held_back(conn, event)? {
+        return Ok(None);
+    }
+    for (id, label) in actions {
+        notification.action(id, label);
+    }
+    let handle = match notification.show() {
+        Ok(handle) => handle,
+        Err(e) => return fall_back(conn, notification, e.into()).map(|_| None),
+    };
+    let mut response = String::new();
+    handle.wait_for_action(|action| response = action.to_string());
+    Ok(actions
+        .iter()
+        .find(|(id, label)| *id == response || *label == response)
+        .map(|(id, _)| id.clone()))
+}
+
+///Do what a button on a timing notification asks
+fn perform(conn: &mut Connection, action: &str) -> Result<(), TTError> {
+    match action {
+        "stop" => log::stop_timing(conn, &false),
+        "switch" => {
+            let actions: Actions = recent_categories(&mut conn.transaction()?, SWITCH_CATEGORIES)?
+                .into_iter()
+                .map(|category| (format!("start:{}", category), category))
+                .collect();
+            let mut switch = notification(conn, &NotificationEvent::Started)?;
+            switch.summary(&l10n::text("switch-summary", &[]));
+            let chosen = choose(conn, &NotificationEvent::Started, &mut switch, &actions)?;
+            match chosen {
+                Some(chosen) => perform(conn, &chosen),
+                None => Ok(()),
+            }
+        }
+        _ => match action.strip_prefix("start:") {
+            Some(category) => log::start_timing(conn, &category.to_string(), &None, &false),
+            //keep going
+            None => Ok(()),
+        },
+    }
+}
+
+///Show a notification about what's being timed.  With the notification-actions option set, it has Stop, Keep going,
+/// and Switch… buttons, and this waits until one's clicked (or the notification's dismissed).
+pub(crate) fn show_timing(
+    conn: &mut Connection,
+    event: &NotificationEvent,
+    notification: &mut Notification,
+) -> Result<(), TTError> {
+    let options = db::get_options(&conn.transaction()?)?;
+    if options.get("notification-actions").map(|o| o.as_str()) != Some("true") {
+        return show(conn, event, notification);
+    }
+    match choose(conn, event, notification, &timing_actions())? {
+        Some(action) => perform(conn, &action),
+        None => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn test_db() -> Connection {
+        let mut conn = Connection::open_in_memory().unwrap();
+        db::initialize_db(&mut conn).unwrap();
+        let tx = conn.transaction().unwrap();
+        for category in ["a", "b", "c"] {
+            db::add_category(&tx, &category.to_string()).unwrap();
+        }
+        tx.commit().unwrap();
+        conn
+    }
+
+    #[test]
+    fn test_template_values() {
+        let mut conn = test_db();
+        let time = TimeWindow {
+            id: None,
+            category: "a".to_string(),
+            start_time: 1000,
+            end_time: Some(1000 + 90 * 60),
+            note: None,
+        };
+        let values = template_values(&mut conn.transaction().unwrap(), Some(&time)).unwrap();
+        assert_eq!(
+            "a for 01:30 (00:00 today)",
+            fill("{category} for {elapsed} ({today_total} today)", &values)
+        );
+        let values = template_values(&mut conn.transaction().unwrap(), None).unwrap();
+        assert_eq!(" {nope}", fill("{category} {nope}", &values));
+    }
+
+    #[test]
+    fn test_event_settings() {
+        let options = db::Options::from([(
+            "notification-urgency".to_string(),
+            "wrap-up:critical, started : low".to_string(),
+        )]);
+        let urgency = |event| event_setting(&options, "notification-urgency", &event);
+        assert_eq!(
+            Some("critical".to_string()),
+            urgency(NotificationEvent::WrapUp)
+        );
+        assert_eq!(Some("low".to_string()), urgency(NotificationEvent::Started));
+        assert_eq!(None, urgency(NotificationEvent::Stopped));
+        assert!(event_settings("wrap-up").is_err());
+        assert!(event_settings("lunch:low").is_err());
+    }
+
+    #[test]
+    fn test_perform() {
+        let mut conn = test_db();
+        perform(&mut conn, "start:b").unwrap();
+        let open = db::get_last_open_time(&conn.transaction().unwrap()).unwrap();
+        assert_eq!("b", open.unwrap().category);
+        perform(&mut conn, "keep").unwrap();
+        assert!(db::get_last_open_time(&conn.transaction().unwrap())
+            .unwrap()
+            .is_some());
+        perform(&mut conn, "stop").unwrap();
+        assert_eq!(
+            None,
+            db::get_last_open_time(&conn.transaction().unwrap()).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_in_quiet_hours() {
+        use chrono::TimeZone;
+        //2024-01-05 is a friday
+        let at = |day, hour| Local.with_ymd_and_hms(2024, 1, day, hour, 30, 0).unwrap();
+        let quiet = |hours: &str, day, hour| in_quiet_hours(hours, &at(day, hour)).unwrap();
+        assert!(quiet("22:00-08:00,weekends", 5, 23));
+        assert!(quiet("22:00-08:00,weekends", 5, 7));
+        assert!(!quiet("22:00-08:00,weekends", 5, 8));
+        assert!(quiet("22:00-08:00,weekends", 6, 12));
+        assert!(quiet("12:00-13:00, sat-sun", 5, 12));
+        assert!(!quiet("12:00-13:00", 5, 13));
+        assert!(in_quiet_hours("22:00-8pm", &at(5, 1)).is_err());
+        assert!(in_quiet_hours("someday", &at(5, 1)).is_err());
+    }
+
+    #[test]
+    fn test_fall_back() {
+        let mut conn = test_db();
+        let mut notification = Notification::new();
+        notification
+            .summary("Not timing anything")
+            .body("What are you\nworking on?");
+        assert_eq!(
+            "\x07[ttjr] Not timing anything: What are you working on?\n",
+            fallback_line(&notification)
+        );
+        let error = || TTError::System {
+            message: "no notification server".to_string(),
+        };
+        assert!(fall_back(&mut conn, &notification, error()).is_err());
+        let path = std::env::temp_dir().join(format!("ttjr-fallback-{}", std::process::id()));
+        let tx = conn.transaction().unwrap();
+        db::set_option(
+            &tx,
+            &crate::cli::OptionName::NotifyFallback,
+            &path.to_string_lossy().to_string(),
+        )
+        .unwrap();
+        tx.commit().unwrap();
+        fall_back(&mut conn, &notification, error()).unwrap();
+        fall_back(&mut conn, &notification, error()).unwrap();
+        let written = std::fs::read_to_string(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(fallback_line(&notification).repeat(2), written);
+    }
+
+    #[test]
+    fn test_recent_categories() {
+        let mut conn = test_db();
+        log::start_timing(&mut conn, &"c".to_string(), &None, &false).unwrap();
+        assert_eq!(
+            vec!["c", "a"],
+            recent_categories(&mut conn.transaction().unwrap(), 2).unwrap()
+        );
+    }
+}
@@ -0,0 +1,369 @@
+/*
+This file is part of Timetrack Jr.
+Timetrack Jr. is free software: you can redistribute it and/or modify it under the terms of the GNU General Public License as published by the Free Software Foundation, either version 3 of the License, or (at your option) any later version.
+Timetrack Jr. is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU General Public License for more details.
+You should have received a copy of the GNU General Public License along with Timetrack Jr. If not, see <https://www.gnu.org/licenses/>.
+*/
+use crate::{
+    cli::FilterMode,
+    commands::log,
+    db::{self, TimeWindow},
+    zone::Zone,
+    TTError,
+};
+use chrono::{NaiveDateTime, Utc};
+use once_cell::sync::Lazy;
+use regex::Regex;
+use rusqlite::{Connection, Transaction};
+use std::collections::BTreeMap;
+
+static HEADING: Lazy<Regex> = Lazy::new(|| Regex::new(r"^(\*+)\s+(.*?)\s*$").unwrap());
+static TAGS: Lazy<Regex> = Lazy::new(|| Regex::new(r"\s+(:[^\s:]+(?::[^\s:]+)*:)$").unwrap());
+static CLOCK: Lazy<Regex> =
+    Lazy::new(|| Regex::new(r"^\s*CLOCK:\s*\[([^\]]+)\](?:--\[([^\]]+)\])?").unwrap());
+static TIMESTAMP: Lazy<Regex> =
+    Lazy::new(|| Regex::new(r"^(\d{4}-\d{2}-\d{2})(?:\s+[^\s\d]+)?\s+(\d{1,2}:\d{2})$").unwrap());
+
+const TODO_KEYWORDS: [&str; 9] = [
+    "TODO",
+    "DONE",
+    "NEXT",
+    "STARTED",
+    "WAITING",
+    "HOLD",
+    "CANCELLED",
+    "CANCELED",
+    "IN-PROGRESS",
+];
+const ORG_TIMESTAMP: &str = "[%Y-%m-%d %a %H:%M]";
+
+///An org heading, without its stars, TODO keyword, priority, or tags
+#[derive(Debug, Clone, PartialEq)]
+struct Heading {
+    title: String,
+    tags: Vec<String>,
+}
+
+///A CLOCK line, with the headings it's under (innermost last)
+#[derive(Debug, PartialEq)]
+struct Clock {
+    headings: Vec<Heading>,
+    start: NaiveDateTime,
+    end: Option<NaiveDateTime>,
+}
+
+fn parse_heading(text: &str) -> Heading {
+    let mut text = text.to_string();
+    let mut tags = vec![];
+    if let Some(captures) = TAGS.captures(&text.clone()) {
+        tags = captures[1]
+            .split(':')
+            .filter(|t| !t.is_empty())
+            .map(|t| t.to_string())
+            .collect();
+        text.truncate(captures.get(0).unwrap().start());
+    }
+    let mut words: Vec<&str> = text.split_whitespace().collect();
+    if words.first().is_some_and(|w| TODO_KEYWORDS.contains(w)) {
+        words.remove(0);
+    }
+    if words
+        .first()
+        .is_some_and(|w| w.starts_with("[#") && w.ends_with(']'))
+    {
+        words.remove(0);
+    }
+    Heading {
+        title: words.join(" "),
+        tags,
+    }
+}
+
+fn parse_timestamp(timestamp: &str) -> Option<NaiveDateTime> {
+    let captures = TIMESTAMP.captures(timestamp.trim())?;
+    NaiveDateTime::parse_from_str(
+        &format!("{} {}", &captures[1], &captures[2]),
+        "%Y-%m-%d %H:%M",
+    )
+    .ok()
+}
+
+///Every CLOCK line in an org document
+fn parse_clocks(org: &str) -> Vec<Clock> {
+    let mut headings: Vec<(usize, Heading)> = vec![];
+    let mut clocks = vec![];
+    for line in org.lines() {
+        if let Some(captures) = HEADING.captures(line) {
+            let level = captures[1].len();
+            headings.retain(|(l, _)| *l < level);
+            headings.push((level, parse_heading(&captures[2])));
+        } else if let Some(captures) = CLOCK.captures(line) {
+            if let Some(start) = parse_timestamp(&captures[1]) {
+                clocks.push(Clock {
+                    headings: headings.iter().map(|(_, h)| h.clone()).collect(),
+                    start,
+                    end: captures.get(2).and_then(|e| parse_timestamp(e.as_str())),
+                });
+            }
+        }
+    }
+    clocks
+}
+
+///The category for a clock: the innermost heading whose title (or one of its tags) is a category
+fn clock_category(clock: &Clock, categories: &db::Categories) -> Option<String> {
+    clock.headings.iter().rev().find_map(|h| {
+        std::iter::once(&h.title)
+            .chain(h.tags.iter())
+            .find(|c| categories.contains(*c))
+            .cloned()
+    })
+}
+
+///Import CLOCK lines from an org file as times.  Clocks already in ttjr (to the minute, since that's all org records)
+/// are skipped, so files can be imported repeatedly.
+pub fn import_org(
+    conn: &mut Connection,
+    path: &str,
+    timezone: &Option<Zone>,
+    create_categories: &bool,
+    allow_unusual_times: &bool,
+    dry_run: &bool,
+) -> Result<(), TTError> {
+    let zone = timezone.unwrap_or_default();
+    let clocks = parse_clocks(&std::fs::read_to_string(path)?);
+    let (times, duplicates, skipped) = db::write_or_dry_run(conn, dry_run, |tx| {
+        import_clocks(tx, &clocks, &zone, create_categories, allow_unusual_times)
+    })?;
+    let verb = match dry_run {
+        true => "Would import",
+        false => "Imported",
+    };
+    println!(
+        "{} {} times, {} were already in ttjr",
+        verb,
+        times.len(),
+        duplicates
+    );
+    if *dry_run {
+        log::print_sample(&times);
+    }
+    for skipped in skipped {
+        println!("Skipped: {}", skipped);
+    }
+    Ok(())
+}
+
+///Log `clocks`, returning the times added, how many were already logged, and why any others were skipped
+fn import_clocks(
+    tx: &mut Transaction,
+    clocks: &[Clock],
+    zone: &Zone,
+    create_categories: &bool,
+    allow_unusual_times: &bool,
+) -> Result<(Vec<TimeWindow>, usize, Vec<String>), TTError> {
+    let (options, now) = (db::get_options(tx)?, Utc::now().timestamp());
+    let mut categories = db::get_categories(tx)?;
+    let existing = db::get_times(tx, None, None, &FilterMode::Start)?;
+    let mut duplicates = 0;
+    let mut skipped = vec![];
+    //times are inserted all at once at the end
+    let (mut times, mut descriptions) = (vec![], vec![]);
+    for clock in clocks {
+        let describe = || format!("CLOCK at {}", clock.start.format("%Y-%m-%d %H:%M"));
+        let category = match clock_category(clock, &categories) {
+            Some(category) => category,
+            None => match (clock.headings.last(), create_categories) {
+                (Some(heading), true) if !heading.title.is_empty() => {
+                    db::add_category(tx, &heading.title)?;
+                    categories.insert(heading.title.clone());
+                    heading.title.clone()
+                }
+                _ => {
+                    skipped.push(format!(
+                        "{} isn't under a heading named for a category",
+                        describe()
+                    ));
+                    continue;
+                }
+            },
+        };
+        let (start, end) = match (
+            zone.timestamp(&clock.start),
+            clock.end.map(|e| zone.timestamp(&e)),
+        ) {
+            (Some(start), None) => (start, None),
+            (Some(start), Some(Some(end))) => (start, Some(end)),
+            _ => {
+                skipped.push(format!("{} isn't a valid time", describe()));
+                continue;
+            }
+        };
+        if !allow_unusual_times {
+            let unusual = [Some(start), end]
+                .into_iter()
+                .flatten()
+                .find_map(|t| db::check_plausible(&options, t, now).err());
+            if let Some(unusual) = unusual {
+                skipped.push(format!("{}: {}", describe(), unusual));
+                continue;
+            }
+        }
+        let same_minute = |a: i64, b: i64| a.div_euclid(60) == b.div_euclid(60);
+        if existing.iter().any(|t| {
+            t.category == category
+                && same_minute(t.start_time, start)
+                && match (t.end_time, end) {
+                    (Some(a), Some(b)) => same_minute(a, b),
+                    (a, b) => a.is_none() && b.is_none(),
+                }
+        }) {
+            duplicates += 1;
+            continue;
+        }
+        descriptions.push(describe());
+        times.push(TimeWindow {
+            id: None,
+            category,
+            start_time: start,
+            end_time: end,
+            note: None,
+        });
+    }
+    let overlapping = db::insert_times(tx, &times, allow_unusual_times)?;
+    for index in &overlapping {
+        skipped.push(format!("{} overlaps another time", descriptions[*index]));
+    }
+    let imported = times
+        .into_iter()
+        .enumerate()
+        .filter(|(index, _)| !overlapping.contains(index))
+        .map(|(_, time)| time)
+        .collect();
+    Ok((imported, duplicates, skipped))
+}
+
+///Times as an org document with a heading per category, each with a LOGBOOK of CLOCK lines (newest first, like org)
+pub(crate) fn export_org(
+    outfile: &mut dyn std::io::Write,
+    times: Vec<TimeWindow>,
+    zone: &Zone,
+) -> Result<(), TTError> {
+    let mut by_category: BTreeMap<&String, Vec<&TimeWindow>> = BTreeMap::new();
+    for time in &times {
+        by_category.entry(&time.category).or_default().push(time);
+    }
+    for (category, mut times) in by_category {
+        times.sort_by_key(|t| -t.start_time);
+        writeln!(outfile, "* {}\n:LOGBOOK:", category)?;
+        for time in times {
+            let start = zone.format(&time.start_time, ORG_TIMESTAMP);
+            match time.end_time {
+                Some(end) => {
+                    let minutes = (end.div_euclid(60) - time.start_time.div_euclid(60)).max(0);
+                    writeln!(
+                        outfile,
+                        "CLOCK: {}--{} => {:>2}:{:02}",
+                        start,
+                        zone.format(&end, ORG_TIMESTAMP),
+                        minutes / 60,
+                        minutes % 60
+                    )?
+                }
+                None => writeln!(outfile, "CLOCK: {}", start)?,
+            }
+        }
+        writeln!(outfile, ":END:")?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::NaiveDate;
+
+    fn datetime(day: u32, hour: u32, minute: u32) -> NaiveDateTime {
+        NaiveDate::from_ymd_opt(2024, 1, day)
+            .unwrap()
+            .and_hms_opt(hour, minute, 0)
+            .unwrap()
+    }
+
+    #[test]
+    fn test_parse_clocks() {
+        let org = "#+TITLE: work
+* client-a
+** TODO [#A] Fix the login page   :urgent:bug:
+:LOGBOOK:
+CLOCK: [2024-01-02 Tue 10:00]--[2024-01-02 Tue 11:30] =>  1:30
+CLOCK: [2024-01-03 Wed 09:15]
+:END:
+* Admin :internal:
+  CLOCK: [2024-01-02 Tue 08:00]--[2024-01-02 Tue 08:30] =>  0:30
+";
+        let clocks = parse_clocks(org);
+        assert_eq!(3, clocks.len());
+        assert_eq!(
+            Heading {
+                title: "Fix the login page".to_string(),
+                tags: vec!["urgent".to_string(), "bug".to_string()],
+            },
+            clocks[0].headings[1]
+        );
+        assert_eq!(datetime(2, 10, 0), clocks[0].start);
+        assert_eq!(Some(datetime(2, 11, 30)), clocks[0].end);
+        assert_eq!(None, clocks[1].end);
+        assert_eq!(1, clocks[2].headings.len());
+
+        let categories: db::Categories = ["client-a", "internal"].map(String::from).into();
+        assert_eq!(
+            Some("client-a".to_string()),
+            clock_category(&clocks[0], &categories)
+        );
+        assert_eq!(
+            Some("internal".to_string()),
+            clock_category(&clocks[2], &categories)
+        );
+    }
+
+    #[test]
+    fn test_export_org() {
+        let zone: Zone = "UTC".parse().unwrap();
+        let mut out = vec![];
+        export_org(
+            &mut out,
+            vec![
+                TimeWindow {
+                    id: Some(1),
+                    category: "work".to_string(),
+                    start_time: 1704189600,
+                    end_time: Some(1704195000),
+                    note: None,
+                },
+                TimeWindow {
+                    id: Some(2),
+                    category: "work".to_string(),
+                    start_time: 1704272400,
+                    end_time: None,
+                    note: None,
+                },
+            ],
+            &zone,
+        )
+        .unwrap();
+        let org = String::from_utf8(out).unwrap();
+        assert_eq!(
+            "* work
+:LOGBOOK:
+CLOCK: [2024-01-03 Wed 09:00]
+CLOCK: [2024-01-02 Tue 10:00]--[2024-01-02 Tue 11:30] =>  1:30
+:END:
+",
+            org
+        );
+        //and it can be read back in
+        let clocks = parse_clocks(&org);
+        assert_eq!(Some(datetime(2, 11, 30)), clocks[1].end);
+    }
+}
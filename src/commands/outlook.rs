@@ -0,0 +1,348 @@
+/*
+This file is part of Timetrack Jr.
+Timetrack Jr. is free software: you can redistribute it and/or modify it under the terms of the GNU General Public License as published by the Free Software Foundation, either version 3 of the License, or (at your option) any later version.
+Timetrack Jr. is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU General Public License for more details.
+You should have received a copy of the GNU General Public License along with Timetrack Jr. If not, see <https://www.gnu.org/licenses/>.
+*/
+use crate::{
+    cli::{self, FilterMode},
+    commands::{
+        export::unix_to_utc,
+        sync::{api_request, api_request_if_exists, event_changes, EventChange},
+    },
+    db::{self, PendingTime, PushedEvent, TimeWindow},
+    TTError,
+};
+use chrono::{NaiveDateTime, Utc};
+use rusqlite::Connection;
+use serde::Deserialize;
+use serde_json::{json, Value};
+use std::collections::{BTreeMap, BTreeSet};
+
+const API: &str = "https://graph.microsoft.com/v1.0";
+///Outlook category put on events pushed from ttjr, so they aren't imported back as meetings
+const OUTLOOK_CATEGORY: &str = "Timetrack Jr.";
+const DATETIME_FORMAT: &str = "%Y-%m-%dT%H:%M:%S";
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct GraphDateTime {
+    date_time: String,
+}
+
+#[derive(Deserialize)]
+struct ResponseStatus {
+    response: Option<String>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Meeting {
+    id: String,
+    subject: Option<String>,
+    start: GraphDateTime,
+    end: GraphDateTime,
+    #[serde(default)]
+    categories: Vec<String>,
+    #[serde(default)]
+    is_cancelled: bool,
+    #[serde(default)]
+    is_all_day: bool,
+    response_status: Option<ResponseStatus>,
+}
+
+struct Outlook {
+    authorization: String,
+    ///Path of the calendar times are pushed to
+    calendar: String,
+    ///outlook-category option for each category that has one
+    outlook_categories: BTreeMap<String, String>,
+    categories: db::Categories,
+}
+
+///Graph returns times (in UTC, as requested) like 2024-01-02T10:00:00.0000000
+fn parse_datetime(datetime: &GraphDateTime) -> Result<i64, TTError> {
+    NaiveDateTime::parse_from_str(&datetime.date_time, "%Y-%m-%dT%H:%M:%S%.f")
+        .map(|d| d.and_utc().timestamp())
+        .map_err(|_| TTError::Network {
+            message: format!(
+                "Outlook returned an invalid time \"{}\"",
+                datetime.date_time
+            ),
+        })
+}
+
+fn graph_datetime(tstamp: i64) -> Value {
+    json!({
+        "dateTime": unix_to_utc(&tstamp).format(DATETIME_FORMAT).to_string(),
+        "timeZone": "UTC",
+    })
+}
+
+impl Outlook {
+    fn headers(&self) -> [(&str, &str); 2] {
+        [
+            ("Authorization", &self.authorization),
+            ("Prefer", "outlook.timezone=\"UTC\""),
+        ]
+    }
+
+    fn request(&self, method: &str, path: &str, body: Option<&Value>) -> Result<Value, TTError> {
+        api_request(method, &format!("{}{}", API, path), &self.headers(), body)
+    }
+
+    ///Make a request to an event, returning None if it was deleted in Outlook
+    fn event_request(
+        &self,
+        method: &str,
+        event_id: &str,
+        body: Option<&Value>,
+    ) -> Result<Option<Value>, TTError> {
+        api_request_if_exists(
+            method,
+            &format!("{}/me/events/{}", API, event_id),
+            &self.headers(),
+            body,
+        )
+    }
+
+    fn event_body(&self, time: &TimeWindow) -> Value {
+        json!({
+            "subject": time.category,
+            "body": {
+                "contentType": "text",
+                "content": format!("Timetrack Jr. time {}", time.id.unwrap_or(-1)),
+            },
+            "start": graph_datetime(time.start_time),
+            "end": graph_datetime(time.end_time.unwrap_or(time.start_time)),
+            "categories": [OUTLOOK_CATEGORY],
+            "showAs": "free",
+            "isReminderOn": false,
+        })
+    }
+
+    fn create(&self, time: &TimeWindow) -> Result<String, TTError> {
+        let created = self.request(
+            "POST",
+            &format!("{}/events", self.calendar),
+            Some(&self.event_body(time)),
+        )?;
+        match created["id"].as_str() {
+            Some(id) => Ok(id.to_string()),
+            None => Err(TTError::Network {
+                message: "Outlook didn't return the id of the new event".to_string(),
+            }),
+        }
+    }
+
+    ///Meetings are matched to categories by Outlook category (with the outlook-category category option), then by subject
+    fn category(&self, meeting: &Meeting) -> Option<String> {
+        let by_outlook_category = self
+            .outlook_categories
+            .iter()
+            .find(|(_, c)| meeting.categories.contains(c))
+            .map(|(category, _)| category.clone());
+        by_outlook_category.or(meeting
+            .subject
+            .clone()
+            .filter(|s| self.categories.contains(s)))
+    }
+
+    ///Finished meetings (that weren't declined or pushed from ttjr) between `start` and `end`
+    fn meetings(&self, start: i64, end: i64) -> Result<Vec<PendingTime>, TTError> {
+        let mut url = format!(
+            "{}/me/calendar/calendarView?startDateTime={}&endDateTime={}&$top=100",
+            API,
+            graph_datetime(start)["dateTime"]
+                .as_str()
+                .unwrap_or_default(),
+            graph_datetime(end)["dateTime"].as_str().unwrap_or_default(),
+        );
+        let mut pending = vec![];
+        loop {
+            let page = api_request("GET", &url, &self.headers(), None)?;
+            let meetings: Vec<Meeting> = serde_json::from_value(page["value"].clone())?;
+            for meeting in meetings {
+                let declined = meeting
+                    .response_status
+                    .as_ref()
+                    .and_then(|r| r.response.as_deref())
+                    == Some("declined");
+                if meeting.is_cancelled
+                    || meeting.is_all_day
+                    || declined
+                    || meeting.categories.iter().any(|c| c == OUTLOOK_CATEGORY)
+                {
+                    continue;
+                }
+                let end_time = parse_datetime(&meeting.end)?;
+                if end_time > end {
+                    continue;
+                }
+                pending.push(PendingTime {
+                    id: None,
+                    source: "outlook".to_string(),
+                    category: self.category(&meeting),
+                    title: meeting.subject.clone().unwrap_or_default(),
+                    source_id: meeting.id,
+                    start_time: parse_datetime(&meeting.start)?,
+                    end_time,
+                });
+            }
+            match page["@odata.nextLink"].as_str() {
+                Some(next) => url = next.to_string(),
+                None => break,
+            }
+        }
+        Ok(pending)
+    }
+}
+
+fn record(conn: &mut Connection, event_id: String, time: &TimeWindow) -> Result<(), TTError> {
+    let event = PushedEvent {
+        time_id: time.id.unwrap_or(-1),
+        event_id,
+        etag: None,
+        category: time.category.clone(),
+        start_time: time.start_time,
+        end_time: time.end_time.unwrap_or(time.start_time),
+    };
+    db::write(conn, |tx| db::set_pushed_event(tx, "outlook", &event))
+}
+
+///Mirror finished times since `start_time` (or the last 30 days) to an Outlook calendar with Microsoft Graph,
+/// optionally importing finished meetings as pending times that need to be confirmed
+pub fn sync_outlook(
+    conn: &mut Connection,
+    start_time: &Option<String>,
+    import_meetings: &bool,
+) -> Result<(), TTError> {
+    let start_time = start_time.clone().unwrap_or("30 days ago".to_string());
+    let start = cli::time_string_to_tstamp(&Some(start_time.clone()))
+        .ok_or_else(|| TTError::unparseable(&start_time, "start-time"))?;
+    let end = Utc::now().timestamp();
+    let (options, category_options, categories, times, pushed) = {
+        let mut tx = conn.transaction()?;
+        (
+            db::get_options(&tx)?,
+            db::get_category_options(&tx)?,
+            db::get_categories(&tx)?,
+            db::get_times(&mut tx, Some(start), Some(end), &FilterMode::Start)?,
+            db::get_pushed_events(&tx, "outlook")?,
+        )
+    };
+    let token = std::env::var("OUTLOOK_ACCESS_TOKEN")
+        .ok()
+        .or(options.get("outlook-access-token").cloned())
+        .ok_or(TTError::NotConfigured {
+            message: "Set a Microsoft Graph access token in OUTLOOK_ACCESS_TOKEN (or with `ttjr set-option outlook-access-token <token>`)".to_string(),
+        })?;
+    let outlook = Outlook {
+        authorization: format!("Bearer {}", token),
+        calendar: match options.get("outlook-calendar") {
+            Some(calendar) => format!("/me/calendars/{}", calendar),
+            None => "/me/calendar".to_string(),
+        },
+        outlook_categories: category_options
+            .iter()
+            .filter_map(|(category, options)| {
+                Some((category.clone(), options.get("outlook-category")?.clone()))
+            })
+            .collect(),
+        categories,
+    };
+
+    let deleted_locally: BTreeSet<i64> = {
+        let tx = conn.transaction()?;
+        pushed
+            .iter()
+            .map(|p| p.time_id)
+            .filter(|id| {
+                matches!(
+                    db::get_time(&tx, *id),
+                    Err(TTError::Sql(rusqlite::Error::QueryReturnedNoRows))
+                )
+            })
+            .collect()
+    };
+    let (mut created, mut updated, mut deleted) = (0, 0, 0);
+    for change in event_changes(&times, pushed, |id| deleted_locally.contains(&id)) {
+        match change {
+            EventChange::Create(time) => {
+                let event_id = outlook.create(&time)?;
+                record(conn, event_id, &time)?;
+                created += 1;
+            }
+            EventChange::Update(time, event) => {
+                let body = outlook.event_body(&time);
+                let event_id = match outlook.event_request("PATCH", &event.event_id, Some(&body))? {
+                    Some(_) => event.event_id,
+                    //deleted in Outlook, put it back
+                    None => outlook.create(&time)?,
+                };
+                record(conn, event_id, &time)?;
+                updated += 1;
+            }
+            EventChange::Delete(event) => {
+                outlook.event_request("DELETE", &event.event_id, None)?;
+                db::write(conn, |tx| {
+                    db::delete_pushed_event(tx, "outlook", event.time_id)
+                })?;
+                deleted += 1;
+            }
+        }
+    }
+    println!(
+        "Created {} events, updated {} events, deleted {} events",
+        created, updated, deleted
+    );
+
+    if *import_meetings {
+        let meetings = outlook.meetings(start, end)?;
+        let imported = db::write(conn, |tx| {
+            let mut imported = 0;
+            for meeting in &meetings {
+                imported += db::add_pending_time(tx, meeting)? as usize;
+            }
+            Ok(imported)
+        })?;
+        println!(
+            "Imported {} meetings, review them with `ttjr list-pending`",
+            imported
+        );
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_meeting_category() {
+        let outlook = Outlook {
+            authorization: String::new(),
+            calendar: String::new(),
+            outlook_categories: BTreeMap::from([("client-a".to_string(), "Client A".to_string())]),
+            categories: ["client-a", "standup"].map(String::from).into(),
+        };
+        let meeting = |subject: &str, categories: Value| -> Meeting {
+            serde_json::from_value(json!({
+                "id": "1",
+                "subject": subject,
+                "start": {"dateTime": "2024-01-02T10:00:00.0000000", "timeZone": "UTC"},
+                "end": {"dateTime": "2024-01-02T10:30:00.0000000", "timeZone": "UTC"},
+                "categories": categories,
+            }))
+            .unwrap()
+        };
+        let by_category = meeting("Weekly sync", json!(["Client A"]));
+        assert_eq!(Some("client-a".to_string()), outlook.category(&by_category));
+        assert_eq!(1704189600, parse_datetime(&by_category.start).unwrap());
+        assert_eq!(
+            Some("standup".to_string()),
+            outlook.category(&meeting("standup", json!([])))
+        );
+        assert_eq!(None, outlook.category(&meeting("Lunch", json!([]))));
+    }
+}
@@ -0,0 +1,205 @@
+/*
+This file is part of Timetrack Jr.
+Timetrack Jr. is free software: you can redistribute it and/or modify it under the terms of the GNU General Public License as published by the Free Software Foundation, either version 3 of the License, or (at your option) any later version.
+Timetrack Jr. is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU General Public License for more details.
+You should have received a copy of the GNU General Public License along with Timetrack Jr. If not, see <https://www.gnu.org/licenses/>.
+*/
+//`ttjr pomodoro`: time a category in work intervals separated by breaks, notifying when each one's over.  Breaks are
+// timed as the break-category if it exists, otherwise nothing's timed during them.
+use crate::{
+    cli::NotificationEvent,
+    commands::{log, notify},
+    db, l10n, TTError,
+};
+use chrono::Utc;
+use rusqlite::Connection;
+use std::{thread, time::Duration};
+
+///Read a whole number of minutes from a pomodoro-*-minutes option
+fn minutes(options: &db::Options, option: &str, default: u64) -> u64 {
+    options
+        .get(option)
+        .and_then(|m| m.parse().ok())
+        .unwrap_or(default)
+}
+
+///Stop timing `category` at `ended`.  Returns false (changing nothing) if something other than `category` has been
+/// started since the work interval did, since that's not the pomodoro's to stop.
+fn end_interval(conn: &mut Connection, category: &str, ended: i64) -> Result<bool, TTError> {
+    let open = db::get_last_open_time(&conn.transaction()?)?;
+    if !matches!(open, Some(time) if time.category == category) {
+        return Ok(false);
+    }
+    log::stop_timing_at(conn, ended)?;
+    Ok(true)
+}
+
+///End the work interval at `ended`, timing the break as `break_category` if there is one.  Returns false (changing
+/// nothing) if something other than `category` has been started since the work interval did.
+fn start_break(
+    conn: &mut Connection,
+    category: &str,
+    break_category: &Option<String>,
+    ended: i64,
+) -> Result<bool, TTError> {
+    if !end_interval(conn, category, ended)? {
+        return Ok(false);
+    }
+    if let Some(break_category) = break_category {
+        //times can't share an endpoint
+        log::start_timing_at(conn, break_category, ended + 1)?;
+    }
+    Ok(true)
+}
+
+pub fn pomodoro(
+    conn: &mut Connection,
+    category: &String,
+    cycles: &Option<u32>,
+) -> Result<(), TTError> {
+    let tx = conn.transaction()?;
+    let options = db::get_options(&tx)?;
+    let work = minutes(&options, "pomodoro-work-minutes", 25);
+    let rest = minutes(&options, "pomodoro-break-minutes", 5);
+    let break_category = Some(
+        options
+            .get("break-category")
+            .cloned()
+            .unwrap_or("break".to_string()),
+    )
+    .filter(|b| db::get_categories(&tx).is_ok_and(|categories| categories.contains(b)));
+    drop(tx);
+    let mut cycle = 1;
+    loop {
+        log::start_timing(conn, category, &None, &false)?;
+        println!(
+            "Pomodoro {}: timing {} for {} minutes",
+            cycle, category, work
+        );
+        thread::sleep(Duration::from_secs(work * 60));
+        let ended = Utc::now().timestamp();
+        if matches!(cycles, Some(last) if cycle >= *last) {
+            if !end_interval(conn, category, ended)? {
+                println!(
+                    "{} isn't being timed anymore, ending the pomodoro",
+                    category
+                );
+                return Ok(());
+            }
+            let mut notification = notify::notification(conn, &NotificationEvent::Pomodoro)?;
+            notification
+                .summary(&l10n::text("pomodoro-done", &[("count", cycle.into())]))
+                .body(&l10n::text(
+                    "pomodoro-done-body",
+                    &[("category", category.as_str().into())],
+                ));
+            return notify::show(conn, &NotificationEvent::Pomodoro, &notification);
+        }
+        let mut notification = notify::notification(conn, &NotificationEvent::Pomodoro)?;
+        notification
+            .summary(&l10n::text("pomodoro-break", &[("count", cycle.into())]))
+            .body(&l10n::text(
+                "pomodoro-break-body",
+                &[("minutes", rest.into())],
+            ));
+        let actions = vec![("stop".to_string(), l10n::text("pomodoro-stop", &[]))];
+        let choice = notify::choose(
+            conn,
+            &NotificationEvent::Pomodoro,
+            &mut notification,
+            &actions,
+        )?;
+        if choice.as_deref() == Some("stop") {
+            end_interval(conn, category, ended)?;
+            return Ok(());
+        }
+        if !start_break(conn, category, &break_category, ended)? {
+            println!(
+                "{} isn't being timed anymore, ending the pomodoro",
+                category
+            );
+            return Ok(());
+        }
+        println!("Break for {} minutes", rest);
+        //the notification may have waited for an answer, which counts towards the break
+        let remaining = (rest * 60).saturating_sub((Utc::now().timestamp() - ended) as u64);
+        thread::sleep(Duration::from_secs(remaining));
+        //don't take over from something started during the break
+        match db::get_last_open_time(&conn.transaction()?)? {
+            Some(time) if Some(&time.category) != break_category.as_ref() => {
+                println!(
+                    "{} was started during the break, ending the pomodoro",
+                    time.category
+                );
+                return Ok(());
+            }
+            _ => {}
+        }
+        let mut notification = notify::notification(conn, &NotificationEvent::Pomodoro)?;
+        notification
+            .summary(&l10n::text("pomodoro-resume", &[]))
+            .body(&l10n::text(
+                "pomodoro-resume-body",
+                &[("category", category.as_str().into())],
+            ));
+        notify::show(conn, &NotificationEvent::Pomodoro, &notification)?;
+        cycle += 1;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_start_break() {
+        let mut conn = Connection::open_in_memory().unwrap();
+        db::initialize_db(&mut conn).unwrap();
+        for category in ["work", "break", "other"] {
+            let tx = conn.transaction().unwrap();
+            db::add_category(&tx, &category.to_string()).unwrap();
+            tx.commit().unwrap();
+        }
+        let break_category = Some("break".to_string());
+        let started = Utc::now().timestamp() - 60 * 60;
+        log::start_timing_at(&mut conn, "work", started).unwrap();
+        let ended = started + 25 * 60;
+        assert!(start_break(&mut conn, "work", &break_category, ended).unwrap());
+        let tx = conn.transaction().unwrap();
+        let open = db::get_last_open_time(&tx).unwrap().unwrap();
+        assert_eq!(
+            ("break", ended + 1),
+            (open.category.as_str(), open.start_time)
+        );
+        assert_eq!(
+            Some(ended),
+            db::get_last_closed_time(&tx).unwrap().unwrap().end_time
+        );
+        drop(tx);
+        //something else being timed by the end of the work interval ends the pomodoro, without stopping it
+        assert!(!start_break(&mut conn, "work", &break_category, ended + 60).unwrap());
+        assert!(!end_interval(&mut conn, "work", ended + 60).unwrap());
+        let tx = conn.transaction().unwrap();
+        assert_eq!(None, db::get_last_open_time(&tx).unwrap().unwrap().end_time);
+        drop(tx);
+        //without a break category nothing's timed during breaks
+        log::stop_timing_at(&mut conn, ended + 5 * 60).unwrap();
+        log::start_timing_at(&mut conn, "work", ended + 6 * 60).unwrap();
+        assert!(start_break(&mut conn, "work", &None, ended + 30 * 60).unwrap());
+        let tx = conn.transaction().unwrap();
+        assert!(db::get_last_open_time(&tx).unwrap().is_none());
+        drop(tx);
+        //switching to another category mid-interval leaves it running when the interval's over
+        log::start_timing_at(&mut conn, "work", ended + 31 * 60).unwrap();
+        log::stop_timing_at(&mut conn, ended + 32 * 60).unwrap();
+        log::start_timing_at(&mut conn, "other", ended + 32 * 60 + 1).unwrap();
+        assert!(!end_interval(&mut conn, "work", ended + 35 * 60).unwrap());
+        let tx = conn.transaction().unwrap();
+        let open = db::get_last_open_time(&tx).unwrap().unwrap();
+        assert_eq!(("other", None), (open.category.as_str(), open.end_time));
+        let mut options = db::Options::new();
+        assert_eq!(25, minutes(&options, "pomodoro-work-minutes", 25));
+        options.insert("pomodoro-work-minutes".to_string(), "50".to_string());
+        assert_eq!(50, minutes(&options, "pomodoro-work-minutes", 25));
+    }
+}
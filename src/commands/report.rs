@@ -0,0 +1,1595 @@
+/*
+This file is part of Timetrack Jr.
+Timetrack Jr. is free software: you can redistribute it and/or modify it under the terms of the GNU General Public License as published by the Free Software Foundation, either version 3 of the License, or (at your option) any later version.
+Timetrack Jr. is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU General Public License for more details.
+You should have received a copy of the GNU General Public License along with Timetrack Jr. If not, see <https://www.gnu.org/licenses/>.
+*/
+use crate::{
+    cli::{self, EarningsGroup, FilterMode, NotificationEvent, ReportFilter, TableOptions},
+    commands::export::{parse_time_range, roll_up_category, select_categories, write_range_header},
+    commands::notify,
+    commands::table::{number, text, ReportTable},
+    db::{self, TimeWindow},
+    zone::Zone,
+    TTError,
+};
+use chrono::{Datelike, Local, NaiveDate};
+use rusqlite::{Connection, Transaction};
+use serde::Serialize;
+use serde_json::json;
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    io::{self, Write},
+};
+
+///Format a number of seconds as HH:MM
+pub(crate) fn hours_minutes(seconds: u64) -> String {
+    format!("{:02}:{:02}", seconds / 60 / 60, seconds / 60 % 60)
+}
+
+///Render values as a row of unicode block characters scaled to the largest value
+pub(crate) fn sparkline(values: &[u64]) -> String {
+    const BARS: [char; 8] = ['▁', '▂', '▃', '▄', '▅', '▆', '▇', '█'];
+    let max = values.iter().copied().max().unwrap_or(0);
+    values
+        .iter()
+        .map(|v| match max {
+            0 => BARS[0],
+            max => BARS[((v * (BARS.len() as u64 - 1)) / max) as usize],
+        })
+        .collect()
+}
+
+///Print a JSON document to stdout, used by reports run with --json
+pub(crate) fn print_json(document: &serde_json::Value) -> Result<(), TTError> {
+    println!("{}", serde_json::to_string_pretty(document)?);
+    Ok(())
+}
+
+///Times selected by a report filter, along with the parsed start/end of the range
+struct ReportTimes {
+    times: Vec<TimeWindow>,
+    start: Option<i64>,
+    end: Option<i64>,
+    zone: Zone,
+}
+
+///Times selected by a report filter, which may be empty
+fn select_times(conn: &mut Connection, filter: &ReportFilter) -> Result<ReportTimes, TTError> {
+    let zone = filter.timezone.unwrap_or_default();
+    let (start, end) = parse_time_range(&filter.start_time, &filter.end_time, &zone)?;
+    let mut tx = conn.transaction()?;
+    let mode = filter.filter_mode.clone().unwrap_or(FilterMode::Overlap);
+    let times = db::get_times(&mut tx, start, end, &mode)?;
+    let mut times = select_categories(&tx, times, filter)?;
+    tx.commit()?;
+    if matches!(mode, FilterMode::Overlap) {
+        times = times.iter().map(|t| t.clipped(start, end)).collect();
+    }
+    Ok(ReportTimes {
+        times,
+        start,
+        end,
+        zone,
+    })
+}
+
+fn fetch_times(conn: &mut Connection, filter: &ReportFilter) -> Result<ReportTimes, TTError> {
+    let report = select_times(conn, filter)?;
+    if report.times.is_empty() {
+        return Err(TTError::NothingFound {
+            message: "Didn't find any times to summarize".to_string(),
+        });
+    }
+    Ok(report)
+}
+
+#[derive(Debug, PartialEq, Eq, Serialize)]
+struct CategoryStats {
+    ///number of sessions, including any that are still open
+    count: u64,
+    ///total seconds of closed sessions
+    total: u64,
+    mean: u64,
+    median: u64,
+    longest: u64,
+    first_activity: i64,
+    last_activity: i64,
+    ///number of distinct days with at least one session
+    active_days: u64,
+}
+
+///Median of sorted values, 0 if there aren't any
+fn median(sorted: &[u64]) -> u64 {
+    match sorted.len() {
+        0 => 0,
+        n if n % 2 == 0 => (sorted[n / 2 - 1] + sorted[n / 2]) / 2,
+        n => sorted[n / 2],
+    }
+}
+
+///Calculate per-category stats.  Open times count as sessions but don't contribute to durations.
+fn category_stats(times: &[TimeWindow], zone: &Zone) -> BTreeMap<String, CategoryStats> {
+    let mut by_category = BTreeMap::<&String, Vec<&TimeWindow>>::new();
+    for time in times {
+        by_category.entry(&time.category).or_default().push(time);
+    }
+
+    let mut stats = BTreeMap::new();
+    for (category, times) in by_category {
+        let mut durations: Vec<u64> = times
+            .iter()
+            .filter_map(|t| t.end_time.map(|end| (end - t.start_time).unsigned_abs()))
+            .collect();
+        durations.sort_unstable();
+        let total: u64 = durations.iter().sum();
+        let median = median(&durations);
+        let days: BTreeSet<NaiveDate> = times.iter().map(|t| zone.date(&t.start_time)).collect();
+
+        stats.insert(
+            category.clone(),
+            CategoryStats {
+                count: times.len() as u64,
+                total,
+                mean: total.checked_div(durations.len() as u64).unwrap_or(0),
+                median,
+                longest: durations.last().copied().unwrap_or(0),
+                first_activity: times.iter().map(|t| t.start_time).min().unwrap(),
+                last_activity: times
+                    .iter()
+                    .map(|t| t.end_time.unwrap_or(t.start_time))
+                    .max()
+                    .unwrap(),
+                active_days: days.len() as u64,
+            },
+        );
+    }
+    stats
+}
+
+pub fn stats(
+    conn: &mut Connection,
+    filter: &ReportFilter,
+    table_options: &TableOptions,
+) -> Result<(), TTError> {
+    let report = fetch_times(conn, filter)?;
+    let stats = category_stats(&report.times, &report.zone);
+    if filter.json {
+        return print_json(&json!({
+            "start": report.start,
+            "end": report.end,
+            "categories": stats,
+        }));
+    }
+    let mut handle: Box<dyn Write> = Box::new(io::stdout());
+    write_range_header(&mut handle, report.start, report.end, &report.zone)?;
+
+    let mut table = ReportTable::new(vec![
+        text("category"),
+        number("sessions"),
+        number("total"),
+        number("mean"),
+        number("median"),
+        number("longest"),
+        text("first"),
+        text("last"),
+        number("per-day"),
+        number("days"),
+    ]);
+    let datetime = |tstamp: &i64| report.zone.format(tstamp, "%Y-%m-%d %H:%M");
+    for (category, stats) in &stats {
+        table.row(vec![
+            category.clone(),
+            stats.count.to_string(),
+            hours_minutes(stats.total),
+            hours_minutes(stats.mean),
+            hours_minutes(stats.median),
+            hours_minutes(stats.longest),
+            datetime(&stats.first_activity),
+            datetime(&stats.last_activity),
+            format!("{:.2}", stats.count as f64 / stats.active_days as f64),
+            stats.active_days.to_string(),
+        ]);
+    }
+    table.totals(vec![
+        "total".to_string(),
+        stats.values().map(|s| s.count).sum::<u64>().to_string(),
+        hours_minutes(stats.values().map(|s| s.total).sum()),
+        "".to_string(),
+        "".to_string(),
+        hours_minutes(stats.values().map(|s| s.longest).max().unwrap_or(0)),
+        stats
+            .values()
+            .map(|s| s.first_activity)
+            .min()
+            .map_or("".to_string(), |t| datetime(&t)),
+        stats
+            .values()
+            .map(|s| s.last_activity)
+            .max()
+            .map_or("".to_string(), |t| datetime(&t)),
+        "".to_string(),
+        "".to_string(),
+    ]);
+    handle.write_all(table.render(table_options)?.as_bytes())?;
+    handle.flush()?;
+    Ok(())
+}
+
+///Entries longer than this multiple of their category's median are flagged as anomalies
+const ANOMALY_MEDIAN_MULTIPLE: u64 = 3;
+///Categories need at least this many closed entries before entries are compared to the median
+const ANOMALY_MIN_ENTRIES: usize = 3;
+///Entries longer than this are always flagged as anomalies
+const ANOMALY_MAX_SECONDS: u64 = 18 * 60 * 60;
+
+#[derive(Debug, PartialEq, Eq, Serialize)]
+struct Anomaly {
+    id: Option<i64>,
+    category: String,
+    start_time: i64,
+    end_time: Option<i64>,
+    ///for open entries, the time they've been running up to now
+    #[serde(rename = "duration_seconds")]
+    duration: u64,
+    reasons: Vec<String>,
+}
+
+///Find entries which are probably mistakes (like forgetting to stop timing), oldest first
+fn find_anomalies(times: &[TimeWindow], now: i64) -> Vec<Anomaly> {
+    let mut durations = BTreeMap::<&String, Vec<u64>>::new();
+    for time in times {
+        if let Some(end) = time.end_time {
+            durations
+                .entry(&time.category)
+                .or_default()
+                .push((end - time.start_time).unsigned_abs());
+        }
+    }
+    let medians: BTreeMap<&String, u64> = durations
+        .into_iter()
+        .filter(|(_, d)| d.len() >= ANOMALY_MIN_ENTRIES)
+        .map(|(category, mut d)| {
+            d.sort_unstable();
+            (category, median(&d))
+        })
+        .collect();
+
+    let mut anomalies = vec![];
+    for time in times {
+        let duration = (time.end_time.unwrap_or(now) - time.start_time).unsigned_abs();
+        let mut reasons = vec![];
+        if duration > ANOMALY_MAX_SECONDS {
+            reasons.push(format!(
+                "longer than {} hours",
+                ANOMALY_MAX_SECONDS / 60 / 60
+            ));
+        }
+        if let Some(median) = medians.get(&time.category) {
+            if *median > 0 && duration > median * ANOMALY_MEDIAN_MULTIPLE {
+                reasons.push(format!(
+                    "more than {}x the category's median of {}",
+                    ANOMALY_MEDIAN_MULTIPLE,
+                    hours_minutes(*median)
+                ));
+            }
+        }
+        if !reasons.is_empty() {
+            anomalies.push(Anomaly {
+                id: time.id,
+                category: time.category.clone(),
+                start_time: time.start_time,
+                end_time: time.end_time,
+                duration,
+                reasons,
+            });
+        }
+    }
+    anomalies.sort_by_key(|a| a.start_time);
+    anomalies
+}
+
+pub fn anomalies(conn: &mut Connection, filter: &ReportFilter) -> Result<(), TTError> {
+    //clipping entries to the requested range would hide how long they really were
+    let report = fetch_times(
+        conn,
+        &ReportFilter {
+            filter_mode: Some(filter.filter_mode.clone().unwrap_or(FilterMode::Start)),
+            ..filter.clone()
+        },
+    )?;
+    let anomalies = find_anomalies(&report.times, Local::now().timestamp());
+    if filter.json {
+        return print_json(&json!({
+            "start": report.start,
+            "end": report.end,
+            "anomalies": anomalies,
+        }));
+    }
+    let mut handle: Box<dyn Write> = Box::new(io::stdout());
+    write_range_header(&mut handle, report.start, report.end, &report.zone)?;
+    if anomalies.is_empty() {
+        handle.write_all("No anomalies found\n".as_bytes())?;
+    }
+    for anomaly in anomalies {
+        handle.write_all(
+            format!(
+                "{} (id {}): {} from {} to {}\n  {}\n",
+                anomaly.category,
+                anomaly.id.unwrap_or(-1),
+                hours_minutes(anomaly.duration),
+                report.zone.rfc2822(&anomaly.start_time),
+                anomaly
+                    .end_time
+                    .map_or("now (still running)".to_string(), |e| report
+                        .zone
+                        .rfc2822(&e)),
+                anomaly.reasons.join(", "),
+            )
+            .as_bytes(),
+        )?;
+    }
+    handle.flush()?;
+    Ok(())
+}
+
+///Split closed times which cross midnight (in `zone`) into one piece per day, so that each day gets credited
+/// with the time actually spent on it.  Open times are passed through as-is.
+pub(crate) fn split_by_day(times: &[TimeWindow], zone: &Zone) -> Vec<TimeWindow> {
+    let mut split = vec![];
+    for time in times {
+        let mut piece = time.clone();
+        while let Some(end) = piece.end_time {
+            let next_midnight =
+                zone.midnight(zone.date(&piece.start_time) + chrono::Duration::days(1));
+            if end <= next_midnight {
+                break;
+            }
+            split.push(piece.clipped(None, Some(next_midnight)));
+            piece = piece.clipped(Some(next_midnight), None);
+        }
+        split.push(piece);
+    }
+    split
+}
+
+///Number of days of activity shown as a sparkline in summaries
+const RECENT_DAYS: i64 = 14;
+
+///Daily totals for each category selected by `filter` over the `RECENT_DAYS` days ending on `last_day`,
+/// counting running time up to now
+pub(crate) fn recent_activity(
+    tx: &mut Transaction,
+    filter: &ReportFilter,
+    last_day: NaiveDate,
+    zone: &Zone,
+) -> Result<BTreeMap<String, Vec<u64>>, TTError> {
+    let first_day = last_day - chrono::Duration::days(RECENT_DAYS - 1);
+    let start = zone.midnight(first_day);
+    let end = zone.midnight(last_day + chrono::Duration::days(1)) - 1;
+    let now = Local::now().timestamp();
+    let times = db::get_times(tx, Some(start), Some(end), &FilterMode::Overlap)?;
+    let times: Vec<TimeWindow> = select_categories(tx, times, filter)?
+        .iter()
+        .map(|t| TimeWindow {
+            end_time: Some(t.end_time.unwrap_or(now)),
+            ..t.clone()
+        })
+        .map(|t| t.clipped(Some(start), Some(end)))
+        .collect();
+    Ok(daily_series(&split_by_day(&times, zone), first_day, zone))
+}
+
+///Closed time per category for each of the `RECENT_DAYS` days starting on `first_day`
+fn daily_series(
+    times: &[TimeWindow],
+    first_day: NaiveDate,
+    zone: &Zone,
+) -> BTreeMap<String, Vec<u64>> {
+    let mut series = BTreeMap::<String, Vec<u64>>::new();
+    for time in times {
+        let index = (zone.date(&time.start_time) - first_day).num_days();
+        if let (Some(end), 0..RECENT_DAYS) = (time.end_time, index) {
+            series
+                .entry(time.category.clone())
+                .or_insert_with(|| vec![0; RECENT_DAYS as usize])[index as usize] +=
+                (end - time.start_time).unsigned_abs();
+        }
+    }
+    series
+}
+
+///Time logged beyond a daily cap, per category
+pub(crate) type Excess = BTreeMap<String, u64>;
+
+///Trim closed times so that no more than `cap` seconds are counted on any day, across all categories.
+/// Time is counted in the order it was logged, so the time trimmed is the time logged last each day.
+/// Returns the trimmed times along with how much time was trimmed from each category.
+pub(crate) fn apply_daily_cap(
+    times: &[TimeWindow],
+    cap: u64,
+    zone: &Zone,
+) -> (Vec<TimeWindow>, Excess) {
+    let mut pieces = split_by_day(times, zone);
+    pieces.sort_by_key(|t| t.start_time);
+    let mut counted = BTreeMap::<NaiveDate, u64>::new();
+    let mut excess = Excess::new();
+    let mut capped = vec![];
+    for mut time in pieces {
+        if let Some(end) = time.end_time {
+            let day_total = counted.entry(zone.date(&time.start_time)).or_default();
+            let duration = (end - time.start_time).unsigned_abs();
+            let allowed = duration.min(cap.saturating_sub(*day_total));
+            *day_total += allowed;
+            if allowed < duration {
+                *excess.entry(time.category.clone()).or_default() += duration - allowed;
+                if allowed == 0 {
+                    continue;
+                }
+                time.end_time = Some(time.start_time + allowed as i64);
+            }
+        }
+        capped.push(time);
+    }
+    (capped, excess)
+}
+
+///Convert a --daily-cap in hours to seconds
+pub(crate) fn daily_cap_seconds(hours: &f64) -> Result<u64, TTError> {
+    if *hours <= 0.0 {
+        return Err(TTError::invalid(
+            "--daily-cap",
+            &hours.to_string(),
+            "a positive number of hours",
+        ));
+    }
+    Ok((hours * 60.0 * 60.0).round() as u64)
+}
+
+///Total closed time per day for a category, including days with no time between the first and last day
+fn daily_totals(times: &[TimeWindow], category: &String, zone: &Zone) -> BTreeMap<NaiveDate, u64> {
+    let mut totals = BTreeMap::<NaiveDate, u64>::new();
+    for time in times.iter().filter(|t| &t.category == category) {
+        let total = totals.entry(zone.date(&time.start_time)).or_default();
+        if let Some(end) = time.end_time {
+            *total += (end - time.start_time).unsigned_abs();
+        }
+    }
+    if let (Some(first), Some(last)) = (
+        totals.keys().next().copied(),
+        totals.keys().next_back().copied(),
+    ) {
+        for day in first.iter_days().take_while(|d| d <= &last) {
+            totals.entry(day).or_default();
+        }
+    }
+    totals
+}
+
+///Trailing moving average over `window` values (averages over fewer values at the start of the series)
+fn moving_average(values: &[u64], window: usize) -> Vec<u64> {
+    (0..values.len())
+        .map(|i| {
+            let from = (i + 1).saturating_sub(window.max(1));
+            let slice = &values[from..=i];
+            slice.iter().sum::<u64>() / slice.len() as u64
+        })
+        .collect()
+}
+
+pub fn trend(
+    conn: &mut Connection,
+    category: &String,
+    window: &usize,
+    filter: &ReportFilter,
+    table_options: &TableOptions,
+) -> Result<(), TTError> {
+    let report = fetch_times(conn, filter)?;
+    let totals = daily_totals(
+        &split_by_day(&report.times, &report.zone),
+        category,
+        &report.zone,
+    );
+    if totals.is_empty() {
+        return Err(TTError::NothingFound {
+            message: format!("Didn't find any times for category \"{}\"", category),
+        });
+    }
+    let values: Vec<u64> = totals.values().copied().collect();
+    let averages = moving_average(&values, *window);
+    if filter.json {
+        return print_json(&json!({
+            "start": report.start,
+            "end": report.end,
+            "category": category,
+            "window": window,
+            "days": totals
+                .iter()
+                .zip(averages.iter())
+                .map(|((day, total), average)| json!({
+                    "date": day.to_string(),
+                    "total_seconds": total,
+                    "average_seconds": average,
+                }))
+                .collect::<Vec<_>>(),
+        }));
+    }
+
+    let mut handle: Box<dyn Write> = Box::new(io::stdout());
+    write_range_header(&mut handle, report.start, report.end, &report.zone)?;
+    handle.write_all(format!("{}-day moving average\n", window).as_bytes())?;
+    let mut table = ReportTable::new(vec![text("date"), number("total"), number("average")]);
+    for ((day, total), average) in totals.iter().zip(averages.iter()) {
+        table.row(vec![
+            day.to_string(),
+            hours_minutes(*total),
+            hours_minutes(*average),
+        ]);
+    }
+    table.totals(vec![
+        "total".to_string(),
+        hours_minutes(values.iter().sum()),
+        "".to_string(),
+    ]);
+    handle.write_all(table.render(table_options)?.as_bytes())?;
+    handle.write_all(format!("daily:   {}\n", sparkline(&values)).as_bytes())?;
+    handle.write_all(format!("average: {}\n", sparkline(&averages)).as_bytes())?;
+    handle.flush()?;
+    Ok(())
+}
+
+///Hourly rate for each category which has one configured
+fn category_rates(conn: &mut Connection) -> Result<BTreeMap<String, f64>, TTError> {
+    let tx = conn.transaction()?;
+    let mut rates = BTreeMap::new();
+    for (category, options) in db::get_category_options(&tx)? {
+        if let Some(rate) = options.get("rate").and_then(|r| r.parse::<f64>().ok()) {
+            rates.insert(category, rate);
+        }
+    }
+    Ok(rates)
+}
+
+///Client for each category which has one configured
+fn category_clients(conn: &mut Connection) -> Result<BTreeMap<String, String>, TTError> {
+    let tx = conn.transaction()?;
+    let mut clients = BTreeMap::new();
+    for (category, options) in db::get_category_options(&tx)? {
+        if let Some(client) = options.get("client") {
+            clients.insert(category, client.clone());
+        }
+    }
+    Ok(clients)
+}
+
+fn currency(conn: &mut Connection) -> Result<String, TTError> {
+    let tx = conn.transaction()?;
+    Ok(db::get_options(&tx)?
+        .get("currency")
+        .cloned()
+        .unwrap_or_default())
+}
+
+fn earnings_group(
+    time: &TimeWindow,
+    category: &String,
+    group_by: &EarningsGroup,
+    zone: &Zone,
+) -> String {
+    let date = zone.date(&time.start_time);
+    match group_by {
+        EarningsGroup::Week => {
+            let week = date.iso_week();
+            format!("{}-W{:02}", week.year(), week.week())
+        }
+        EarningsGroup::Month => date.format("%Y-%m").to_string(),
+        EarningsGroup::Category => category.clone(),
+    }
+}
+
+#[derive(Default, Serialize)]
+struct Billed {
+    #[serde(rename = "total_seconds")]
+    seconds: u64,
+    amount: f64,
+}
+
+impl Billed {
+    fn amount(&self, currency: &String) -> String {
+        format!("{}{:.2}", currency, self.amount)
+    }
+}
+
+pub fn earnings(
+    conn: &mut Connection,
+    group_by: &EarningsGroup,
+    daily_cap: &Option<f64>,
+    filter: &ReportFilter,
+    table_options: &TableOptions,
+) -> Result<(), TTError> {
+    let rates = category_rates(conn)?;
+    if rates.is_empty() {
+        return Err(TTError::NotConfigured {
+            message: "No categories have a rate, use `ttjr set-category-option <category> rate <hourly-rate>` to set one".to_string(),
+        });
+    }
+    let currency = currency(conn)?;
+    //rates belong to un-rolled-up categories, so calculate amounts before applying --depth
+    let report = fetch_times(
+        conn,
+        &ReportFilter {
+            depth: None,
+            ..filter.clone()
+        },
+    )?;
+
+    let (times, excess) = match daily_cap {
+        Some(cap) => apply_daily_cap(&report.times, daily_cap_seconds(cap)?, &report.zone),
+        None => (report.times, Excess::new()),
+    };
+    //excess is only interesting for billable categories
+    let excess: Excess = excess
+        .into_iter()
+        .filter(|(category, _)| rates.contains_key(category))
+        .collect();
+
+    //time and amount logged per group, per category
+    let mut groups = BTreeMap::<String, BTreeMap<String, Billed>>::new();
+    for time in &times {
+        if let (Some(end), Some(rate)) = (time.end_time, rates.get(&time.category)) {
+            let seconds = (end - time.start_time).unsigned_abs();
+            let category = filter.depth.map_or(time.category.clone(), |d| {
+                roll_up_category(&time.category, d)
+            });
+            let group = earnings_group(time, &category, group_by, &report.zone);
+            let billed = groups
+                .entry(group)
+                .or_default()
+                .entry(category)
+                .or_default();
+            billed.seconds += seconds;
+            billed.amount += seconds as f64 / 60.0 / 60.0 * rate;
+        }
+    }
+    let mut total = Billed::default();
+    for billed in groups.values().flat_map(|categories| categories.values()) {
+        total.seconds += billed.seconds;
+        total.amount += billed.amount;
+    }
+    let group_total = |categories: &BTreeMap<String, Billed>| Billed {
+        seconds: categories.values().map(|b| b.seconds).sum(),
+        amount: categories.values().map(|b| b.amount).sum(),
+    };
+
+    if filter.json {
+        return print_json(&json!({
+            "start": report.start,
+            "end": report.end,
+            "currency": currency,
+            "groups": groups
+                .iter()
+                .map(|(group, categories)| (group.clone(), json!({
+                    "total": group_total(categories),
+                    "categories": categories,
+                })))
+                .collect::<serde_json::Map<_, _>>(),
+            "total": total,
+            "excess_seconds": excess,
+        }));
+    }
+
+    let mut handle: Box<dyn Write> = Box::new(io::stdout());
+    write_range_header(&mut handle, report.start, report.end, &report.zone)?;
+    //grouped by category, each group is just the one category
+    let mut columns = match group_by {
+        EarningsGroup::Category => vec![],
+        EarningsGroup::Week => vec![text("week")],
+        EarningsGroup::Month => vec![text("month")],
+    };
+    columns.extend([text("category"), number("time"), number("amount")]);
+    let mut table = ReportTable::new(columns);
+    let row = |labels: Vec<&String>, billed: &Billed| {
+        let mut cells: Vec<String> = labels.into_iter().cloned().collect();
+        cells.extend([hours_minutes(billed.seconds), billed.amount(&currency)]);
+        cells
+    };
+    for (group, categories) in &groups {
+        if matches!(group_by, EarningsGroup::Category) {
+            table.row(row(vec![group], &group_total(categories)));
+            continue;
+        }
+        for (category, billed) in categories {
+            table.row(row(vec![group, category], billed));
+        }
+        table.row(row(
+            vec![group, &"total".to_string()],
+            &group_total(categories),
+        ));
+    }
+    table.totals(match group_by {
+        EarningsGroup::Category => row(vec![&"total".to_string()], &total),
+        _ => row(vec![&"total".to_string(), &"".to_string()], &total),
+    });
+    handle.write_all(table.render(table_options)?.as_bytes())?;
+    for (category, seconds) in &excess {
+        handle.write_all(
+            format!(
+                "{}: {} over the daily cap (not counted)\n",
+                category,
+                hours_minutes(*seconds)
+            )
+            .as_bytes(),
+        )?;
+    }
+    handle.flush()?;
+    Ok(())
+}
+
+///Hours and amounts per client, per category, for times in a month.  Categories without a rate are billed at 0.
+fn client_rollup(
+    times: &[TimeWindow],
+    clients: &BTreeMap<String, String>,
+    rates: &BTreeMap<String, f64>,
+) -> BTreeMap<String, BTreeMap<String, Billed>> {
+    let mut rollup = BTreeMap::<String, BTreeMap<String, Billed>>::new();
+    for time in times {
+        if let (Some(end), Some(client)) = (time.end_time, clients.get(&time.category)) {
+            let seconds = (end - time.start_time).unsigned_abs();
+            let billed = rollup
+                .entry(client.clone())
+                .or_default()
+                .entry(time.category.clone())
+                .or_default();
+            billed.seconds += seconds;
+            billed.amount +=
+                seconds as f64 / 60.0 / 60.0 * rates.get(&time.category).copied().unwrap_or(0.0);
+        }
+    }
+    rollup
+}
+
+pub fn invoice(
+    conn: &mut Connection,
+    month: &Option<String>,
+    client: &Option<String>,
+    zone: &Zone,
+    json: &bool,
+    table_options: &TableOptions,
+) -> Result<(), TTError> {
+    let day = match month {
+        Some(input) => match zone.parse_time(month) {
+            Some(tstamp) => zone.date(&tstamp),
+            None => return Err(TTError::unparseable(input, "month")),
+        },
+        None => zone.today(),
+    };
+    let first = day.with_day(1).unwrap();
+    let next = first + chrono::Months::new(1);
+    let (start, end) = (zone.midnight(first), zone.midnight(next) - 1);
+
+    let mut clients = category_clients(conn)?;
+    if let Some(client) = client {
+        clients.retain(|_, c| c == client);
+    }
+    if clients.is_empty() {
+        return Err(TTError::NotConfigured {
+            message: match client {
+                Some(client) => format!("No categories have client \"{}\"", client),
+                None => "No categories have a client, use `ttjr set-category-option <category> client <client>` to set one".to_string(),
+            },
+        });
+    }
+    let rates = category_rates(conn)?;
+    let currency = currency(conn)?;
+    let mut tx = conn.transaction()?;
+    let times: Vec<TimeWindow> =
+        db::get_times(&mut tx, Some(start), Some(end), &FilterMode::Overlap)?
+            .iter()
+            .map(|t| t.clipped(Some(start), Some(end)))
+            .collect();
+    tx.commit()?;
+    let rollup = client_rollup(&times, &clients, &rates);
+    let client_total = |categories: &BTreeMap<String, Billed>| Billed {
+        seconds: categories.values().map(|b| b.seconds).sum(),
+        amount: categories.values().map(|b| b.amount).sum(),
+    };
+    let month_name = first.format("%Y-%m").to_string();
+
+    if *json {
+        return print_json(&json!({
+            "month": month_name,
+            "currency": currency,
+            "clients": rollup
+                .iter()
+                .map(|(client, categories)| (client.clone(), json!({
+                    "total": client_total(categories),
+                    "categories": categories,
+                })))
+                .collect::<serde_json::Map<_, _>>(),
+        }));
+    }
+    println!("Invoice summary for {}", month_name);
+    if rollup.is_empty() {
+        println!("No time logged for any client");
+        return Ok(());
+    }
+    let mut table = ReportTable::new(vec![
+        text("client"),
+        text("category"),
+        number("hours"),
+        number("amount"),
+    ]);
+    let row = |client: &String, category: &str, billed: &Billed| {
+        vec![
+            client.clone(),
+            category.to_string(),
+            format_hours(billed.seconds),
+            billed.amount(&currency),
+        ]
+    };
+    for (client, categories) in &rollup {
+        for (category, billed) in categories {
+            table.row(row(client, category, billed));
+        }
+        table.row(row(client, "total", &client_total(categories)));
+    }
+    let everything = Billed {
+        seconds: rollup.values().map(|c| client_total(c).seconds).sum(),
+        amount: rollup.values().map(|c| client_total(c).amount).sum(),
+    };
+    table.totals(row(&"total".to_string(), "", &everything));
+    print!("{}", table.render(table_options)?);
+    Ok(())
+}
+
+///Format a number of seconds as decimal hours, the way they usually appear on an invoice
+fn format_hours(seconds: u64) -> String {
+    format!("{:.2}", seconds as f64 / 60.0 / 60.0)
+}
+
+///The Monday of the week containing `day`
+fn monday_of(day: NaiveDate) -> NaiveDate {
+    day - chrono::Duration::days(day.weekday().num_days_from_monday() as i64)
+}
+
+///Unix timestamp of midnight (in `zone`) on the Monday of the week containing `day`
+pub(crate) fn start_of_week(day: NaiveDate, zone: &Zone) -> i64 {
+    zone.midnight(monday_of(day))
+}
+
+///Seconds logged per category between `start` and `end`, counting running time up to now
+pub(crate) fn totals_between(
+    tx: &mut Transaction,
+    start: i64,
+    end: i64,
+) -> Result<BTreeMap<String, u64>, TTError> {
+    let now = Local::now().timestamp();
+    let mut totals = BTreeMap::<String, u64>::new();
+    for time in db::get_times(tx, Some(start), Some(end), &FilterMode::Overlap)? {
+        let time = time.clipped(Some(start), Some(end));
+        *totals.entry(time.category).or_default() +=
+            (time.end_time.unwrap_or(now) - time.start_time).unsigned_abs();
+    }
+    Ok(totals)
+}
+
+///Seconds logged per category during the week starting at `week_start`, counting running time up to now
+fn week_totals(tx: &mut Transaction, week_start: i64) -> Result<BTreeMap<String, u64>, TTError> {
+    totals_between(tx, week_start, week_start + 7 * 24 * 60 * 60 - 1)
+}
+
+///Categories which have a duration-valued category option set (i.e. weekly-cap), along with the option in seconds
+pub(crate) fn category_durations(
+    tx: &Transaction,
+    option_name: &str,
+) -> Result<BTreeMap<String, u64>, TTError> {
+    let mut durations = BTreeMap::new();
+    for (category, options) in db::get_category_options(tx)? {
+        if let Some(duration) = options
+            .get(option_name)
+            .and_then(|d| cli::duration_string_to_seconds(d))
+        {
+            durations.insert(category, duration.unsigned_abs());
+        }
+    }
+    Ok(durations)
+}
+
+///Planned seconds per day of the week (starting with monday) for each category with a schedule
+fn category_schedules(conn: &mut Connection) -> Result<BTreeMap<String, [u64; 7]>, TTError> {
+    let tx = conn.transaction()?;
+    let mut schedules = BTreeMap::new();
+    for (category, options) in db::get_category_options(&tx)? {
+        if let Some(planned) = options
+            .get("schedule")
+            .and_then(|s| cli::schedule_string_to_seconds(s))
+        {
+            schedules.insert(category, planned);
+        }
+    }
+    Ok(schedules)
+}
+
+#[derive(Debug, PartialEq, Eq)]
+struct ScheduledDay {
+    planned: u64,
+    actual: u64,
+}
+
+impl ScheduledDay {
+    fn shortfall(&self) -> u64 {
+        self.planned.saturating_sub(self.actual)
+    }
+}
+
+///Planned and actual time for each scheduled category on each day from `first` through `last`.
+/// Expects times already split by day, with open times closed.
+fn schedule_diff(
+    times: &[TimeWindow],
+    schedules: &BTreeMap<String, [u64; 7]>,
+    first: NaiveDate,
+    last: NaiveDate,
+    zone: &Zone,
+) -> BTreeMap<NaiveDate, BTreeMap<String, ScheduledDay>> {
+    let mut days = BTreeMap::<NaiveDate, BTreeMap<String, ScheduledDay>>::new();
+    for day in first.iter_days().take_while(|d| d <= &last) {
+        let planned = days.entry(day).or_default();
+        for (category, schedule) in schedules {
+            let seconds = schedule[day.weekday().num_days_from_monday() as usize];
+            if seconds > 0 {
+                planned.insert(
+                    category.clone(),
+                    ScheduledDay {
+                        planned: seconds,
+                        actual: 0,
+                    },
+                );
+            }
+        }
+    }
+    for time in times.iter().filter(|t| schedules.contains_key(&t.category)) {
+        if let (Some(end), Some(day)) = (time.end_time, days.get_mut(&zone.date(&time.start_time)))
+        {
+            day.entry(time.category.clone())
+                .or_insert(ScheduledDay {
+                    planned: 0,
+                    actual: 0,
+                })
+                .actual += (end - time.start_time).unsigned_abs();
+        }
+    }
+    days
+}
+
+pub fn schedule(
+    conn: &mut Connection,
+    filter: &ReportFilter,
+    table_options: &TableOptions,
+) -> Result<(), TTError> {
+    let schedules = category_schedules(conn)?;
+    if schedules.is_empty() {
+        return Err(TTError::NotConfigured {
+            message: "No categories have a schedule, use `ttjr set-category-option <category> schedule <schedule>` to set one".to_string(),
+        });
+    }
+    //schedules belong to un-rolled-up categories
+    let report = select_times(
+        conn,
+        &ReportFilter {
+            depth: None,
+            ..filter.clone()
+        },
+    )?;
+    let now = Local::now().timestamp();
+    let first = report
+        .start
+        .map_or(monday_of(report.zone.today()), |s| report.zone.date(&s));
+    let last = report
+        .end
+        .map_or(report.zone.today(), |e| report.zone.date(&e));
+    //count running time up to now
+    let times: Vec<TimeWindow> = report
+        .times
+        .iter()
+        .map(|t| TimeWindow {
+            end_time: Some(t.end_time.unwrap_or(now)),
+            ..t.clone()
+        })
+        .collect();
+    let days = schedule_diff(
+        &split_by_day(&times, &report.zone),
+        &schedules,
+        first,
+        last,
+        &report.zone,
+    );
+
+    if filter.json {
+        return print_json(&json!({
+            "start": report.start,
+            "end": report.end,
+            "days": days
+                .iter()
+                .map(|(day, categories)| (day.to_string(), json!(categories
+                    .iter()
+                    .map(|(category, scheduled)| (category.clone(), json!({
+                        "planned_seconds": scheduled.planned,
+                        "actual_seconds": scheduled.actual,
+                        "shortfall_seconds": scheduled.shortfall(),
+                    })))
+                    .collect::<serde_json::Map<_, _>>())))
+                .collect::<serde_json::Map<_, _>>(),
+        }));
+    }
+    let mut handle: Box<dyn Write> = Box::new(io::stdout());
+    handle.write_all(
+        format!("Planned vs. actual time from {} through {}\n", first, last).as_bytes(),
+    )?;
+    let mut table = ReportTable::new(vec![
+        text("date"),
+        text("category"),
+        number("planned"),
+        number("actual"),
+        number("short"),
+    ]);
+    for (day, categories) in &days {
+        for (category, scheduled) in categories {
+            table.row(vec![
+                day.to_string(),
+                category.clone(),
+                hours_minutes(scheduled.planned),
+                hours_minutes(scheduled.actual),
+                match scheduled.shortfall() {
+                    0 => "".to_string(),
+                    short => hours_minutes(short),
+                },
+            ]);
+        }
+    }
+    let all = || days.values().flat_map(|categories| categories.values());
+    table.totals(vec![
+        "total".to_string(),
+        "".to_string(),
+        hours_minutes(all().map(|s| s.planned).sum()),
+        hours_minutes(all().map(|s| s.actual).sum()),
+        hours_minutes(all().map(|s| s.shortfall()).sum()),
+    ]);
+    handle.write_all(table.render(table_options)?.as_bytes())?;
+    handle.flush()?;
+    Ok(())
+}
+
+#[derive(Serialize)]
+pub(crate) struct BudgetStatus {
+    pub category: String,
+    ///seconds logged so far this week, including any running time
+    #[serde(rename = "used_seconds")]
+    pub used: u64,
+    #[serde(rename = "cap_seconds")]
+    pub cap: u64,
+    pub over: bool,
+}
+
+///Time used this week (starting on monday in `zone`) for every category with a weekly-cap
+pub(crate) fn budget_status(
+    conn: &mut Connection,
+    zone: &Zone,
+) -> Result<Vec<BudgetStatus>, TTError> {
+    let mut tx = conn.transaction()?;
+    let totals = week_totals(&mut tx, start_of_week(zone.today(), zone))?;
+    Ok(category_durations(&tx, "weekly-cap")?
+        .into_iter()
+        .map(|(category, cap)| {
+            let used = totals.get(&category).copied().unwrap_or(0);
+            BudgetStatus {
+                category,
+                used,
+                cap,
+                over: used > cap,
+            }
+        })
+        .collect())
+}
+
+pub fn budget(
+    conn: &mut Connection,
+    notify: &bool,
+    zone: &Zone,
+    json: &bool,
+    table_options: &TableOptions,
+) -> Result<(), TTError> {
+    let statuses = budget_status(conn, zone)?;
+    if statuses.is_empty() {
+        return Err(TTError::NotConfigured {
+            message: "No categories have a weekly cap, use `ttjr set-category-option <category> weekly-cap <duration>` to set one".to_string(),
+        });
+    }
+    if !*json {
+        let mut table = ReportTable::new(vec![
+            text("category"),
+            number("used"),
+            number("cap"),
+            number("percent"),
+            text("status"),
+        ]);
+        for status in &statuses {
+            table.row(vec![
+                status.category.clone(),
+                hours_minutes(status.used),
+                hours_minutes(status.cap),
+                format!("{:.2}%", status.used as f64 / status.cap as f64 * 100.0),
+                if status.over { "OVER BUDGET" } else { "" }.to_string(),
+            ]);
+        }
+        let (used, cap) = (
+            statuses.iter().map(|s| s.used).sum::<u64>(),
+            statuses.iter().map(|s| s.cap).sum::<u64>(),
+        );
+        table.totals(vec![
+            "total".to_string(),
+            hours_minutes(used),
+            hours_minutes(cap),
+            format!("{:.2}%", used as f64 / cap as f64 * 100.0),
+            "".to_string(),
+        ]);
+        println!("Time logged this week:");
+        print!("{}", table.render(table_options)?);
+    }
+    for status in &statuses {
+        if *notify && status.over {
+            let mut notification = notify::notification(conn, &NotificationEvent::OverBudget)?;
+            notification
+                .summary(&format!("Over budget: {}", status.category))
+                .body(&format!(
+                    "{} logged this week, weekly cap is {}",
+                    hours_minutes(status.used),
+                    hours_minutes(status.cap)
+                ));
+            notify::show(conn, &NotificationEvent::OverBudget, &notification)?;
+        }
+    }
+    if *json {
+        print_json(&json!(statuses))?;
+    }
+    Ok(())
+}
+
+///Percent of a goal achieved, capped at 100% so that beating one goal can't make up for missing another
+fn adherence(actual: u64, target: u64) -> f64 {
+    if target == 0 {
+        return 100.0;
+    }
+    (actual as f64 / target as f64 * 100.0).min(100.0)
+}
+
+pub fn goals(
+    conn: &mut Connection,
+    week_of: &Option<String>,
+    zone: &Zone,
+    json: &bool,
+    table_options: &TableOptions,
+) -> Result<(), TTError> {
+    let day = match week_of {
+        Some(input) => match zone.parse_time(week_of) {
+            Some(tstamp) => zone.date(&tstamp),
+            None => return Err(TTError::unparseable(input, "week-of")),
+        },
+        None => zone.today(),
+    };
+    let week_start = start_of_week(day, zone);
+    let mut tx = conn.transaction()?;
+    let goals = category_durations(&tx, "weekly-goal")?;
+    if goals.is_empty() {
+        return Err(TTError::NotConfigured {
+            message: "No categories have a weekly goal, use `ttjr set-category-option <category> weekly-goal <duration>` to set one".to_string(),
+        });
+    }
+    let totals = week_totals(&mut tx, week_start)?;
+    let actuals: BTreeMap<&String, u64> = goals
+        .keys()
+        .map(|category| (category, totals.get(category).copied().unwrap_or(0)))
+        .collect();
+    let score = goals
+        .iter()
+        .map(|(category, target)| adherence(actuals[category], *target))
+        .sum::<f64>()
+        / goals.len() as f64;
+
+    if *json {
+        return print_json(&json!({
+            "week_start": zone.date(&week_start).to_string(),
+            "categories": goals
+                .iter()
+                .map(|(category, target)| (category.clone(), json!({
+                    "actual_seconds": actuals[category],
+                    "target_seconds": target,
+                    "percent": adherence(actuals[category], *target),
+                    "over_seconds": actuals[category].saturating_sub(*target),
+                })))
+                .collect::<serde_json::Map<_, _>>(),
+            "score": score,
+        }));
+    }
+
+    println!("Goals for the week of {}", zone.date(&week_start));
+    let mut table = ReportTable::new(vec![
+        text("category"),
+        number("actual"),
+        number("target"),
+        number("adherence"),
+        number("over"),
+    ]);
+    let over = |actual: u64, target: u64| match actual.saturating_sub(target) {
+        0 => "".to_string(),
+        over => hours_minutes(over),
+    };
+    for (category, target) in &goals {
+        table.row(vec![
+            category.clone(),
+            hours_minutes(actuals[category]),
+            hours_minutes(*target),
+            format!("{:.1}%", adherence(actuals[category], *target)),
+            over(actuals[category], *target),
+        ]);
+    }
+    table.totals(vec![
+        "total".to_string(),
+        hours_minutes(actuals.values().sum()),
+        hours_minutes(goals.values().sum()),
+        format!("{:.1}%", score),
+        "".to_string(),
+    ]);
+    print!("{}", table.render(table_options)?);
+    println!("Overall score: {:.1}%", score);
+    Ok(())
+}
+
+#[derive(Debug, PartialEq, Eq, Serialize)]
+struct DayAttendance {
+    first_start: i64,
+    last_end: i64,
+    ///seconds of logged time
+    tracked: u64,
+}
+
+impl DayAttendance {
+    ///seconds between the first start and last end that weren't tracked
+    fn gaps(&self) -> u64 {
+        ((self.last_end - self.first_start).unsigned_abs()).saturating_sub(self.tracked)
+    }
+}
+
+///First start/last end/total tracked per (local) day.  Open times are treated as ending at `now`
+fn daily_attendance(
+    times: &[TimeWindow],
+    now: i64,
+    zone: &Zone,
+) -> BTreeMap<NaiveDate, DayAttendance> {
+    let mut days = BTreeMap::<NaiveDate, DayAttendance>::new();
+    for time in times {
+        let end = time.end_time.unwrap_or(now);
+        let day = days
+            .entry(zone.date(&time.start_time))
+            .or_insert(DayAttendance {
+                first_start: time.start_time,
+                last_end: end,
+                tracked: 0,
+            });
+        day.first_start = day.first_start.min(time.start_time);
+        day.last_end = day.last_end.max(end);
+        day.tracked += (end - time.start_time).unsigned_abs();
+    }
+    days
+}
+
+pub fn attendance(
+    conn: &mut Connection,
+    filter: &ReportFilter,
+    table_options: &TableOptions,
+) -> Result<(), TTError> {
+    let report = fetch_times(conn, filter)?;
+    let days = daily_attendance(
+        &split_by_day(&report.times, &report.zone),
+        Local::now().timestamp(),
+        &report.zone,
+    );
+    if filter.json {
+        return print_json(&json!({
+            "start": report.start,
+            "end": report.end,
+            "days": days
+                .iter()
+                .map(|(day, attendance)| (day.to_string(), json!({
+                    "first_start": attendance.first_start,
+                    "last_end": attendance.last_end,
+                    "tracked_seconds": attendance.tracked,
+                    "gap_seconds": attendance.gaps(),
+                })))
+                .collect::<serde_json::Map<_, _>>(),
+        }));
+    }
+    let mut handle: Box<dyn Write> = Box::new(io::stdout());
+    write_range_header(&mut handle, report.start, report.end, &report.zone)?;
+    let mut table = ReportTable::new(vec![
+        text("date"),
+        number("start"),
+        number("end"),
+        number("tracked"),
+        number("gaps"),
+    ]);
+    for (day, attendance) in &days {
+        table.row(vec![
+            day.to_string(),
+            report.zone.format(&attendance.first_start, "%H:%M"),
+            report.zone.format(&attendance.last_end, "%H:%M"),
+            hours_minutes(attendance.tracked),
+            hours_minutes(attendance.gaps()),
+        ]);
+    }
+    table.totals(vec![
+        "total".to_string(),
+        "".to_string(),
+        "".to_string(),
+        hours_minutes(days.values().map(|a| a.tracked).sum()),
+        hours_minutes(days.values().map(|a| a.gaps()).sum()),
+    ]);
+    handle.write_all(table.render(table_options)?.as_bytes())?;
+    handle.flush()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn time(category: &str, start_time: i64, end_time: Option<i64>) -> TimeWindow {
+        TimeWindow {
+            id: None,
+            category: category.to_string(),
+            start_time,
+            end_time,
+            note: None,
+        }
+    }
+
+    #[test]
+    fn test_category_stats() {
+        let times = vec![
+            time("work", 0, Some(60)),
+            time("work", 100, Some(400)),
+            time("work", 500, Some(600)),
+            time("play", 1000, Some(1100)),
+            time("play", 2000, Some(2300)),
+            time("play", 3000, None),
+        ];
+        let stats = category_stats(&times, &Zone::Local);
+
+        let work = stats.get("work").unwrap();
+        assert_eq!(3, work.count);
+        assert_eq!(460, work.total);
+        assert_eq!(153, work.mean);
+        assert_eq!(100, work.median);
+        assert_eq!(300, work.longest);
+        assert_eq!(0, work.first_activity);
+        assert_eq!(600, work.last_activity);
+
+        //open times are counted but don't contribute to durations
+        let play = stats.get("play").unwrap();
+        assert_eq!(3, play.count);
+        assert_eq!(400, play.total);
+        assert_eq!(200, play.median);
+        assert_eq!(3000, play.last_activity);
+    }
+
+    #[test]
+    fn test_find_anomalies() {
+        let hour = 3600;
+        let times = vec![
+            time("work", 0, Some(hour)),
+            time("work", 2 * hour, Some(3 * hour)),
+            time("work", 4 * hour, Some(6 * hour)),
+            time("work", 7 * hour, Some(11 * hour)),
+            time("lunch", 12 * hour, Some(12 * hour + 1800)),
+            time("lunch", 13 * hour, Some(33 * hour)),
+            time("work", 34 * hour, None),
+        ];
+        let anomalies = find_anomalies(&times, 54 * hour);
+        //the 4h work entry isn't quite 3x the 1.5h median
+        assert_eq!(
+            vec![13 * hour, 34 * hour],
+            anomalies.iter().map(|a| a.start_time).collect::<Vec<_>>()
+        );
+        //lunch doesn't have enough entries to compare against the median
+        assert_eq!(1, anomalies[0].reasons.len());
+        //the open entry has been running for 20h, so it's flagged for both reasons
+        assert_eq!(2, anomalies[1].reasons.len());
+        assert_eq!(20 * hour as u64, anomalies[1].duration);
+    }
+
+    #[test]
+    fn test_moving_average() {
+        assert_eq!(vec![2, 3, 5, 7], moving_average(&[2, 4, 6, 8], 2));
+        assert_eq!(vec![2, 3, 4, 5], moving_average(&[2, 4, 6, 8], 7));
+        assert_eq!(Vec::<u64>::new(), moving_average(&[], 7));
+    }
+
+    #[test]
+    fn test_sparkline() {
+        assert_eq!("▁▄█", sparkline(&[0, 5, 10]));
+        assert_eq!("▁▁", sparkline(&[0, 0]));
+    }
+
+    #[test]
+    fn test_daily_attendance() {
+        let day_start = Local::now()
+            .date_naive()
+            .and_hms_opt(9, 0, 0)
+            .unwrap()
+            .and_local_timezone(Local)
+            .earliest()
+            .unwrap()
+            .timestamp();
+        let times = vec![
+            time("work", day_start, Some(day_start + 3600)),
+            time("work", day_start + 7200, Some(day_start + 10800)),
+            time("play", day_start + 10800, None),
+        ];
+        let days = daily_attendance(&times, day_start + 14400, &Zone::Local);
+        assert_eq!(1, days.len());
+        let day = days.values().next().unwrap();
+        assert_eq!(day_start, day.first_start);
+        assert_eq!(day_start + 14400, day.last_end);
+        assert_eq!(3 * 3600, day.tracked);
+        assert_eq!(3600, day.gaps());
+    }
+
+    #[test]
+    fn test_split_by_day() {
+        let midnight = (Local::now().date_naive() + chrono::Duration::days(1))
+            .and_hms_opt(0, 0, 0)
+            .unwrap()
+            .and_local_timezone(Local)
+            .earliest()
+            .unwrap()
+            .timestamp();
+        let split = split_by_day(
+            &[
+                time("work", midnight - 3600, Some(midnight + 7200)),
+                time("work", midnight + 7200, None),
+            ],
+            &Zone::Local,
+        );
+        assert_eq!(
+            vec![
+                time("work", midnight - 3600, Some(midnight)),
+                time("work", midnight, Some(midnight + 7200)),
+                time("work", midnight + 7200, None),
+            ],
+            split
+        );
+    }
+
+    #[test]
+    fn test_apply_daily_cap() {
+        let nine = Local::now()
+            .date_naive()
+            .and_hms_opt(9, 0, 0)
+            .unwrap()
+            .and_local_timezone(Local)
+            .earliest()
+            .unwrap()
+            .timestamp();
+        let hour = 3600;
+        let (capped, excess) = apply_daily_cap(
+            &[
+                time("a", nine, Some(nine + 5 * hour)),
+                time("b", nine + 5 * hour, Some(nine + 9 * hour)),
+                time("a", nine + 9 * hour, Some(nine + 10 * hour)),
+                time("a", nine + 10 * hour, None),
+            ],
+            8 * hour as u64,
+            &Zone::Local,
+        );
+        assert_eq!(
+            vec![
+                time("a", nine, Some(nine + 5 * hour)),
+                time("b", nine + 5 * hour, Some(nine + 8 * hour)),
+                time("a", nine + 10 * hour, None),
+            ],
+            capped
+        );
+        assert_eq!(Some(&(hour as u64)), excess.get("b"));
+        assert_eq!(Some(&(hour as u64)), excess.get("a"));
+    }
+
+    #[test]
+    fn test_schedule_diff() {
+        let monday = NaiveDate::from_ymd_opt(2022, 10, 31).unwrap();
+        let nine = Zone::Local.midnight(monday) + 9 * 3600;
+        let schedules = BTreeMap::from([
+            ("coding".to_string(), [7200, 7200, 0, 0, 0, 0, 0]),
+            ("email".to_string(), [0, 1800, 0, 0, 0, 0, 0]),
+        ]);
+        let days = schedule_diff(
+            &[
+                time("coding", nine, Some(nine + 3600)),
+                time("email", nine + 3600, Some(nine + 5400)),
+                time("lunch", nine + 5400, Some(nine + 7200)),
+                time("coding", nine + 86400, Some(nine + 86400 + 9000)),
+            ],
+            &schedules,
+            monday,
+            monday + chrono::Duration::days(2),
+            &Zone::Local,
+        );
+        assert_eq!(3, days.len());
+        let first = &days[&monday];
+        assert_eq!(3600, first["coding"].shortfall());
+        //unplanned time on a scheduled category is still reported
+        assert_eq!(0, first["email"].planned);
+        assert_eq!(1800, first["email"].actual);
+        assert!(!first.contains_key("lunch"));
+        let second = &days[&(monday + chrono::Duration::days(1))];
+        assert_eq!(0, second["coding"].shortfall());
+        assert_eq!(1800, second["email"].shortfall());
+        assert!(days[&(monday + chrono::Duration::days(2))].is_empty());
+    }
+
+    #[test]
+    fn test_client_rollup() {
+        let clients = BTreeMap::from([
+            ("a/design".to_string(), "Acme".to_string()),
+            ("a/support".to_string(), "Acme".to_string()),
+            ("b".to_string(), "Bolt".to_string()),
+        ]);
+        let rates = BTreeMap::from([("a/design".to_string(), 100.0), ("b".to_string(), 50.0)]);
+        let rollup = client_rollup(
+            &[
+                time("a/design", 0, Some(5400)),
+                time("a/support", 5400, Some(7200)),
+                time("b", 7200, Some(14400)),
+                time("lunch", 14400, Some(16200)),
+                time("b", 16200, None),
+            ],
+            &clients,
+            &rates,
+        );
+        assert_eq!(
+            vec!["Acme", "Bolt"],
+            rollup.keys().collect::<Vec<&String>>()
+        );
+        assert_eq!(150.0, rollup["Acme"]["a/design"].amount);
+        assert_eq!(1800, rollup["Acme"]["a/support"].seconds);
+        assert_eq!(0.0, rollup["Acme"]["a/support"].amount);
+        assert_eq!(7200, rollup["Bolt"]["b"].seconds);
+        assert_eq!(100.0, rollup["Bolt"]["b"].amount);
+    }
+
+    #[test]
+    fn test_daily_series() {
+        let first_day = NaiveDate::from_ymd_opt(2022, 10, 1).unwrap();
+        let day = |n: i64| Zone::Local.midnight(first_day + chrono::Duration::days(n)) + 3600;
+        let series = daily_series(
+            &[
+                time("work", day(0), Some(day(0) + 60)),
+                time("work", day(0) + 120, Some(day(0) + 180)),
+                time("work", day(13), Some(day(13) + 30)),
+                time("work", day(14), Some(day(14) + 30)),
+                time("play", day(2), None),
+            ],
+            first_day,
+            &Zone::Local,
+        );
+        let mut expected = vec![0; 14];
+        expected[0] = 120;
+        expected[13] = 30;
+        assert_eq!(BTreeMap::from([("work".to_string(), expected)]), series);
+    }
+
+    #[test]
+    fn test_adherence() {
+        assert_eq!(50.0, adherence(30, 60));
+        assert_eq!(100.0, adherence(90, 60));
+        assert_eq!(100.0, adherence(0, 0));
+    }
+}
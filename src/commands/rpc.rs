@@ -0,0 +1,244 @@
+/*
+This file is part of Timetrack Jr.
+Timetrack Jr. is free software: you can redistribute it and/or modify it under the terms of the GNU General Public License as published by the Free Software Foundation, either version 3 of the License, or (at your option) any later version.
+Timetrack Jr. is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU General Public License for more details.
+You should have received a copy of the GNU General Public License along with Timetrack Jr. If not, see <https://www.gnu.org/licenses/>.
+*/
+use crate::{cli::Amendment, commands::log, db, TTError};
+use rusqlite::Connection;
+use serde::Deserialize;
+use serde_json::{json, Value};
+use std::io::{BufRead, Write};
+
+//standard JSON-RPC 2.0 error codes
+const PARSE_ERROR: i64 = -32700;
+const INVALID_REQUEST: i64 = -32600;
+const METHOD_NOT_FOUND: i64 = -32601;
+const INVALID_PARAMS: i64 = -32602;
+///Errors from ttjr itself (like starting a category that doesn't exist)
+const APPLICATION_ERROR: i64 = -32000;
+
+#[derive(Deserialize)]
+struct Request {
+    jsonrpc: String,
+    method: String,
+    #[serde(default)]
+    params: Value,
+}
+
+#[derive(Deserialize)]
+struct StartParams {
+    category: String,
+    note: Option<String>,
+}
+
+///Same as the amend-time arguments, times are anything the CLI accepts
+#[derive(Deserialize)]
+struct AmendParams {
+    id: i64,
+    start_time: Option<String>,
+    end_time: Option<String>,
+    duration: Option<String>,
+    category: Option<String>,
+    note: Option<String>,
+    #[serde(default)]
+    allow_unusual_times: bool,
+}
+
+struct RpcError {
+    code: i64,
+    message: String,
+}
+
+impl From<TTError> for RpcError {
+    fn from(err: TTError) -> Self {
+        RpcError {
+            code: APPLICATION_ERROR,
+            message: err.to_string(),
+        }
+    }
+}
+
+impl From<rusqlite::Error> for RpcError {
+    fn from(err: rusqlite::Error) -> Self {
+        TTError::from(err).into()
+    }
+}
+
+fn params<'a, T: Deserialize<'a>>(params: &'a Value) -> Result<T, RpcError> {
+    T::deserialize(params).map_err(|e| RpcError {
+        code: INVALID_PARAMS,
+        message: format!("Invalid params: {}", e),
+    })
+}
+
+fn current(conn: &mut Connection) -> Result<Value, TTError> {
+    Ok(json!(db::get_last_open_time(&conn.transaction()?)?))
+}
+
+fn call(conn: &mut Connection, method: &str, raw_params: &Value) -> Result<Value, RpcError> {
+    match method {
+        "start" => {
+            let start: StartParams = params(raw_params)?;
+            log::start_timing(conn, &start.category, &start.note, &false)?;
+            Ok(current(conn)?)
+        }
+        "stop" => {
+            let open = db::get_last_open_time(&conn.transaction()?)?;
+            log::stop_timing(conn, &false)?;
+            match open.and_then(|time| time.id) {
+                Some(id) => Ok(json!(db::get_time(&conn.transaction()?, id)?)),
+                None => Ok(Value::Null),
+            }
+        }
+        "current" => Ok(current(conn)?),
+        "amend" => {
+            let amend: AmendParams = params(raw_params)?;
+            log::amend_time(
+                conn,
+                &amend.id,
+                &Amendment {
+                    start_time: amend.start_time,
+                    end_time: amend.end_time,
+                    duration: amend.duration,
+                    category: amend.category,
+                    note: amend.note,
+                    allow_unusual_times: amend.allow_unusual_times,
+                },
+                &false,
+            )?;
+            Ok(json!(db::get_time(&conn.transaction()?, amend.id)?))
+        }
+        _ => Err(RpcError {
+            code: METHOD_NOT_FOUND,
+            message: format!("Unknown method \"{}\"", method),
+        }),
+    }
+}
+
+fn error_response(id: Value, error: RpcError) -> Value {
+    json!({
+        "jsonrpc": "2.0",
+        "id": id,
+        "error": { "code": error.code, "message": error.message },
+    })
+}
+
+///Handle one line of input, returning the response to send (if any)
+fn handle_line(conn: &mut Connection, line: &str) -> Option<Value> {
+    let raw: Value = match serde_json::from_str(line) {
+        Ok(raw) => raw,
+        Err(e) => {
+            return Some(error_response(
+                Value::Null,
+                RpcError {
+                    code: PARSE_ERROR,
+                    message: format!("Parse error: {}", e),
+                },
+            ))
+        }
+    };
+    //requests without an id are notifications, which don't get a response (but `"id": null` isn't one)
+    let id = raw.get("id").cloned();
+    let request = match Request::deserialize(&raw) {
+        Ok(request) if request.jsonrpc == "2.0" => request,
+        _ => {
+            return Some(error_response(
+                id.unwrap_or(Value::Null),
+                RpcError {
+                    code: INVALID_REQUEST,
+                    message: "Invalid request, expected a JSON-RPC 2.0 request object".to_string(),
+                },
+            ))
+        }
+    };
+    let result = call(conn, &request.method, &request.params);
+    let id = id?;
+    Some(match result {
+        Ok(result) => json!({ "jsonrpc": "2.0", "id": id, "result": result }),
+        Err(error) => error_response(id, error),
+    })
+}
+
+///Speak JSON-RPC 2.0 (one message per line) until the input is closed
+pub fn rpc(
+    conn: &mut Connection,
+    input: &mut dyn BufRead,
+    output: &mut dyn Write,
+) -> Result<(), TTError> {
+    for line in input.lines() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        if let Some(response) = handle_line(conn, &line) {
+            writeln!(output, "{}", response)?;
+            output.flush()?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::commands::config;
+
+    #[test]
+    fn test_rpc() {
+        let mut conn = Connection::open_in_memory().unwrap();
+        db::initialize_db(&mut conn).unwrap();
+        config::add_category(&mut conn, &"work".to_string()).unwrap();
+        let mut input = concat!(
+            r#"{"jsonrpc": "2.0", "id": 1, "method": "start", "params": {"category": "work"}}"#,
+            "\n\n",
+            r#"{"jsonrpc": "2.0", "method": "current"}"#,
+            "\n",
+            r#"{"jsonrpc": "2.0", "id": 2, "method": "amend", "params": {"id": 1, "start_time": "2022-11-01 10:00"}}"#,
+            "\n",
+            r#"{"jsonrpc": "2.0", "id": 3, "method": "stop"}"#,
+            "\n",
+            r#"{"jsonrpc": "2.0", "id": 4, "method": "start", "params": {"category": "nope"}}"#,
+            "\n",
+            r#"{"jsonrpc": "2.0", "id": 5, "method": "dance"}"#,
+            "\n",
+            r#"{"jsonrpc": "2.0", "id": 6, "method": "amend", "params": {}}"#,
+            "\n",
+            r#"{"jsonrpc": "2.0", "id": null, "method": "current"}"#,
+            "\n",
+            "not json\n",
+        )
+        .as_bytes();
+        let mut output = vec![];
+        rpc(&mut conn, &mut input, &mut output).unwrap();
+        let responses: Vec<Value> = String::from_utf8(output)
+            .unwrap()
+            .lines()
+            .map(|l| serde_json::from_str(l).unwrap())
+            .collect();
+
+        //the notification doesn't get a response
+        assert_eq!(8, responses.len());
+        assert_eq!(json!(1), responses[0]["id"]);
+        assert_eq!("work", responses[0]["result"]["category"]);
+        assert_eq!(Value::Null, responses[0]["result"]["end_time"]);
+        assert_eq!(json!(2), responses[1]["id"]);
+        assert_eq!(
+            db::get_time(&conn.transaction().unwrap(), 1)
+                .unwrap()
+                .start_time,
+            responses[1]["result"]["start_time"]
+        );
+        assert_eq!(json!(1), responses[2]["result"]["id"]);
+        assert!(responses[2]["result"]["end_time"].is_i64());
+        assert_eq!(json!(APPLICATION_ERROR), responses[3]["error"]["code"]);
+        assert_eq!(json!(METHOD_NOT_FOUND), responses[4]["error"]["code"]);
+        assert_eq!(json!(INVALID_PARAMS), responses[5]["error"]["code"]);
+        //but a null id does
+        assert_eq!(Value::Null, responses[6]["id"]);
+        //nothing's being timed after the stop
+        assert_eq!(Some(&Value::Null), responses[6].get("result"));
+        assert_eq!(json!(PARSE_ERROR), responses[7]["error"]["code"]);
+        assert_eq!(Value::Null, responses[7]["id"]);
+    }
+}
@@ -0,0 +1,119 @@
+/*
+This file is part of Timetrack Jr.
+Timetrack Jr. is free software: you can redistribute it and/or modify it under the terms of the GNU General Public License as published by the Free Software Foundation, either version 3 of the License, or (at your option) any later version.
+Timetrack Jr. is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU General Public License for more details.
+You should have received a copy of the GNU General Public License along with Timetrack Jr. If not, see <https://www.gnu.org/licenses/>.
+*/
+//The tokio runtime the network features share when built with the async feature.  Only gRPC (tonic) is actually async:
+// rusqlite, tiny_http and ureq are all blocking, so DB work, HTTP requests and webhook deliveries run on tokio's
+// blocking pool (each job with its own pooled connection).  That isn't async I/O, just a thread pool that grows as
+// needed, but it keeps slow network work (a webhook endpoint that takes 30s to answer, a client streaming /events)
+// from holding up starting and stopping timers.
+use crate::{db::Pool, TTError};
+use once_cell::sync::Lazy;
+use rusqlite::Connection;
+use std::sync::{
+    atomic::{AtomicBool, Ordering},
+    Arc,
+};
+use tokio::runtime::{Builder, Runtime};
+
+static RUNTIME: Lazy<Runtime> = Lazy::new(|| {
+    Builder::new_multi_thread()
+        .enable_all()
+        .thread_name("ttjr-async")
+        .build()
+        .expect("Couldn't start the async runtime")
+});
+
+pub(crate) fn runtime() -> &'static Runtime {
+    &RUNTIME
+}
+
+///Run blocking DB work on a pooled connection without holding up the runtime
+pub(crate) async fn with_conn<T: Send + 'static>(
+    pool: &Pool,
+    operation: impl FnOnce(&mut Connection) -> Result<T, TTError> + Send + 'static,
+) -> Result<T, TTError> {
+    let pool = pool.clone();
+    tokio::task::spawn_blocking(move || operation(&mut *pool.get()?))
+        .await
+        .map_err(|e| TTError::System {
+            message: format!("Background task failed: {}", e),
+        })?
+}
+
+///A job the daemon runs in the background on each check, so it can carry on with the rest of its features while the
+/// job waits on the network.  A run is skipped while the last one is still going.
+pub(crate) struct Background {
+    pool: Pool,
+    running: Arc<AtomicBool>,
+}
+
+impl Background {
+    pub(crate) fn new(pool: Pool) -> Background {
+        Background {
+            pool,
+            running: Arc::new(AtomicBool::new(false)),
+        }
+    }
+
+    ///Start `job` unless it's still running from last time.  Failures are printed like the daemon's other warnings.
+    pub(crate) fn spawn<T: Send + 'static>(
+        &self,
+        name: &'static str,
+        job: fn(&mut Connection) -> Result<T, TTError>,
+    ) {
+        if self.running.swap(true, Ordering::SeqCst) {
+            return;
+        }
+        let pool = self.pool.clone();
+        let running = self.running.clone();
+        runtime().spawn(async move {
+            if let Err(e) = with_conn(&pool, job).await {
+                eprintln!("{} failed: {}", name, e);
+            }
+            running.store(false, Ordering::SeqCst);
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db;
+    use std::{thread, time::Duration};
+
+    #[test]
+    fn test_background() {
+        let path = std::env::temp_dir()
+            .join(format!("ttjr-runtime-{}.sqlite3", std::process::id()))
+            .to_string_lossy()
+            .to_string();
+        let pool = db::open_pool(&path, 2).unwrap();
+        db::initialize_db(&mut pool.get().unwrap()).unwrap();
+        let count = runtime()
+            .block_on(with_conn(&pool, |conn| {
+                Ok(db::get_categories(&conn.transaction()?)?.len())
+            }))
+            .unwrap();
+        assert_eq!(0, count);
+
+        let background = Background::new(pool.clone());
+        background.spawn("slow", |_| {
+            thread::sleep(Duration::from_millis(200));
+            Ok(())
+        });
+        //still running, so this one's skipped
+        background.spawn("add", |conn| {
+            let tx = conn.transaction()?;
+            db::add_category(&tx, &"skipped".to_string())?;
+            Ok(tx.commit()?)
+        });
+        thread::sleep(Duration::from_millis(400));
+        assert!(!background.running.load(Ordering::SeqCst));
+        let categories = db::get_categories(&pool.get().unwrap().transaction().unwrap()).unwrap();
+        assert!(categories.is_empty());
+        let _ = std::fs::remove_file(&path);
+    }
+}
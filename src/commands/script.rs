@@ -0,0 +1,235 @@
+/*
+This file is part of Timetrack Jr.
+Timetrack Jr. is free software: you can redistribute it and/or modify it under the terms of the GNU General Public License as published by the Free Software Foundation, either version 3 of the License, or (at your option) any later version.
+Timetrack Jr. is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU General Public License for more details.
+You should have received a copy of the GNU General Public License along with Timetrack Jr. If not, see <https://www.gnu.org/licenses/>.
+*/
+//`ttjr script`: runs a Rhai script (https://rhai.rs) for custom reports and automations.  Rhai scripts can't touch
+// files, the network, or other programs, only the functions registered here.  Those read times, categories, and
+// options, and the ones which change the database only work with --allow-writes.
+use super::{config, log};
+use crate::{
+    cli::{self, Amendment, FilterMode},
+    db::{self, Pool},
+    TTError,
+};
+use rhai::{serde::to_dynamic, Array, Dynamic, Engine, EvalAltResult, Map, Scope};
+use rusqlite::Transaction;
+use serde::Serialize;
+use std::fs;
+
+type ScriptResult = Result<Dynamic, Box<EvalAltResult>>;
+
+fn script_error(error: TTError) -> Box<EvalAltResult> {
+    error.to_string().into()
+}
+
+///A time string (like --start-time takes) as a timestamp
+fn tstamp(time: &str) -> Result<i64, Box<EvalAltResult>> {
+    cli::time_string_to_tstamp(&Some(time.to_string()))
+        .ok_or(format!("Couldn't understand the time \"{}\"", time).into())
+}
+
+///Run `read` on a connection from `pool`, handing its result to the script
+fn read<T: Serialize>(
+    pool: &Pool,
+    read: impl FnOnce(&mut Transaction) -> Result<T, TTError>,
+) -> ScriptResult {
+    let mut conn = pool.get().map_err(|e| script_error(e.into()))?;
+    let mut tx = conn.transaction().map_err(|e| script_error(e.into()))?;
+    to_dynamic(read(&mut tx).map_err(script_error)?)
+}
+
+///Refuses to change the database unless the script was run with --allow-writes
+fn writable(allow_writes: bool, function: &str) -> Result<(), Box<EvalAltResult>> {
+    match allow_writes {
+        true => Ok(()),
+        false => Err(format!(
+            "{}() changes the database, run the script with --allow-writes to let it",
+            function
+        )
+        .into()),
+    }
+}
+
+///The string `field` of `changes`, if it's there
+fn field(changes: &Map, field: &str) -> Result<Option<String>, Box<EvalAltResult>> {
+    match changes.get(field) {
+        None => Ok(None),
+        Some(value) => value
+            .clone()
+            .into_string()
+            .map(Some)
+            .map_err(|t| format!("{} should be a string, not {}", field, t).into()),
+    }
+}
+
+fn engine(pool: &Pool, allow_writes: bool) -> Engine {
+    let mut engine = Engine::new();
+    //no running arbitrary strings as code either
+    engine.disable_symbol("eval");
+
+    let p = pool.clone();
+    engine.register_fn("times", move || {
+        read(&p, |tx| db::get_times(tx, None, None, &FilterMode::Start))
+    });
+    let p = pool.clone();
+    engine.register_fn("times", move |start: &str, end: &str| -> ScriptResult {
+        let (start, end) = (tstamp(start)?, tstamp(end)?);
+        read(&p, |tx| {
+            db::get_times(tx, Some(start), Some(end), &FilterMode::Start)
+        })
+    });
+    let p = pool.clone();
+    engine.register_fn("current", move || read(&p, |tx| db::get_last_open_time(tx)));
+    let p = pool.clone();
+    engine.register_fn("categories", move || read(&p, |tx| db::get_categories(tx)));
+    let p = pool.clone();
+    engine.register_fn("options", move || read(&p, |tx| db::get_options(tx)));
+    let p = pool.clone();
+    engine.register_fn("category_options", move || {
+        read(&p, |tx| db::get_category_options(tx))
+    });
+    engine.register_fn("now", || chrono::Utc::now().timestamp());
+    engine.register_fn("parse_time", tstamp);
+    engine.register_fn("format_time", |tstamp: i64, format: &str| {
+        chrono::DateTime::from_timestamp(tstamp, 0)
+            .map(|t| t.with_timezone(&chrono::Local).format(format).to_string())
+            .unwrap_or_default()
+    });
+
+    let p = pool.clone();
+    engine.register_fn("start", move |category: &str| -> ScriptResult {
+        writable(allow_writes, "start")?;
+        let mut conn = p.get().map_err(|e| script_error(e.into()))?;
+        log::start_timing(&mut conn, &category.to_string(), &None, &false).map_err(script_error)?;
+        Ok(Dynamic::UNIT)
+    });
+    let p = pool.clone();
+    engine.register_fn("stop", move || -> ScriptResult {
+        writable(allow_writes, "stop")?;
+        let mut conn = p.get().map_err(|e| script_error(e.into()))?;
+        log::stop_timing(&mut conn, &false).map_err(script_error)?;
+        Ok(Dynamic::UNIT)
+    });
+    let p = pool.clone();
+    engine.register_fn("amend", move |id: i64, changes: Map| -> ScriptResult {
+        writable(allow_writes, "amend")?;
+        let mut conn = p.get().map_err(|e| script_error(e.into()))?;
+        let amendment = Amendment {
+            start_time: field(&changes, "start_time")?,
+            end_time: field(&changes, "end_time")?,
+            duration: field(&changes, "duration")?,
+            category: field(&changes, "category")?,
+            note: field(&changes, "note")?,
+            ..Default::default()
+        };
+        let (_, after) =
+            log::amend_time(&mut conn, &id, &amendment, &false).map_err(script_error)?;
+        to_dynamic(after)
+    });
+    let p = pool.clone();
+    engine.register_fn("delete_time", move |id: i64| -> ScriptResult {
+        writable(allow_writes, "delete_time")?;
+        let mut conn = p.get().map_err(|e| script_error(e.into()))?;
+        log::delete_time(&mut conn, &id, &false).map_err(script_error)?;
+        Ok(Dynamic::UNIT)
+    });
+    let p = pool.clone();
+    engine.register_fn("add_category", move |category: &str| -> ScriptResult {
+        writable(allow_writes, "add_category")?;
+        let mut conn = p.get().map_err(|e| script_error(e.into()))?;
+        config::add_category(&mut conn, &category.to_string()).map_err(script_error)?;
+        Ok(Dynamic::UNIT)
+    });
+    engine
+}
+
+///Run `source` with `args` in its ARGS constant
+fn run(pool: &Pool, source: &str, args: &[String], allow_writes: bool) -> Result<(), TTError> {
+    let mut scope = Scope::new();
+    scope.push_constant(
+        "ARGS",
+        args.iter().cloned().map(Dynamic::from).collect::<Array>(),
+    );
+    engine(pool, allow_writes)
+        .run_with_scope(&mut scope, source)
+        .map_err(|e| TTError::Script {
+            message: e.to_string(),
+        })
+}
+
+pub fn script(
+    db_path: &String,
+    file: &String,
+    args: &[String],
+    allow_writes: &bool,
+) -> Result<(), TTError> {
+    let source = fs::read_to_string(file).map_err(|e| TTError::System {
+        message: format!("Couldn't read {}: {}", file, e),
+    })?;
+    run(&db::open_pool(db_path, 1)?, &source, args, *allow_writes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_script() {
+        let path = std::env::temp_dir()
+            .join(format!("ttjr-script-{}.sqlite3", std::process::id()))
+            .to_string_lossy()
+            .to_string();
+        let _ = fs::remove_file(&path);
+        let pool = db::open_pool(&path, 1).unwrap();
+        db::initialize_db(&mut pool.get().unwrap()).unwrap();
+
+        //reading is fine without --allow-writes, changing things isn't
+        run(
+            &pool,
+            r#"if categories().len() != 0 || current() != () { throw "not empty"; }"#,
+            &[],
+            false,
+        )
+        .unwrap();
+        let refused = run(&pool, r#"add_category("work");"#, &[], false).unwrap_err();
+        assert!(
+            refused.to_string().contains("--allow-writes"),
+            "{}",
+            refused
+        );
+        assert!(run(&pool, r#"eval("1")"#, &[], true).is_err());
+
+        run(
+            &pool,
+            r#"
+            add_category(ARGS[0]);
+            start(ARGS[0]);
+            stop();
+            let time = times()[0];
+            let amended = amend(time.id, #{ start_time: "2022-11-14 09:00", end_time: "2022-11-14 10:30" });
+            if amended.end_time - amended.start_time != 5400 { throw "not amended"; }
+            "#,
+            &["work".to_string()],
+            true,
+        )
+        .unwrap();
+        let error = run(&pool, r#"times("whenever", "now")"#, &[], false).unwrap_err();
+        assert!(error.to_string().contains("whenever"), "{}", error);
+
+        let mut conn = pool.get().unwrap();
+        let times = db::get_times(
+            &mut conn.transaction().unwrap(),
+            None,
+            None,
+            &FilterMode::Start,
+        )
+        .unwrap();
+        assert_eq!(1, times.len());
+        assert_eq!("work", times[0].category);
+        assert_eq!(Some(times[0].start_time + 5400), times[0].end_time);
+
+        let _ = fs::remove_file(&path);
+    }
+}
@@ -0,0 +1,915 @@
+/*
+This file is part of Timetrack Jr.
+Timetrack Jr. is free software: you can redistribute it and/or modify it under the terms of the GNU General Public License as published by the Free Software Foundation, either version 3 of the License, or (at your option) any later version.
+Timetrack Jr. is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU General Public License for more details.
+You should have received a copy of the GNU General Public License along with Timetrack Jr. If not, see <https://www.gnu.org/licenses/>.
+*/
+#[cfg(feature = "async")]
+use crate::commands::runtime;
+use crate::{
+    cli::{ExportFormat, FilterMode, Order, Page, ReportFilter, SummaryOptions},
+    commands::{
+        config,
+        export::{list_times, write_export},
+        grafana, log, metrics, webhook,
+    },
+    db::{self, Pool, TimeWindow},
+    zone::Zone,
+    TTError,
+};
+use chrono::Utc;
+use clap::ValueEnum;
+use rusqlite::{hooks::Action, Connection};
+use serde::Deserialize;
+use serde_json::json;
+use std::{
+    collections::BTreeMap,
+    io::Write,
+    net::TcpListener,
+    sync::{
+        mpsc::{channel, Receiver, RecvTimeoutError, Sender},
+        Arc, Mutex,
+    },
+    thread,
+    time::Duration,
+};
+use tiny_http::{Header, Method, Request, Response, Server};
+
+///Number of requests handled at once (and the number of pooled DB connections).  With the async feature, requests
+/// beyond this wait for a connection instead of a worker.
+const WORKERS: u32 = 4;
+
+#[derive(Deserialize)]
+struct NewCategory {
+    name: String,
+}
+
+#[derive(Deserialize)]
+struct StartTiming {
+    category: String,
+}
+
+///Body of requests which create or amend times, fields left out of an amendment are unchanged
+#[derive(Deserialize)]
+struct TimeFields {
+    category: Option<String>,
+    start_time: Option<i64>,
+    end_time: Option<i64>,
+    ///an empty note removes the time's note
+    note: Option<String>,
+    ///allow times in the future or from before the earliest-time option, like --allow-unusual-times
+    #[serde(default)]
+    allow_unusual_times: bool,
+}
+
+///Decode %-escapes (and `+` in query strings) in a URL component
+fn percent_decode(component: &str, plus_as_space: bool) -> String {
+    let bytes = component.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let escaped = bytes
+            .get(i + 1..i + 3)
+            .and_then(|hex| std::str::from_utf8(hex).ok())
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+        match (bytes[i], escaped) {
+            (b'%', Some(byte)) => {
+                decoded.push(byte);
+                i += 2;
+            }
+            (b'+', _) if plus_as_space => decoded.push(b' '),
+            (byte, _) => decoded.push(byte),
+        }
+        i += 1;
+    }
+    String::from_utf8_lossy(&decoded).to_string()
+}
+
+///Query string parameters, repeated parameters keep every value
+fn parse_query(query: &str) -> BTreeMap<String, Vec<String>> {
+    let mut params = BTreeMap::<String, Vec<String>>::new();
+    for pair in query.split('&').filter(|p| !p.is_empty()) {
+        let (name, value) = pair.split_once('=').unwrap_or((pair, ""));
+        params
+            .entry(percent_decode(name, true))
+            .or_default()
+            .push(percent_decode(value, true));
+    }
+    params
+}
+
+///Build a report filter from query parameters named like the command line flags (start, end, category_match, etc.)
+fn query_filter(query: &BTreeMap<String, Vec<String>>) -> Result<ReportFilter, TTError> {
+    let one = |name: &str| query.get(name).and_then(|v| v.last()).cloned();
+    let all = |name: &str| query.get(name).cloned().unwrap_or_default();
+    Ok(ReportFilter {
+        start_time: one("start"),
+        end_time: one("end"),
+        filter_mode: match one("filter_mode") {
+            Some(mode) => Some(
+                FilterMode::from_str(&mode, true)
+                    .map_err(|_| TTError::invalid("filter_mode", &mode, "start or overlap"))?,
+            ),
+            None => None,
+        },
+        category_match: all("category_match"),
+        category_regex: all("category_regex"),
+        tag: all("tag"),
+        depth: one("depth").map(|d| d.parse()).transpose()?,
+        timezone: one("timezone")
+            .map(|z| z.parse::<Zone>())
+            .transpose()
+            .map_err(|message| TTError::InvalidArgument { message })?,
+        json: true,
+    })
+}
+
+///Paging through times with the limit, offset, after, and order query parameters
+fn query_page(query: &BTreeMap<String, Vec<String>>) -> Result<Page, TTError> {
+    let one = |name: &str| query.get(name).and_then(|v| v.last());
+    Ok(Page {
+        order: match one("order") {
+            Some(order) => Order::from_str(order, true)
+                .map_err(|_| TTError::invalid("order", order, "asc or desc"))?,
+            None => Order::Asc,
+        },
+        after: one("after").map(|a| a.parse()).transpose()?,
+        offset: one("offset").map(|o| o.parse()).transpose()?.unwrap_or(0),
+        limit: one("limit").map(|l| l.parse()).transpose()?,
+    })
+}
+
+fn parse_body<'a, T: Deserialize<'a>>(body: &'a str) -> Result<T, TTError> {
+    serde_json::from_str(body).map_err(|e| TTError::InvalidArgument {
+        message: format!("Invalid request body: {}", e),
+    })
+}
+
+fn parse_id(id: &str) -> Result<i64, TTError> {
+    id.parse().map_err(|_| TTError::InvalidArgument {
+        message: format!("Invalid time ID \"{}\"", id),
+    })
+}
+
+fn get_time(conn: &mut Connection, id: i64) -> Result<TimeWindow, TTError> {
+    let tx = conn.transaction()?;
+    db::get_time(&tx, id)
+}
+
+///Log a new time (with no `id`) or amend an existing one, sending webhooks the way the CLI does
+fn save_time(
+    conn: &mut Connection,
+    id: Option<i64>,
+    fields: TimeFields,
+) -> Result<TimeWindow, TTError> {
+    let mut time = match id {
+        Some(id) => get_time(conn, id)?,
+        None => TimeWindow {
+            id: None,
+            category: fields.category.clone().ok_or(TTError::InvalidArgument {
+                message: "category is required".to_string(),
+            })?,
+            start_time: fields.start_time.ok_or(TTError::InvalidArgument {
+                message: "start_time is required".to_string(),
+            })?,
+            end_time: None,
+            note: None,
+        },
+    };
+    if let Some(category) = fields.category {
+        time.category = category;
+    }
+    if let Some(start) = fields.start_time {
+        time.start_time = start;
+    }
+    if let Some(end) = fields.end_time {
+        time.end_time = Some(end);
+    }
+    if let Some(note) = fields.note {
+        time.note = Some(note).filter(|note| !note.is_empty());
+    }
+    let saved = db::write(conn, |tx| {
+        db::upsert_time(tx, time.clone(), &fields.allow_unusual_times)?;
+        let saved = db::get_time(tx, id.unwrap_or(tx.last_insert_rowid()))?;
+        //same event types as the event stream
+        let event_type = match (id, saved.end_time) {
+            (None, None) => "start",
+            _ => "amend",
+        };
+        webhook::enqueue(tx, event_type, json!({ "time": saved }))?;
+        Ok(saved)
+    })?;
+    Ok(saved)
+}
+
+type RouteResult = Result<(u16, Vec<u8>), TTError>;
+
+///Handle a single API request, returning the status code and JSON body of the response
+fn route(conn: &mut Connection, method: &Method, url: &str, body: &str) -> RouteResult {
+    let (path, query) = url.split_once('?').unwrap_or((url, ""));
+    let query = parse_query(query);
+    let segments: Vec<String> = path
+        .trim_matches('/')
+        .split('/')
+        .map(|s| percent_decode(s, false))
+        .collect();
+    let segments: Vec<&str> = segments.iter().map(|s| s.as_str()).collect();
+
+    let document = match (method, segments.as_slice()) {
+        (Method::Get, ["categories"]) => json!(db::get_categories(&conn.transaction()?)?),
+        (Method::Post, ["categories"]) => {
+            let category: NewCategory = parse_body(body)?;
+            config::add_category(conn, &category.name)?;
+            return Ok((
+                201,
+                json!({ "name": category.name }).to_string().into_bytes(),
+            ));
+        }
+        (Method::Delete, ["categories", name]) => {
+            let delete_logged_times = query.contains_key("delete_logged_times");
+            config::delete_category(conn, &name.to_string(), &delete_logged_times, &true)?;
+            json!({})
+        }
+        (Method::Get, ["times"]) => {
+            let filter = ReportFilter {
+                depth: None,
+                ..query_filter(&query)?
+            };
+            json!(list_times(conn, &filter, &query_page(&query)?)?)
+        }
+        (Method::Post, ["times"]) => {
+            let time = save_time(conn, None, parse_body(body)?)?;
+            return Ok((201, json!(time).to_string().into_bytes()));
+        }
+        (Method::Get, ["times", id]) => json!(get_time(conn, parse_id(id)?)?),
+        (Method::Put, ["times", id]) => {
+            json!(save_time(conn, Some(parse_id(id)?), parse_body(body)?)?)
+        }
+        (Method::Delete, ["times", id]) => {
+            log::delete_time(conn, &parse_id(id)?, &false)?;
+            json!({})
+        }
+        (Method::Post, ["start"]) => {
+            let start: StartTiming = parse_body(body)?;
+            log::start_timing(conn, &start.category, &None, &false)?;
+            json!(db::get_last_open_time(&conn.transaction()?)?)
+        }
+        (Method::Post, ["stop"]) => {
+            log::stop_timing(conn, &false)?;
+            json!({})
+        }
+        (Method::Get, ["current"]) => json!(db::get_last_open_time(&conn.transaction()?)?),
+        (Method::Get, ["summary"]) => {
+            let mut summary = vec![];
+            write_export(
+                conn,
+                &ExportFormat::Summary,
+                &mut summary,
+                &query_filter(&query)?,
+                &SummaryOptions::default(),
+                &Page::default(),
+            )?;
+            return Ok((200, summary));
+        }
+        (Method::Get | Method::Post, ["grafana", rest @ ..]) => {
+            match grafana::route(conn, rest, body, Utc::now())? {
+                Some(document) => document,
+                None => return Ok(not_found(method, &segments)),
+            }
+        }
+        _ => return Ok(not_found(method, &segments)),
+    };
+    Ok((200, document.to_string().into_bytes()))
+}
+
+fn not_found(method: &Method, segments: &[&str]) -> (u16, Vec<u8>) {
+    let message = format!("No such endpoint: {} /{}", method, segments.join("/"));
+    (404, json!({ "error": message }).to_string().into_bytes())
+}
+
+///Status code and JSON body for a failed request
+fn error_response(error: TTError) -> (u16, Vec<u8>) {
+    let (status, message) = match error {
+        TTError::Sql(rusqlite::Error::QueryReturnedNoRows) => (404, "Not found".to_string()),
+        e @ (TTError::CategoryNotFound { .. }
+        | TTError::TimeNotFound { .. }
+        | TTError::NotFound { .. }) => (404, e.to_string()),
+        e @ TTError::OverlappingTime { .. } => (409, e.to_string()),
+        e @ (TTError::ParseTime { .. }
+        | TTError::UnusualTime { .. }
+        | TTError::InvalidValue { .. }
+        | TTError::InvalidArgument { .. }) => (400, e.to_string()),
+        e @ TTError::DatabaseBusy { .. } => (503, e.to_string()),
+        e => (500, e.to_string()),
+    };
+    (status, json!({ "error": message }).to_string().into_bytes())
+}
+
+///How often to send a comment down idle event streams, so clients (and proxies) know the connection is alive
+const KEEPALIVE: Duration = Duration::from_secs(15);
+///How often to check whether another process has changed the DB
+const WATCH_INTERVAL: Duration = Duration::from_secs(1);
+
+///A row changed by a request, recorded by an update hook and turned into an event once the request is finished
+struct Change {
+    action: Action,
+    table: String,
+    rowid: i64,
+    ///whether the row was the open time before the request
+    was_open: bool,
+}
+
+///Event describing a committed change, if it's one that event streams care about
+fn change_event(
+    conn: &mut Connection,
+    change: &Change,
+) -> Result<Option<serde_json::Value>, TTError> {
+    match change.table.as_str() {
+        "times" if matches!(change.action, Action::SQLITE_DELETE) => {
+            Ok(Some(json!({ "type": "delete", "id": change.rowid })))
+        }
+        "times" => {
+            let time = match get_time(conn, change.rowid) {
+                Ok(time) => time,
+                //changed and then deleted by the same request
+                Err(TTError::Sql(rusqlite::Error::QueryReturnedNoRows)) => return Ok(None),
+                Err(e) => return Err(e),
+            };
+            let event_type = match (&change.action, time.end_time) {
+                (Action::SQLITE_INSERT, None) => "start",
+                (Action::SQLITE_UPDATE, Some(_)) if change.was_open => "stop",
+                _ => "amend",
+            };
+            Ok(Some(json!({ "type": event_type, "time": time })))
+        }
+        "categories" => Ok(Some(json!({ "type": "categories" }))),
+        _ => Ok(None),
+    }
+}
+
+///Run a request, collecting the changes it makes with an update hook
+fn route_recording_changes(
+    conn: &mut Connection,
+    method: &Method,
+    url: &str,
+    body: &str,
+) -> (RouteResult, Vec<Change>) {
+    let changes = Arc::new(Mutex::new(vec![]));
+    let recorder = changes.clone();
+    //times are updated in place whether they're stopped or amended, so remember which one was open
+    let open = conn
+        .transaction()
+        .ok()
+        .and_then(|tx| db::get_last_open_time(&tx).ok().flatten())
+        .and_then(|open| open.id);
+    conn.update_hook(Some(move |action, _: &str, table: &str, rowid| {
+        recorder.lock().unwrap().push(Change {
+            action,
+            table: table.to_string(),
+            rowid,
+            was_open: table == "times" && open == Some(rowid),
+        })
+    }));
+    let result = route(conn, method, url, body);
+    conn.update_hook(None::<fn(Action, &str, &str, i64)>);
+    let changes = std::mem::take(&mut *changes.lock().unwrap());
+    (result, changes)
+}
+
+///Fans events out to every connected event stream
+#[derive(Default)]
+struct Events {
+    subscribers: Mutex<Vec<Sender<String>>>,
+    ///the open time as of the last published events.  Starts and stops are seen both by a request's update hook and
+    /// by watch_for_changes, this is how they're only published once.
+    open: Mutex<Option<TimeWindow>>,
+}
+
+impl Events {
+    fn subscribe(&self) -> Receiver<String> {
+        let (sender, receiver) = channel();
+        self.subscribers.lock().unwrap().push(sender);
+        receiver
+    }
+
+    fn publish(&self, event: &serde_json::Value) {
+        let event = event.to_string();
+        //streams which have disconnected drop their receiver, so stop sending to them
+        self.subscribers
+            .lock()
+            .unwrap()
+            .retain(|subscriber| subscriber.send(event.clone()).is_ok());
+    }
+
+    ///Publish the events for a request's changes, leaving out starts and stops watch_for_changes already published
+    fn publish_request(&self, request_events: Vec<serde_json::Value>, open: Option<TimeWindow>) {
+        let mut known = self.open.lock().unwrap();
+        let known_id = known.as_ref().and_then(|time| time.id);
+        for event in request_events {
+            let id = event["time"]["id"].as_i64();
+            let published = match event["type"].as_str() {
+                Some("start") => id.is_some() && known_id == id,
+                Some("stop") => id.is_some() && known_id != id,
+                _ => false,
+            };
+            if !published {
+                self.publish(&event);
+            }
+        }
+        *known = open;
+    }
+
+    ///Publish start/stop/amend events for however the open time changed since the last events were published
+    fn publish_open(&self, conn: &mut Connection, open: Option<TimeWindow>) -> Result<(), TTError> {
+        let mut known = self.open.lock().unwrap();
+        match (known.as_ref(), open.as_ref()) {
+            (Some(before), Some(now)) if before.id == now.id => {
+                if before != now {
+                    self.publish(&json!({ "type": "amend", "time": now }));
+                }
+            }
+            (before, now) => {
+                if let Some(id) = before.and_then(|before| before.id) {
+                    match get_time(conn, id) {
+                        Ok(time) => self.publish(&json!({ "type": "stop", "time": time })),
+                        Err(TTError::Sql(rusqlite::Error::QueryReturnedNoRows)) => {
+                            self.publish(&json!({ "type": "delete", "id": id }))
+                        }
+                        Err(e) => return Err(e),
+                    }
+                }
+                if let Some(now) = now {
+                    self.publish(&json!({ "type": "start", "time": now }));
+                }
+            }
+        }
+        *known = open;
+        Ok(())
+    }
+}
+
+///Respond to a request with a server-sent event stream, on its own thread since the stream stays open
+fn stream_events(request: Request, events: &Events) {
+    let receiver = events.subscribe();
+    thread::spawn(move || {
+        let mut writer = request.into_writer();
+        let mut send = |chunk: &str| {
+            writer
+                .write_all(chunk.as_bytes())
+                .and_then(|_| writer.flush())
+        };
+        if send("HTTP/1.1 200 OK\r\nContent-Type: text/event-stream\r\nCache-Control: no-cache\r\nConnection: close\r\n\r\n").is_err() {
+            return;
+        }
+        loop {
+            let chunk = match receiver.recv_timeout(KEEPALIVE) {
+                Ok(event) => format!("data: {}\n\n", event),
+                Err(RecvTimeoutError::Timeout) => ": keepalive\n\n".to_string(),
+                Err(RecvTimeoutError::Disconnected) => return,
+            };
+            if send(&chunk).is_err() {
+                //client went away
+                return;
+            }
+        }
+    });
+}
+
+///Publish events for changes committed by another connection (like the CLI, or one of our workers): start/stop/amend
+/// for the open time, then "changed".  Update hooks only see changes made by the request being handled, so this is how
+/// we hear about everything else.
+fn check_for_changes(
+    conn: &mut Connection,
+    last_version: &mut Option<i64>,
+    events: &Events,
+) -> Result<(), TTError> {
+    let version = conn.query_row("PRAGMA data_version", (), |row| row.get(0))?;
+    if *last_version == Some(version) {
+        return Ok(());
+    }
+    let open = db::get_last_open_time(&conn.transaction()?)?;
+    events.publish_open(conn, open)?;
+    //the first check only finds out what's open
+    if last_version.is_some() {
+        events.publish(&json!({ "type": "changed" }));
+    }
+    *last_version = Some(version);
+    Ok(())
+}
+
+///Check for changes from other connections every WATCH_INTERVAL.  Failures (like a locked DB) are only warnings, the
+/// next check starts over with a new connection.
+fn watch_for_changes(pool: Pool, events: Arc<Events>) {
+    thread::spawn(move || loop {
+        //data_version is only comparable on the connection it came from
+        let watched: Result<(), TTError> =
+            pool.get().map_err(TTError::from).and_then(|mut conn| {
+                let mut last_version = None;
+                loop {
+                    check_for_changes(&mut conn, &mut last_version, &events)?;
+                    thread::sleep(WATCH_INTERVAL);
+                }
+            });
+        if let Err(e) = watched {
+            eprintln!("Unable to check for changes: {}", e);
+            thread::sleep(WATCH_INTERVAL);
+        }
+    });
+}
+
+///The web dashboard, served at /
+const DASHBOARD: &str = include_str!("dashboard.html");
+
+fn respond(request: Request, status: u16, content_type: &str, body: Vec<u8>) {
+    let response = Response::from_data(body)
+        .with_status_code(status)
+        .with_header(
+            format!("Content-Type: {}", content_type)
+                .parse::<Header>()
+                .unwrap(),
+        );
+    if let Err(e) = request.respond(response) {
+        eprintln!("Unable to send response: {}", e);
+    }
+}
+
+fn handle(mut request: Request, pool: &Pool, events: &Events, started: i64) {
+    if request.method() == &Method::Get {
+        match request.url().split('?').next() {
+            Some("/events") => return stream_events(request, events),
+            Some("/") => {
+                return respond(
+                    request,
+                    200,
+                    "text/html; charset=utf-8",
+                    DASHBOARD.as_bytes().to_vec(),
+                )
+            }
+            Some("/metrics") => {
+                let metrics = pool.get().map_err(TTError::from).and_then(|mut conn| {
+                    metrics::render(&mut conn, Utc::now().timestamp(), started)
+                });
+                return match metrics {
+                    Ok(metrics) => respond(
+                        request,
+                        200,
+                        "text/plain; version=0.0.4",
+                        metrics.into_bytes(),
+                    ),
+                    Err(e) => {
+                        let (status, body) = error_response(e);
+                        respond(request, status, "application/json", body)
+                    }
+                };
+            }
+            _ => {}
+        }
+    }
+    let mut body = String::new();
+    let (status, body) = match request.as_reader().read_to_string(&mut body) {
+        Err(e) => error_response(e.into()),
+        Ok(_) => match pool.get() {
+            Err(e) => error_response(e.into()),
+            Ok(mut conn) => {
+                let (result, changes) =
+                    route_recording_changes(&mut conn, request.method(), request.url(), &body);
+                let mut request_events = vec![];
+                for change in &changes {
+                    match change_event(&mut conn, change) {
+                        Ok(Some(event)) => request_events.push(event),
+                        Ok(None) => {}
+                        Err(e) => eprintln!("Unable to publish event: {}", e),
+                    }
+                }
+                let open = conn
+                    .transaction()
+                    .map_err(TTError::from)
+                    .and_then(|tx| db::get_last_open_time(&tx));
+                match open {
+                    Ok(open) => events.publish_request(request_events, open),
+                    Err(e) => {
+                        eprintln!("Unable to publish events: {}", e);
+                        request_events
+                            .iter()
+                            .for_each(|event| events.publish(event));
+                    }
+                }
+                result.unwrap_or_else(error_response)
+            }
+        },
+    };
+    respond(request, status, "application/json", body);
+}
+
+///The socket systemd is holding for us (see `daemon --install-systemd --socket`), if there is one
+#[cfg(unix)]
+fn activated_listener() -> Option<TcpListener> {
+    use std::os::unix::io::FromRawFd;
+    let pid = std::env::var("LISTEN_PID").ok()?.parse::<u32>().ok()?;
+    let fds = std::env::var("LISTEN_FDS").ok()?.parse::<u32>().ok()?;
+    //only take it once
+    std::env::remove_var("LISTEN_FDS");
+    match pid == std::process::id() && fds >= 1 {
+        //passed sockets start at fd 3, which nothing else will have opened
+        true => Some(unsafe { TcpListener::from_raw_fd(3) }),
+        false => None,
+    }
+}
+
+#[cfg(not(unix))]
+fn activated_listener() -> Option<TcpListener> {
+    None
+}
+
+pub fn serve(db_path: &String, bind_addr: &String) -> Result<(), TTError> {
+    //one extra connection to watch for changes from other processes
+    let pool = db::open_pool(db_path, WORKERS + 1)?;
+    let listen_error = |e| TTError::System {
+        message: format!("Unable to listen on {}: {}", bind_addr, e),
+    };
+    let server = Arc::new(match activated_listener() {
+        Some(listener) => Server::from_listener(listener, None).map_err(listen_error)?,
+        None => Server::http(bind_addr).map_err(listen_error)?,
+    });
+    let started = Utc::now().timestamp();
+    let events = Arc::new(Events::default());
+    watch_for_changes(pool.clone(), events.clone());
+    println!("Listening on http://{}", bind_addr);
+
+    serve_requests(server, pool, events, started);
+    Ok(())
+}
+
+///Handle requests on a fixed set of worker threads
+#[cfg(not(feature = "async"))]
+fn serve_requests(server: Arc<Server>, pool: Pool, events: Arc<Events>, started: i64) {
+    let workers: Vec<_> = (0..WORKERS)
+        .map(|_| {
+            let server = server.clone();
+            let pool = pool.clone();
+            let events = events.clone();
+            thread::spawn(move || {
+                for request in server.incoming_requests() {
+                    handle(request, &pool, &events, started);
+                }
+            })
+        })
+        .collect();
+    for worker in workers {
+        let _ = worker.join();
+    }
+}
+
+///Handle each request on the runtime's blocking pool, so a slow request doesn't keep others waiting for a worker.
+/// tiny_http and the handlers are still blocking, this only swaps the fixed workers for a pool that grows as needed.
+#[cfg(feature = "async")]
+fn serve_requests(server: Arc<Server>, pool: Pool, events: Arc<Events>, started: i64) {
+    for request in server.incoming_requests() {
+        let pool = pool.clone();
+        let events = events.clone();
+        runtime::runtime().spawn_blocking(move || handle(request, &pool, &events, started));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn request(conn: &mut Connection, method: Method, url: &str, body: &str) -> (u16, String) {
+        let (status, body) = route(conn, &method, url, body).unwrap_or_else(error_response);
+        (status, String::from_utf8(body).unwrap())
+    }
+
+    ///An empty DB which takes the made-up times in these tests as plausible
+    fn test_db() -> Connection {
+        let mut conn = Connection::open_in_memory().unwrap();
+        db::initialize_db(&mut conn).unwrap();
+        let tx = conn.transaction().unwrap();
+        db::set_option(
+            &tx,
+            &crate::cli::OptionName::EarliestTime,
+            &"1970-01-01".to_string(),
+        )
+        .unwrap();
+        tx.commit().unwrap();
+        conn
+    }
+
+    #[test]
+    fn test_percent_decode() {
+        assert_eq!("work/coding", percent_decode("work%2Fcoding", false));
+        assert_eq!("a+b", percent_decode("a+b", false));
+        assert_eq!("a b", percent_decode("a+b", true));
+        assert_eq!("100%", percent_decode("100%", false));
+        assert_eq!("%zz", percent_decode("%zz", false));
+    }
+
+    #[test]
+    fn test_paging() {
+        let mut conn = test_db();
+        config::add_category(&mut conn, &"work".to_string()).unwrap();
+        for (start, end) in [(100, 200), (400, 500), (250, 300)] {
+            let time = format!(
+                r#"{{"category": "work", "start_time": {}, "end_time": {}}}"#,
+                start, end
+            );
+            assert_eq!(201, request(&mut conn, Method::Post, "/times", &time).0);
+        }
+        let ids = |conn: &mut Connection, query: &str| {
+            let (status, body) = request(conn, Method::Get, &format!("/times?{}", query), "");
+            assert_eq!(200, status, "{}", body);
+            serde_json::from_str::<Vec<TimeWindow>>(&body)
+                .unwrap()
+                .into_iter()
+                .map(|t| t.id.unwrap())
+                .collect::<Vec<_>>()
+        };
+        //sorted by start time
+        assert_eq!(vec![1, 3, 2], ids(&mut conn, ""));
+        assert_eq!(vec![2, 3], ids(&mut conn, "order=desc&limit=2"));
+        assert_eq!(vec![3], ids(&mut conn, "offset=1&limit=1"));
+        assert_eq!(vec![2], ids(&mut conn, "after=3"));
+        assert_eq!(vec![1], ids(&mut conn, "after=3&order=desc"));
+        assert_eq!(404, request(&mut conn, Method::Get, "/times?after=9", "").0);
+        assert_eq!(
+            400,
+            request(&mut conn, Method::Get, "/times?order=up", "").0
+        );
+    }
+
+    #[test]
+    fn test_route() {
+        let mut conn = test_db();
+
+        assert_eq!(
+            201,
+            request(
+                &mut conn,
+                Method::Post,
+                "/categories",
+                r#"{"name": "work/coding"}"#
+            )
+            .0
+        );
+        assert_eq!(
+            (200, r#"["work/coding"]"#.to_string()),
+            request(&mut conn, Method::Get, "/categories", "")
+        );
+        assert_eq!(
+            (
+                201,
+                r#"{"id":1,"category":"work/coding","start_time":100,"end_time":200}"#.to_string()
+            ),
+            request(
+                &mut conn,
+                Method::Post,
+                "/times",
+                r#"{"category": "work/coding", "start_time": 100, "end_time": 200}"#
+            )
+        );
+        //same overlap validation as the CLI
+        assert_eq!(
+            409,
+            request(
+                &mut conn,
+                Method::Post,
+                "/times",
+                r#"{"category": "work/coding", "start_time": 150, "end_time": 250}"#
+            )
+            .0
+        );
+        assert_eq!(
+            200,
+            request(&mut conn, Method::Put, "/times/1", r#"{"end_time": 300}"#).0
+        );
+        //same check for typos as the CLI
+        let future = r#"{"end_time": 99999999999}"#;
+        assert_eq!(400, request(&mut conn, Method::Put, "/times/1", future).0);
+        let allowed = r#"{"end_time": 99999999999, "allow_unusual_times": true}"#;
+        assert_eq!(200, request(&mut conn, Method::Put, "/times/1", allowed).0);
+        assert_eq!(
+            200,
+            request(&mut conn, Method::Put, "/times/1", r#"{"end_time": 300}"#).0
+        );
+        //notes can be set when logging or amending, and removed with an empty note
+        assert!(
+            request(&mut conn, Method::Put, "/times/1", r#"{"note": "standup"}"#)
+                .1
+                .contains(r#""note":"standup""#)
+        );
+        assert!(
+            !request(&mut conn, Method::Put, "/times/1", r#"{"note": ""}"#)
+                .1
+                .contains("note")
+        );
+        assert_eq!(
+            (
+                200,
+                r#"[{"id":1,"category":"work/coding","start_time":100,"end_time":300}]"#
+                    .to_string()
+            ),
+            request(&mut conn, Method::Get, "/times?category_match=work%2F*", "")
+        );
+        assert_eq!(404, request(&mut conn, Method::Get, "/times/2", "").0);
+        assert_eq!(404, request(&mut conn, Method::Get, "/nope", "").0);
+
+        assert_eq!(
+            (200, "null".to_string()),
+            request(&mut conn, Method::Get, "/current", "")
+        );
+        request(
+            &mut conn,
+            Method::Post,
+            "/start",
+            r#"{"category": "work/coding"}"#,
+        );
+        assert!(request(&mut conn, Method::Get, "/current", "")
+            .1
+            .contains(r#""end_time":null"#));
+        request(&mut conn, Method::Post, "/stop", "");
+        assert_eq!(
+            (200, "null".to_string()),
+            request(&mut conn, Method::Get, "/current", "")
+        );
+
+        assert!(request(&mut conn, Method::Get, "/summary", "")
+            .1
+            .contains("work/coding"));
+        assert_eq!(200, request(&mut conn, Method::Delete, "/times/1", "").0);
+    }
+
+    #[test]
+    fn test_change_events() {
+        let mut conn = test_db();
+        let mut events = |method: Method, url: &str, body: &str| {
+            let (result, changes) = route_recording_changes(&mut conn, &method, url, body);
+            assert!(result.is_ok());
+            changes
+                .iter()
+                .filter_map(|c| change_event(&mut conn, c).unwrap())
+                .map(|e| e["type"].as_str().unwrap().to_string())
+                .collect::<Vec<String>>()
+        };
+        assert_eq!(
+            vec!["categories"],
+            events(Method::Post, "/categories", r#"{"name": "work"}"#)
+        );
+        assert_eq!(
+            vec!["start"],
+            events(Method::Post, "/start", r#"{"category": "work"}"#)
+        );
+        assert_eq!(vec!["stop"], events(Method::Post, "/stop", ""));
+        assert_eq!(
+            vec!["amend"],
+            events(Method::Put, "/times/1", r#"{"start_time": 100}"#)
+        );
+        assert_eq!(vec!["delete"], events(Method::Delete, "/times/1", ""));
+        assert!(events(Method::Get, "/current", "").is_empty());
+    }
+
+    #[test]
+    fn test_open_events() {
+        let mut conn = test_db();
+        let events = Events::default();
+        let receiver = events.subscribe();
+        let published = || {
+            receiver
+                .try_iter()
+                .map(|e| serde_json::from_str::<serde_json::Value>(&e).unwrap()["type"].to_string())
+                .collect::<Vec<String>>()
+        };
+        let open =
+            |conn: &mut Connection| db::get_last_open_time(&conn.transaction().unwrap()).unwrap();
+
+        route(
+            &mut conn,
+            &Method::Post,
+            "/categories",
+            r#"{"name": "work"}"#,
+        )
+        .unwrap();
+        //started from the CLI, so only the watcher sees it
+        log::start_timing(&mut conn, &"work".to_string(), &None, &false).unwrap();
+        let started = open(&mut conn);
+        events.publish_open(&mut conn, started.clone()).unwrap();
+        assert_eq!(vec![r#""start""#], published());
+        //a request's hook seeing the same start doesn't publish it again
+        let start = json!({ "type": "start", "time": started });
+        events.publish_request(vec![start], started);
+        assert!(published().is_empty());
+
+        route(&mut conn, &Method::Put, "/times/1", r#"{"note": "hi"}"#).unwrap();
+        let amended = open(&mut conn);
+        events.publish_open(&mut conn, amended).unwrap();
+        assert_eq!(vec![r#""amend""#], published());
+
+        log::stop_timing(&mut conn, &false).unwrap();
+        events.publish_open(&mut conn, None).unwrap();
+        assert_eq!(vec![r#""stop""#], published());
+        let stop = json!({ "type": "stop", "time": get_time(&mut conn, 1).unwrap() });
+        events.publish_request(vec![stop], None);
+        assert!(published().is_empty());
+        events.publish_open(&mut conn, None).unwrap();
+        assert!(published().is_empty());
+    }
+}
@@ -0,0 +1,270 @@
+/*
+This file is part of Timetrack Jr.
+Timetrack Jr. is free software: you can redistribute it and/or modify it under the terms of the GNU General Public License as published by the Free Software Foundation, either version 3 of the License, or (at your option) any later version.
+Timetrack Jr. is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU General Public License for more details.
+You should have received a copy of the GNU General Public License along with Timetrack Jr. If not, see <https://www.gnu.org/licenses/>.
+*/
+//Installing the daemon so it runs in the background.  Linux gets a systemd user unit (and optionally a socket unit
+// for the REST API, which serve picks up from LISTEN_FDS), macOS a launchd agent, and windows a scheduled task run at
+// logon (a real service would run outside the desktop session, where notifications and idle detection don't work).
+use crate::{db, TTError};
+use rusqlite::Connection;
+use std::{fs, path::PathBuf, process::Command};
+
+const SERVICE: &str = "ttjr.service";
+const SOCKET: &str = "ttjr.socket";
+const LAUNCHD_LABEL: &str = "ttjr.daemon";
+const TASK_NAME: &str = "Timetrack Jr daemon";
+
+///Quote a string for a systemd ExecStart line (% starts a specifier, so it's doubled)
+fn quote(s: &str) -> String {
+    format!(
+        "\"{}\"",
+        s.replace('\\', "\\\\")
+            .replace('"', "\\\"")
+            .replace('%', "%%")
+    )
+}
+
+///A user unit running the daemon.  Paths to ttjr and the DB are baked in since units don't run with the login
+/// shell's PATH or working directory.
+fn service_unit(ttjr: &str, db_path: &str, interval: &u64, socket: &bool) -> String {
+    let socket = match socket {
+        true => format!("Requires={}\nAfter={}\n", SOCKET, SOCKET),
+        false => String::new(),
+    };
+    format!(
+        "[Unit]\nDescription=Timetrack Jr. daemon\n{}\n[Service]\nExecStart={} --db-path {} daemon --interval {}\nRestart=on-failure\n\n[Install]\nWantedBy=default.target\n",
+        socket,
+        quote(ttjr),
+        quote(db_path),
+        interval
+    )
+}
+
+///A socket unit holding the REST API's address for the daemon
+fn socket_unit(bind_addr: &str) -> String {
+    format!(
+        "[Unit]\nDescription=Timetrack Jr. REST API\n\n[Socket]\nListenStream={}\n\n[Install]\nWantedBy=sockets.target\n",
+        bind_addr
+    )
+}
+
+///Escape a string for a plist
+fn xml_escape(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+}
+
+///A launchd agent running the daemon, restarting it if it fails and logging to `log_path`
+fn launchd_plist(ttjr: &str, db_path: &str, interval: &u64, log_path: &str) -> String {
+    let arguments = [
+        ttjr,
+        "--db-path",
+        db_path,
+        "daemon",
+        "--interval",
+        &interval.to_string(),
+    ]
+    .iter()
+    .map(|arg| format!("        <string>{}</string>\n", xml_escape(arg)))
+    .collect::<String>();
+    format!(
+        r#"<?xml version="1.0" encoding="UTF-8"?>
+<!DOCTYPE plist PUBLIC "-//Apple//DTD PLIST 1.0//EN" "http://www.apple.com/DTDs/PropertyList-1.0.dtd">
+<plist version="1.0">
+<dict>
+    <key>Label</key>
+    <string>{}</string>
+    <key>ProgramArguments</key>
+    <array>
+{}    </array>
+    <key>RunAtLoad</key>
+    <true/>
+    <key>KeepAlive</key>
+    <dict>
+        <key>SuccessfulExit</key>
+        <false/>
+    </dict>
+    <key>StandardOutPath</key>
+    <string>{}</string>
+    <key>StandardErrorPath</key>
+    <string>{}</string>
+</dict>
+</plist>
+"#,
+        LAUNCHD_LABEL,
+        arguments,
+        xml_escape(log_path),
+        xml_escape(log_path)
+    )
+}
+
+///The command line a scheduled task runs
+fn task_command(ttjr: &str, db_path: &str, interval: &u64) -> String {
+    format!(
+        "\"{}\" --db-path \"{}\" daemon --interval {}",
+        ttjr, db_path, interval
+    )
+}
+
+fn home() -> Result<PathBuf, TTError> {
+    std::env::var("HOME")
+        .map(PathBuf::from)
+        .map_err(|_| TTError::System {
+            message: "Couldn't find your home directory, set HOME".to_string(),
+        })
+}
+
+///~/.config/systemd/user (or under $XDG_CONFIG_HOME)
+fn unit_dir() -> Result<PathBuf, TTError> {
+    let config = match (std::env::var("XDG_CONFIG_HOME"), std::env::var("HOME")) {
+        (Ok(config), _) if !config.is_empty() => PathBuf::from(config),
+        (_, Ok(home)) => PathBuf::from(home).join(".config"),
+        _ => {
+            return Err(TTError::System {
+                message: "Couldn't find your config directory, set HOME or XDG_CONFIG_HOME"
+                    .to_string(),
+            })
+        }
+    };
+    Ok(config.join("systemd").join("user"))
+}
+
+fn run(program: &str, args: &[&str]) -> Result<(), TTError> {
+    let failed = |e: String| TTError::System {
+        message: format!("`{} {}` failed: {}", program, args.join(" "), e),
+    };
+    let output = Command::new(program)
+        .args(args)
+        .output()
+        .map_err(|e| failed(e.to_string()))?;
+    match output.status.success() {
+        true => Ok(()),
+        false => Err(failed(
+            String::from_utf8_lossy(&output.stderr).trim().to_string(),
+        )),
+    }
+}
+
+///Write and enable a systemd user unit running the daemon, with a socket unit for the REST API if `socket`
+pub fn install_systemd(
+    conn: &mut Connection,
+    db_path: &str,
+    interval: &u64,
+    socket: &bool,
+) -> Result<(), TTError> {
+    let dir = unit_dir()?;
+    fs::create_dir_all(&dir)?;
+    let ttjr = std::env::current_exe()?;
+    let db_path = fs::canonicalize(db_path)?;
+    let service = dir.join(SERVICE);
+    fs::write(
+        &service,
+        service_unit(
+            &ttjr.to_string_lossy(),
+            &db_path.to_string_lossy(),
+            interval,
+            socket,
+        ),
+    )?;
+    println!("Wrote {}", service.display());
+    let mut units = vec![SERVICE];
+    if *socket {
+        let bind_addr = db::get_options(&conn.transaction()?)?
+            .get("daemon-bind-addr")
+            .cloned()
+            .unwrap_or("127.0.0.1:8080".to_string());
+        let path = dir.join(SOCKET);
+        fs::write(&path, socket_unit(&bind_addr))?;
+        println!("Wrote {}", path.display());
+        units.insert(0, SOCKET);
+    }
+    run("systemctl", &["--user", "daemon-reload"])?;
+    run(
+        "systemctl",
+        &[&["--user", "enable", "--now"], units.as_slice()].concat(),
+    )?;
+    println!("Enabled {}", units.join(" and "));
+    Ok(())
+}
+
+///Write and load a launchd agent running the daemon
+pub fn install_launchd(db_path: &str, interval: &u64) -> Result<(), TTError> {
+    let home = home()?;
+    let dir = home.join("Library").join("LaunchAgents");
+    fs::create_dir_all(&dir)?;
+    let ttjr = std::env::current_exe()?;
+    let db_path = fs::canonicalize(db_path)?;
+    let log_path = home.join("Library").join("Logs").join("ttjr.log");
+    let path = dir.join(format!("{}.plist", LAUNCHD_LABEL));
+    let path_string = path.to_string_lossy().to_string();
+    //reloading picks up changes to an agent that's already installed
+    if path.exists() {
+        let _ = run("launchctl", &["unload", &path_string]);
+    }
+    fs::write(
+        &path,
+        launchd_plist(
+            &ttjr.to_string_lossy(),
+            &db_path.to_string_lossy(),
+            interval,
+            &log_path.to_string_lossy(),
+        ),
+    )?;
+    println!("Wrote {}", path.display());
+    run("launchctl", &["load", "-w", &path_string])?;
+    println!(
+        "Loaded {} (logging to {})",
+        LAUNCHD_LABEL,
+        log_path.display()
+    );
+    Ok(())
+}
+
+///Register and start a scheduled task running the daemon at logon
+pub fn install_winservice(db_path: &str, interval: &u64) -> Result<(), TTError> {
+    let ttjr = std::env::current_exe()?;
+    let db_path = fs::canonicalize(db_path)?;
+    let command = task_command(
+        &ttjr.to_string_lossy(),
+        &db_path.to_string_lossy(),
+        interval,
+    );
+    run(
+        "schtasks",
+        &[
+            "/Create", "/F", "/SC", "ONLOGON", "/RL", "LIMITED", "/TN", TASK_NAME, "/TR", &command,
+        ],
+    )?;
+    run("schtasks", &["/Run", "/TN", TASK_NAME])?;
+    println!("Registered and started the \"{}\" task", TASK_NAME);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_units() {
+        let unit = service_unit("/opt/ttjr", "/home/me/100% \"time\".sqlite3", &30, &false);
+        assert!(unit.contains(
+            "ExecStart=\"/opt/ttjr\" --db-path \"/home/me/100%% \\\"time\\\".sqlite3\" daemon --interval 30\n"
+        ));
+        assert!(unit.contains("WantedBy=default.target"));
+        assert!(!unit.contains("Requires="));
+        assert!(service_unit("/opt/ttjr", "/db", &60, &true).contains("Requires=ttjr.socket\n"));
+        assert!(socket_unit("127.0.0.1:8080").contains("ListenStream=127.0.0.1:8080\n"));
+        let plist = launchd_plist("/opt/ttjr", "/Users/me/a&b.sqlite3", &30, "/tmp/ttjr.log");
+        assert!(plist.contains(
+            "        <string>/Users/me/a&amp;b.sqlite3</string>\n        <string>daemon</string>\n"
+        ));
+        assert!(plist.contains("<string>ttjr.daemon</string>"));
+        assert_eq!(
+            "\"C:\\ttjr.exe\" --db-path \"C:\\Users\\me\\ttjr.sqlite3\" daemon --interval 60",
+            task_command("C:\\ttjr.exe", "C:\\Users\\me\\ttjr.sqlite3", &60)
+        );
+    }
+}
@@ -0,0 +1,96 @@
+/*
+This file is part of Timetrack Jr.
+Timetrack Jr. is free software: you can redistribute it and/or modify it under the terms of the GNU General Public License as published by the Free Software Foundation, either version 3 of the License, or (at your option) any later version.
+Timetrack Jr. is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU General Public License for more details.
+You should have received a copy of the GNU General Public License along with Timetrack Jr. If not, see <https://www.gnu.org/licenses/>.
+*/
+//Stand-ins for notify_rust when ttjr's built without the notifications feature (for servers, where it'd pull in
+// D-Bus).  Notifications are put together as usual but can't be shown, so they go to the notify-fallback option
+// instead, or fail saying the feature's missing.
+use crate::commands::not_compiled;
+use std::io;
+
+pub(crate) struct Notification {
+    pub summary: String,
+    pub body: String,
+}
+
+#[allow(dead_code)]
+pub(crate) enum Timeout {
+    Milliseconds(u32),
+}
+
+#[cfg(not(target_os = "macos"))]
+pub(crate) enum Urgency {
+    Low,
+    Normal,
+    Critical,
+}
+
+#[cfg(all(unix, not(target_os = "macos")))]
+#[allow(dead_code)]
+pub(crate) enum Hint {
+    SuppressSound(bool),
+}
+
+///A shown notification, which there can't be
+pub(crate) enum NotificationHandle {}
+
+impl NotificationHandle {
+    pub fn wait_for_action(self, _: impl FnOnce(&str)) {
+        match self {}
+    }
+}
+
+impl Notification {
+    pub fn new() -> Notification {
+        Notification {
+            summary: String::new(),
+            body: String::new(),
+        }
+    }
+
+    pub fn appname(&mut self, _: &str) -> &mut Notification {
+        self
+    }
+
+    pub fn summary(&mut self, summary: &str) -> &mut Notification {
+        self.summary = summary.to_string();
+        self
+    }
+
+    pub fn body(&mut self, body: &str) -> &mut Notification {
+        self.body = body.to_string();
+        self
+    }
+
+    pub fn action(&mut self, _: &str, _: &str) -> &mut Notification {
+        self
+    }
+
+    pub fn timeout(&mut self, _: Timeout) -> &mut Notification {
+        self
+    }
+
+    pub fn sound_name(&mut self, _: &str) -> &mut Notification {
+        self
+    }
+
+    #[cfg(not(target_os = "macos"))]
+    pub fn urgency(&mut self, _: Urgency) -> &mut Notification {
+        self
+    }
+
+    #[cfg(all(unix, not(target_os = "macos")))]
+    pub fn hint(&mut self, _: Hint) -> &mut Notification {
+        self
+    }
+
+    ///Fails with an io error, which turns into a TTError like notify_rust's errors do
+    pub fn show(&self) -> Result<NotificationHandle, io::Error> {
+        Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            not_compiled("notifications").to_string(),
+        ))
+    }
+}
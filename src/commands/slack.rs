@@ -0,0 +1,84 @@
+/*
+This file is part of Timetrack Jr.
+Timetrack Jr. is free software: you can redistribute it and/or modify it under the terms of the GNU General Public License as published by the Free Software Foundation, either version 3 of the License, or (at your option) any later version.
+Timetrack Jr. is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU General Public License for more details.
+You should have received a copy of the GNU General Public License along with Timetrack Jr. If not, see <https://www.gnu.org/licenses/>.
+*/
+use crate::{commands::sync::api_request, db, TTError};
+use rusqlite::Connection;
+use serde_json::{json, Value};
+
+const API: &str = "https://slack.com/api/users.profile.set";
+const DEFAULT_EMOJI: &str = ":stopwatch:";
+
+///The profile fields that show `category` as the status (or clear the status when not timing)
+fn profile(category: Option<&str>, category_options: &db::CategoryOptions) -> Value {
+    match category {
+        Some(category) => json!({
+            "status_text": category,
+            "status_emoji": category_options
+                .get(category)
+                .and_then(|o| o.get("slack-emoji"))
+                .map(|e| e.as_str())
+                .unwrap_or(DEFAULT_EMOJI),
+            "status_expiration": 0,
+        }),
+        None => json!({
+            "status_text": "",
+            "status_emoji": "",
+            "status_expiration": 0,
+        }),
+    }
+}
+
+///Set your Slack status to the category being timed (or clear it), if a slack-token is set
+pub(crate) fn update_status(conn: &mut Connection, category: Option<&str>) -> Result<(), TTError> {
+    let (options, category_options) = {
+        let tx = conn.transaction()?;
+        (db::get_options(&tx)?, db::get_category_options(&tx)?)
+    };
+    let Some(token) = std::env::var("SLACK_TOKEN")
+        .ok()
+        .or(options.get("slack-token").cloned())
+    else {
+        return Ok(());
+    };
+    let response = api_request(
+        "POST",
+        API,
+        &[("Authorization", &format!("Bearer {}", token))],
+        Some(&json!({ "profile": profile(category, &category_options) })),
+    )?;
+    //slack reports errors in the body rather than with the status code
+    match response["ok"].as_bool() {
+        Some(true) => Ok(()),
+        _ => Err(TTError::Network {
+            message: format!(
+                "Slack refused the status update: {}",
+                response["error"].as_str().unwrap_or("unknown error")
+            ),
+        }),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_profile() {
+        let category_options = db::CategoryOptions::from([(
+            "deep work".to_string(),
+            db::Options::from([("slack-emoji".to_string(), ":headphones:".to_string())]),
+        )]);
+        assert_eq!(
+            json!({"status_text": "deep work", "status_emoji": ":headphones:", "status_expiration": 0}),
+            profile(Some("deep work"), &category_options)
+        );
+        assert_eq!(
+            ":stopwatch:",
+            profile(Some("email"), &category_options)["status_emoji"]
+        );
+        assert_eq!("", profile(None, &category_options)["status_text"]);
+    }
+}
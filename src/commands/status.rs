@@ -0,0 +1,154 @@
+/*
+This file is part of Timetrack Jr.
+Timetrack Jr. is free software: you can redistribute it and/or modify it under the terms of the GNU General Public License as published by the Free Software Foundation, either version 3 of the License, or (at your option) any later version.
+Timetrack Jr. is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU General Public License for more details.
+You should have received a copy of the GNU General Public License along with Timetrack Jr. If not, see <https://www.gnu.org/licenses/>.
+*/
+use crate::{
+    cli::StatusFormat,
+    db::{self, TimeWindow},
+    zone::Zone,
+    TTError,
+};
+use chrono::Utc;
+use rusqlite::Connection;
+use serde_json::{json, Value};
+
+///Time since `start` as hours:minutes
+pub(crate) fn elapsed(start: i64, now: i64) -> String {
+    let minutes = (now - start).max(0) / 60;
+    format!("{:02}:{:02}", minutes / 60, minutes % 60)
+}
+
+///Time since `start` as compactly as possible, i.e. 5m or 1h23m
+fn short_elapsed(start: i64, now: i64) -> String {
+    let minutes = (now - start).max(0) / 60;
+    match minutes / 60 {
+        0 => format!("{}m", minutes),
+        hours => format!("{}h{:02}m", hours, minutes % 60),
+    }
+}
+
+///A waybar custom module's JSON (empty text when not timing, so the module hides itself)
+fn waybar(current: Option<&TimeWindow>, now: i64) -> Value {
+    match current {
+        Some(time) => json!({
+            "text": format!("{} {}", time.category, elapsed(time.start_time, now)),
+            "tooltip": format!(
+                "Timing {} since {}",
+                time.category,
+                Zone::Local.format(&time.start_time, "%H:%M")
+            ),
+            "class": "timing",
+            "alt": "timing",
+        }),
+        None => json!({
+            "text": "",
+            "tooltip": "Not timing",
+            "class": "idle",
+            "alt": "idle",
+        }),
+    }
+}
+
+///`template` filled in for the time being recorded, or `not_timing`, cut down to `max_length` characters
+fn plain(
+    current: Option<&TimeWindow>,
+    now: i64,
+    template: &str,
+    not_timing: &str,
+    max_length: &Option<usize>,
+) -> String {
+    let line = match current {
+        Some(time) => template
+            .replace("{category}", &time.category)
+            .replace("{elapsed}", &elapsed(time.start_time, now))
+            .replace("{start}", &Zone::Local.format(&time.start_time, "%H:%M")),
+        None => not_timing.to_string(),
+    };
+    truncate(line, max_length)
+}
+
+fn truncate(line: String, max_length: &Option<usize>) -> String {
+    match max_length {
+        Some(max) if line.chars().count() > *max => match max {
+            0 => String::new(),
+            _ => line.chars().take(max - 1).chain(['…']).collect(),
+        },
+        _ => line,
+    }
+}
+
+///Print one line describing what's being timed, for status bars which run a command every few seconds
+pub fn status(
+    conn: &mut Connection,
+    format: &StatusFormat,
+    template: &str,
+    not_timing: &str,
+    max_length: &Option<usize>,
+) -> Result<(), TTError> {
+    let current = db::get_last_open_time(&conn.transaction()?)?;
+    let now = Utc::now().timestamp();
+    let line = match format {
+        StatusFormat::Waybar => waybar(current.as_ref(), now).to_string(),
+        StatusFormat::Plain => plain(current.as_ref(), now, template, not_timing, max_length),
+        StatusFormat::Starship => truncate(
+            current
+                .map(|t| format!("{} {}", t.category, short_elapsed(t.start_time, now)))
+                .unwrap_or_default(),
+            max_length,
+        ),
+    };
+    println!("{}", line);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_waybar() {
+        let time = TimeWindow {
+            id: Some(1),
+            category: "work".to_string(),
+            start_time: 1000,
+            end_time: None,
+            note: None,
+        };
+        let status = waybar(Some(&time), 1000 + 83 * 60 + 59);
+        assert_eq!("work 01:23", status["text"]);
+        assert_eq!("timing", status["class"]);
+        assert_eq!(json!(""), waybar(None, 1000)["text"]);
+        assert_eq!("idle", waybar(None, 1000)["class"]);
+    }
+
+    #[test]
+    fn test_plain() {
+        let time = TimeWindow {
+            id: Some(1),
+            category: "client-a".to_string(),
+            start_time: 0,
+            end_time: None,
+            note: None,
+        };
+        let template = "[{category}] {elapsed}";
+        assert_eq!(
+            "[client-a] 02:05",
+            plain(Some(&time), 7500, template, "idle", &None)
+        );
+        assert_eq!(
+            "[client-a…",
+            plain(Some(&time), 7500, template, "idle", &Some(10))
+        );
+        assert_eq!("idle", plain(None, 7500, template, "idle", &Some(10)));
+    }
+
+    #[test]
+    fn test_short_elapsed() {
+        assert_eq!("0m", short_elapsed(100, 159));
+        assert_eq!("59m", short_elapsed(0, 3599));
+        assert_eq!("1h05m", short_elapsed(0, 3900));
+        assert_eq!("26h00m", short_elapsed(0, 26 * 3600));
+    }
+}
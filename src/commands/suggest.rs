@@ -0,0 +1,265 @@
+/*
+This file is part of Timetrack Jr.
+Timetrack Jr. is free software: you can redistribute it and/or modify it under the terms of the GNU General Public License as published by the Free Software Foundation, either version 3 of the License, or (at your option) any later version.
+Timetrack Jr. is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU General Public License for more details.
+You should have received a copy of the GNU General Public License along with Timetrack Jr. If not, see <https://www.gnu.org/licenses/>.
+*/
+use crate::{
+    cli::{self, NotificationEvent},
+    commands::notify,
+    db, TTError,
+};
+use chrono::Utc;
+use rusqlite::Connection;
+use std::{collections::BTreeMap, process::Command, thread, time::Duration};
+
+///The latest thing that happened in a git repo (a commit, checkout, rebase...)
+#[derive(Debug, Clone, PartialEq)]
+struct Activity {
+    repo: String,
+    branch: String,
+    ///Reflog message, like "commit: Fix login" or "checkout: moving from main to fix-login"
+    description: String,
+    time: i64,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct Suggestion {
+    category: String,
+    activity: Activity,
+}
+
+///Expand a leading ~ to $HOME
+fn expand_home(path: &str) -> String {
+    match (path.strip_prefix("~/"), std::env::var("HOME")) {
+        (Some(rest), Ok(home)) => format!("{}/{}", home, rest),
+        _ => path.to_string(),
+    }
+}
+
+///Run git in `repo`, returning its trimmed output (None if it failed)
+pub(crate) fn git(repo: &str, args: &[&str]) -> Option<String> {
+    let output = Command::new("git")
+        .arg("-C")
+        .arg(repo)
+        .args(args)
+        .output()
+        .ok()?;
+    match output.status.success() {
+        true => Some(String::from_utf8_lossy(&output.stdout).trim().to_string()),
+        false => None,
+    }
+}
+
+///The latest entry in the repo's HEAD reflog, None if the path isn't a git repo (or has no history)
+fn latest_activity(repo: &str) -> Option<Activity> {
+    let path = expand_home(repo);
+    let branch = git(&path, &["rev-parse", "--abbrev-ref", "HEAD"])?;
+    let latest = git(
+        &path,
+        &["log", "-g", "-n", "1", "--format=%ct%x09%gs", "HEAD"],
+    )?;
+    let (time, description) = latest.split_once('\t')?;
+    Some(Activity {
+        repo: repo.to_string(),
+        branch,
+        description: description.to_string(),
+        time: time.parse().ok()?,
+    })
+}
+
+///Category for git activity: a category named in the branch (the longest, if several are), otherwise the
+/// category whose git-repo option lists the repo
+fn category_for(
+    activity: &Activity,
+    categories: &db::Categories,
+    repos: &BTreeMap<String, Vec<String>>,
+) -> Option<String> {
+    let branch = activity.branch.to_lowercase();
+    categories
+        .iter()
+        .filter(|c| branch.contains(&c.to_lowercase()))
+        .max_by_key(|c| c.len())
+        .or(repos
+            .iter()
+            .find(|(_, r)| r.contains(&activity.repo))
+            .map(|(category, _)| category))
+        .cloned()
+}
+
+///Categories matching git activity since `since`, most recent first (each category only appears once)
+fn suggestions(
+    activities: &[Activity],
+    categories: &db::Categories,
+    repos: &BTreeMap<String, Vec<String>>,
+    since: i64,
+) -> Vec<Suggestion> {
+    let mut activities: Vec<&Activity> = activities.iter().filter(|a| a.time >= since).collect();
+    activities.sort_by_key(|a| -a.time);
+    let mut suggestions: Vec<Suggestion> = vec![];
+    for activity in activities {
+        if let Some(category) = category_for(activity, categories, repos) {
+            if !suggestions.iter().any(|s| s.category == category) {
+                suggestions.push(Suggestion {
+                    category,
+                    activity: activity.clone(),
+                });
+            }
+        }
+    }
+    suggestions
+}
+
+///Suggest categories from activity in the git repos listed in git-repo category options, and whether the running timer matches
+fn check(conn: &mut Connection, since: i64) -> Result<(Option<String>, Vec<Suggestion>), TTError> {
+    let (categories, category_options, open) = {
+        let tx = conn.transaction()?;
+        (
+            db::get_categories(&tx)?,
+            db::get_category_options(&tx)?,
+            db::get_last_open_time(&tx)?,
+        )
+    };
+    let repos: BTreeMap<String, Vec<String>> = category_options
+        .iter()
+        .filter_map(|(category, options)| {
+            let repos = options.get("git-repo")?;
+            Some((
+                category.clone(),
+                repos.split(',').map(|r| r.trim().to_string()).collect(),
+            ))
+        })
+        .collect();
+    let mut all_repos: Vec<&String> = repos.values().flatten().collect();
+    all_repos.sort();
+    all_repos.dedup();
+    let activities: Vec<Activity> = all_repos
+        .into_iter()
+        .filter_map(|r| latest_activity(r))
+        .collect();
+    Ok((
+        open.map(|t| t.category),
+        suggestions(&activities, &categories, &repos, since),
+    ))
+}
+
+fn describe(suggestion: &Suggestion) -> String {
+    format!(
+        "{} ({} in {} on {}, {} minutes ago)",
+        suggestion.category,
+        suggestion.activity.description,
+        suggestion.activity.repo,
+        suggestion.activity.branch,
+        (Utc::now().timestamp() - suggestion.activity.time).max(0) / 60
+    )
+}
+
+///Print categories suggested by recent git activity.  With `watch`, keep checking every `interval` seconds and
+/// report (and optionally notify) whenever the top suggestion doesn't match what's being timed.
+pub fn suggest(
+    conn: &mut Connection,
+    since: &str,
+    watch: &bool,
+    interval: &u64,
+    notify: &bool,
+) -> Result<(), TTError> {
+    let since_tstamp = || {
+        cli::time_string_to_tstamp(&Some(since.to_string()))
+            .ok_or_else(|| TTError::unparseable(since, "--since"))
+    };
+    if !*watch {
+        let (timing, suggestions) = check(conn, since_tstamp()?)?;
+        if suggestions.is_empty() {
+            println!("No recent git activity in repos with a git-repo category option");
+        }
+        for suggestion in &suggestions {
+            println!("Suggested: {}", describe(suggestion));
+        }
+        if let Some(timing) = timing {
+            println!("Currently timing: {}", timing);
+        }
+        return Ok(());
+    }
+
+    let mut last_reported: Option<Suggestion> = None;
+    loop {
+        if let Some((summary, message)) = mismatch(conn, since_tstamp()?, &mut last_reported)? {
+            println!("{}", message);
+            if *notify {
+                let mut notification = notify::notification(conn, &NotificationEvent::Suggest)?;
+                notification.summary(&summary).body(&message);
+                notify::show(conn, &NotificationEvent::Suggest, &notification)?;
+            }
+        }
+        thread::sleep(Duration::from_secs(*interval));
+    }
+}
+
+///A summary and message if the top suggestion doesn't match what's being timed, unless it's `last_reported`
+/// (which is updated, so each mismatch is only reported once)
+pub(crate) fn mismatch(
+    conn: &mut Connection,
+    since: i64,
+    last_reported: &mut Option<Suggestion>,
+) -> Result<Option<(String, String)>, TTError> {
+    let (timing, suggestions) = check(conn, since)?;
+    let top = match suggestions.first() {
+        Some(top) => top,
+        None => return Ok(None),
+    };
+    if timing.as_ref() == Some(&top.category) || last_reported.as_ref() == Some(top) {
+        return Ok(None);
+    }
+    let message = match &timing {
+        Some(timing) => format!(
+            "Timing {}, but you seem to be working on {}",
+            timing,
+            describe(top)
+        ),
+        None => format!(
+            "Not timing, but you seem to be working on {}",
+            describe(top)
+        ),
+    };
+    *last_reported = Some(top.clone());
+    Ok(Some((format!("Working on {}?", top.category), message)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_suggestions() {
+        let activity = |repo: &str, branch: &str, time| Activity {
+            repo: repo.to_string(),
+            branch: branch.to_string(),
+            description: "commit: stuff".to_string(),
+            time,
+        };
+        let categories: db::Categories = ["client-a", "client-a-support", "internal"]
+            .map(String::from)
+            .into();
+        let repos = BTreeMap::from([
+            ("internal".to_string(), vec!["~/src/tools".to_string()]),
+            ("client-a".to_string(), vec!["~/src/app".to_string()]),
+        ]);
+        let suggested: Vec<String> = suggestions(
+            &[
+                activity("~/src/app", "main", 100),
+                activity("~/src/tools", "client-a-support/fix-export", 300),
+                activity("~/src/tools", "main", 200),
+                activity("~/src/other", "main", 400),
+                activity("~/src/app", "main", 10),
+            ],
+            &categories,
+            &repos,
+            50,
+        )
+        .into_iter()
+        .map(|s| s.category)
+        .collect();
+        //branch names win over the repo's category, unmapped repos and old activity are ignored
+        assert_eq!(vec!["client-a-support", "internal", "client-a"], suggested);
+    }
+}
@@ -0,0 +1,614 @@
+/*
+This file is part of Timetrack Jr.
+Timetrack Jr. is free software: you can redistribute it and/or modify it under the terms of the GNU General Public License as published by the Free Software Foundation, either version 3 of the License, or (at your option) any later version.
+Timetrack Jr. is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU General Public License for more details.
+You should have received a copy of the GNU General Public License along with Timetrack Jr. If not, see <https://www.gnu.org/licenses/>.
+*/
+use crate::{
+    cli::{ConflictRule, FilterMode},
+    db::{self, PushedEvent, SyncedTime, TimeWindow},
+    TTError,
+};
+use rusqlite::Connection;
+use serde_json::Value;
+use std::collections::{BTreeMap, BTreeSet};
+
+///A finished time entry in a remote time tracker
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct RemoteTime {
+    pub id: String,
+    ///None if the entry couldn't be matched to a category
+    pub category: Option<String>,
+    pub start_time: i64,
+    pub end_time: i64,
+}
+
+///A remote time tracker that times can be synced with
+pub(crate) trait Remote {
+    ///Name of the service, used to keep track of which times have been synced with it
+    fn service(&self) -> &'static str;
+    ///Finished entries which started between `start` and `end`
+    fn list(&mut self, start: i64, end: i64) -> Result<Vec<RemoteTime>, TTError>;
+    ///Create an entry for a (finished) time, returning the new entry's id
+    fn create(&mut self, time: &TimeWindow) -> Result<String, TTError>;
+    fn update(&mut self, id: &str, time: &TimeWindow) -> Result<(), TTError>;
+    fn delete(&mut self, id: &str) -> Result<(), TTError>;
+}
+
+///What a sync did
+#[derive(Debug, Default, PartialEq)]
+pub(crate) struct SyncReport {
+    pub pushed: usize,
+    pub pulled: usize,
+    pub deleted_remote: usize,
+    pub deleted_local: usize,
+    ///Descriptions of changes that weren't synced (conflicts and entries that couldn't be synced)
+    pub skipped: Vec<String>,
+}
+
+impl std::fmt::Display for SyncReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(
+            f,
+            "Pushed {} changes, pulled {} changes, deleted {} remote and {} local entries",
+            self.pushed, self.pulled, self.deleted_remote, self.deleted_local
+        )?;
+        for skipped in &self.skipped {
+            writeln!(f, "Skipped: {}", skipped)?;
+        }
+        Ok(())
+    }
+}
+
+///Make a request to a JSON API, returning the parsed response (null if the response was empty)
+pub(crate) fn api_request(
+    method: &str,
+    url: &str,
+    headers: &[(&str, &str)],
+    body: Option<&Value>,
+) -> Result<Value, TTError> {
+    api_request_if_exists(method, url, headers, body)?.ok_or(TTError::Network {
+        message: format!("{} {} failed: not found", method, url),
+    })
+}
+
+///Like `api_request`, but returns None if the resource doesn't exist (404 or 410)
+pub(crate) fn api_request_if_exists(
+    method: &str,
+    url: &str,
+    headers: &[(&str, &str)],
+    body: Option<&Value>,
+) -> Result<Option<Value>, TTError> {
+    let error = |e: &dyn std::fmt::Display| TTError::Network {
+        message: format!("{} {} failed: {}", method, url, e),
+    };
+    let mut request = ureq::http::Request::builder().method(method).uri(url);
+    for (name, value) in headers {
+        request = request.header(*name, *value);
+    }
+    let response = match body {
+        Some(body) => ureq::run(
+            request
+                .header("Content-Type", "application/json")
+                .body(body.to_string())
+                .map_err(|e| error(&e))?,
+        ),
+        None => ureq::run(request.body(()).map_err(|e| error(&e))?),
+    };
+    let text = match response {
+        Err(ureq::Error::StatusCode(404 | 410)) => return Ok(None),
+        response => response
+            .map_err(|e| error(&e))?
+            .body_mut()
+            .read_to_string()
+            .map_err(|e| error(&e))?,
+    };
+    match text.trim().is_empty() {
+        true => Ok(Some(Value::Null)),
+        false => Ok(Some(serde_json::from_str(&text)?)),
+    }
+}
+
+fn matches_snapshot(time: &TimeWindow, synced: &SyncedTime) -> bool {
+    time.category == synced.category
+        && time.start_time == synced.start_time
+        && time.end_time == Some(synced.end_time)
+}
+
+fn remote_matches_snapshot(time: &RemoteTime, synced: &SyncedTime) -> bool {
+    //entries whose project isn't mapped to a category keep whatever category they had
+    time.category.as_ref().is_none_or(|c| c == &synced.category)
+        && time.start_time == synced.start_time
+        && time.end_time == synced.end_time
+}
+
+fn snapshot(remote_id: &str, time: &TimeWindow) -> SyncedTime {
+    SyncedTime {
+        remote_id: remote_id.to_string(),
+        time_id: time.id.unwrap_or(-1),
+        category: time.category.clone(),
+        start_time: time.start_time,
+        end_time: time.end_time.unwrap_or(time.start_time),
+    }
+}
+
+///Sync state for a single service.  Each change is committed as soon as it's made on the remote,
+/// so an error part of the way through doesn't leave duplicates behind
+struct Syncer<'a> {
+    conn: &'a mut Connection,
+    remote: &'a mut dyn Remote,
+    service: &'static str,
+    report: SyncReport,
+}
+
+impl Syncer<'_> {
+    fn record(&mut self, remote_id: &str, time: &TimeWindow) -> Result<(), TTError> {
+        let service = self.service;
+        db::write(self.conn, |tx| {
+            db::set_synced_time(tx, service, &snapshot(remote_id, time))
+        })
+    }
+
+    fn forget(&mut self, remote_id: &str) -> Result<(), TTError> {
+        let service = self.service;
+        db::write(self.conn, |tx| {
+            db::delete_synced_time(tx, service, remote_id)
+        })
+    }
+
+    fn local_time(&mut self, id: i64) -> Result<Option<TimeWindow>, TTError> {
+        match db::get_time(&self.conn.transaction()?, id) {
+            Ok(time) => Ok(Some(time)),
+            Err(TTError::Sql(rusqlite::Error::QueryReturnedNoRows)) => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
+    fn push_new(&mut self, time: &TimeWindow) -> Result<(), TTError> {
+        let remote_id = self.remote.create(time)?;
+        self.record(&remote_id, time)?;
+        self.report.pushed += 1;
+        Ok(())
+    }
+
+    fn push_update(&mut self, remote_id: &str, time: &TimeWindow) -> Result<(), TTError> {
+        self.remote.update(remote_id, time)?;
+        self.record(remote_id, time)?;
+        self.report.pushed += 1;
+        Ok(())
+    }
+
+    fn delete_remote(&mut self, remote_id: &str) -> Result<(), TTError> {
+        self.remote.delete(remote_id)?;
+        self.forget(remote_id)?;
+        self.report.deleted_remote += 1;
+        Ok(())
+    }
+
+    ///Write a remote entry to the local DB (as a new time if `id` is None), skipping it if it overlaps other times
+    fn pull(
+        &mut self,
+        remote: &RemoteTime,
+        id: Option<i64>,
+        category: String,
+    ) -> Result<(), TTError> {
+        let time = TimeWindow {
+            id,
+            category,
+            start_time: remote.start_time,
+            end_time: Some(remote.end_time),
+            note: None,
+        };
+        let service = self.service;
+        let pulled = db::write(self.conn, |tx| {
+            db::upsert_time(tx, time.clone(), &true)?;
+            let time = TimeWindow {
+                id: id.or(Some(tx.last_insert_rowid())),
+                ..time.clone()
+            };
+            db::set_synced_time(tx, service, &snapshot(&remote.id, &time))
+        });
+        match pulled {
+            Ok(_) => self.report.pulled += 1,
+            Err(e) if e.recoverable() => self
+                .report
+                .skipped
+                .push(format!("remote entry {}: {}", remote.id, e)),
+            Err(e) => return Err(e),
+        }
+        Ok(())
+    }
+
+    fn delete_local(&mut self, synced: &SyncedTime) -> Result<(), TTError> {
+        let service = self.service;
+        db::write(self.conn, |tx| {
+            db::delete_time(tx, &synced.time_id)?;
+            db::delete_synced_time(tx, service, &synced.remote_id)
+        })?;
+        self.report.deleted_local += 1;
+        Ok(())
+    }
+
+    fn conflict(&mut self, synced: &SyncedTime, description: &str) {
+        self.report.skipped.push(format!(
+            "time {} / remote entry {} {} (use --conflicts local or --conflicts remote to pick a side)",
+            synced.time_id, synced.remote_id, description
+        ));
+    }
+
+    ///Reconcile a previously-synced time with its remote entry
+    fn reconcile(
+        &mut self,
+        synced: &SyncedTime,
+        remote: Option<&RemoteTime>,
+        rule: &ConflictRule,
+    ) -> Result<(), TTError> {
+        //times can't be synced while they're open, leave them until they're stopped
+        let local = match self.local_time(synced.time_id)? {
+            Some(time) if time.end_time.is_none() => return Ok(()),
+            local => local,
+        };
+        let pull_category = |r: &RemoteTime| r.category.clone().unwrap_or(synced.category.clone());
+        match (local, remote) {
+            (None, None) => self.forget(&synced.remote_id)?,
+            (None, Some(remote)) => match (remote_matches_snapshot(remote, synced), rule) {
+                (true, _) | (false, ConflictRule::Local) => self.delete_remote(&remote.id)?,
+                (false, ConflictRule::Remote) => {
+                    self.forget(&synced.remote_id)?;
+                    self.pull(remote, None, pull_category(remote))?
+                }
+                (false, ConflictRule::Skip) => {
+                    self.conflict(synced, "was deleted locally but changed remotely")
+                }
+            },
+            (Some(local), None) => match (matches_snapshot(&local, synced), rule) {
+                (true, _) | (false, ConflictRule::Remote) => self.delete_local(synced)?,
+                (false, ConflictRule::Local) => {
+                    self.forget(&synced.remote_id)?;
+                    self.push_new(&local)?
+                }
+                (false, ConflictRule::Skip) => {
+                    self.conflict(synced, "was changed locally but deleted remotely")
+                }
+            },
+            (Some(local), Some(remote)) => {
+                let local_changed = !matches_snapshot(&local, synced);
+                let remote_changed = !remote_matches_snapshot(remote, synced);
+                let same = local.category == pull_category(remote)
+                    && local.start_time == remote.start_time
+                    && local.end_time == Some(remote.end_time);
+                match (local_changed, remote_changed, rule) {
+                    (false, false, _) => {}
+                    //both changed the same way
+                    _ if same => self.record(&remote.id, &local)?,
+                    (true, false, _) | (true, true, ConflictRule::Local) => {
+                        self.push_update(&remote.id, &local)?
+                    }
+                    (false, true, _) | (true, true, ConflictRule::Remote) => {
+                        self.pull(remote, local.id, pull_category(remote))?
+                    }
+                    (true, true, ConflictRule::Skip) => {
+                        self.conflict(synced, "was changed both locally and remotely")
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+///A change needed to make a calendar match the local times
+#[derive(Debug, PartialEq)]
+pub(crate) enum EventChange {
+    Create(TimeWindow),
+    Update(TimeWindow, PushedEvent),
+    Delete(PushedEvent),
+}
+
+///Changes needed to mirror finished `times` to a calendar that `pushed` events have been pushed to.
+/// Events are only deleted if their time was deleted, so a time left out of `times` because it's still open keeps its event.
+pub(crate) fn event_changes(
+    times: &[TimeWindow],
+    pushed: Vec<PushedEvent>,
+    deleted: impl Fn(i64) -> bool,
+) -> Vec<EventChange> {
+    let mut pushed: BTreeMap<i64, PushedEvent> =
+        pushed.into_iter().map(|p| (p.time_id, p)).collect();
+    let mut changes = vec![];
+    for time in times {
+        let Some(end_time) = time.end_time else {
+            continue;
+        };
+        match pushed.remove(&time.id.unwrap_or(-1)) {
+            None => changes.push(EventChange::Create(time.clone())),
+            Some(event)
+                if event.category != time.category
+                    || event.start_time != time.start_time
+                    || event.end_time != end_time =>
+            {
+                changes.push(EventChange::Update(time.clone(), event))
+            }
+            Some(_) => {}
+        }
+    }
+    for event in pushed.into_values() {
+        if deleted(event.time_id) {
+            changes.push(EventChange::Delete(event));
+        }
+    }
+    changes
+}
+
+///Two-way sync of finished times which started between `start` and `end` with a remote time tracker
+#[tracing::instrument(
+    level = "debug",
+    skip_all,
+    fields(service = remote.service(), remote_rows, local_rows, pushed, pulled, skipped)
+)]
+pub(crate) fn sync(
+    conn: &mut Connection,
+    remote: &mut dyn Remote,
+    start: i64,
+    end: i64,
+    rule: &ConflictRule,
+) -> Result<SyncReport, TTError> {
+    let service = remote.service();
+    let remote_times: BTreeMap<String, RemoteTime> = remote
+        .list(start, end)?
+        .into_iter()
+        .map(|t| (t.id.clone(), t))
+        .collect();
+    let (synced, local_times) = {
+        let mut tx = conn.transaction()?;
+        (
+            db::get_synced_times(&tx, service)?,
+            db::get_times(&mut tx, Some(start), Some(end), &FilterMode::Start)?,
+        )
+    };
+    let span = tracing::Span::current();
+    span.record("remote_rows", remote_times.len());
+    span.record("local_rows", local_times.len());
+    let mut syncer = Syncer {
+        conn,
+        remote,
+        service,
+        report: SyncReport::default(),
+    };
+
+    let synced_local: BTreeSet<i64> = synced.iter().map(|s| s.time_id).collect();
+    let synced_remote: BTreeSet<&String> = synced.iter().map(|s| &s.remote_id).collect();
+    for entry in &synced {
+        let remote = remote_times.get(&entry.remote_id);
+        //an entry missing from a window it wasn't in hasn't been deleted, it just wasn't listed
+        if remote.is_none() && (entry.start_time < start || entry.start_time > end) {
+            continue;
+        }
+        syncer.reconcile(entry, remote, rule)?;
+    }
+    for time in local_times {
+        if time.end_time.is_some() && !synced_local.contains(&time.id.unwrap_or(-1)) {
+            syncer.push_new(&time)?;
+        }
+    }
+    for remote in remote_times.values() {
+        if synced_remote.contains(&remote.id) {
+            continue;
+        }
+        match &remote.category {
+            Some(category) => syncer.pull(remote, None, category.clone())?,
+            None => syncer.report.skipped.push(format!(
+                "remote entry {} doesn't match any category",
+                remote.id
+            )),
+        }
+    }
+    db::write(syncer.conn, |tx| db::set_last_synced(tx, service, end))?;
+    span.record("pushed", syncer.report.pushed);
+    span.record("pulled", syncer.report.pulled);
+    span.record("skipped", syncer.report.skipped.len());
+    Ok(syncer.report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::commands::config;
+
+    ///An in-memory remote for testing
+    #[derive(Default)]
+    struct FakeRemote {
+        entries: BTreeMap<String, RemoteTime>,
+        next_id: usize,
+    }
+
+    impl Remote for FakeRemote {
+        fn service(&self) -> &'static str {
+            "fake"
+        }
+
+        fn list(&mut self, start: i64, end: i64) -> Result<Vec<RemoteTime>, TTError> {
+            Ok(self
+                .entries
+                .values()
+                .filter(|e| e.start_time >= start && e.start_time <= end)
+                .cloned()
+                .collect())
+        }
+
+        fn create(&mut self, time: &TimeWindow) -> Result<String, TTError> {
+            self.next_id += 1;
+            let id = format!("r{}", self.next_id);
+            self.update(&id, time)?;
+            Ok(id)
+        }
+
+        fn update(&mut self, id: &str, time: &TimeWindow) -> Result<(), TTError> {
+            self.entries.insert(
+                id.to_string(),
+                RemoteTime {
+                    id: id.to_string(),
+                    category: Some(time.category.clone()),
+                    start_time: time.start_time,
+                    end_time: time.end_time.unwrap(),
+                },
+            );
+            Ok(())
+        }
+
+        fn delete(&mut self, id: &str) -> Result<(), TTError> {
+            self.entries.remove(id);
+            Ok(())
+        }
+    }
+
+    fn add_time(conn: &mut Connection, start: i64, end: Option<i64>) {
+        let mut tx = conn.transaction().unwrap();
+        db::upsert_time(
+            &mut tx,
+            TimeWindow {
+                id: None,
+                category: "work".to_string(),
+                start_time: start,
+                end_time: end,
+                note: None,
+            },
+            &true,
+        )
+        .unwrap();
+        tx.commit().unwrap();
+    }
+
+    fn local_times(conn: &mut Connection) -> Vec<(i64, Option<i64>)> {
+        db::get_times(
+            &mut conn.transaction().unwrap(),
+            None,
+            None,
+            &FilterMode::Start,
+        )
+        .unwrap()
+        .into_iter()
+        .map(|t| (t.start_time, t.end_time))
+        .collect()
+    }
+
+    #[test]
+    fn test_sync() {
+        let mut conn = Connection::open_in_memory().unwrap();
+        db::initialize_db(&mut conn).unwrap();
+        config::add_category(&mut conn, &"work".to_string()).unwrap();
+        let mut remote = FakeRemote::default();
+        add_time(&mut conn, 100, Some(200));
+        //open times aren't synced
+        add_time(&mut conn, 900, None);
+        remote.entries.insert(
+            "x".to_string(),
+            RemoteTime {
+                id: "x".to_string(),
+                category: Some("work".to_string()),
+                start_time: 300,
+                end_time: 400,
+            },
+        );
+        remote.entries.insert(
+            "y".to_string(),
+            RemoteTime {
+                id: "y".to_string(),
+                category: None,
+                start_time: 500,
+                end_time: 600,
+            },
+        );
+
+        let report = sync(&mut conn, &mut remote, 0, 1000, &ConflictRule::Skip).unwrap();
+        assert_eq!((1, 1), (report.pushed, report.pulled));
+        assert_eq!(1, report.skipped.len());
+        assert_eq!(
+            vec![(100, Some(200)), (300, Some(400)), (900, None)],
+            local_times(&mut conn)
+        );
+        assert_eq!(200, remote.entries["r1"].end_time);
+
+        //syncing again does nothing
+        let report = sync(&mut conn, &mut remote, 0, 1000, &ConflictRule::Skip).unwrap();
+        assert_eq!((0, 0), (report.pushed, report.pulled));
+
+        //one-sided changes and deletions flow to the other side
+        log_amend(&mut conn, 1, 250);
+        remote.entries.get_mut("x").unwrap().end_time = 450;
+        remote.entries.remove("y");
+        let report = sync(&mut conn, &mut remote, 0, 1000, &ConflictRule::Skip).unwrap();
+        assert_eq!((1, 1), (report.pushed, report.pulled));
+        assert_eq!(250, remote.entries["r1"].end_time);
+        assert_eq!(
+            vec![(100, Some(250)), (300, Some(450)), (900, None)],
+            local_times(&mut conn)
+        );
+        remote.entries.remove("x");
+        let report = sync(&mut conn, &mut remote, 0, 1000, &ConflictRule::Skip).unwrap();
+        assert_eq!(1, report.deleted_local);
+        assert_eq!(vec![(100, Some(250)), (900, None)], local_times(&mut conn));
+
+        //conflicting changes are skipped unless a side is picked
+        log_amend(&mut conn, 1, 260);
+        remote.entries.get_mut("r1").unwrap().end_time = 270;
+        let report = sync(&mut conn, &mut remote, 0, 1000, &ConflictRule::Skip).unwrap();
+        assert_eq!(1, report.skipped.len());
+        let report = sync(&mut conn, &mut remote, 0, 1000, &ConflictRule::Remote).unwrap();
+        assert_eq!(1, report.pulled);
+        assert_eq!(vec![(100, Some(270)), (900, None)], local_times(&mut conn));
+        assert_eq!(
+            Some(1000),
+            db::get_last_synced(&conn.transaction().unwrap(), "fake").unwrap()
+        );
+    }
+
+    #[test]
+    fn test_event_changes() {
+        let time = |id, start, end| TimeWindow {
+            id: Some(id),
+            category: "work".to_string(),
+            start_time: start,
+            end_time: end,
+            note: None,
+        };
+        let event = |id, start, end| PushedEvent {
+            time_id: id,
+            event_id: format!("e{}", id),
+            etag: None,
+            category: "work".to_string(),
+            start_time: start,
+            end_time: end,
+        };
+        let changes = event_changes(
+            &[
+                time(1, 0, Some(10)),
+                time(2, 20, Some(35)),
+                time(3, 40, Some(50)),
+                time(4, 60, None),
+            ],
+            vec![
+                event(2, 20, 30),
+                event(3, 40, 50),
+                event(5, 70, 80),
+                event(6, 90, 100),
+            ],
+            |id| id == 5,
+        );
+        assert_eq!(
+            vec![
+                EventChange::Create(time(1, 0, Some(10))),
+                EventChange::Update(time(2, 20, Some(35)), event(2, 20, 30)),
+                EventChange::Delete(event(5, 70, 80)),
+            ],
+            changes
+        );
+    }
+
+    fn log_amend(conn: &mut Connection, id: i64, end: i64) {
+        let mut tx = conn.transaction().unwrap();
+        let mut time = db::get_time(&tx, id).unwrap();
+        time.end_time = Some(end);
+        db::upsert_time(&mut tx, time, &true).unwrap();
+        tx.commit().unwrap();
+    }
+}
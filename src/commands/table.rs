@@ -0,0 +1,147 @@
+/*
+This file is part of Timetrack Jr.
+Timetrack Jr. is free software: you can redistribute it and/or modify it under the terms of the GNU General Public License as published by the Free Software Foundation, either version 3 of the License, or (at your option) any later version.
+Timetrack Jr. is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU General Public License for more details.
+You should have received a copy of the GNU General Public License along with Timetrack Jr. If not, see <https://www.gnu.org/licenses/>.
+*/
+use crate::{cli::TableOptions, TTError};
+use comfy_table::{presets, CellAlignment, Table};
+
+///A column in a report table.  Names are what users pass to --columns.
+pub(crate) struct Column {
+    pub name: &'static str,
+    ///right-align numbers and durations so they line up
+    pub numeric: bool,
+}
+
+pub(crate) const fn text(name: &'static str) -> Column {
+    Column {
+        name,
+        numeric: false,
+    }
+}
+
+pub(crate) const fn number(name: &'static str) -> Column {
+    Column {
+        name,
+        numeric: true,
+    }
+}
+
+///Rows of report output, rendered as an aligned table showing the columns selected with --columns.
+/// Every row (including the totals row) has one cell per column.
+pub(crate) struct ReportTable {
+    columns: Vec<Column>,
+    rows: Vec<Vec<String>>,
+    totals: Option<Vec<String>>,
+}
+
+impl ReportTable {
+    pub fn new(columns: Vec<Column>) -> ReportTable {
+        ReportTable {
+            columns,
+            rows: vec![],
+            totals: None,
+        }
+    }
+
+    pub fn row(&mut self, cells: Vec<String>) {
+        self.rows.push(cells);
+    }
+
+    ///Set the totals row, only shown with --totals.  Use empty cells for columns that don't have a total.
+    pub fn totals(&mut self, cells: Vec<String>) {
+        self.totals = Some(cells);
+    }
+
+    ///Indexes of the columns to show, in the order they should be shown
+    fn selected(&self, options: &TableOptions) -> Result<Vec<usize>, TTError> {
+        if options.columns.is_empty() {
+            return Ok((0..self.columns.len()).collect());
+        }
+        options
+            .columns
+            .iter()
+            .map(|name| {
+                self.columns
+                    .iter()
+                    .position(|c| c.name.eq_ignore_ascii_case(name.trim()))
+                    .ok_or(TTError::InvalidArgument {
+                        message: format!(
+                            "Unknown column \"{}\", available columns are: {}",
+                            name,
+                            self.columns
+                                .iter()
+                                .map(|c| c.name)
+                                .collect::<Vec<&str>>()
+                                .join(", ")
+                        ),
+                    })
+            })
+            .collect()
+    }
+
+    pub fn render(&self, options: &TableOptions) -> Result<String, TTError> {
+        let selected = self.selected(options)?;
+        let mut table = Table::new();
+        table
+            .load_preset(presets::NOTHING)
+            .set_header(selected.iter().map(|i| self.columns[*i].name));
+        let mut rows: Vec<&Vec<String>> = self.rows.iter().collect();
+        if options.totals {
+            rows.extend(self.totals.iter());
+        }
+        for row in rows {
+            table.add_row(selected.iter().map(|i| row[*i].as_str()));
+        }
+        for (position, index) in selected.iter().enumerate() {
+            if self.columns[*index].numeric {
+                if let Some(column) = table.column_mut(position) {
+                    column.set_cell_alignment(CellAlignment::Right);
+                }
+            }
+        }
+        Ok(table.to_string() + "\n")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn options(columns: &[&str], totals: bool) -> TableOptions {
+        TableOptions {
+            columns: columns.iter().map(|c| c.to_string()).collect(),
+            totals,
+        }
+    }
+
+    #[test]
+    fn test_render() {
+        let mut table = ReportTable::new(vec![text("category"), number("total")]);
+        table.row(vec!["work".to_string(), "10:00".to_string()]);
+        table.row(vec!["exercise".to_string(), "1:30".to_string()]);
+        table.totals(vec!["total".to_string(), "11:30".to_string()]);
+
+        let rendered = table.render(&options(&[], false)).unwrap();
+        assert_eq!(
+            vec![
+                " category  total ",
+                " work      10:00 ",
+                " exercise   1:30 "
+            ],
+            rendered.lines().collect::<Vec<&str>>()
+        );
+
+        let rendered = table.render(&options(&["Total"], true)).unwrap();
+        assert_eq!(
+            vec![" total ", " 10:00 ", "  1:30 ", " 11:30 "],
+            rendered.lines().collect::<Vec<&str>>()
+        );
+
+        assert!(matches!(
+            table.render(&options(&["nope"], false)),
+            Err(TTError::InvalidArgument { .. })
+        ));
+    }
+}
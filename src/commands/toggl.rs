@@ -0,0 +1,205 @@
+/*
+This file is part of Timetrack Jr.
+Timetrack Jr. is free software: you can redistribute it and/or modify it under the terms of the GNU General Public License as published by the Free Software Foundation, either version 3 of the License, or (at your option) any later version.
+Timetrack Jr. is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU General Public License for more details.
+You should have received a copy of the GNU General Public License along with Timetrack Jr. If not, see <https://www.gnu.org/licenses/>.
+*/
+use crate::{
+    cli::{self, ConflictRule},
+    commands::{
+        export::unix_to_utc,
+        sync::{self, api_request, Remote, RemoteTime},
+    },
+    db::{self, TimeWindow},
+    TTError,
+};
+use base64::Engine;
+use chrono::{DateTime, SecondsFormat, Utc};
+use rusqlite::Connection;
+use serde::Deserialize;
+use serde_json::{json, Value};
+use std::collections::BTreeMap;
+
+const API: &str = "https://api.track.toggl.com/api/v9";
+
+#[derive(Deserialize)]
+struct TogglEntry {
+    id: i64,
+    project_id: Option<i64>,
+    description: Option<String>,
+    start: String,
+    stop: Option<String>,
+}
+
+struct Toggl {
+    authorization: String,
+    workspace_id: i64,
+    ///toggl-project option for each category that has one
+    projects: BTreeMap<String, i64>,
+    categories: db::Categories,
+}
+
+fn parse_timestamp(timestamp: &str) -> Result<i64, TTError> {
+    DateTime::parse_from_rfc3339(timestamp)
+        .map(|d| d.timestamp())
+        .map_err(|_| TTError::Network {
+            message: format!("Toggl returned an invalid timestamp \"{}\"", timestamp),
+        })
+}
+
+fn rfc3339(tstamp: i64) -> String {
+    unix_to_utc(&tstamp).to_rfc3339_opts(SecondsFormat::Secs, true)
+}
+
+impl Toggl {
+    fn request(&self, method: &str, path: &str, body: Option<&Value>) -> Result<Value, TTError> {
+        api_request(
+            method,
+            &format!("{}{}", API, path),
+            &[("Authorization", &self.authorization)],
+            body,
+        )
+    }
+
+    ///Entries are matched to categories by project (with the toggl-project category option), then by description
+    fn category(&self, entry: &TogglEntry) -> Option<String> {
+        let by_project = entry.project_id.and_then(|project| {
+            self.projects
+                .iter()
+                .find(|(_, p)| **p == project)
+                .map(|(category, _)| category.clone())
+        });
+        by_project.or(entry
+            .description
+            .clone()
+            .filter(|d| self.categories.contains(d)))
+    }
+
+    fn entry_body(&self, time: &TimeWindow) -> Value {
+        let end = time.end_time.unwrap_or(time.start_time);
+        json!({
+            "created_with": "timetrack_jr",
+            "workspace_id": self.workspace_id,
+            "project_id": self.projects.get(&time.category),
+            "description": time.category,
+            "start": rfc3339(time.start_time),
+            "stop": rfc3339(end),
+            "duration": end - time.start_time,
+        })
+    }
+}
+
+impl Remote for Toggl {
+    fn service(&self) -> &'static str {
+        "toggl"
+    }
+
+    fn list(&mut self, start: i64, end: i64) -> Result<Vec<RemoteTime>, TTError> {
+        let entries: Vec<TogglEntry> = serde_json::from_value(self.request(
+            "GET",
+            &format!(
+                "/me/time_entries?start_date={}&end_date={}",
+                rfc3339(start),
+                rfc3339(end + 1)
+            ),
+            None,
+        )?)?;
+        let mut times = vec![];
+        for entry in entries {
+            //running entries are synced once they're stopped
+            if let Some(stop) = &entry.stop {
+                times.push(RemoteTime {
+                    id: entry.id.to_string(),
+                    category: self.category(&entry),
+                    start_time: parse_timestamp(&entry.start)?,
+                    end_time: parse_timestamp(stop)?,
+                });
+            }
+        }
+        Ok(times)
+    }
+
+    fn create(&mut self, time: &TimeWindow) -> Result<String, TTError> {
+        let created = self.request(
+            "POST",
+            &format!("/workspaces/{}/time_entries", self.workspace_id),
+            Some(&self.entry_body(time)),
+        )?;
+        match &created["id"] {
+            Value::Number(id) => Ok(id.to_string()),
+            _ => Err(TTError::Network {
+                message: "Toggl didn't return the id of the new time entry".to_string(),
+            }),
+        }
+    }
+
+    fn update(&mut self, id: &str, time: &TimeWindow) -> Result<(), TTError> {
+        self.request(
+            "PUT",
+            &format!("/workspaces/{}/time_entries/{}", self.workspace_id, id),
+            Some(&self.entry_body(time)),
+        )?;
+        Ok(())
+    }
+
+    fn delete(&mut self, id: &str) -> Result<(), TTError> {
+        self.request(
+            "DELETE",
+            &format!("/workspaces/{}/time_entries/{}", self.workspace_id, id),
+            None,
+        )?;
+        Ok(())
+    }
+}
+
+///Two-way sync of finished times with Toggl Track, starting at `start_time` (or 30 days ago)
+pub fn sync_toggl(
+    conn: &mut Connection,
+    start_time: &Option<String>,
+    conflicts: &ConflictRule,
+) -> Result<(), TTError> {
+    let (options, category_options, categories) = {
+        let tx = conn.transaction()?;
+        (
+            db::get_options(&tx)?,
+            db::get_category_options(&tx)?,
+            db::get_categories(&tx)?,
+        )
+    };
+    let token = std::env::var("TOGGL_API_TOKEN")
+        .ok()
+        .or(options.get("toggl-api-token").cloned())
+        .ok_or(TTError::NotConfigured {
+            message: "Set your Toggl API token with `ttjr set-option toggl-api-token <token>` (or the TOGGL_API_TOKEN environment variable)".to_string(),
+        })?;
+    let mut toggl = Toggl {
+        authorization: format!(
+            "Basic {}",
+            base64::engine::general_purpose::STANDARD.encode(format!("{}:api_token", token))
+        ),
+        workspace_id: 0,
+        projects: category_options
+            .iter()
+            .filter_map(|(category, options)| {
+                let project = options.get("toggl-project")?.parse().ok()?;
+                Some((category.clone(), project))
+            })
+            .collect(),
+        categories,
+    };
+    toggl.workspace_id = match options.get("toggl-workspace") {
+        Some(workspace) => workspace.parse()?,
+        None => toggl.request("GET", "/me", None)?["default_workspace_id"]
+            .as_i64()
+            .ok_or(TTError::NotConfigured {
+                message: "Couldn't find your default Toggl workspace, set one with `ttjr set-option toggl-workspace <id>`".to_string(),
+            })?,
+    };
+
+    let start_time = start_time.clone().unwrap_or("30 days ago".to_string());
+    let start = cli::time_string_to_tstamp(&Some(start_time.clone()))
+        .ok_or_else(|| TTError::unparseable(&start_time, "start-time"))?;
+    let report = sync::sync(conn, &mut toggl, start, Utc::now().timestamp(), conflicts)?;
+    print!("{}", report);
+    Ok(())
+}
@@ -0,0 +1,371 @@
+/*
+This file is part of Timetrack Jr.
+Timetrack Jr. is free software: you can redistribute it and/or modify it under the terms of the GNU General Public License as published by the Free Software Foundation, either version 3 of the License, or (at your option) any later version.
+Timetrack Jr. is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU General Public License for more details.
+You should have received a copy of the GNU General Public License along with Timetrack Jr. If not, see <https://www.gnu.org/licenses/>.
+*/
+//The macOS menu bar item and the tray icon.  macOS and windows use tray-icon, which needs an event loop on the
+// main thread.  Everywhere else uses a StatusNotifierItem (through ksni) which talks D-Bus in the background, so
+// it doesn't need GTK.
+use crate::{
+    commands::{log, status::elapsed},
+    db::{self, TimeWindow},
+    zone::Zone,
+    TTError,
+};
+use rusqlite::Connection;
+
+const START: &str = "start:";
+const STOP: &str = "stop";
+const QUIT: &str = "quit";
+const ICON_SIZE: u32 = 32;
+
+///A menu item that was clicked
+#[derive(Debug, PartialEq)]
+enum Action {
+    Start(String),
+    Stop,
+    Quit,
+}
+
+fn action(menu_id: &str) -> Option<Action> {
+    match menu_id {
+        STOP => Some(Action::Stop),
+        QUIT => Some(Action::Quit),
+        id => id
+            .strip_prefix(START)
+            .map(|category| Action::Start(category.to_string())),
+    }
+}
+
+///What's shown next to the icon in the menu bar: the category and how long it's been timed
+fn title(current: Option<&TimeWindow>, now: i64) -> String {
+    match current {
+        Some(time) => format!("{} {}", time.category, elapsed(time.start_time, now)),
+        None => String::new(),
+    }
+}
+
+fn tooltip(current: Option<&TimeWindow>, now: i64) -> String {
+    match current {
+        Some(time) => format!(
+            "Timing {} since {} ({})",
+            time.category,
+            Zone::Local.format(&time.start_time, "%H:%M"),
+            elapsed(time.start_time, now)
+        ),
+        None => "Not timing".to_string(),
+    }
+}
+
+///A filled circle (green while timing, grey otherwise) as RGBA pixels
+fn icon_rgba(timing: bool) -> Vec<u8> {
+    let color = match timing {
+        true => [0x2e, 0xa0, 0x43],
+        false => [0x80, 0x80, 0x80],
+    };
+    let center = ICON_SIZE as f64 / 2.0;
+    let mut rgba = Vec::with_capacity((ICON_SIZE * ICON_SIZE * 4) as usize);
+    for y in 0..ICON_SIZE {
+        for x in 0..ICON_SIZE {
+            let distance = (x as f64 + 0.5 - center).hypot(y as f64 + 0.5 - center);
+            rgba.extend_from_slice(&color);
+            rgba.push(match distance <= center - 1.0 {
+                true => 0xff,
+                false => 0,
+            });
+        }
+    }
+    rgba
+}
+
+fn tray_error(e: impl std::fmt::Display) -> TTError {
+    TTError::System {
+        message: format!("Couldn't update the tray icon: {}", e),
+    }
+}
+
+fn perform(conn: &mut Connection, action: Action) -> Result<(), TTError> {
+    let result = match action {
+        Action::Start(category) => log::start_timing(conn, &category, &None, &false),
+        Action::Stop => log::stop_timing(conn, &false),
+        Action::Quit => Ok(()),
+    };
+    match result {
+        Err(e) if e.recoverable() => {
+            eprintln!("{}", e);
+            Ok(())
+        }
+        result => result,
+    }
+}
+
+///Categories, and whatever's being timed
+fn load(conn: &mut Connection) -> Result<(db::Categories, Option<TimeWindow>), TTError> {
+    let tx = conn.transaction()?;
+    Ok((db::get_categories(&tx)?, db::get_last_open_time(&tx)?))
+}
+
+///A start item for each category, then stop (only enabled while timing) and quit
+#[cfg(any(target_os = "macos", windows))]
+fn menu(categories: &db::Categories, timing: bool) -> Result<tray_icon::menu::Menu, TTError> {
+    use tray_icon::menu::{Menu, MenuItem, PredefinedMenuItem};
+    let menu = Menu::new();
+    for category in categories {
+        //a single & marks a mnemonic
+        let item = MenuItem::with_id(
+            format!("{}{}", START, category),
+            category.replace('&', "&&"),
+            true,
+            None,
+        );
+        menu.append(&item).map_err(tray_error)?;
+    }
+    menu.append_items(&[
+        &PredefinedMenuItem::separator(),
+        &MenuItem::with_id(STOP, "Stop timing", timing, None),
+        &MenuItem::with_id(QUIT, "Quit", true, None),
+    ])
+    .map_err(tray_error)?;
+    Ok(menu)
+}
+
+///Run a tray-icon status item until it's quit, updating it every `interval` seconds (and after every click)
+#[cfg(any(target_os = "macos", windows))]
+fn run_tray_icon(conn: &mut Connection, interval: &u64) -> Result<(), TTError> {
+    use std::time::{Duration, Instant};
+    use tao::{
+        event::{Event, StartCause},
+        event_loop::{ControlFlow, EventLoopBuilder},
+        platform::run_return::EventLoopExtRunReturn,
+    };
+    use tray_icon::{menu::MenuEvent, Icon, TrayIcon, TrayIconBuilder};
+
+    let mut event_loop = EventLoopBuilder::<MenuEvent>::with_user_event().build();
+    #[cfg(target_os = "macos")]
+    {
+        use tao::platform::macos::{ActivationPolicy, EventLoopExtMacOS};
+        //no dock icon, just the status item
+        event_loop.set_activation_policy(ActivationPolicy::Accessory);
+    }
+    let proxy = event_loop.create_proxy();
+    MenuEvent::set_event_handler(Some(move |event| {
+        let _ = proxy.send_event(event);
+    }));
+
+    let mut icon: Option<TrayIcon> = None;
+    //the menu is rebuilt when categories change or timing starts/stops
+    let mut menu_for: Option<(db::Categories, bool)> = None;
+    let mut refresh = |icon: &TrayIcon, conn: &mut Connection| -> Result<(), TTError> {
+        let (categories, current) = load(conn)?;
+        let now = chrono::Utc::now().timestamp();
+        let timing = current.is_some();
+        if menu_for.as_ref().map(|(_, t)| *t) != Some(timing) {
+            let rgba = icon_rgba(timing);
+            let image = Icon::from_rgba(rgba, ICON_SIZE, ICON_SIZE).map_err(tray_error)?;
+            icon.set_icon(Some(image)).map_err(tray_error)?;
+        }
+        let new_menu_for = (categories, timing);
+        if menu_for.as_ref() != Some(&new_menu_for) {
+            icon.set_menu(Some(Box::new(menu(&new_menu_for.0, timing)?)));
+            menu_for = Some(new_menu_for);
+        }
+        icon.set_title(Some(title(current.as_ref(), now)));
+        icon.set_tooltip(Some(tooltip(current.as_ref(), now)))
+            .map_err(tray_error)
+    };
+
+    let mut error = None;
+    event_loop.run_return(|event, _, control_flow| {
+        let result = match event {
+            //the status item can only be created once the event loop is running
+            Event::NewEvents(StartCause::Init) => TrayIconBuilder::new()
+                .build()
+                .map(|i| icon = Some(i))
+                .map_err(tray_error),
+            Event::UserEvent(event) => match action(&event.id.0) {
+                Some(Action::Quit) => {
+                    *control_flow = ControlFlow::Exit;
+                    return;
+                }
+                Some(action) => perform(conn, action),
+                None => Ok(()),
+            },
+            Event::NewEvents(StartCause::ResumeTimeReached { .. }) => Ok(()),
+            _ => return,
+        };
+        let result = result.and_then(|_| match &icon {
+            Some(icon) => refresh(icon, conn),
+            None => Ok(()),
+        });
+        match result {
+            Ok(_) => {
+                *control_flow =
+                    ControlFlow::WaitUntil(Instant::now() + Duration::from_secs(*interval))
+            }
+            Err(e) => {
+                error = Some(e);
+                *control_flow = ControlFlow::Exit;
+            }
+        }
+    });
+    match error {
+        Some(e) => Err(e),
+        None => Ok(()),
+    }
+}
+
+///Show what's being timed (and for how long) in the macOS menu bar, with a menu to start timing any category or stop.
+/// The elapsed time is updated every `interval` seconds.
+#[cfg(all(feature = "menubar", target_os = "macos"))]
+pub fn menubar(conn: &mut Connection, interval: &u64) -> Result<(), TTError> {
+    run_tray_icon(conn, interval)
+}
+
+///Show what's being timed in the system tray (in the tooltip), with a menu to start timing any category or stop
+#[cfg(all(feature = "tray", any(target_os = "macos", windows)))]
+pub fn tray(conn: &mut Connection, interval: &u64) -> Result<(), TTError> {
+    run_tray_icon(conn, interval)
+}
+
+///The StatusNotifierItem, which only holds what to show.  Clicks are sent back to be handled with the DB connection.
+#[cfg(all(feature = "tray", unix, not(target_os = "macos")))]
+struct Sni {
+    categories: db::Categories,
+    current: Option<TimeWindow>,
+    now: i64,
+    actions: std::sync::mpsc::Sender<Action>,
+}
+
+#[cfg(all(feature = "tray", unix, not(target_os = "macos")))]
+impl ksni::Tray for Sni {
+    fn id(&self) -> String {
+        "timetrack_jr".to_string()
+    }
+
+    fn title(&self) -> String {
+        title(self.current.as_ref(), self.now)
+    }
+
+    fn icon_pixmap(&self) -> Vec<ksni::Icon> {
+        //ksni wants ARGB
+        let mut data = icon_rgba(self.current.is_some());
+        data.chunks_exact_mut(4)
+            .for_each(|pixel| pixel.rotate_right(1));
+        vec![ksni::Icon {
+            width: ICON_SIZE as i32,
+            height: ICON_SIZE as i32,
+            data,
+        }]
+    }
+
+    fn tool_tip(&self) -> ksni::ToolTip {
+        ksni::ToolTip {
+            title: "Timetrack Jr.".to_string(),
+            description: tooltip(self.current.as_ref(), self.now),
+            ..Default::default()
+        }
+    }
+
+    fn menu(&self) -> Vec<ksni::MenuItem<Self>> {
+        use ksni::menu::{MenuItem, StandardItem};
+        let item = |label: String, enabled: bool, id: String| -> MenuItem<Self> {
+            StandardItem {
+                //a single _ marks an access key
+                label: label.replace('_', "__"),
+                enabled,
+                activate: Box::new(move |sni: &mut Self| {
+                    if let Some(action) = action(&id) {
+                        let _ = sni.actions.send(action);
+                    }
+                }),
+                ..Default::default()
+            }
+            .into()
+        };
+        let mut menu: Vec<MenuItem<Self>> = self
+            .categories
+            .iter()
+            .map(|c| item(c.clone(), true, format!("{}{}", START, c)))
+            .collect();
+        menu.push(MenuItem::Separator);
+        menu.push(item(
+            "Stop timing".to_string(),
+            self.current.is_some(),
+            STOP.to_string(),
+        ));
+        menu.push(item("Quit".to_string(), true, QUIT.to_string()));
+        menu
+    }
+}
+
+///Show what's being timed in the system tray (in the tooltip), with a menu to start timing any category or stop
+#[cfg(all(feature = "tray", unix, not(target_os = "macos")))]
+pub fn tray(conn: &mut Connection, interval: &u64) -> Result<(), TTError> {
+    use ksni::blocking::TrayMethods;
+    use std::{sync::mpsc, time::Duration};
+
+    let (categories, current) = load(conn)?;
+    let (sender, actions) = mpsc::channel();
+    let handle = Sni {
+        categories,
+        current,
+        now: chrono::Utc::now().timestamp(),
+        actions: sender,
+    }
+    .spawn()
+    .map_err(tray_error)?;
+    loop {
+        match actions.recv_timeout(Duration::from_secs(*interval)) {
+            Ok(Action::Quit) => break,
+            Ok(action) => perform(conn, action)?,
+            Err(mpsc::RecvTimeoutError::Timeout) => {}
+            Err(mpsc::RecvTimeoutError::Disconnected) => break,
+        }
+        let (categories, current) = load(conn)?;
+        handle.update(|sni| {
+            sni.categories = categories;
+            sni.current = current;
+            sni.now = chrono::Utc::now().timestamp();
+        });
+    }
+    handle.shutdown().wait();
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_title_and_actions() {
+        let time = TimeWindow {
+            id: Some(1),
+            category: "work".to_string(),
+            start_time: 0,
+            end_time: None,
+            note: None,
+        };
+        assert_eq!("work 01:01", title(Some(&time), 3660));
+        assert_eq!("", title(None, 3660));
+        assert_eq!("Not timing", tooltip(None, 3660));
+
+        assert_eq!(
+            Some(Action::Start("a: b".to_string())),
+            action("start:a: b")
+        );
+        assert_eq!(Some(Action::Stop), action("stop"));
+        assert_eq!(Some(Action::Quit), action("quit"));
+        assert_eq!(None, action("other"));
+    }
+
+    #[test]
+    fn test_icon() {
+        let rgba = icon_rgba(true);
+        assert_eq!((ICON_SIZE * ICON_SIZE * 4) as usize, rgba.len());
+        //transparent corner, opaque middle
+        assert_eq!(0, rgba[3]);
+        let middle = ((ICON_SIZE / 2 * ICON_SIZE + ICON_SIZE / 2) * 4) as usize;
+        assert_eq!([0x2e, 0xa0, 0x43, 0xff], rgba[middle..middle + 4]);
+    }
+}
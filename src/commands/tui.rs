@@ -0,0 +1,303 @@
+/*
+This file is part of Timetrack Jr.
+Timetrack Jr. is free software: you can redistribute it and/or modify it under the terms of the GNU General Public License as published by the Free Software Foundation, either version 3 of the License, or (at your option) any later version.
+Timetrack Jr. is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU General Public License for more details.
+You should have received a copy of the GNU General Public License along with Timetrack Jr. If not, see <https://www.gnu.org/licenses/>.
+*/
+//`ttjr tui`: a full-screen terminal dashboard of what's being timed, today's times, and today's totals, with keys to
+// start, switch, and stop timing.  It re-reads the database every second, so it keeps up with other ttjrs.
+use crate::{
+    cli::FilterMode,
+    commands::{log, status::elapsed},
+    db::{self, TimeWindow},
+    zone::Zone,
+    TTError,
+};
+use chrono::Utc;
+use ratatui::{
+    crossterm::event::{self, Event, KeyCode, KeyEventKind},
+    layout::{Constraint, Layout},
+    style::{Modifier, Style, Stylize},
+    text::Line,
+    widgets::{Block, List, ListItem, ListState, Paragraph, Row, Table},
+    Frame,
+};
+use rusqlite::Connection;
+use std::{collections::BTreeMap, time::Duration};
+
+const HELP: &str =
+    "↑/↓ pick a category · enter start/switch · 1-9 start that category · s stop · q quit";
+
+///Something a key asks for
+#[derive(Debug, PartialEq)]
+enum Action {
+    Up,
+    Down,
+    Start(usize),
+    Stop,
+    Quit,
+}
+
+fn action(key: KeyCode, selected: usize) -> Option<Action> {
+    match key {
+        KeyCode::Up | KeyCode::Char('k') => Some(Action::Up),
+        KeyCode::Down | KeyCode::Char('j') => Some(Action::Down),
+        KeyCode::Enter => Some(Action::Start(selected)),
+        KeyCode::Char(c @ '1'..='9') => Some(Action::Start(c as usize - '1' as usize)),
+        KeyCode::Char('s') => Some(Action::Stop),
+        KeyCode::Char('q') | KeyCode::Esc => Some(Action::Quit),
+        _ => None,
+    }
+}
+
+///What's on screen
+#[derive(Default)]
+struct Dashboard {
+    categories: Vec<String>,
+    current: Option<TimeWindow>,
+    ///Today's times (the parts of them since midnight), open ones ending now
+    today: Vec<TimeWindow>,
+    now: i64,
+    list: ListState,
+    ///The last thing that went wrong, like starting a time that would overlap another
+    message: Option<String>,
+}
+
+impl Dashboard {
+    fn load(&mut self, conn: &mut Connection, now: i64) -> Result<(), TTError> {
+        let mut tx = conn.transaction()?;
+        let midnight = Zone::Local.midnight(Zone::Local.today());
+        self.categories = db::get_categories(&tx)?.into_iter().collect();
+        self.current = db::get_last_open_time(&tx)?;
+        self.today = db::get_times(&mut tx, Some(midnight), Some(now), &FilterMode::Overlap)?
+            .into_iter()
+            .map(|time| time.clipped(Some(midnight), Some(now)))
+            .collect();
+        self.now = now;
+        let last = self.categories.len().saturating_sub(1);
+        self.list
+            .select(Some(self.list.selected().unwrap_or(0).min(last)));
+        Ok(())
+    }
+
+    ///Seconds timed today in each category, most first
+    fn totals(&self) -> Vec<(&str, i64)> {
+        let mut totals: BTreeMap<&str, i64> = BTreeMap::new();
+        for time in &self.today {
+            *totals.entry(&time.category).or_default() +=
+                time.end_time.unwrap_or(self.now) - time.start_time;
+        }
+        let mut totals: Vec<_> = totals.into_iter().collect();
+        totals.sort_by_key(|(_, seconds)| -seconds);
+        totals
+    }
+
+    ///Do what a key asked, returning false to quit
+    fn perform(&mut self, conn: &mut Connection, action: Action) -> Result<bool, TTError> {
+        let result = match action {
+            Action::Up => {
+                self.list.select_previous();
+                Ok(())
+            }
+            Action::Down => {
+                self.list.select_next();
+                Ok(())
+            }
+            Action::Start(index) => match self.categories.get(index) {
+                Some(category) => {
+                    self.list.select(Some(index));
+                    log::start_timing(conn, category, &None, &false)
+                }
+                None => Ok(()),
+            },
+            Action::Stop => log::stop_timing(conn, &false),
+            Action::Quit => return Ok(false),
+        };
+        self.message = match result {
+            Ok(()) => None,
+            Err(e) if e.recoverable() => Some(e.to_string()),
+            Err(e) => return Err(e),
+        };
+        Ok(true)
+    }
+
+    fn render(&mut self, frame: &mut Frame) {
+        let [timing, middle, footer] = Layout::vertical([
+            Constraint::Length(3),
+            Constraint::Min(5),
+            Constraint::Length(1),
+        ])
+        .areas(frame.area());
+        let [today, side] =
+            Layout::horizontal([Constraint::Percentage(60), Constraint::Percentage(40)])
+                .areas(middle);
+        let [totals, categories] =
+            Layout::vertical([Constraint::Percentage(50), Constraint::Percentage(50)]).areas(side);
+
+        let current = match &self.current {
+            Some(time) => Line::from(vec![
+                time.category.clone().bold().green(),
+                format!(
+                    " since {} ({})",
+                    Zone::Local.format(&time.start_time, "%H:%M"),
+                    elapsed(time.start_time, self.now)
+                )
+                .into(),
+            ]),
+            None => Line::from("Not timing".dim()),
+        };
+        frame.render_widget(
+            Paragraph::new(current).block(Block::bordered().title(" Timing ")),
+            timing,
+        );
+
+        let rows = self.today.iter().rev().map(|time| {
+            Row::new(vec![
+                Zone::Local.format(&time.start_time, "%H:%M"),
+                time.end_time
+                    .map_or("…".to_string(), |end| Zone::Local.format(&end, "%H:%M")),
+                elapsed(time.start_time, time.end_time.unwrap_or(self.now)),
+                time.category.clone(),
+            ])
+        });
+        frame.render_widget(
+            Table::new(
+                rows,
+                [
+                    Constraint::Length(5),
+                    Constraint::Length(5),
+                    Constraint::Length(5),
+                    Constraint::Fill(1),
+                ],
+            )
+            .header(Row::new(vec!["Start", "End", "Time", "Category"]).bold())
+            .block(Block::bordered().title(" Today ")),
+            today,
+        );
+
+        let totals_rows = self
+            .totals()
+            .into_iter()
+            .map(|(category, seconds)| Row::new(vec![elapsed(0, seconds), category.to_string()]));
+        frame.render_widget(
+            Table::new(totals_rows, [Constraint::Length(5), Constraint::Fill(1)])
+                .block(Block::bordered().title(" Totals ")),
+            totals,
+        );
+
+        let items = self
+            .categories
+            .iter()
+            .enumerate()
+            .map(|(index, category)| match index {
+                0..=8 => ListItem::new(format!("{} {}", index + 1, category)),
+                _ => ListItem::new(format!("  {}", category)),
+            });
+        frame.render_stateful_widget(
+            List::new(items)
+                .block(Block::bordered().title(" Categories "))
+                .highlight_style(Style::new().add_modifier(Modifier::REVERSED)),
+            categories,
+            &mut self.list,
+        );
+
+        let footer_text = match &self.message {
+            Some(message) => Line::from(message.clone().red()),
+            None => Line::from(HELP.dim()),
+        };
+        frame.render_widget(Paragraph::new(footer_text), footer);
+    }
+}
+
+fn terminal_error(e: std::io::Error) -> TTError {
+    TTError::System {
+        message: format!("Couldn't draw the dashboard: {}", e),
+    }
+}
+
+pub fn tui(conn: &mut Connection) -> Result<(), TTError> {
+    let mut dashboard = Dashboard::default();
+    dashboard.load(conn, Utc::now().timestamp())?;
+    //ratatui puts the terminal back as it was when it's restored, or on a panic
+    let mut terminal = ratatui::init();
+    let result = (|| loop {
+        terminal
+            .draw(|frame| dashboard.render(frame))
+            .map_err(terminal_error)?;
+        //redraw at least every second, for the elapsed time and changes from elsewhere
+        if event::poll(Duration::from_secs(1))? {
+            let selected = dashboard.list.selected().unwrap_or(0);
+            let action = match event::read()? {
+                Event::Key(key) if key.kind == KeyEventKind::Press => action(key.code, selected),
+                _ => None,
+            };
+            if let Some(action) = action {
+                if !dashboard.perform(conn, action)? {
+                    return Ok(());
+                }
+            }
+        }
+        dashboard.load(conn, Utc::now().timestamp())?;
+    })();
+    ratatui::restore();
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ratatui::{backend::TestBackend, Terminal};
+
+    #[test]
+    fn test_tui() {
+        assert_eq!(Some(Action::Start(2)), action(KeyCode::Char('3'), 0));
+        assert_eq!(Some(Action::Start(5)), action(KeyCode::Enter, 5));
+        assert_eq!(Some(Action::Quit), action(KeyCode::Char('q'), 0));
+        assert_eq!(None, action(KeyCode::Char('x'), 0));
+
+        let mut conn = Connection::open_in_memory().unwrap();
+        db::initialize_db(&mut conn).unwrap();
+        for category in ["work", "break"] {
+            crate::commands::config::add_category(&mut conn, &category.to_string()).unwrap();
+        }
+        let mut dashboard = Dashboard::default();
+        dashboard.load(&mut conn, Utc::now().timestamp()).unwrap();
+        assert_eq!(vec!["break", "work"], dashboard.categories);
+        assert!(dashboard.perform(&mut conn, Action::Down).unwrap());
+        assert!(dashboard
+            .perform(&mut conn, Action::Start(dashboard.list.selected().unwrap()))
+            .unwrap());
+        dashboard.load(&mut conn, Utc::now().timestamp()).unwrap();
+        assert_eq!(
+            Some("work"),
+            dashboard.current.as_ref().map(|t| t.category.as_str())
+        );
+        assert_eq!(
+            vec!["work"],
+            dashboard
+                .totals()
+                .iter()
+                .map(|(c, _)| *c)
+                .collect::<Vec<_>>()
+        );
+        //starting a category that isn't there does nothing
+        assert!(dashboard.perform(&mut conn, Action::Start(7)).unwrap());
+        assert!(!dashboard.perform(&mut conn, Action::Quit).unwrap());
+
+        let mut terminal = Terminal::new(TestBackend::new(80, 20)).unwrap();
+        terminal.draw(|frame| dashboard.render(frame)).unwrap();
+        let screen: String = terminal
+            .backend()
+            .buffer()
+            .content()
+            .iter()
+            .map(|cell| cell.symbol())
+            .collect();
+        assert!(screen.contains("work since"), "{}", screen);
+        assert!(screen.contains("2 work"), "{}", screen);
+
+        dashboard.perform(&mut conn, Action::Stop).unwrap();
+        dashboard.load(&mut conn, Utc::now().timestamp()).unwrap();
+        assert_eq!(None, dashboard.current);
+    }
+}
@@ -0,0 +1,254 @@
+/*
+This file is part of Timetrack Jr.
+Timetrack Jr. is free software: you can redistribute it and/or modify it under the terms of the GNU General Public License as published by the Free Software Foundation, either version 3 of the License, or (at your option) any later version.
+Timetrack Jr. is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU General Public License for more details.
+You should have received a copy of the GNU General Public License along with Timetrack Jr. If not, see <https://www.gnu.org/licenses/>.
+*/
+//Events are queued whether or not ttjr's built with the webhooks feature, so webhooks added by a build with it (on
+// the same DB) don't miss changes made by one without it.  Only delivering them needs the feature.
+#[cfg(feature = "webhooks")]
+use crate::db::WebhookDelivery;
+use crate::{commands::report, db, TTError};
+use chrono::Utc;
+#[cfg(feature = "webhooks")]
+use hmac::{Hmac, Mac};
+use rusqlite::{Connection, Transaction};
+use serde_json::{json, Map, Value};
+#[cfg(feature = "webhooks")]
+use sha2::Sha256;
+#[cfg(feature = "webhooks")]
+use std::time::Duration;
+
+///Deliveries are given up on after this many failures
+#[cfg(feature = "webhooks")]
+const MAX_ATTEMPTS: i64 = 10;
+#[cfg(feature = "webhooks")]
+const TIMEOUT: Duration = Duration::from_secs(10);
+
+///Queue an event (`start`, `stop`, `amend`, or `delete`) for every webhook.  Call this in the transaction making the
+/// change, so the event is only sent if the change is committed.
+pub(crate) fn enqueue(tx: &Transaction, event: &str, fields: Value) -> Result<(), TTError> {
+    let now = Utc::now().timestamp();
+    let mut payload = Map::new();
+    payload.insert("type".to_string(), json!(event));
+    payload.insert("timestamp".to_string(), json!(now));
+    if let Value::Object(fields) = fields {
+        payload.extend(fields);
+    }
+    db::enqueue_webhook_event(tx, &Value::Object(payload).to_string(), now)
+}
+
+///Hex HMAC-SHA256 of the body, sent as `X-Ttjr-Signature: sha256=<hex>`
+#[cfg(feature = "webhooks")]
+fn signature(secret: &str, body: &str) -> String {
+    let mut mac = Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("HMAC takes any key");
+    mac.update(body.as_bytes());
+    mac.finalize()
+        .into_bytes()
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect()
+}
+
+///Seconds to wait before retrying after `attempts` failures: 30s, doubling up to a day
+#[cfg(feature = "webhooks")]
+fn backoff(attempts: i64) -> i64 {
+    (30 * 2i64.pow(attempts.clamp(0, 12) as u32)).min(24 * 60 * 60)
+}
+
+#[cfg(feature = "webhooks")]
+fn post(agent: &ureq::Agent, delivery: &WebhookDelivery) -> Result<(), String> {
+    let event: Value = serde_json::from_str(&delivery.payload).unwrap_or_default();
+    let mut request = agent
+        .post(&delivery.webhook.url)
+        .header("Content-Type", "application/json")
+        .header("User-Agent", "timetrack_jr")
+        .header("X-Ttjr-Event", event["type"].as_str().unwrap_or_default())
+        .header("X-Ttjr-Delivery", &delivery.id.to_string());
+    if let Some(secret) = &delivery.webhook.secret {
+        request = request.header(
+            "X-Ttjr-Signature",
+            &format!("sha256={}", signature(secret, &delivery.payload)),
+        );
+    }
+    request
+        .send(&delivery.payload)
+        .map(|_| ())
+        .map_err(|e| e.to_string())
+}
+
+///POST every queued event that's due.  Failures are retried with backoff the next time this runs (from
+/// `ttjr deliver-webhooks` or the daemon's webhooks feature), so they're only warnings.  Returns how many were
+/// delivered.
+#[cfg(feature = "webhooks")]
+pub(crate) fn deliver(conn: &mut Connection) -> Result<usize, TTError> {
+    let now = Utc::now().timestamp();
+    let due = db::get_due_webhook_deliveries(&conn.transaction()?, now)?;
+    if due.is_empty() {
+        return Ok(0);
+    }
+    let agent = ureq::Agent::new_with_config(
+        ureq::Agent::config_builder()
+            .timeout_global(Some(TIMEOUT))
+            .build(),
+    );
+    let mut delivered = 0;
+    for delivery in due {
+        match post(&agent, &delivery) {
+            Ok(_) => {
+                db::write(conn, |tx| db::delete_webhook_delivery(tx, delivery.id))?;
+                delivered += 1;
+            }
+            Err(e) if delivery.attempts + 1 >= MAX_ATTEMPTS => {
+                eprintln!(
+                    "Giving up on delivering an event to {} after {} attempts: {}",
+                    delivery.webhook.url, MAX_ATTEMPTS, e
+                );
+                db::write(conn, |tx| db::delete_webhook_delivery(tx, delivery.id))?;
+            }
+            Err(e) => {
+                eprintln!(
+                    "Couldn't deliver an event to {} (will retry): {}",
+                    delivery.webhook.url, e
+                );
+                db::write(conn, |tx| {
+                    db::retry_webhook_delivery(
+                        tx,
+                        delivery.id,
+                        now + backoff(delivery.attempts),
+                        &e,
+                    )
+                })?;
+            }
+        }
+    }
+    Ok(delivered)
+}
+
+///Without the webhooks feature events stay queued, for a build with it to deliver
+#[cfg(not(feature = "webhooks"))]
+pub(crate) fn deliver(_: &mut Connection) -> Result<usize, TTError> {
+    Ok(0)
+}
+
+#[cfg(feature = "webhooks")]
+pub fn add_webhook(
+    conn: &mut Connection,
+    url: &str,
+    secret: &Option<String>,
+    json: &bool,
+) -> Result<(), TTError> {
+    if !url.starts_with("http://") && !url.starts_with("https://") {
+        return Err(TTError::invalid("Webhook URLs", url, "http(s)"));
+    }
+    let id = db::write(conn, |tx| db::add_webhook(tx, url, secret))?;
+    match json {
+        true => report::print_json(&json!({ "id": id }))?,
+        false => println!("Added webhook {}", id),
+    }
+    Ok(())
+}
+
+pub fn delete_webhook(conn: &mut Connection, id: &i64) -> Result<(), TTError> {
+    match db::write(conn, |tx| db::delete_webhook(tx, *id))? {
+        true => Ok(()),
+        false => Err(TTError::NotFound {
+            kind: "webhook",
+            id: *id,
+        }),
+    }
+}
+
+pub fn list_webhooks(conn: &mut Connection, json: &bool) -> Result<(), TTError> {
+    let tx = conn.transaction()?;
+    let backlog = db::get_webhook_backlog(&tx)?;
+    let webhooks = db::get_webhooks(&tx)?;
+    if *json {
+        //secrets stay out of the output
+        return report::print_json(&json!(webhooks
+            .iter()
+            .map(|webhook| json!({
+                "id": webhook.id,
+                "url": webhook.url,
+                "signed": webhook.secret.is_some(),
+                "undelivered": backlog.get(&webhook.id).unwrap_or(&0),
+            }))
+            .collect::<Vec<_>>()));
+    }
+    for webhook in webhooks {
+        println!(
+            "{}: {}{} ({} undelivered)",
+            webhook.id,
+            webhook.url,
+            match webhook.secret {
+                Some(_) => " (signed)",
+                None => "",
+            },
+            backlog.get(&webhook.id).unwrap_or(&0)
+        );
+    }
+    Ok(())
+}
+
+///Retry any undelivered events that are due
+#[cfg(feature = "webhooks")]
+pub fn deliver_webhooks(conn: &mut Connection, json: &bool) -> Result<(), TTError> {
+    let delivered = deliver(conn)?;
+    match json {
+        true => report::print_json(&json!({ "delivered": delivered }))?,
+        false => println!("Delivered {} events", delivered),
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    #[cfg(feature = "webhooks")]
+    fn test_signature() {
+        //from RFC 4231, test case 2
+        assert_eq!(
+            "5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3843",
+            signature("Jefe", "what do ya want for nothing?")
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "webhooks")]
+    fn test_backoff() {
+        assert_eq!(30, backoff(0));
+        assert_eq!(120, backoff(2));
+        assert_eq!(24 * 60 * 60, backoff(12));
+        assert_eq!(24 * 60 * 60, backoff(100));
+    }
+
+    #[test]
+    fn test_outbox() {
+        let mut conn = Connection::open_in_memory().unwrap();
+        db::initialize_db(&mut conn).unwrap();
+        let tx = conn.transaction().unwrap();
+        db::add_webhook(&tx, "http://a", &None).unwrap();
+        db::add_webhook(&tx, "http://b", &Some("secret".to_string())).unwrap();
+        enqueue(&tx, "delete", json!({"id": 3})).unwrap();
+        let due = db::get_due_webhook_deliveries(&tx, Utc::now().timestamp()).unwrap();
+        assert_eq!(2, due.len());
+        let payload: Value = serde_json::from_str(&due[0].payload).unwrap();
+        assert_eq!("delete", payload["type"]);
+        assert_eq!(3, payload["id"]);
+
+        db::retry_webhook_delivery(&tx, due[0].id, i64::MAX, "refused").unwrap();
+        let due = db::get_due_webhook_deliveries(&tx, Utc::now().timestamp()).unwrap();
+        assert_eq!(
+            vec!["http://b"],
+            due.iter()
+                .map(|d| d.webhook.url.as_str())
+                .collect::<Vec<_>>()
+        );
+        //deleting a webhook drops what it hasn't delivered
+        db::delete_webhook(&tx, 2).unwrap();
+        assert_eq!(Some(&1), db::get_webhook_backlog(&tx).unwrap().get(&1));
+        assert_eq!(None, db::get_webhook_backlog(&tx).unwrap().get(&2));
+    }
+}
@@ -0,0 +1,169 @@
+/*
+This file is part of Timetrack Jr.
+Timetrack Jr. is free software: you can redistribute it and/or modify it under the terms of the GNU General Public License as published by the Free Software Foundation, either version 3 of the License, or (at your option) any later version.
+Timetrack Jr. is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU General Public License for more details.
+You should have received a copy of the GNU General Public License along with Timetrack Jr. If not, see <https://www.gnu.org/licenses/>.
+*/
+//Rules matching the focused window to a category.  Linux asks xdotool (X11), macOS asks System Events for the
+// frontmost app and its window, and windows calls GetForegroundWindow.
+use crate::{
+    commands::report,
+    db::{self, WindowRule},
+    TTError,
+};
+use regex::Regex;
+use rusqlite::Connection;
+use serde_json::json;
+#[cfg(not(windows))]
+use std::process::Command;
+
+fn compile(pattern: &str) -> Result<Regex, TTError> {
+    Regex::new(pattern).map_err(|e| TTError::InvalidArgument {
+        message: format!("Invalid window rule pattern \"{}\": {}", pattern, e),
+    })
+}
+
+///The first rule whose pattern matches `title`
+pub(crate) fn matching_rule<'a>(
+    rules: &'a [WindowRule],
+    title: &str,
+) -> Result<Option<&'a WindowRule>, TTError> {
+    for rule in rules {
+        if compile(&rule.pattern)?.is_match(title) {
+            return Ok(Some(rule));
+        }
+    }
+    Ok(None)
+}
+
+fn unknown() -> TTError {
+    TTError::System {
+        message: "Couldn't find the focused window (on linux, install xdotool)".to_string(),
+    }
+}
+
+#[cfg(not(windows))]
+fn run(program: &str, args: &[&str]) -> Result<String, TTError> {
+    let output = Command::new(program)
+        .args(args)
+        .output()
+        .map_err(|_| unknown())?;
+    match output.status.success() {
+        true => Ok(String::from_utf8_lossy(&output.stdout).trim().to_string()),
+        false => Err(unknown()),
+    }
+}
+
+///The focused window's title
+#[cfg(all(unix, not(target_os = "macos")))]
+pub(crate) fn active_window() -> Result<String, TTError> {
+    run("xdotool", &["getactivewindow", "getwindowname"])
+}
+
+///The frontmost app and its window's title, like "Safari - Timetrack Jr."
+#[cfg(target_os = "macos")]
+pub(crate) fn active_window() -> Result<String, TTError> {
+    run(
+        "osascript",
+        &[
+            "-e",
+            r#"tell application "System Events"
+                set frontApp to first application process whose frontmost is true
+                set windowTitle to ""
+                try
+                    set windowTitle to name of front window of frontApp
+                end try
+                return (name of frontApp) & " - " & windowTitle
+            end tell"#,
+        ],
+    )
+}
+
+///The focused window's title
+#[cfg(windows)]
+pub(crate) fn active_window() -> Result<String, TTError> {
+    use std::ffi::c_void;
+    #[link(name = "user32")]
+    extern "system" {
+        fn GetForegroundWindow() -> *mut c_void;
+        fn GetWindowTextW(hwnd: *mut c_void, text: *mut u16, max_count: i32) -> i32;
+    }
+    let mut title = [0u16; 512];
+    //GetWindowTextW writes at most title.len() characters, including the terminating null
+    let length = unsafe {
+        let hwnd = GetForegroundWindow();
+        if hwnd.is_null() {
+            return Err(unknown());
+        }
+        GetWindowTextW(hwnd, title.as_mut_ptr(), title.len() as i32)
+    };
+    Ok(String::from_utf16_lossy(&title[..length.max(0) as usize]))
+}
+
+pub fn add_window_rule(
+    conn: &mut Connection,
+    pattern: &str,
+    category: &str,
+    json: &bool,
+) -> Result<(), TTError> {
+    compile(pattern)?;
+    let id = db::write(conn, |tx| {
+        if !db::get_categories(tx)?.contains(category) {
+            return Err(TTError::CategoryNotFound {
+                category: category.to_string(),
+            });
+        }
+        db::add_window_rule(tx, pattern, category)
+    })?;
+    match json {
+        true => report::print_json(&json!({ "id": id }))?,
+        false => println!("Added window rule {}", id),
+    }
+    Ok(())
+}
+
+pub fn delete_window_rule(conn: &mut Connection, id: &i64) -> Result<(), TTError> {
+    match db::write(conn, |tx| db::delete_window_rule(tx, *id))? {
+        true => Ok(()),
+        false => Err(TTError::NotFound {
+            kind: "window rule",
+            id: *id,
+        }),
+    }
+}
+
+pub fn list_window_rules(conn: &mut Connection, json: &bool) -> Result<(), TTError> {
+    let rules = db::get_window_rules(&conn.transaction()?)?;
+    if *json {
+        return report::print_json(&json!(rules));
+    }
+    for rule in rules {
+        println!("{}: {} -> {}", rule.id, rule.pattern, rule.category);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_matching_rule() {
+        let rule = |id, pattern: &str, category: &str| WindowRule {
+            id,
+            pattern: pattern.to_string(),
+            category: category.to_string(),
+        };
+        let rules = vec![
+            rule(1, r"(?i)zoom meeting|google meet", "meetings"),
+            rule(2, r"\.rs - Visual Studio Code$", "coding"),
+            rule(3, r"Visual Studio Code", "other"),
+        ];
+        let category = |title| matching_rule(&rules, title).unwrap().map(|r| r.id);
+        assert_eq!(Some(1), category("Zoom Meeting"));
+        assert_eq!(Some(2), category("main.rs - Visual Studio Code"));
+        assert_eq!(Some(3), category("README.md - Visual Studio Code"));
+        assert_eq!(None, category("Slack"));
+        assert!(matching_rule(&[rule(4, "(", "oops")], "x").is_err());
+    }
+}
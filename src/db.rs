@@ -5,36 +5,160 @@ Timetrack Jr. is distributed in the hope that it will be useful, but WITHOUT ANY
 You should have received a copy of the GNU General Public License along with Timetrack Jr. If not, see <https://www.gnu.org/licenses/>.
 */
 
-use crate::{cli, TTError};
-use chrono::{DateTime, NaiveDateTime, Timelike};
+use crate::{cli, zone::Zone, TTError};
+use chrono::{NaiveDate, Timelike};
 use clap::ValueEnum;
 use fallible_iterator::FallibleIterator;
 use once_cell::sync::Lazy;
 use regex::Regex;
-use rusqlite::{named_params, Connection, Row, ToSql, Transaction};
+use rusqlite::{named_params, Connection, ErrorCode, Row, ToSql, Transaction, TransactionBehavior};
 use serde::{Deserialize, Serialize};
 use std::{
     collections::{BTreeMap, BTreeSet},
-    time::{SystemTime, UNIX_EPOCH},
+    time::{Duration, SystemTime, UNIX_EPOCH},
 };
 
 const VERSION: &str = env!("CARGO_PKG_VERSION");
+///Where open times end in the times_span index
+const OPEN_END: i64 = 1 << 52;
+///How many times `write` tries a transaction while the DB is locked (waiting 10ms, then 20ms, 40ms, etc. in between)
+const BUSY_ATTEMPTS: u32 = 8;
+///How long other statements wait for a lock (rusqlite's default)
+const BUSY_TIMEOUT: Duration = Duration::from_secs(5);
 
 #[derive(Serialize, Deserialize)]
 pub struct Config {
     options: Options,
     categories: Categories,
+    category_options: CategoryOptions,
+    ///What each global key combination does (i.e. "ctrl+alt+w": "start work")
+    hotkeys: BTreeMap<String, String>,
 }
 
 pub type Options = BTreeMap<String, String>;
 pub type Categories = BTreeSet<String>;
+///Options for each category, keyed by category name
+pub type CategoryOptions = BTreeMap<String, Options>;
 
-#[derive(Serialize, Deserialize, PartialEq, Eq, Debug)]
+#[derive(Serialize, Deserialize, PartialEq, Eq, Debug, Clone)]
 pub struct TimeWindow {
     pub id: Option<i64>,
     pub category: String,
     pub start_time: i64,
     pub end_time: Option<i64>,
+    ///What was done, for more detail than the category (like which ticket was worked on)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub note: Option<String>,
+}
+
+impl TimeWindow {
+    ///A copy of the time with its start/end clamped to the window.  Open times are left open.
+    pub fn clipped(&self, start: Option<i64>, end: Option<i64>) -> TimeWindow {
+        let clamp = |t: i64| {
+            let t = start.map_or(t, |s| t.max(s));
+            end.map_or(t, |e| t.min(e))
+        };
+        TimeWindow {
+            id: self.id,
+            category: self.category.clone(),
+            start_time: clamp(self.start_time),
+            end_time: self.end_time.map(clamp),
+            note: self.note.clone(),
+        }
+    }
+}
+
+///A time that's been synced with a remote service (like Toggl), as it was after the last sync.
+/// Comparing this to the local and remote versions shows which side has changed since.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SyncedTime {
+    pub remote_id: String,
+    pub time_id: i64,
+    pub category: String,
+    pub start_time: i64,
+    pub end_time: i64,
+}
+
+///An event that a time was pushed to in a calendar, as it was when it was last pushed
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PushedEvent {
+    pub time_id: i64,
+    ///URL or id of the event in the calendar
+    pub event_id: String,
+    ///Version of the event the calendar returned when it was pushed (if any), used to avoid overwriting changes made there
+    pub etag: Option<String>,
+    pub category: String,
+    pub start_time: i64,
+    pub end_time: i64,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Webhook {
+    pub id: i64,
+    pub url: String,
+    ///Key for the HMAC signature header, if the receiver wants to verify deliveries
+    pub secret: Option<String>,
+}
+
+///A global key combination and what it does
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Hotkey {
+    pub keypress: String,
+    pub action: String,
+    ///The category to start, for the start action
+    pub category: Option<String>,
+}
+
+///A category the daemon starts timing at a time of day, on some days of the week
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct AutoStart {
+    pub id: i64,
+    ///Like "weekdays" or "mon,wed" (see cli::days_string_to_days)
+    pub days: String,
+    ///HH:MM
+    pub time: String,
+    pub category: String,
+}
+
+///A regex for focused window titles, and the category they mean you're working on
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct WindowRule {
+    pub id: i64,
+    pub pattern: String,
+    pub category: String,
+}
+
+impl Hotkey {
+    ///The action and its category, like "start work"
+    pub fn description(&self) -> String {
+        match &self.category {
+            Some(category) => format!("{} {}", self.action, category),
+            None => self.action.clone(),
+        }
+    }
+}
+
+///An event in the webhook outbox, along with where it's going
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct WebhookDelivery {
+    pub id: i64,
+    pub webhook: Webhook,
+    pub payload: String,
+    pub attempts: i64,
+}
+
+///A time imported from elsewhere (like an Outlook meeting) that isn't logged until it's confirmed
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct PendingTime {
+    pub id: Option<i64>,
+    ///Where the time came from and its id there, so it's only imported once
+    pub source: String,
+    pub source_id: String,
+    pub title: String,
+    ///Category the time will be logged under, if one could be guessed
+    pub category: Option<String>,
+    pub start_time: i64,
+    pub end_time: i64,
 }
 
 fn row_to_time_window(row: &Row) -> Result<TimeWindow, rusqlite::Error> {
@@ -43,6 +167,7 @@ fn row_to_time_window(row: &Row) -> Result<TimeWindow, rusqlite::Error> {
         category: row.get("category")?,
         start_time: row.get("start_time")?,
         end_time: row.get("end_time")?,
+        note: row.get("note")?,
     })
 }
 
@@ -52,6 +177,17 @@ static BUSINESS_HOURS_PATTERN: Lazy<Regex> =
 #[derive(Eq, PartialEq, Debug)]
 pub struct HourMinute(u32, u32);
 
+impl HourMinute {
+    ///The hour and minute of a time of day
+    pub fn of(time: &impl Timelike) -> HourMinute {
+        HourMinute(time.hour(), time.minute())
+    }
+
+    pub fn seconds_since_midnight(&self) -> i64 {
+        (self.0 * 60 * 60 + self.1 * 60) as i64
+    }
+}
+
 impl std::fmt::Display for HourMinute {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         write!(f, "{:02}:{:02}", self.0, self.1)
@@ -66,14 +202,129 @@ impl std::cmp::Ord for HourMinute {
 
 impl std::cmp::PartialOrd for HourMinute {
     fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
-        self.to_string().partial_cmp(&other.to_string())
+        Some(self.cmp(other))
+    }
+}
+
+///Opens connections to the DB for a Pool, with the same settings as the connection made in main
+pub struct ConnectionManager {
+    pub path: String,
+}
+
+impl r2d2::ManageConnection for ConnectionManager {
+    type Connection = Connection;
+    type Error = rusqlite::Error;
+
+    fn connect(&self) -> Result<Connection, rusqlite::Error> {
+        let mut conn = Connection::open(&self.path)?;
+        trace_sql(&mut conn);
+        conn.execute("PRAGMA foreign_keys = ON", ())?;
+        //pooled connections are used concurrently, so wait for other writers rather than failing
+        conn.busy_timeout(BUSY_TIMEOUT)?;
+        //they also live as long as the server, so keep plenty of statements prepared
+        conn.set_prepared_statement_cache_capacity(64);
+        Ok(conn)
+    }
+
+    fn is_valid(&self, conn: &mut Connection) -> Result<(), rusqlite::Error> {
+        conn.execute_batch("")
+    }
+
+    fn has_broken(&self, _conn: &mut Connection) -> bool {
+        false
+    }
+}
+
+pub type Pool = r2d2::Pool<ConnectionManager>;
+
+///Open a pool of up to `size` connections to an (already initialized) DB
+pub fn open_pool(path: &String, size: u32) -> Result<Pool, TTError> {
+    Ok(r2d2::Pool::builder()
+        .max_size(size)
+        .build(ConnectionManager { path: path.clone() })?)
+}
+
+///Log every statement run on `conn` (with its parameters filled in) when --verbose is on
+fn trace_sql(conn: &mut Connection) {
+    fn log(sql: &str) {
+        tracing::debug!(target: "ttjr::sql", "{}", sql);
+    }
+    if tracing::enabled!(target: "ttjr::sql", tracing::Level::DEBUG) {
+        conn.trace(Some(log));
     }
 }
 
+fn busy(error: &TTError) -> bool {
+    matches!(
+        error,
+        TTError::Sql(rusqlite::Error::SqliteFailure(e, _))
+            if matches!(e.code, ErrorCode::DatabaseBusy | ErrorCode::DatabaseLocked)
+    )
+}
+
+///Run `operation` in a transaction and commit it, trying again (with exponential backoff) while another connection
+/// has the DB locked, so ttjr's running at once (from hotkeys, the daemon, an exporter) take turns instead of failing
+pub fn write<T>(
+    conn: &mut Connection,
+    operation: impl FnMut(&mut Transaction) -> Result<T, TTError>,
+) -> Result<T, TTError> {
+    write_or_dry_run(conn, &false, operation)
+}
+
+///Like `write`, but with `dry_run` the transaction is rolled back instead of committed (so `operation` can report
+/// what it would've changed)
+pub fn write_or_dry_run<T>(
+    conn: &mut Connection,
+    dry_run: &bool,
+    mut operation: impl FnMut(&mut Transaction) -> Result<T, TTError>,
+) -> Result<T, TTError> {
+    //backing off replaces sqlite's own waiting
+    conn.busy_timeout(Duration::ZERO)?;
+    let result = write_with_backoff(conn, dry_run, &mut operation);
+    conn.busy_timeout(BUSY_TIMEOUT)?;
+    result
+}
+
+fn write_with_backoff<T>(
+    conn: &mut Connection,
+    dry_run: &bool,
+    operation: &mut impl FnMut(&mut Transaction) -> Result<T, TTError>,
+) -> Result<T, TTError> {
+    let mut delay = Duration::from_millis(10);
+    for attempt in 1..=BUSY_ATTEMPTS {
+        //immediate, so the write lock is taken up front rather than when the first write happens
+        let result = conn
+            .transaction_with_behavior(TransactionBehavior::Immediate)
+            .map_err(TTError::from)
+            .and_then(|mut tx| {
+                let value = operation(&mut tx)?;
+                match dry_run {
+                    true => tx.rollback()?,
+                    false => tx.commit()?,
+                }
+                Ok(value)
+            });
+        match result {
+            Err(e) if busy(&e) && attempt < BUSY_ATTEMPTS => {
+                tracing::debug!("the DB is locked, trying again in {:?}", delay);
+                std::thread::sleep(delay);
+                delay *= 2;
+            }
+            Err(e) if busy(&e) => break,
+            result => return result,
+        }
+    }
+    Err(TTError::DatabaseBusy {
+        attempts: BUSY_ATTEMPTS,
+    })
+}
+
 pub fn initialize_db(conn: &mut Connection) -> Result<(), TTError> {
+    trace_sql(conn);
     conn.execute("PRAGMA foreign_keys = ON", ())?;
 
-    let tx = conn.transaction()?;
+    //immediate, so ttjrs starting at once wait for each other (with the busy timeout) instead of deadlocking
+    let tx = conn.transaction_with_behavior(TransactionBehavior::Immediate)?;
 
     tx.execute(
         "CREATE TABLE IF NOT EXISTS options (
@@ -84,8 +335,10 @@ pub fn initialize_db(conn: &mut Connection) -> Result<(), TTError> {
     )?;
 
     //might use this later to handle DB migrations if that's a thing
+    //(only written when it changes, so commands which just read don't write to the DB)
     tx.execute(
-        "REPLACE INTO options (name, value) VALUES ('dbversion', ?)",
+        "INSERT INTO options (name, value) VALUES ('dbversion', ?1)
+            ON CONFLICT(name) DO UPDATE SET value = excluded.value WHERE value != excluded.value",
         (VERSION,),
     )?;
 
@@ -102,11 +355,204 @@ pub fn initialize_db(conn: &mut Connection) -> Result<(), TTError> {
             category TEXT NOT NULL,
             start_time INTEGER NOT NULL CHECK (start_time >= 0),
             end_time INTEGER CHECK (end_time is null or end_time >= start_time),
+            note TEXT,
             FOREIGN KEY(category) REFERENCES categories(name) ON UPDATE CASCADE ON DELETE RESTRICT
         )",
         (),
     )?;
 
+    //notes were added after the times table, so older DBs need the column
+    let noted = tx
+        .prepare("SELECT 1 FROM pragma_table_info('times') WHERE name = 'note'")?
+        .exists(())?;
+    if !noted {
+        tx.execute("ALTER TABLE times ADD COLUMN note TEXT", ())?;
+    }
+
+    //times are listed in order of when they started
+    tx.execute(
+        "CREATE INDEX IF NOT EXISTS times_by_start ON times (start_time, id)",
+        (),
+    )?;
+
+    //an R*Tree of when times start and end (open times end at OPEN_END), so checking a time for overlaps doesn't
+    //scan every other time.  Its bounds are floats, rounded outwards, so it only narrows down candidates.
+    let indexed = tx
+        .prepare("SELECT 1 FROM sqlite_master WHERE name = 'times_span'")?
+        .exists(())?;
+    if !indexed {
+        tx.execute(
+            "CREATE VIRTUAL TABLE times_span USING rtree(id, start_time, end_time)",
+            (),
+        )?;
+        tx.execute(
+            "INSERT INTO times_span SELECT id, start_time, COALESCE(end_time, ?1) FROM times",
+            (OPEN_END,),
+        )?;
+        tx.execute_batch(&format!(
+            "CREATE TRIGGER times_span_insert AFTER INSERT ON times BEGIN
+                INSERT OR REPLACE INTO times_span VALUES (new.id, new.start_time, COALESCE(new.end_time, {open}));
+            END;
+            CREATE TRIGGER times_span_update AFTER UPDATE OF id, start_time, end_time ON times BEGIN
+                DELETE FROM times_span WHERE id = old.id;
+                INSERT OR REPLACE INTO times_span VALUES (new.id, new.start_time, COALESCE(new.end_time, {open}));
+            END;
+            CREATE TRIGGER times_span_delete AFTER DELETE ON times BEGIN
+                DELETE FROM times_span WHERE id = old.id;
+            END;",
+            open = OPEN_END
+        ))?;
+    }
+
+    tx.execute(
+        "CREATE TABLE IF NOT EXISTS category_options (
+            category TEXT NOT NULL,
+            name TEXT NOT NULL,
+            value TEXT NOT NULL,
+            PRIMARY KEY(category, name),
+            FOREIGN KEY(category) REFERENCES categories(name) ON UPDATE CASCADE ON DELETE CASCADE
+        )",
+        (),
+    )?;
+
+    //no foreign key on time_id, so entries stay around after a time is deleted and the deletion can be synced
+    tx.execute(
+        "CREATE TABLE IF NOT EXISTS synced_times (
+            service TEXT NOT NULL,
+            remote_id TEXT NOT NULL,
+            time_id INTEGER NOT NULL,
+            category TEXT NOT NULL,
+            start_time INTEGER NOT NULL,
+            end_time INTEGER NOT NULL,
+            PRIMARY KEY(service, remote_id)
+        )",
+        (),
+    )?;
+
+    tx.execute(
+        "CREATE TABLE IF NOT EXISTS sync_state (
+            service TEXT PRIMARY KEY,
+            last_synced INTEGER NOT NULL
+        )",
+        (),
+    )?;
+
+    tx.execute(
+        "CREATE TABLE IF NOT EXISTS annotations (
+            time_id INTEGER NOT NULL,
+            annotation TEXT NOT NULL,
+            PRIMARY KEY(time_id, annotation),
+            FOREIGN KEY(time_id) REFERENCES times(id) ON DELETE CASCADE
+        )",
+        (),
+    )?;
+
+    tx.execute(
+        "CREATE TABLE IF NOT EXISTS tags (
+            name TEXT PRIMARY KEY
+        )",
+        (),
+    )?;
+
+    tx.execute(
+        "CREATE TABLE IF NOT EXISTS time_tags (
+            time_id INTEGER NOT NULL,
+            tag TEXT NOT NULL,
+            PRIMARY KEY(time_id, tag),
+            FOREIGN KEY(time_id) REFERENCES times(id) ON DELETE CASCADE,
+            FOREIGN KEY(tag) REFERENCES tags(name) ON DELETE CASCADE
+        )",
+        (),
+    )?;
+
+    //also no foreign key here, so deleted times can be removed from calendars
+    tx.execute(
+        "CREATE TABLE IF NOT EXISTS pushed_events (
+            calendar TEXT NOT NULL,
+            time_id INTEGER NOT NULL,
+            event_id TEXT NOT NULL,
+            etag TEXT,
+            category TEXT NOT NULL,
+            start_time INTEGER NOT NULL,
+            end_time INTEGER NOT NULL,
+            PRIMARY KEY(calendar, time_id)
+        )",
+        (),
+    )?;
+
+    //confirmed and dismissed times are kept (as resolved) so they aren't imported again
+    tx.execute(
+        "CREATE TABLE IF NOT EXISTS pending_times (
+            id INTEGER PRIMARY KEY,
+            source TEXT NOT NULL,
+            source_id TEXT NOT NULL,
+            title TEXT NOT NULL,
+            category TEXT,
+            start_time INTEGER NOT NULL,
+            end_time INTEGER NOT NULL,
+            resolved INTEGER NOT NULL DEFAULT 0,
+            UNIQUE(source, source_id),
+            FOREIGN KEY(category) REFERENCES categories(name) ON UPDATE CASCADE ON DELETE SET NULL
+        )",
+        (),
+    )?;
+
+    tx.execute(
+        "CREATE TABLE IF NOT EXISTS webhooks (
+            id INTEGER PRIMARY KEY,
+            url TEXT NOT NULL,
+            secret TEXT
+        )",
+        (),
+    )?;
+
+    //events waiting to be POSTed, written in the same transaction as the change so none are lost
+    tx.execute(
+        "CREATE TABLE IF NOT EXISTS webhook_outbox (
+            id INTEGER PRIMARY KEY,
+            webhook_id INTEGER NOT NULL,
+            payload TEXT NOT NULL,
+            attempts INTEGER NOT NULL DEFAULT 0,
+            next_attempt INTEGER NOT NULL,
+            last_error TEXT,
+            FOREIGN KEY(webhook_id) REFERENCES webhooks(id) ON DELETE CASCADE
+        )",
+        (),
+    )?;
+
+    tx.execute(
+        "CREATE TABLE IF NOT EXISTS auto_starts (
+            id INTEGER PRIMARY KEY,
+            days TEXT NOT NULL,
+            time TEXT NOT NULL,
+            category TEXT NOT NULL,
+            FOREIGN KEY(category) REFERENCES categories(name) ON UPDATE CASCADE ON DELETE CASCADE
+        )",
+        (),
+    )?;
+
+    //checked in order of id, the first matching rule wins
+    tx.execute(
+        "CREATE TABLE IF NOT EXISTS window_rules (
+            id INTEGER PRIMARY KEY,
+            pattern TEXT NOT NULL,
+            category TEXT NOT NULL,
+            FOREIGN KEY(category) REFERENCES categories(name) ON UPDATE CASCADE ON DELETE CASCADE
+        )",
+        (),
+    )?;
+
+    //global key combinations for `ttjr daemon`, keypress is stored the way keyboard::Keypress displays it
+    tx.execute(
+        "CREATE TABLE IF NOT EXISTS hotkeys (
+            keypress TEXT PRIMARY KEY,
+            action TEXT NOT NULL,
+            category TEXT,
+            FOREIGN KEY(category) REFERENCES categories(name) ON UPDATE CASCADE ON DELETE CASCADE
+        )",
+        (),
+    )?;
+
     tx.commit()?;
 
     return Ok(());
@@ -124,7 +570,7 @@ pub fn set_option(
         )?;
         Ok(())
     } else {
-        Err(TTError::TTError {
+        Err(TTError::InvalidArgument {
             message: format!("Unknown Option Name {:?}", option_name),
         })
     }
@@ -138,7 +584,7 @@ pub fn unset_option(tx: &Transaction, option_name: &cli::OptionName) -> Result<(
         )?;
         Ok(())
     } else {
-        Err(TTError::TTError {
+        Err(TTError::InvalidArgument {
             message: format!("Unknown Option Name {:?}", option_name),
         })
     }
@@ -146,7 +592,7 @@ pub fn unset_option(tx: &Transaction, option_name: &cli::OptionName) -> Result<(
 
 pub fn get_options(conn: &Transaction) -> Result<Options, TTError> {
     let mut options: Options = Options::new();
-    let mut stmt = conn.prepare("SELECT name, value FROM options")?;
+    let mut stmt = conn.prepare_cached("SELECT name, value FROM options")?;
     let mut rows = stmt.query(())?;
 
     while let Some(row) = rows.next()? {
@@ -156,9 +602,61 @@ pub fn get_options(conn: &Transaction) -> Result<Options, TTError> {
     Ok(options)
 }
 
+pub fn set_category_option(
+    tx: &Transaction,
+    category_name: &String,
+    option_name: &cli::CategoryOptionName,
+    option_value: &String,
+) -> Result<(), TTError> {
+    if let Some(option_name) = ValueEnum::to_possible_value(option_name) {
+        tx.execute(
+            "REPLACE INTO category_options (category, name, value) VALUES (?, ?, ?)",
+            (category_name, option_name.get_name(), option_value),
+        )?;
+        Ok(())
+    } else {
+        Err(TTError::InvalidArgument {
+            message: format!("Unknown Category Option Name {:?}", option_name),
+        })
+    }
+}
+
+pub fn unset_category_option(
+    tx: &Transaction,
+    category_name: &String,
+    option_name: &cli::CategoryOptionName,
+) -> Result<(), TTError> {
+    if let Some(option_name) = ValueEnum::to_possible_value(option_name) {
+        tx.execute(
+            "DELETE FROM category_options WHERE category = ? AND name = ?",
+            (category_name, option_name.get_name()),
+        )?;
+        Ok(())
+    } else {
+        Err(TTError::InvalidArgument {
+            message: format!("Unknown Category Option Name {:?}", option_name),
+        })
+    }
+}
+
+pub fn get_category_options(conn: &Transaction) -> Result<CategoryOptions, TTError> {
+    let mut options = CategoryOptions::new();
+    let mut stmt = conn.prepare("SELECT category, name, value FROM category_options")?;
+    let mut rows = stmt.query(())?;
+
+    while let Some(row) = rows.next()? {
+        options
+            .entry(row.get(0)?)
+            .or_default()
+            .insert(row.get(1)?, row.get(2)?);
+    }
+
+    Ok(options)
+}
+
 pub fn get_categories(conn: &Transaction) -> Result<Categories, TTError> {
     let mut categories = Categories::new();
-    let mut stmt = conn.prepare("SELECT name FROM categories order by name")?;
+    let mut stmt = conn.prepare_cached("SELECT name FROM categories order by name")?;
     let mut rows = stmt.query(())?;
 
     while let Some(row) = rows.next()? {
@@ -172,6 +670,11 @@ pub fn get_config(conn: &Transaction) -> Result<Config, TTError> {
     return Ok(Config {
         options: get_options(conn)?,
         categories: get_categories(conn)?,
+        category_options: get_category_options(conn)?,
+        hotkeys: get_hotkeys(conn)?
+            .into_iter()
+            .map(|h| (h.keypress.clone(), h.description()))
+            .collect(),
     });
 }
 
@@ -180,42 +683,108 @@ pub fn add_category(conn: &Transaction, category_name: &String) -> Result<(), TT
     Ok(())
 }
 
+///Delete a category, returning how many times were deleted with it
 pub fn delete_category(
     tx: &Transaction,
     category_name: &String,
     delete_logged_times: &bool,
-) -> Result<(), TTError> {
+) -> Result<usize, TTError> {
+    let mut deleted = 0;
     if *delete_logged_times {
-        tx.execute("DELETE FROM times WHERE category=?", (&category_name,))?;
+        deleted = tx.execute("DELETE FROM times WHERE category=?", (&category_name,))?;
     }
     tx.execute("DELETE FROM categories WHERE name=?", (&category_name,))?;
 
+    Ok(deleted)
+}
+
+///Earliest believable time when the earliest-time option isn't set
+const DEFAULT_EARLIEST_TIME: &str = "2000-01-01";
+
+///How far in the future a time can be, for clocks that are a little out
+const FUTURE_GRACE: i64 = 60;
+
+///Check that a time someone's entered is believable: not in the future (like a typo'd year, which would throw
+/// off "all time" summaries), or from before the earliest-time option.
+pub fn check_plausible(options: &Options, tstamp: i64, now: i64) -> Result<(), TTError> {
+    let earliest = options
+        .get("earliest-time")
+        .map_or(DEFAULT_EARLIEST_TIME.to_string(), |e| e.clone());
+    let reason = if tstamp > now + FUTURE_GRACE {
+        "in the future".to_string()
+    } else if cli::time_string_to_tstamp(&Some(earliest.clone())).is_some_and(|e| tstamp < e) {
+        format!("before {} (the earliest-time option)", earliest)
+    } else {
+        return Ok(());
+    };
+    Err(TTError::UnusualTime {
+        time: Zone::Local.format(&tstamp, "%Y-%m-%d %H:%M"),
+        reason,
+    })
+}
+
+///Check the start and end of times about to be written with check_plausible, skipping any left as they were (so an
+/// old time can still be amended) unless unusual times are allowed
+fn check_plausible_times(
+    tx: &Transaction,
+    times: &[TimeWindow],
+    allow_unusual_times: &bool,
+) -> Result<(), TTError> {
+    if *allow_unusual_times {
+        return Ok(());
+    }
+    let options = get_options(tx)?;
+    let now = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs() as i64;
+    for time in times {
+        let before = match time.id.map(|id| get_time(tx, id)) {
+            Some(Ok(before)) => (Some(before.start_time), before.end_time),
+            Some(Err(TTError::Sql(rusqlite::Error::QueryReturnedNoRows))) | None => (None, None),
+            Some(Err(e)) => return Err(e),
+        };
+        if before.0 != Some(time.start_time) {
+            check_plausible(&options, time.start_time, now)?;
+        }
+        if let Some(end) = time.end_time.filter(|_| time.end_time != before.1) {
+            check_plausible(&options, end, now)?;
+        }
+    }
     Ok(())
 }
 
-///Update a time in the DB.  does NOT commit the transaction
-pub fn upsert_time(tx: &mut Transaction, time: TimeWindow) -> Result<(), TTError> {
+///Update a time in the DB, refusing unusual start and end times unless they're allowed.  does NOT commit the
+/// transaction
+#[tracing::instrument(level = "debug", skip_all, fields(id = ?time.id, category = %time.category))]
+pub fn upsert_time(
+    tx: &mut Transaction,
+    time: TimeWindow,
+    allow_unusual_times: &bool,
+) -> Result<(), TTError> {
+    check_plausible_times(tx, std::slice::from_ref(&time), allow_unusual_times)?;
+
     //must not overlap with an existing complete time
     //if there is an on open time, the time being upserted must be:
     //  a.the same time
     //  b. a different time AND not overlapping with the _start_ of the open time
 
     //disallow overlapping time entries
-    let mut stmt = tx.prepare(
-        "SELECT id c \
-        FROM times
+    //(statements run on every start/stop/amend are cached, so pooled connections only prepare them once)
+    //(times_span narrows it down to times which start before this one ends and end after it starts)
+    let mut stmt = tx.prepare_cached(
+        "SELECT times.id c \
+        FROM times_span span JOIN times ON times.id = span.id
         WHERE 
-            (id IS DISTINCT FROM :id) 
+            span.start_time <= COALESCE(:end, :start) AND span.end_time >= :start
+            AND (times.id IS DISTINCT FROM :id) 
             AND (
                 --upserted start time is in the middle of an already-recorded time
-                (:start >= start_time AND  :start <= end_time)
+                (:start >= times.start_time AND  :start <= times.end_time)
                 
                 --upserted end time is in the middle of an already-recorded time
                 --use coalesce because :end might be null
-                OR COALESCE(:end >= start_time AND :end <= end_time, FALSE)
+                OR COALESCE(:end >= times.start_time AND :end <= times.end_time, FALSE)
 
                 --If there is an open time, the upserted time must be entirely before the open time
-                OR (end_time IS NULL AND (:start >= start_time OR COALESCE(:end >= start_time, FALSE)))
+                OR (times.end_time IS NULL AND (:start >= times.start_time OR COALESCE(:end >= times.start_time, FALSE)))
             )
         ")?;
     let rows = stmt.query(named_params! {
@@ -223,68 +792,106 @@ pub fn upsert_time(tx: &mut Transaction, time: TimeWindow) -> Result<(), TTError
         ":start": time.start_time,
         ":end": time.end_time
     })?;
-    let overlapping_ids: Vec<String> = rows
+    let overlapping_ids = rows
         .map(|row| -> Result<i64, _> { row.get(0) })
-        .collect::<Vec<i64>>()?
-        .iter()
-        .map(|i| i.to_string())
-        .collect();
-    if overlapping_ids.len() > 0 {
-        return Err(TTError::TTError {
-            message: format!(
-                "Attempted to insert time that overlaps with other times! (overlapped IDs: {}) (time to insert: {:?}) (example overlap: {:?})",
-                overlapping_ids.join(", "),
-                time,
-                get_time(tx, str::parse::<i64>(overlapping_ids.get(0).unwrap()).unwrap()).unwrap()
-            ),
+        .collect::<Vec<i64>>()?;
+    if !overlapping_ids.is_empty() {
+        return Err(TTError::OverlappingTime {
+            ids: overlapping_ids,
         });
     }
 
-    let mut params: Vec<(&str, &dyn ToSql)> = Vec::new();
-
-    if let Some(id) = &time.id {
-        params.push((":id", id));
-    }
-    if let Some(end_time) = &time.end_time {
-        params.push((":end_time", end_time));
-    }
-
-    params.push((":category", &time.category));
-
-    params.push((":start_time", &time.start_time));
-
-    let param_names: Vec<String> = params
-        .iter()
-        .map(|(name, _)| name[1..].to_string())
-        .collect();
-
-    let param_placeholders: Vec<&str> = params.iter().map(|(name, _)| *name).collect();
-
-    let query = format!(
-        "REPLACE INTO times ({}) VALUES ({})",
-        param_names.join(", "),
-        param_placeholders.join(", ")
-    );
-
-    tx.execute(&query[..], &params[..])?;
+    //update existing times in place, REPLACE would delete them first and cascade to their tags and annotations
+    tx.prepare_cached(
+        "INSERT INTO times (id, category, start_time, end_time, note)
+            VALUES (:id, :category, :start_time, :end_time, :note)
+            ON CONFLICT(id) DO UPDATE SET
+                category = excluded.category,
+                start_time = excluded.start_time,
+                end_time = excluded.end_time,
+                note = excluded.note",
+    )?
+    .execute(named_params! {
+        ":id": time.id,
+        ":category": time.category,
+        ":start_time": time.start_time,
+        ":end_time": time.end_time,
+        ":note": time.note,
+    })?;
 
     Ok(())
 }
 
 pub fn get_time(tx: &Transaction, id: i64) -> Result<TimeWindow, TTError> {
-    tx.query_row_and_then("SELECT * FROM times WHERE id=?", (id,), |row| {
-        Ok(TimeWindow {
-            id: Some(row.get("id").unwrap()),
-            category: row.get("category").unwrap(),
-            start_time: row.get("start_time").unwrap(),
-            end_time: row.get("end_time").unwrap(),
+    tx.prepare_cached("SELECT * FROM times WHERE id=?")?
+        .query_row((id,), |row| {
+            Ok(TimeWindow {
+                id: Some(row.get("id").unwrap()),
+                category: row.get("category").unwrap(),
+                start_time: row.get("start_time").unwrap(),
+                end_time: row.get("end_time").unwrap(),
+                note: row.get("note").unwrap(),
+            })
         })
-    })
+        .map_err(TTError::from)
+}
+
+///Insert a batch of new times (for imports) far faster than upserting them one at a time: they're inserted without
+/// checking each one, then checked for overlaps with a single query.  Times overlapping any other time (including
+/// others in the batch) are taken back out, and their indexes in `times` are returned.  Unusual times fail the whole
+/// batch unless they're allowed.
+#[tracing::instrument(level = "debug", skip_all, fields(rows = times.len(), rejected))]
+pub fn insert_times(
+    tx: &mut Transaction,
+    times: &[TimeWindow],
+    allow_unusual_times: &bool,
+) -> Result<Vec<usize>, TTError> {
+    check_plausible_times(tx, times, allow_unusual_times)?;
+    let mut inserted = BTreeMap::new();
+    {
+        let mut stmt = tx.prepare_cached(
+            "INSERT INTO times (category, start_time, end_time, note) VALUES (?, ?, ?, ?)",
+        )?;
+        for (index, time) in times.iter().enumerate() {
+            stmt.execute((&time.category, time.start_time, time.end_time, &time.note))?;
+            inserted.insert(tx.last_insert_rowid(), index);
+        }
+    }
+    let first = match inserted.keys().next() {
+        Some(id) => *id,
+        None => return Ok(vec![]),
+    };
+    //sorted by start, a time overlaps another if it starts before an earlier one ends or ends after the next one starts
+    //(open times never end)
+    let mut stmt = tx.prepare(
+        "SELECT id FROM (
+            SELECT id, start_time, COALESCE(end_time, 9223372036854775807) AS end_time,
+                MAX(COALESCE(end_time, 9223372036854775807)) OVER (
+                    ORDER BY start_time, id ROWS BETWEEN UNBOUNDED PRECEDING AND 1 PRECEDING
+                ) AS earlier_end,
+                LEAD(start_time) OVER (ORDER BY start_time, id) AS next_start
+            FROM times
+        )
+        WHERE id >= ? AND (start_time <= earlier_end OR next_start <= end_time)",
+    )?;
+    let overlapping = stmt
+        .query_map((first,), |row| row.get::<_, i64>(0))?
+        .collect::<Result<Vec<i64>, _>>()?;
+    drop(stmt);
+    let mut rejected = vec![];
+    for id in overlapping {
+        tx.execute("DELETE FROM times WHERE id=?", (id,))?;
+        rejected.extend(inserted.get(&id));
+    }
+    rejected.sort();
+    tracing::Span::current().record("rejected", rejected.len());
+    Ok(rejected)
 }
 
 pub fn get_last_open_time(tx: &Transaction) -> Result<Option<TimeWindow>, TTError> {
-    let mut stmt =
-        tx.prepare("SELECT * FROM times WHERE end_time IS NULL ORDER BY start_time DESC LIMIT 1")?;
+    let mut stmt = tx.prepare_cached(
+        "SELECT * FROM times WHERE end_time IS NULL ORDER BY start_time DESC LIMIT 1",
+    )?;
     let mut rows = stmt.query(())?;
     if let Some(row) = rows.next()? {
         Ok(Some(TimeWindow {
@@ -292,6 +899,7 @@ pub fn get_last_open_time(tx: &Transaction) -> Result<Option<TimeWindow>, TTErro
             category: row.get("category").unwrap(),
             start_time: row.get("start_time").unwrap(),
             end_time: row.get("end_time").unwrap(),
+            note: row.get("note").unwrap(),
         }))
     } else {
         Ok(None)
@@ -316,24 +924,44 @@ pub fn parse_time(time_string: &String) -> Result<HourMinute, TTError> {
             .unwrap();
 
         if hour > 23 {
-            return Err(TTError::TTError {
-                message: format!("Got hour={}, but hour must be 0-23", hour),
+            return Err(TTError::ParseTime {
+                input: time_string.clone(),
+                reason: format!("got hour={}, but hour must be 0-23", hour),
             });
         } else if minute > 59 {
-            return Err(TTError::TTError {
-                message: format!("Got minute={}, but minute must be 0-59", minute),
+            return Err(TTError::ParseTime {
+                input: time_string.clone(),
+                reason: format!("got minute={}, but minute must be 0-59", minute),
             });
         } else {
             return Ok(HourMinute(hour, minute));
         }
     } else {
-        return Err(TTError::TTError {
-            message: "Time must a 24-hour time formatted like HH:MM (i.e. 10:30, 09:15, 8:00, etc)"
+        return Err(TTError::ParseTime {
+            input: time_string.clone(),
+            reason: "time must a 24-hour time formatted like HH:MM (i.e. 10:30, 09:15, 8:00, etc)"
                 .to_string(),
         });
     }
 }
 
+///The first end-of-business after a time started at `start_time`, on the wall clock in `zone`.  Each day's
+/// end-of-business gets that day's UTC offset, so times spanning a DST change end at the right hour; if DST skips
+/// over end-of-business, it's as long after midnight as end-of-business usually is.
+pub fn end_of_business_after(start_time: i64, end_of_business: &HourMinute, zone: &Zone) -> i64 {
+    let on = |day: NaiveDate| {
+        day.and_hms_opt(end_of_business.0, end_of_business.1, 0)
+            .and_then(|wall_clock| zone.timestamp(&wall_clock))
+            .unwrap_or(zone.midnight(day) + end_of_business.seconds_since_midnight())
+    };
+    let start_day = zone.date(&start_time);
+    match on(start_day) {
+        //started after end-of-business, so it ends the next day
+        end if end <= start_time => on(start_day.succ_opt().unwrap_or(start_day)),
+        end => end,
+    }
+}
+
 ///End any times which don't have a recorded end time.
 /// End times are set to the lesser of <current time> <next EOB (relative to start time)>
 pub fn end_open_times(tx: &mut Transaction, end_of_business: HourMinute) -> Result<(), TTError> {
@@ -345,35 +973,28 @@ pub fn end_open_times(tx: &mut Transaction, end_of_business: HourMinute) -> Resu
 
         while let Some(row) = results.next()? {
             let mut logged_time = row_to_time_window(row)?;
-            let start_date: DateTime<chrono::Local> = DateTime::from_utc(
-                NaiveDateTime::from_timestamp(logged_time.start_time, 0),
-                *chrono::Local::now().offset(),
-            );
-
-            //calculate the first EOB datetime that is AFTER the logged start time
-            //set the hour and minute to EOB
-            let mut end_date = start_date
-                .clone()
-                .with_hour(end_of_business.0)
-                .unwrap()
-                .with_minute(end_of_business.1)
-                .unwrap();
-            //if the end_date is before the start date (i.e. if the hour/minute of the start time is AFTER EOB)
-            //then bump out the date by one day for the end time
-            if end_date <= start_date {
-                end_date += chrono::Duration::days(1);
-            }
+            let end_date =
+                end_of_business_after(logged_time.start_time, &end_of_business, &Zone::Local);
 
             let now_date = chrono::Local::now();
 
-            logged_time.end_time = Some(std::cmp::min(end_date, now_date).timestamp());
+            logged_time.end_time = Some(std::cmp::min(end_date, now_date.timestamp()));
+            tracing::debug!(
+                "ending time {} ({}) at {}: the first end-of-day ({}) after it started is {}, and it's now {}",
+                logged_time.id.unwrap_or_default(),
+                logged_time.category,
+                Zone::Local.rfc3339(&logged_time.end_time.unwrap()),
+                end_of_business,
+                Zone::Local.rfc3339(&end_date),
+                now_date.to_rfc3339()
+            );
 
             updated_times.push(logged_time);
         }
     }
 
     for time in updated_times {
-        upsert_time(tx, time)?;
+        upsert_time(tx, time, &false)?;
     }
 
     Ok(())
@@ -390,7 +1011,11 @@ pub fn end_open_times_immediately(tx: &mut Transaction) -> Result<(), TTError> {
     return Ok(());
 }
 
-pub fn start_timing(tx: &mut Transaction, category: &String) -> Result<(), TTError> {
+pub fn start_timing(
+    tx: &mut Transaction,
+    category: &String,
+    note: &Option<String>,
+) -> Result<(), TTError> {
     upsert_time(
         tx,
         TimeWindow {
@@ -398,62 +1023,584 @@ pub fn start_timing(tx: &mut Transaction, category: &String) -> Result<(), TTErr
             category: category.clone(),
             start_time: SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs() as i64,
             end_time: None,
+            note: note.clone(),
         },
+        &false,
     )
 }
 
-pub fn delete_time(tx: &mut Transaction, id: &i64) -> Result<usize, TTError> {
-    Ok(tx.execute("DELETE FROM times WHERE id=?", (id,))?)
+pub fn delete_time(tx: &mut Transaction, id: &i64) -> Result<usize, TTError> {
+    Ok(tx.execute("DELETE FROM times WHERE id=?", (id,))?)
+}
+
+///Times streamed straight from the DB, see iter_times
+pub type TimeIter<'a> = dyn Iterator<Item = Result<TimeWindow, TTError>> + 'a;
+
+///Fetch times between start_date and end_date.
+/// With FilterMode::Start, only times which _start_ inside the window are returned.
+/// With FilterMode::Overlap, any time which overlaps the window at all is returned (unclipped, see TimeWindow::clipped)
+#[tracing::instrument(level = "debug", skip(tx), fields(rows))]
+pub fn get_times(
+    tx: &mut Transaction,
+    start_date: Option<i64>,
+    end_date: Option<i64>,
+    mode: &cli::FilterMode,
+) -> Result<Vec<TimeWindow>, TTError> {
+    let times: Vec<TimeWindow> = iter_times(
+        tx,
+        start_date,
+        end_date,
+        mode,
+        &cli::Page::default(),
+        |times| times.collect(),
+    )?;
+    tracing::Span::current().record("rows", times.len());
+    Ok(times)
+}
+
+///Like get_times, but hands `read` the times one row at a time as they're read from the DB, so exports of big
+/// databases don't have to hold every time in memory.  Times come in `page`'s order, starting after `page.after`
+/// (the offset and limit are left to the reader, since it may filter times further).
+pub fn iter_times<T>(
+    tx: &Transaction,
+    start_date: Option<i64>,
+    end_date: Option<i64>,
+    mode: &cli::FilterMode,
+    page: &cli::Page,
+    read: impl FnOnce(&mut TimeIter) -> Result<T, TTError>,
+) -> Result<T, TTError> {
+    let mut clauses = Vec::<&str>::new();
+    let mut values: Vec<&dyn ToSql> = vec![];
+    let mut where_clause = String::new();
+    if let Some(start) = &start_date {
+        match mode {
+            cli::FilterMode::Start => clauses.push("start_time >= ?"),
+            cli::FilterMode::Overlap => clauses.push("(end_time IS NULL OR end_time >= ?)"),
+        }
+        values.push(start);
+    }
+    if let Some(end) = &end_date {
+        clauses.push("start_time <= ?");
+        values.push(end);
+    }
+    let (direction, after) = match page.order {
+        cli::Order::Asc => ("ASC", ">"),
+        cli::Order::Desc => ("DESC", "<"),
+    };
+    let after_clause = format!(
+        "(start_time, id) {} (SELECT start_time, id FROM times WHERE id = ?)",
+        after
+    );
+    if let Some(id) = &page.after {
+        if let Err(TTError::Sql(rusqlite::Error::QueryReturnedNoRows)) = get_time(tx, *id) {
+            return Err(TTError::TimeNotFound { id: *id });
+        }
+        clauses.push(&after_clause);
+        values.push(id);
+    }
+
+    if !values.is_empty() {
+        where_clause = format!("WHERE {}", clauses.join(" AND "));
+    }
+
+    let mut stmt = tx.prepare_cached(&format!(
+        "SELECT id, category, start_time, end_time, note FROM times {} ORDER BY start_time {}, id {}",
+        where_clause, direction, direction
+    ))?;
+
+    for i in 1..(values.len() + 1) {
+        stmt.raw_bind_parameter(i, values.get(i - 1).unwrap())?;
+    }
+    let mut rows = stmt
+        .raw_query()
+        .mapped(row_to_time_window)
+        .map(|row| row.map_err(TTError::from));
+    read(&mut rows)
+}
+
+///Get the times that have been synced with a service, as they were when they were last synced
+pub fn get_synced_times(tx: &Transaction, service: &str) -> Result<Vec<SyncedTime>, TTError> {
+    let mut stmt = tx.prepare(
+        "SELECT remote_id, time_id, category, start_time, end_time FROM synced_times WHERE service=?",
+    )?;
+    let rows = stmt.query_map((service,), |row| {
+        Ok(SyncedTime {
+            remote_id: row.get("remote_id")?,
+            time_id: row.get("time_id")?,
+            category: row.get("category")?,
+            start_time: row.get("start_time")?,
+            end_time: row.get("end_time")?,
+        })
+    })?;
+    let mut synced = vec![];
+    for row in rows {
+        synced.push(row?);
+    }
+    Ok(synced)
+}
+
+pub fn set_synced_time(
+    tx: &Transaction,
+    service: &str,
+    synced: &SyncedTime,
+) -> Result<(), TTError> {
+    tx.execute(
+        "REPLACE INTO synced_times (service, remote_id, time_id, category, start_time, end_time) VALUES (?, ?, ?, ?, ?, ?)",
+        (
+            service,
+            &synced.remote_id,
+            synced.time_id,
+            &synced.category,
+            synced.start_time,
+            synced.end_time,
+        ),
+    )?;
+    Ok(())
+}
+
+pub fn delete_synced_time(tx: &Transaction, service: &str, remote_id: &str) -> Result<(), TTError> {
+    tx.execute(
+        "DELETE FROM synced_times WHERE service=? AND remote_id=?",
+        (service, remote_id),
+    )?;
+    Ok(())
+}
+
+///When a service was last synced successfully
+pub fn get_last_synced(tx: &Transaction, service: &str) -> Result<Option<i64>, TTError> {
+    match tx.query_row(
+        "SELECT last_synced FROM sync_state WHERE service=?",
+        (service,),
+        |row| row.get(0),
+    ) {
+        Ok(last_synced) => Ok(Some(last_synced)),
+        Err(rusqlite::Error::QueryReturnedNoRows) => Ok(None),
+        Err(e) => Err(e.into()),
+    }
+}
+
+pub fn set_last_synced(tx: &Transaction, service: &str, last_synced: i64) -> Result<(), TTError> {
+    tx.execute(
+        "REPLACE INTO sync_state (service, last_synced) VALUES (?, ?)",
+        (service, last_synced),
+    )?;
+    Ok(())
+}
+
+///Get the events that times have been pushed to in a calendar
+pub fn get_pushed_events(tx: &Transaction, calendar: &str) -> Result<Vec<PushedEvent>, TTError> {
+    let mut stmt = tx.prepare(
+        "SELECT time_id, event_id, etag, category, start_time, end_time FROM pushed_events WHERE calendar=?",
+    )?;
+    let rows = stmt.query_map((calendar,), |row| {
+        Ok(PushedEvent {
+            time_id: row.get("time_id")?,
+            event_id: row.get("event_id")?,
+            etag: row.get("etag")?,
+            category: row.get("category")?,
+            start_time: row.get("start_time")?,
+            end_time: row.get("end_time")?,
+        })
+    })?;
+    let mut pushed = vec![];
+    for row in rows {
+        pushed.push(row?);
+    }
+    Ok(pushed)
+}
+
+pub fn set_pushed_event(
+    tx: &Transaction,
+    calendar: &str,
+    pushed: &PushedEvent,
+) -> Result<(), TTError> {
+    tx.execute(
+        "REPLACE INTO pushed_events (calendar, time_id, event_id, etag, category, start_time, end_time) VALUES (?, ?, ?, ?, ?, ?, ?)",
+        (
+            calendar,
+            pushed.time_id,
+            &pushed.event_id,
+            &pushed.etag,
+            &pushed.category,
+            pushed.start_time,
+            pushed.end_time,
+        ),
+    )?;
+    Ok(())
+}
+
+pub fn delete_pushed_event(tx: &Transaction, calendar: &str, time_id: i64) -> Result<(), TTError> {
+    tx.execute(
+        "DELETE FROM pushed_events WHERE calendar=? AND time_id=?",
+        (calendar, time_id),
+    )?;
+    Ok(())
+}
+
+///Attach a bit of context (like the git branch being worked on) to a time, annotations a time already has aren't repeated
+pub fn add_annotation(tx: &Transaction, time_id: i64, annotation: &str) -> Result<(), TTError> {
+    tx.execute(
+        "INSERT OR IGNORE INTO annotations (time_id, annotation) VALUES (?, ?)",
+        (time_id, annotation),
+    )?;
+    Ok(())
+}
+
+///Annotations for each time that has any, in the order they were added
+pub fn get_annotations(tx: &Transaction) -> Result<BTreeMap<i64, Vec<String>>, TTError> {
+    let mut stmt =
+        tx.prepare("SELECT time_id, annotation FROM annotations ORDER BY time_id, rowid")?;
+    let rows = stmt.query_map((), |row| Ok((row.get(0)?, row.get(1)?)))?;
+    let mut annotations: BTreeMap<i64, Vec<String>> = BTreeMap::new();
+    for row in rows {
+        let (time_id, annotation) = row?;
+        annotations.entry(time_id).or_default().push(annotation);
+    }
+    Ok(annotations)
+}
+
+///A tag as it's stored, without the `#` it can be written with (like `#client-a`)
+pub fn parse_tag(tag: &str) -> Result<String, TTError> {
+    let name = tag.strip_prefix('#').unwrap_or(tag);
+    match name.is_empty() || name.contains(|c: char| c.is_whitespace() || c == ',' || c == '#') {
+        true => Err(TTError::InvalidArgument {
+            message: format!(
+                "\"{}\" isn't a tag, tags can't be empty or have spaces, commas, or #s in them",
+                tag
+            ),
+        }),
+        false => Ok(name.to_string()),
+    }
+}
+
+///Tag a time, tags it already has are left alone.  Returns how many tags were added.
+pub fn tag_time(tx: &Transaction, time_id: i64, tags: &[String]) -> Result<usize, TTError> {
+    get_time(tx, time_id).map_err(|_| TTError::TimeNotFound { id: time_id })?;
+    let mut added = 0;
+    for tag in tags {
+        tx.execute("INSERT OR IGNORE INTO tags (name) VALUES (?)", (tag,))?;
+        added += tx.execute(
+            "INSERT OR IGNORE INTO time_tags (time_id, tag) VALUES (?, ?)",
+            (time_id, tag),
+        )?;
+    }
+    Ok(added)
+}
+
+///Take tags off a time, returning how many it had
+pub fn untag_time(tx: &Transaction, time_id: i64, tags: &[String]) -> Result<usize, TTError> {
+    get_time(tx, time_id).map_err(|_| TTError::TimeNotFound { id: time_id })?;
+    let mut removed = 0;
+    for tag in tags {
+        removed += tx.execute(
+            "DELETE FROM time_tags WHERE time_id = ? AND tag = ?",
+            (time_id, tag),
+        )?;
+    }
+    Ok(removed)
+}
+
+///Tags for each time that has any, alphabetically
+pub fn get_tags(tx: &Transaction) -> Result<BTreeMap<i64, Vec<String>>, TTError> {
+    let mut stmt = tx.prepare("SELECT time_id, tag FROM time_tags ORDER BY time_id, tag")?;
+    let rows = stmt.query_map((), |row| Ok((row.get(0)?, row.get(1)?)))?;
+    let mut tags: BTreeMap<i64, Vec<String>> = BTreeMap::new();
+    for row in rows {
+        let (time_id, tag) = row?;
+        tags.entry(time_id).or_default().push(tag);
+    }
+    Ok(tags)
+}
+
+///Ids of the times with any of `tags`
+pub fn get_tagged(tx: &Transaction, tags: &[String]) -> Result<BTreeSet<i64>, TTError> {
+    let mut stmt = tx.prepare_cached(
+        "SELECT DISTINCT time_id FROM time_tags WHERE tag IN (SELECT value FROM json_each(?))",
+    )?;
+    let rows = stmt.query_map((serde_json::to_string(tags)?,), |row| row.get(0))?;
+    Ok(rows.collect::<Result<BTreeSet<i64>, _>>()?)
+}
+
+///Add a pending time unless one from the same source was already added, returns whether it was added
+pub fn add_pending_time(tx: &Transaction, pending: &PendingTime) -> Result<bool, TTError> {
+    let added = tx.execute(
+        "INSERT OR IGNORE INTO pending_times (source, source_id, title, category, start_time, end_time) VALUES (?, ?, ?, ?, ?, ?)",
+        (
+            &pending.source,
+            &pending.source_id,
+            &pending.title,
+            &pending.category,
+            pending.start_time,
+            pending.end_time,
+        ),
+    )?;
+    Ok(added > 0)
+}
+
+fn row_to_pending_time(row: &Row) -> Result<PendingTime, rusqlite::Error> {
+    Ok(PendingTime {
+        id: row.get("id")?,
+        source: row.get("source")?,
+        source_id: row.get("source_id")?,
+        title: row.get("title")?,
+        category: row.get("category")?,
+        start_time: row.get("start_time")?,
+        end_time: row.get("end_time")?,
+    })
+}
+
+///Pending times that haven't been confirmed or dismissed, oldest first
+pub fn get_pending_times(tx: &Transaction) -> Result<Vec<PendingTime>, TTError> {
+    let mut stmt =
+        tx.prepare("SELECT * FROM pending_times WHERE resolved=0 ORDER BY start_time ASC")?;
+    let rows = stmt.query_map((), row_to_pending_time)?;
+    let mut pending = vec![];
+    for row in rows {
+        pending.push(row?);
+    }
+    Ok(pending)
+}
+
+pub fn get_pending_time(tx: &Transaction, id: i64) -> Result<PendingTime, TTError> {
+    match tx.query_row(
+        "SELECT * FROM pending_times WHERE id=? AND resolved=0",
+        (id,),
+        row_to_pending_time,
+    ) {
+        Ok(pending) => Ok(pending),
+        Err(rusqlite::Error::QueryReturnedNoRows) => Err(TTError::NotFound {
+            kind: "pending time",
+            id,
+        }),
+        Err(e) => Err(e.into()),
+    }
+}
+
+///Mark a pending time as confirmed or dismissed
+pub fn resolve_pending_time(tx: &Transaction, id: i64) -> Result<(), TTError> {
+    tx.execute("UPDATE pending_times SET resolved=1 WHERE id=?", (id,))?;
+    Ok(())
+}
+
+pub fn add_webhook(tx: &Transaction, url: &str, secret: &Option<String>) -> Result<i64, TTError> {
+    tx.execute(
+        "INSERT INTO webhooks (url, secret) VALUES (?, ?)",
+        (url, secret),
+    )?;
+    Ok(tx.last_insert_rowid())
+}
+
+///Delete a webhook (and anything it hasn't delivered yet), returning whether it existed
+pub fn delete_webhook(tx: &Transaction, id: i64) -> Result<bool, TTError> {
+    Ok(tx.execute("DELETE FROM webhooks WHERE id=?", (id,))? > 0)
+}
+
+pub fn get_webhooks(tx: &Transaction) -> Result<Vec<Webhook>, TTError> {
+    let mut stmt = tx.prepare("SELECT * FROM webhooks ORDER BY id")?;
+    let rows = stmt.query_map((), |row| {
+        Ok(Webhook {
+            id: row.get("id")?,
+            url: row.get("url")?,
+            secret: row.get("secret")?,
+        })
+    })?;
+    let mut webhooks = vec![];
+    for row in rows {
+        webhooks.push(row?);
+    }
+    Ok(webhooks)
+}
+
+pub fn add_auto_start(
+    tx: &Transaction,
+    days: &str,
+    time: &str,
+    category: &str,
+) -> Result<i64, TTError> {
+    tx.execute(
+        "INSERT INTO auto_starts (days, time, category) VALUES (?, ?, ?)",
+        (days, time, category),
+    )?;
+    Ok(tx.last_insert_rowid())
+}
+
+///Delete an auto-start rule, returning whether it existed
+pub fn delete_auto_start(tx: &Transaction, id: i64) -> Result<bool, TTError> {
+    Ok(tx.execute("DELETE FROM auto_starts WHERE id=?", (id,))? > 0)
+}
+
+pub fn get_auto_starts(tx: &Transaction) -> Result<Vec<AutoStart>, TTError> {
+    let mut stmt = tx.prepare("SELECT * FROM auto_starts ORDER BY time, id")?;
+    let rows = stmt.query_map((), |row| {
+        Ok(AutoStart {
+            id: row.get("id")?,
+            days: row.get("days")?,
+            time: row.get("time")?,
+            category: row.get("category")?,
+        })
+    })?;
+    let mut auto_starts = vec![];
+    for row in rows {
+        auto_starts.push(row?);
+    }
+    Ok(auto_starts)
+}
+
+pub fn add_window_rule(tx: &Transaction, pattern: &str, category: &str) -> Result<i64, TTError> {
+    tx.execute(
+        "INSERT INTO window_rules (pattern, category) VALUES (?, ?)",
+        (pattern, category),
+    )?;
+    Ok(tx.last_insert_rowid())
+}
+
+///Delete a window rule, returning whether it existed
+pub fn delete_window_rule(tx: &Transaction, id: i64) -> Result<bool, TTError> {
+    Ok(tx.execute("DELETE FROM window_rules WHERE id=?", (id,))? > 0)
+}
+
+pub fn get_window_rules(tx: &Transaction) -> Result<Vec<WindowRule>, TTError> {
+    let mut stmt = tx.prepare("SELECT * FROM window_rules ORDER BY id")?;
+    let rows = stmt.query_map((), |row| {
+        Ok(WindowRule {
+            id: row.get("id")?,
+            pattern: row.get("pattern")?,
+            category: row.get("category")?,
+        })
+    })?;
+    let mut rules = vec![];
+    for row in rows {
+        rules.push(row?);
+    }
+    Ok(rules)
+}
+
+///Bind a key combination to an action, replacing whatever it did before
+pub fn set_hotkey(tx: &Transaction, hotkey: &Hotkey) -> Result<(), TTError> {
+    tx.execute(
+        "INSERT INTO hotkeys (keypress, action, category) VALUES (?, ?, ?)
+            ON CONFLICT(keypress) DO UPDATE SET action=excluded.action, category=excluded.category",
+        (&hotkey.keypress, &hotkey.action, &hotkey.category),
+    )?;
+    Ok(())
+}
+
+///Unbind a key combination, returning whether it was bound
+pub fn delete_hotkey(tx: &Transaction, keypress: &str) -> Result<bool, TTError> {
+    Ok(tx.execute("DELETE FROM hotkeys WHERE keypress=?", (keypress,))? > 0)
 }
 
-pub fn get_times(
-    tx: &mut Transaction,
-    start_date: Option<i64>,
-    end_date: Option<i64>,
-) -> Result<Vec<TimeWindow>, TTError> {
-    let mut clauses = Vec::<&str>::new();
-    let mut values: Vec<&dyn ToSql> = vec![];
-    let mut where_clause = String::new();
-    if let Some(start) = &start_date {
-        clauses.push("start_time >= ?");
-        values.push(start);
-    }
-    if let Some(end) = &end_date {
-        clauses.push("start_time <= ?");
-        values.push(end);
+pub fn get_hotkeys(tx: &Transaction) -> Result<Vec<Hotkey>, TTError> {
+    let mut stmt = tx.prepare("SELECT * FROM hotkeys ORDER BY keypress")?;
+    let rows = stmt.query_map((), |row| {
+        Ok(Hotkey {
+            keypress: row.get("keypress")?,
+            action: row.get("action")?,
+            category: row.get("category")?,
+        })
+    })?;
+    let mut hotkeys = vec![];
+    for row in rows {
+        hotkeys.push(row?);
     }
+    Ok(hotkeys)
+}
 
-    if values.len() > 0 {
-        where_clause = format!("WHERE {}", clauses.join(" AND "));
+///The most recently started time that's been stopped
+pub fn get_last_closed_time(tx: &Transaction) -> Result<Option<TimeWindow>, TTError> {
+    let mut stmt = tx.prepare(
+        "SELECT * FROM times WHERE end_time IS NOT NULL ORDER BY start_time DESC LIMIT 1",
+    )?;
+    let mut rows = stmt.query(())?;
+    if let Some(row) = rows.next()? {
+        Ok(Some(TimeWindow {
+            id: Some(row.get("id")?),
+            category: row.get("category")?,
+            start_time: row.get("start_time")?,
+            end_time: row.get("end_time")?,
+            note: row.get("note")?,
+        }))
+    } else {
+        Ok(None)
     }
+}
 
-    let mut stmt = tx.prepare(&format!(
-        "SELECT id, category, start_time, end_time FROM times {}",
-        where_clause
-    ))?;
+///Queue an event for every webhook, due for delivery right away
+pub fn enqueue_webhook_event(tx: &Transaction, payload: &str, now: i64) -> Result<(), TTError> {
+    tx.execute(
+        "INSERT INTO webhook_outbox (webhook_id, payload, next_attempt) SELECT id, ?, ? FROM webhooks",
+        (payload, now),
+    )?;
+    Ok(())
+}
 
-    for i in 1..(values.len() + 1) {
-        stmt.raw_bind_parameter(i, values.get(i - 1).unwrap())?;
+///Queued events due to be (re)tried by `now`, oldest first
+pub fn get_due_webhook_deliveries(
+    tx: &Transaction,
+    now: i64,
+) -> Result<Vec<WebhookDelivery>, TTError> {
+    let mut stmt = tx.prepare(
+        "SELECT o.id, o.payload, o.attempts, w.id AS webhook_id, w.url, w.secret
+            FROM webhook_outbox o JOIN webhooks w ON o.webhook_id = w.id
+            WHERE o.next_attempt <= ? ORDER BY o.id",
+    )?;
+    let rows = stmt.query_map((now,), |row| {
+        Ok(WebhookDelivery {
+            id: row.get("id")?,
+            webhook: Webhook {
+                id: row.get("webhook_id")?,
+                url: row.get("url")?,
+                secret: row.get("secret")?,
+            },
+            payload: row.get("payload")?,
+            attempts: row.get("attempts")?,
+        })
+    })?;
+    let mut deliveries = vec![];
+    for row in rows {
+        deliveries.push(row?);
     }
-    let rows = stmt.raw_query().mapped(|row| row_to_time_window(row));
-    let mut times: Vec<TimeWindow> = Vec::new();
+    Ok(deliveries)
+}
 
+///Number of events still waiting to be delivered to each webhook
+pub fn get_webhook_backlog(tx: &Transaction) -> Result<BTreeMap<i64, i64>, TTError> {
+    let mut stmt =
+        tx.prepare("SELECT webhook_id, COUNT(*) FROM webhook_outbox GROUP BY webhook_id")?;
+    let rows = stmt.query_map((), |row| Ok((row.get(0)?, row.get(1)?)))?;
+    let mut backlog = BTreeMap::new();
     for row in rows {
-        times.push(row?)
+        let (id, count) = row?;
+        backlog.insert(id, count);
     }
+    Ok(backlog)
+}
+
+pub fn delete_webhook_delivery(tx: &Transaction, id: i64) -> Result<(), TTError> {
+    tx.execute("DELETE FROM webhook_outbox WHERE id=?", (id,))?;
+    Ok(())
+}
 
-    return Ok(times);
+///Record a failed delivery, to be tried again at `next_attempt`
+pub fn retry_webhook_delivery(
+    tx: &Transaction,
+    id: i64,
+    next_attempt: i64,
+    error: &str,
+) -> Result<(), TTError> {
+    tx.execute(
+        "UPDATE webhook_outbox SET attempts = attempts + 1, next_attempt = ?, last_error = ? WHERE id=?",
+        (next_attempt, error, id),
+    )?;
+    Ok(())
 }
 
 pub fn rename_category(tx: &mut Transaction, old: &String, new: &String) -> Result<(), TTError> {
     let categories = get_categories(tx)?;
 
     if !categories.contains(old) {
-        return Err(TTError::TTError {
-            message: format!(
-                "Category \"{0}\" cannot be renamed to \"{1}\" because \"{0}\" does not exist",
-                old, new
-            ),
+        return Err(TTError::CategoryNotFound {
+            category: old.clone(),
         });
     }
 
@@ -466,14 +1613,16 @@ pub fn rename_category(tx: &mut Transaction, old: &String, new: &String) -> Resu
     Ok(())
 }
 
+///Delete times in a window, only those with one of `tags` if there are any
 pub fn bulk_delete_times(
     tx: &mut Transaction,
     start_time: &i64,
     end_time: &i64,
     non_inclusive: &bool,
-) -> Result<usize, TTError> {
-    if !(end_time > start_time) {
-        return Err(TTError::TTError {
+    tags: &[String],
+) -> Result<Vec<TimeWindow>, TTError> {
+    if end_time <= start_time {
+        return Err(TTError::InvalidArgument {
             message: format!(
                 "end time ({}) must be greater than start time ({})",
                 end_time, start_time
@@ -487,13 +1636,26 @@ pub fn bulk_delete_times(
             THEN (start_time >= :start AND end_time <= :end) 
             -- default case - any time whose start or end is inside the window
             ELSE (start_time >= :start AND start_time <= :end) OR (end_time >= :start AND end_time <= :end) 
-            END")?;
-    let rows_deleted = stmt.execute(named_params! {
-        ":non_inclusive": non_inclusive,
-        ":start": start_time,
-        ":end": end_time
-    })?;
-    Ok(rows_deleted)
+            END
+            AND (:untagged OR id IN (
+                SELECT time_id FROM time_tags WHERE tag IN (SELECT value FROM json_each(:tags))
+            ))
+        RETURNING *")?;
+    let rows = stmt.query_map(
+        named_params! {
+            ":non_inclusive": non_inclusive,
+            ":start": start_time,
+            ":end": end_time,
+            ":untagged": tags.is_empty(),
+            ":tags": serde_json::to_string(tags)?,
+        },
+        row_to_time_window,
+    )?;
+    let mut deleted = vec![];
+    for row in rows {
+        deleted.push(row?);
+    }
+    Ok(deleted)
 }
 
 #[cfg(test)]
@@ -501,7 +1663,7 @@ mod tests {
 
     use std::time::Duration;
 
-    use chrono::NaiveDate;
+    use chrono::{NaiveDate, Offset};
     use rusqlite::Connection;
 
     use super::*;
@@ -517,11 +1679,11 @@ mod tests {
         let mut conn = get_initialized_db();
         {
             let mut tx = conn.transaction().unwrap();
-            assert!(start_timing(&mut tx, &"work".to_string()).is_err());
+            assert!(start_timing(&mut tx, &"work".to_string(), &None).is_err());
 
-            add_category(&mut tx, &"work".to_string()).unwrap();
+            add_category(&tx, &"work".to_string()).unwrap();
 
-            assert!(start_timing(&mut tx, &"work".to_string()).is_ok());
+            assert!(start_timing(&mut tx, &"work".to_string(), &None).is_ok());
             let mut time = get_time(&tx, 1).unwrap();
             assert_eq!(Some(1), time.id);
             assert_eq!("work".to_string(), time.category);
@@ -537,13 +1699,18 @@ mod tests {
             assert!(time.end_time.unwrap() > time.start_time);
 
             //un-set the end time
-            let start_datetime = DateTime::<chrono::Local>::from_local(
-                NaiveDate::from_ymd(2020, 12, 31).and_hms(12, 12, 0),
-                *chrono::Local::now().offset(),
+            let offset = *chrono::Local::now().offset();
+            let start_datetime = chrono::DateTime::<chrono::Local>::from_naive_utc_and_offset(
+                NaiveDate::from_ymd_opt(2020, 12, 31)
+                    .unwrap()
+                    .and_hms_opt(12, 12, 0)
+                    .unwrap()
+                    - offset.fix(),
+                offset,
             );
             time.end_time = None;
             time.start_time = start_datetime.timestamp();
-            upsert_time(&mut tx, time).unwrap();
+            upsert_time(&mut tx, time, &true).unwrap();
 
             end_open_times(&mut tx, HourMinute(13, 0)).unwrap();
 
@@ -563,7 +1730,7 @@ mod tests {
             //What if EOB is less than start time
             time = get_time(&tx, 1).unwrap();
             time.end_time = None;
-            upsert_time(&mut tx, time).unwrap();
+            upsert_time(&mut tx, time, &true).unwrap();
             end_open_times(&mut tx, HourMinute(11, 0)).unwrap();
             time = get_time(&tx, 1).unwrap();
             //should have been ended at EOB the next day
@@ -588,7 +1755,7 @@ mod tests {
                 eob.0 = 23
             }
             time.start_time = start_datetime.timestamp();
-            upsert_time(&mut tx, time).unwrap();
+            upsert_time(&mut tx, time, &true).unwrap();
             end_open_times(&mut tx, eob).unwrap();
             time = get_time(&tx, 1).unwrap();
             //should have been ended nowish not EOB
@@ -604,6 +1771,96 @@ mod tests {
         assert_eq!("12:12", HourMinute(12, 12).to_string());
     }
 
+    #[test]
+    fn test_check_plausible() {
+        let now = 1667307600;
+        let mut options = Options::new();
+        assert!(check_plausible(&options, now - 1000, now).is_ok());
+        assert!(check_plausible(&options, now + 30, now).is_ok());
+        assert!(matches!(
+            check_plausible(&options, now + 3600, now),
+            Err(TTError::UnusualTime { .. })
+        ));
+        //a day in 1999
+        assert!(check_plausible(&options, 936000000, now).is_err());
+        options.insert("earliest-time".to_string(), "1990-01-01".to_string());
+        assert!(check_plausible(&options, 936000000, now).is_ok());
+        assert!(check_plausible(&options, 0, now).is_err());
+    }
+
+    #[test]
+    fn test_unusual_times_refused() {
+        let mut conn = Connection::open_in_memory().unwrap();
+        initialize_db(&mut conn).unwrap();
+        let mut tx = conn.transaction().unwrap();
+        add_category(&tx, &"work".to_string()).unwrap();
+        let time = |start_time, end_time| TimeWindow {
+            id: None,
+            category: "work".to_string(),
+            start_time,
+            end_time: Some(end_time),
+            note: None,
+        };
+        //a day in 1999
+        assert!(matches!(
+            upsert_time(&mut tx, time(936000000, 936003600), &false),
+            Err(TTError::UnusualTime { .. })
+        ));
+        upsert_time(&mut tx, time(936000000, 936003600), &true).unwrap();
+        //the unusual start of an existing time doesn't get in the way of amending the rest of it
+        let mut amended = get_time(&tx, 1).unwrap();
+        amended.end_time = Some(936007200);
+        assert!(upsert_time(&mut tx, amended.clone(), &false).is_err());
+        amended.end_time = Some(1667307600);
+        upsert_time(&mut tx, amended, &false).unwrap();
+
+        let times = [time(1667311200, 1667314800), time(3000000000, 3000003600)];
+        assert!(insert_times(&mut tx, &times, &false).is_err());
+        assert!(insert_times(&mut tx, &times, &true).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_end_of_business_after() {
+        let zone: Zone = "America/New_York".parse().unwrap();
+        let at = |month, day, hour, minute, second| {
+            zone.timestamp(
+                &NaiveDate::from_ymd_opt(2024, month, day)
+                    .unwrap()
+                    .and_hms_opt(hour, minute, second)
+                    .unwrap(),
+            )
+            .unwrap()
+        };
+        let eob = HourMinute(17, 0);
+        assert_eq!(
+            at(6, 3, 17, 0, 0),
+            end_of_business_after(at(6, 3, 9, 30, 45), &eob, &zone)
+        );
+        assert_eq!(
+            at(6, 4, 17, 0, 0),
+            end_of_business_after(at(6, 3, 17, 0, 0), &eob, &zone)
+        );
+        assert_eq!(
+            at(6, 4, 17, 0, 0),
+            end_of_business_after(at(6, 3, 22, 0, 0), &eob, &zone)
+        );
+        //started in EST the evening before DST starts (2024-03-10), ended at 17:00 EDT (21:00 UTC)
+        assert_eq!(
+            1710104400,
+            end_of_business_after(at(3, 9, 18, 0, 0), &eob, &zone)
+        );
+        //and in EDT the evening before it ends (2024-11-03), ended at 17:00 EST (22:00 UTC)
+        assert_eq!(
+            1730671200,
+            end_of_business_after(at(11, 2, 18, 0, 0), &eob, &zone)
+        );
+        //02:30 doesn't happen when DST starts, so that day ends at 03:30 EDT (07:30 UTC)
+        assert_eq!(
+            1710055800,
+            end_of_business_after(at(3, 9, 23, 0, 0), &HourMinute(2, 30), &zone)
+        );
+    }
+
     #[test]
     fn test_re() {
         assert!(BUSINESS_HOURS_PATTERN.is_match("00:11"));
@@ -660,36 +1917,31 @@ mod tests {
             HourMinute(23, 59),
             parse_time(&"23:59".to_string()).unwrap()
         );
+        let error = |time: &str| parse_time(&time.to_string()).unwrap_err().to_string();
         assert_eq!(
-            TTError::TTError {
-                message: "Got hour=99, but hour must be 0-23".to_string()
-            },
-            parse_time(&"99:0".to_string()).unwrap_err()
+            "Couldn't parse \"99:0\": got hour=99, but hour must be 0-23",
+            error("99:0")
         );
         assert_eq!(
-            TTError::TTError {
-                message: "Got minute=99, but minute must be 0-59".to_string()
-            },
-            parse_time(&"0:99".to_string()).unwrap_err()
+            "Couldn't parse \"0:99\": got minute=99, but minute must be 0-59",
+            error("0:99")
         );
         assert_eq!(
-            TTError::TTError {
-                message: "Got hour=99, but hour must be 0-23".to_string()
-            },
-            parse_time(&"99:99".to_string()).unwrap_err()
+            "Couldn't parse \"99:99\": got hour=99, but hour must be 0-23",
+            error("99:99")
         );
         assert_eq!(
-            TTError::TTError {
-                message: "Got hour=24, but hour must be 0-23".to_string()
-            },
-            parse_time(&"24:0".to_string()).unwrap_err()
+            "Couldn't parse \"24:0\": got hour=24, but hour must be 0-23",
+            error("24:0")
         );
         assert_eq!(
-            TTError::TTError {
-                message: "Got minute=60, but minute must be 0-59".to_string()
-            },
-            parse_time(&"23:60".to_string()).unwrap_err()
+            "Couldn't parse \"23:60\": got minute=60, but minute must be 0-59",
+            error("23:60")
         );
+        assert!(matches!(
+            parse_time(&"noon".to_string()),
+            Err(TTError::ParseTime { .. })
+        ));
     }
 
     #[test]
@@ -710,7 +1962,9 @@ mod tests {
                     category: "work".to_string(),
                     start_time: 47,
                     end_time: None,
+                    note: None,
                 },
+                &true,
             )
             .unwrap();
 
@@ -719,7 +1973,8 @@ mod tests {
                     id: Some(1),
                     category: "work".to_string(),
                     start_time: 47,
-                    end_time: None
+                    end_time: None,
+                    note: None,
                 }),
                 get_time(&tx, tx.last_insert_rowid())
             );
@@ -735,13 +1990,396 @@ mod tests {
                     id: Some(1),
                     category: "play".to_string(),
                     start_time: 47,
-                    end_time: None
+                    end_time: None,
+                    note: None,
                 }),
                 get_time(&tx, tx.last_insert_rowid())
             );
         }
     }
 
+    #[test]
+    pub fn test_category_options() {
+        let mut conn = get_initialized_db();
+        {
+            let mut tx = conn.transaction().unwrap();
+            add_category(&tx, &"work".to_string()).unwrap();
+            set_category_option(
+                &tx,
+                &"work".to_string(),
+                &cli::CategoryOptionName::Rate,
+                &"100".to_string(),
+            )
+            .unwrap();
+            assert_eq!(
+                Some(&"100".to_string()),
+                get_category_options(&tx)
+                    .unwrap()
+                    .get("work")
+                    .unwrap()
+                    .get("rate")
+            );
+
+            //options follow the category when it's renamed
+            rename_category(&mut tx, &"work".to_string(), &"play".to_string()).unwrap();
+            assert!(get_category_options(&tx).unwrap().contains_key("play"));
+
+            unset_category_option(&tx, &"play".to_string(), &cli::CategoryOptionName::Rate)
+                .unwrap();
+            assert!(get_category_options(&tx).unwrap().is_empty());
+
+            //and are deleted with the category
+            set_category_option(
+                &tx,
+                &"play".to_string(),
+                &cli::CategoryOptionName::Rate,
+                &"100".to_string(),
+            )
+            .unwrap();
+            delete_category(&tx, &"play".to_string(), &false).unwrap();
+            assert!(get_category_options(&tx).unwrap().is_empty());
+        }
+    }
+
+    #[test]
+    pub fn test_pending_times() {
+        let mut conn = get_initialized_db();
+        let mut tx = conn.transaction().unwrap();
+        add_category(&tx, &"work".to_string()).unwrap();
+        let pending = PendingTime {
+            id: None,
+            source: "outlook".to_string(),
+            source_id: "abc".to_string(),
+            title: "Standup".to_string(),
+            category: Some("work".to_string()),
+            start_time: 100,
+            end_time: 200,
+        };
+        assert!(add_pending_time(&tx, &pending).unwrap());
+        //the same meeting isn't imported twice
+        assert!(!add_pending_time(&tx, &pending).unwrap());
+
+        //the guessed category follows renames
+        rename_category(&mut tx, &"work".to_string(), &"meetings".to_string()).unwrap();
+        let stored = get_pending_times(&tx).unwrap();
+        assert_eq!(1, stored.len());
+        assert_eq!(Some("meetings".to_string()), stored[0].category);
+
+        //resolved times are hidden, and not imported again
+        resolve_pending_time(&tx, stored[0].id.unwrap()).unwrap();
+        assert!(get_pending_times(&tx).unwrap().is_empty());
+        assert!(get_pending_time(&tx, stored[0].id.unwrap()).is_err());
+        assert!(!add_pending_time(&tx, &pending).unwrap());
+    }
+
+    #[test]
+    pub fn test_annotations_outlive_stopping() {
+        let mut conn = get_initialized_db();
+        let mut tx = conn.transaction().unwrap();
+        add_category(&tx, &"work".to_string()).unwrap();
+        start_timing(&mut tx, &"work".to_string(), &None).unwrap();
+        add_annotation(&tx, 1, "feature/login").unwrap();
+        //stopping at end-of-day rewrites the time, which mustn't take its annotations with it
+        end_open_times(&mut tx, HourMinute(23, 59)).unwrap();
+        assert!(get_last_open_time(&tx).unwrap().is_none());
+        assert_eq!(
+            Some(&vec!["feature/login".to_string()]),
+            get_annotations(&tx).unwrap().get(&1)
+        );
+    }
+
+    #[test]
+    pub fn test_get_times() {
+        let mut conn = get_initialized_db();
+        {
+            let mut tx = conn.transaction().unwrap();
+            add_category(&tx, &"work".to_string()).unwrap();
+            for (start_time, end_time) in [(10, Some(20)), (30, Some(40)), (50, None)] {
+                upsert_time(
+                    &mut tx,
+                    TimeWindow {
+                        id: None,
+                        category: "work".to_string(),
+                        start_time,
+                        end_time,
+                        note: None,
+                    },
+                    &true,
+                )
+                .unwrap();
+            }
+            let starts = |times: Vec<TimeWindow>| -> Vec<i64> {
+                times.iter().map(|t| t.start_time).collect()
+            };
+
+            assert_eq!(
+                vec![30],
+                starts(get_times(&mut tx, Some(15), Some(45), &cli::FilterMode::Start).unwrap())
+            );
+            assert_eq!(
+                vec![10, 30],
+                starts(get_times(&mut tx, Some(15), Some(45), &cli::FilterMode::Overlap).unwrap())
+            );
+            assert_eq!(
+                vec![30, 50],
+                starts(get_times(&mut tx, Some(35), None, &cli::FilterMode::Overlap).unwrap())
+            );
+
+            let time = get_time(&tx, 1).unwrap();
+            assert_eq!(
+                TimeWindow {
+                    id: Some(1),
+                    category: "work".to_string(),
+                    start_time: 15,
+                    end_time: Some(20),
+                    note: None,
+                },
+                time.clipped(Some(15), Some(45))
+            );
+            assert_eq!(Some(12), time.clipped(None, Some(12)).end_time);
+        }
+    }
+
+    #[test]
+    pub fn test_tags() {
+        let mut conn = get_initialized_db();
+        let mut tx = conn.transaction().unwrap();
+        add_category(&tx, &"work".to_string()).unwrap();
+        for (start_time, end_time) in [(10, Some(20)), (30, Some(40)), (50, Some(60))] {
+            upsert_time(
+                &mut tx,
+                TimeWindow {
+                    id: None,
+                    category: "work".to_string(),
+                    start_time,
+                    end_time,
+                    note: None,
+                },
+                &true,
+            )
+            .unwrap();
+        }
+        assert_eq!("client-a", parse_tag("#client-a").unwrap());
+        assert!(parse_tag("#").is_err());
+        assert!(parse_tag("two words").is_err());
+
+        let tags = |names: &[&str]| names.iter().map(|t| t.to_string()).collect::<Vec<_>>();
+        assert_eq!(2, tag_time(&tx, 1, &tags(&["urgent", "client-a"])).unwrap());
+        assert_eq!(
+            1,
+            tag_time(&tx, 2, &tags(&["client-a", "client-a"])).unwrap()
+        );
+        tag_time(&tx, 3, &tags(&["client-b"])).unwrap();
+        assert_eq!(
+            Err(TTError::TimeNotFound { id: 9 }),
+            tag_time(&tx, 9, &tags(&["urgent"]))
+        );
+        assert_eq!(
+            Some(&tags(&["client-a", "urgent"])),
+            get_tags(&tx).unwrap().get(&1)
+        );
+        assert_eq!(
+            BTreeSet::from([1, 2]),
+            get_tagged(&tx, &tags(&["client-a"])).unwrap()
+        );
+        assert_eq!(
+            1,
+            untag_time(&tx, 2, &tags(&["client-a", "urgent"])).unwrap()
+        );
+        assert_eq!(
+            BTreeSet::from([1, 3]),
+            get_tagged(&tx, &tags(&["urgent", "client-b"])).unwrap()
+        );
+
+        //only tagged times in the window are deleted, and their tags go with them
+        let deleted = bulk_delete_times(&mut tx, &0, &100, &false, &tags(&["client-a"])).unwrap();
+        assert_eq!(
+            vec![Some(1)],
+            deleted.iter().map(|t| t.id).collect::<Vec<_>>()
+        );
+        assert_eq!(vec![&3], get_tags(&tx).unwrap().keys().collect::<Vec<_>>());
+        assert_eq!(
+            2,
+            bulk_delete_times(&mut tx, &0, &100, &false, &[])
+                .unwrap()
+                .len()
+        );
+    }
+
+    #[test]
+    pub fn test_rewritten_times_keep_tags() {
+        let mut conn = get_initialized_db();
+        let mut tx = conn.transaction().unwrap();
+        add_category(&tx, &"work".to_string()).unwrap();
+        let started = chrono::Local::now().timestamp() - 60;
+        let mut time = TimeWindow {
+            id: None,
+            category: "work".to_string(),
+            start_time: started,
+            end_time: None,
+            note: None,
+        };
+        upsert_time(&mut tx, time.clone(), &true).unwrap();
+        tag_time(&tx, 1, &["urgent".to_string()]).unwrap();
+        //amending a time (like `amend-time --note`) updates it in place
+        time.id = Some(1);
+        time.note = Some("hello".to_string());
+        upsert_time(&mut tx, time, &true).unwrap();
+        //so does stopping it at end-of-day
+        end_open_times(&mut tx, parse_time(&"23:59".to_string()).unwrap()).unwrap();
+        assert!(get_time(&tx, 1).unwrap().end_time.is_some());
+        assert_eq!(
+            Some(&vec!["urgent".to_string()]),
+            get_tags(&tx).unwrap().get(&1)
+        );
+    }
+
+    #[test]
+    pub fn test_times_span() {
+        let mut conn = get_initialized_db();
+        let spans = |conn: &Connection| {
+            conn.prepare("SELECT id, start_time, end_time FROM times_span ORDER BY id")
+                .unwrap()
+                .query_map((), |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)))
+                .unwrap()
+                .collect::<Result<Vec<(i64, f64, f64)>, _>>()
+                .unwrap()
+        };
+        let mut tx = conn.transaction().unwrap();
+        add_category(&tx, &"work".to_string()).unwrap();
+        for (start_time, end_time) in [(100, Some(200)), (300, None)] {
+            upsert_time(
+                &mut tx,
+                TimeWindow {
+                    id: None,
+                    category: "work".to_string(),
+                    start_time,
+                    end_time,
+                    note: None,
+                },
+                &true,
+            )
+            .unwrap();
+        }
+        assert_eq!(
+            vec![(1, 100.0, 200.0), (2, 300.0, OPEN_END as f64)],
+            spans(&tx)
+        );
+        end_open_times_immediately(&mut tx).unwrap();
+        delete_time(&mut tx, &1).unwrap();
+        upsert_time(
+            &mut tx,
+            TimeWindow {
+                id: Some(2),
+                category: "work".to_string(),
+                start_time: 150,
+                end_time: Some(400),
+                note: None,
+            },
+            &true,
+        )
+        .unwrap();
+        assert_eq!(vec![(2, 150.0, 400.0)], spans(&tx));
+        tx.commit().unwrap();
+
+        //databases from before the index get it filled in
+        conn.execute_batch("DROP TABLE times_span; DROP TRIGGER times_span_insert; DROP TRIGGER times_span_update; DROP TRIGGER times_span_delete")
+            .unwrap();
+        initialize_db(&mut conn).unwrap();
+        assert_eq!(vec![(2, 150.0, 400.0)], spans(&conn));
+    }
+
+    #[test]
+    pub fn test_write_retries() {
+        let path = std::env::temp_dir()
+            .join(format!("ttjr-busy-{}.sqlite3", std::process::id()))
+            .to_string_lossy()
+            .to_string();
+        let mut conn = Connection::open(&path).unwrap();
+        initialize_db(&mut conn).unwrap();
+        let add = |conn: &mut Connection, category: &str| {
+            write(conn, |tx| add_category(tx, &category.to_string()))
+        };
+
+        //another connection holding the lock for a moment is waited out
+        let locked = std::thread::spawn({
+            let path = path.clone();
+            move || {
+                let other = Connection::open(&path).unwrap();
+                other.execute_batch("BEGIN EXCLUSIVE").unwrap();
+                std::thread::sleep(Duration::from_millis(100));
+                other.execute_batch("COMMIT").unwrap();
+            }
+        });
+        std::thread::sleep(Duration::from_millis(20));
+        add(&mut conn, "work").unwrap();
+        locked.join().unwrap();
+
+        //but not forever
+        let other = Connection::open(&path).unwrap();
+        other.execute_batch("BEGIN EXCLUSIVE").unwrap();
+        assert_eq!(
+            Err(TTError::DatabaseBusy { attempts: 8 }),
+            add(&mut conn, "play")
+        );
+        other.execute_batch("COMMIT").unwrap();
+        //and dry runs are rolled back
+        write_or_dry_run(&mut conn, &true, |tx| add_category(tx, &"dry".to_string())).unwrap();
+        assert_eq!(
+            vec!["work"],
+            get_categories(&conn.transaction().unwrap())
+                .unwrap()
+                .into_iter()
+                .collect::<Vec<_>>()
+        );
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[test]
+    pub fn test_insert_times() {
+        let mut conn = get_initialized_db();
+        let mut tx = conn.transaction().unwrap();
+        add_category(&tx, &"work".to_string()).unwrap();
+        let time = |start_time, end_time| TimeWindow {
+            id: None,
+            category: "work".to_string(),
+            start_time,
+            end_time,
+            note: None,
+        };
+        upsert_time(&mut tx, time(100, Some(200)), &true).unwrap();
+        assert_eq!(
+            vec![1, 2, 3, 4],
+            insert_times(
+                &mut tx,
+                &[
+                    time(300, Some(400)),
+                    time(150, Some(250)),
+                    //overlapping each other
+                    time(500, Some(600)),
+                    time(550, Some(650)),
+                    time(50, Some(100)),
+                    time(1000, None),
+                ],
+                &true
+            )
+            .unwrap()
+        );
+        assert_eq!(
+            vec![(100, Some(200)), (300, Some(400)), (1000, None)],
+            get_times(&mut tx, None, None, &cli::FilterMode::Start)
+                .unwrap()
+                .iter()
+                .map(|t| (t.start_time, t.end_time))
+                .collect::<Vec<_>>()
+        );
+        assert_eq!(
+            vec![0],
+            insert_times(&mut tx, &[time(2000, Some(3000))], &true).unwrap()
+        );
+        assert!(insert_times(&mut tx, &[], &true).unwrap().is_empty());
+    }
+
     #[test]
     pub fn test_upsert() {
         let mut conn = get_initialized_db();
@@ -755,7 +2393,9 @@ mod tests {
                     category: "work".to_string(),
                     start_time: 47,
                     end_time: None,
+                    note: None,
                 },
+                &true,
             )
             .unwrap();
 
@@ -764,7 +2404,8 @@ mod tests {
                     id: Some(1),
                     category: "work".to_string(),
                     start_time: 47,
-                    end_time: None
+                    end_time: None,
+                    note: None,
                 }),
                 get_time(&tx, tx.last_insert_rowid())
             );
@@ -778,7 +2419,9 @@ mod tests {
                         category: "work".to_string(),
                         start_time: 51,
                         end_time: None,
+                        note: None,
                     },
+                    &true
                 ),
                 Err(_)
             );
@@ -792,7 +2435,9 @@ mod tests {
                         category: "work".to_string(),
                         start_time: 40,
                         end_time: Some(51),
+                        note: None,
                     },
+                    &true
                 ),
                 Err(_)
             );
@@ -805,7 +2450,9 @@ mod tests {
                     category: "work".to_string(),
                     start_time: 47,
                     end_time: Some(51),
+                    note: None,
                 },
+                &true,
             )
             .unwrap();
 
@@ -817,7 +2464,9 @@ mod tests {
                     category: "work".to_string(),
                     start_time: 52,
                     end_time: None,
+                    note: None,
                 },
+                &true,
             )
             .unwrap();
 
@@ -827,7 +2476,8 @@ mod tests {
                     id: Some(2),
                     category: "work".to_string(),
                     start_time: 52,
-                    end_time: None
+                    end_time: None,
+                    note: None,
                 }),
                 get_time(&tx, tx.last_insert_rowid())
             );
@@ -841,7 +2491,9 @@ mod tests {
                         category: "work".to_string(),
                         start_time: 48,
                         end_time: None,
+                        note: None,
                     },
+                    &true
                 ),
                 Err(_)
             );
@@ -855,9 +2507,11 @@ mod tests {
                         category: "work".to_string(),
                         start_time: 40,
                         end_time: Some(48),
+                        note: None,
                     },
+                    &true
                 ),
-                Err(_)
+                Err(TTError::OverlappingTime { .. })
             );
 
             //change the start and end time
@@ -868,7 +2522,9 @@ mod tests {
                     category: "work".to_string(),
                     start_time: 111,
                     end_time: Some(112),
+                    note: None,
                 },
+                &true,
             )
             .unwrap();
             //check that modified time is reflected in DB
@@ -877,7 +2533,8 @@ mod tests {
                     id: Some(2),
                     category: "work".to_string(),
                     start_time: 111,
-                    end_time: Some(112)
+                    end_time: Some(112),
+                    note: None,
                 }),
                 get_time(&tx, tx.last_insert_rowid())
             );
@@ -0,0 +1,252 @@
+/*
+This file is part of Timetrack Jr.
+Timetrack Jr. is free software: you can redistribute it and/or modify it under the terms of the GNU General Public License as published by the Free Software Foundation, either version 3 of the License, or (at your option) any later version.
+Timetrack Jr. is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU General Public License for more details.
+You should have received a copy of the GNU General Public License along with Timetrack Jr. If not, see <https://www.gnu.org/licenses/>.
+*/
+//Global key combinations (through global-hotkey).  On linux they're grabbed from X11 and events arrive on a channel,
+// macOS and windows only deliver them to an event loop on the thread that registered them.
+use crate::TTError;
+use crossterm::{
+    event::{self, Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers},
+    terminal,
+};
+use global_hotkey::{
+    hotkey::{HotKey, Modifiers},
+    GlobalHotKeyEvent, GlobalHotKeyManager, HotKeyState,
+};
+use std::{fmt, str::FromStr};
+
+///A key combination like ctrl+alt+k
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Keypress(pub HotKey);
+
+impl FromStr for Keypress {
+    type Err = TTError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let hotkey = s.parse::<HotKey>().map_err(|e| TTError::InvalidArgument {
+            message: format!(
+                "\"{}\" isn't a key combination like ctrl+alt+k or super+f5: {}",
+                s, e
+            ),
+        })?;
+        Ok(Keypress(hotkey))
+    }
+}
+
+impl fmt::Display for Keypress {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (modifier, name) in [
+            (Modifiers::CONTROL, "ctrl"),
+            (Modifiers::ALT, "alt"),
+            (Modifiers::SHIFT, "shift"),
+            (Modifiers::SUPER, "super"),
+        ] {
+            if self.0.mods.contains(modifier) {
+                write!(f, "{}+", name)?;
+            }
+        }
+        let key = self.0.key.to_string();
+        let key = key
+            .strip_prefix("Key")
+            .or(key.strip_prefix("Digit"))
+            .unwrap_or(&key);
+        write!(f, "{}", key.to_lowercase())
+    }
+}
+
+///The name of a key the way Keypress parses it, None for keys that can't be hotkeys (like lone modifiers)
+fn key_name(code: &KeyCode) -> Option<String> {
+    match code {
+        KeyCode::Char(' ') => Some("space".to_string()),
+        KeyCode::Char(c) => Some(c.to_lowercase().to_string()),
+        KeyCode::F(n) => Some(format!("f{}", n)),
+        KeyCode::Up => Some("arrowup".to_string()),
+        KeyCode::Down => Some("arrowdown".to_string()),
+        KeyCode::Left => Some("arrowleft".to_string()),
+        KeyCode::Right => Some("arrowright".to_string()),
+        KeyCode::Enter => Some("enter".to_string()),
+        KeyCode::Tab | KeyCode::BackTab => Some("tab".to_string()),
+        KeyCode::Backspace => Some("backspace".to_string()),
+        KeyCode::Delete => Some("delete".to_string()),
+        KeyCode::Insert => Some("insert".to_string()),
+        KeyCode::Home => Some("home".to_string()),
+        KeyCode::End => Some("end".to_string()),
+        KeyCode::PageUp => Some("pageup".to_string()),
+        KeyCode::PageDown => Some("pagedown".to_string()),
+        _ => None,
+    }
+}
+
+///The Keypress for a key pressed in the terminal
+fn keypress_of(key: &KeyEvent) -> Option<Result<Keypress, TTError>> {
+    let mut combination = String::new();
+    for (modifier, name) in [
+        (KeyModifiers::CONTROL, "ctrl"),
+        (KeyModifiers::ALT, "alt"),
+        (KeyModifiers::SHIFT, "shift"),
+        (KeyModifiers::SUPER, "super"),
+    ] {
+        //shift+tab arrives as BackTab
+        if key.modifiers.contains(modifier) || (key.code == KeyCode::BackTab && name == "shift") {
+            combination.push_str(name);
+            combination.push('+');
+        }
+    }
+    key_name(&key.code).map(|name| (combination + name.as_str()).parse())
+}
+
+///Wait for a key combination to be pressed in the terminal, None if it was escape.  Terminals don't pass along
+/// every combination (super is often taken by the desktop), so this is best-effort.
+pub fn get_keypress() -> Result<Option<Keypress>, TTError> {
+    terminal::enable_raw_mode()?;
+    let pressed = loop {
+        match event::read() {
+            Ok(Event::Key(key)) if key.kind == KeyEventKind::Press => {
+                if key.code == KeyCode::Esc {
+                    break Ok(None);
+                }
+                match keypress_of(&key) {
+                    Some(keypress) => break keypress.map(Some),
+                    None => continue,
+                }
+            }
+            Ok(_) => continue,
+            Err(e) => break Err(e.into()),
+        }
+    };
+    terminal::disable_raw_mode()?;
+    pressed
+}
+
+fn hotkey_error(e: impl fmt::Display) -> TTError {
+    TTError::System {
+        message: format!("Couldn't listen for hotkeys: {}", e),
+    }
+}
+
+///Register `keypresses` with the OS
+fn register(keypresses: &[Keypress]) -> Result<GlobalHotKeyManager, TTError> {
+    let manager = GlobalHotKeyManager::new().map_err(hotkey_error)?;
+    for keypress in keypresses {
+        manager.register(keypress.0).map_err(|e| TTError::System {
+            message: format!("Couldn't register {}: {}", keypress, e),
+        })?;
+    }
+    Ok(manager)
+}
+
+///Call `pressed` with the index of each of `keypresses` as it's pressed, until `pressed` fails
+#[cfg(not(any(target_os = "macos", windows)))]
+pub fn listen(
+    keypresses: &[Keypress],
+    mut pressed: impl FnMut(usize) -> Result<(), TTError>,
+) -> Result<(), TTError> {
+    let _manager = register(keypresses)?;
+    loop {
+        let event = GlobalHotKeyEvent::receiver().recv().map_err(hotkey_error)?;
+        if let Some(index) = index_of(keypresses, &event) {
+            pressed(index)?;
+        }
+    }
+}
+
+///Call `pressed` with the index of each of `keypresses` as it's pressed, until `pressed` fails
+#[cfg(any(target_os = "macos", windows))]
+pub fn listen(
+    keypresses: &[Keypress],
+    mut pressed: impl FnMut(usize) -> Result<(), TTError>,
+) -> Result<(), TTError> {
+    use tao::{
+        event::Event,
+        event_loop::{ControlFlow, EventLoopBuilder},
+        platform::run_return::EventLoopExtRunReturn,
+    };
+
+    let mut event_loop = EventLoopBuilder::<GlobalHotKeyEvent>::with_user_event().build();
+    #[cfg(target_os = "macos")]
+    {
+        use tao::platform::macos::{ActivationPolicy, EventLoopExtMacOS};
+        //no dock icon
+        event_loop.set_activation_policy(ActivationPolicy::Accessory);
+    }
+    let proxy = event_loop.create_proxy();
+    GlobalHotKeyEvent::set_event_handler(Some(move |event| {
+        let _ = proxy.send_event(event);
+    }));
+    let _manager = register(keypresses)?;
+
+    let mut error = None;
+    event_loop.run_return(|event, _, control_flow| {
+        *control_flow = ControlFlow::Wait;
+        if let Event::UserEvent(event) = event {
+            if let Some(Err(e)) = index_of(keypresses, &event).map(&mut pressed) {
+                error = Some(e);
+                *control_flow = ControlFlow::Exit;
+            }
+        }
+    });
+    match error {
+        Some(e) => Err(e),
+        None => Ok(()),
+    }
+}
+
+///Which of `keypresses` was pressed (releases are ignored)
+fn index_of(keypresses: &[Keypress], event: &GlobalHotKeyEvent) -> Option<usize> {
+    match event.state() {
+        HotKeyState::Pressed => keypresses.iter().position(|k| k.0.id() == event.id()),
+        HotKeyState::Released => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_keypress() {
+        for (given, expected) in [
+            ("ctrl+alt+k", "ctrl+alt+k"),
+            ("Shift + Control + 1", "ctrl+shift+1"),
+            ("cmd+F5", "super+f5"),
+            ("alt+ArrowUp", "alt+arrowup"),
+            ("space", "space"),
+        ] {
+            let keypress = given.parse::<Keypress>().unwrap();
+            assert_eq!(expected, keypress.to_string());
+            assert_eq!(keypress, expected.parse::<Keypress>().unwrap());
+        }
+        assert!("ctrl+".parse::<Keypress>().is_err());
+        assert!("ctrl+k+j".parse::<Keypress>().is_err());
+        assert!("hyper+k".parse::<Keypress>().is_err());
+    }
+
+    #[test]
+    fn test_keypress_of() {
+        let keypress = |code, modifiers| {
+            keypress_of(&KeyEvent::new(code, modifiers)).map(|k| k.unwrap().to_string())
+        };
+        assert_eq!(
+            Some("ctrl+alt+w".to_string()),
+            keypress(
+                KeyCode::Char('w'),
+                KeyModifiers::CONTROL | KeyModifiers::ALT
+            )
+        );
+        assert_eq!(
+            Some("shift+k".to_string()),
+            keypress(KeyCode::Char('K'), KeyModifiers::SHIFT)
+        );
+        assert_eq!(
+            Some("super+f5".to_string()),
+            keypress(KeyCode::F(5), KeyModifiers::SUPER)
+        );
+        assert_eq!(
+            Some("shift+tab".to_string()),
+            keypress(KeyCode::BackTab, KeyModifiers::NONE)
+        );
+        assert_eq!(None, keypress(KeyCode::CapsLock, KeyModifiers::NONE));
+    }
+}
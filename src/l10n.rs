@@ -0,0 +1,198 @@
+/*
+This file is part of Timetrack Jr.
+Timetrack Jr. is free software: you can redistribute it and/or modify it under the terms of the GNU General Public License as published by the Free Software Foundation, either version 3 of the License, or (at your option) any later version.
+Timetrack Jr. is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU General Public License for more details.
+You should have received a copy of the GNU General Public License along with Timetrack Jr. If not, see <https://www.gnu.org/licenses/>.
+*/
+//Text for people (notifications, the summary export, and errors) in the language picked with the language option.
+// Catalogs are the fluent files in locales/, built in so there's nothing extra to install, and anything a catalog is
+// missing is shown in English.  JSON output isn't translated, so scripts see the same thing in every language.
+use crate::TTError;
+use fluent_bundle::{concurrent::FluentBundle, FluentArgs, FluentResource, FluentValue};
+use once_cell::sync::Lazy;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use unic_langid::LanguageIdentifier;
+
+///(language, catalog) of each language ttjr speaks, English (which has every message) first
+const CATALOGS: [(&str, &str); 2] = [
+    ("en", include_str!("../locales/en.ftl")),
+    ("de", include_str!("../locales/de.ftl")),
+];
+
+///Placeholders in notification templates (see the started-notification option)
+const PLACEHOLDERS: [&str; 4] = ["category", "elapsed", "start_local", "today_total"];
+
+type Bundle = FluentBundle<FluentResource>;
+
+static BUNDLES: Lazy<Vec<Bundle>> = Lazy::new(|| {
+    CATALOGS
+        .iter()
+        .map(|(language, catalog)| {
+            let mut bundle = FluentBundle::new_concurrent(vec![language.parse().unwrap()]);
+            //isolation marks around arguments would end up in notifications and terminals
+            bundle.set_use_isolating(false);
+            bundle
+                .add_resource(FluentResource::try_new(catalog.to_string()).unwrap())
+                .unwrap();
+            bundle
+        })
+        .collect()
+});
+
+///Index in CATALOGS of the language in use
+static LANGUAGE: AtomicUsize = AtomicUsize::new(0);
+
+///The catalog for a language like "de", "de-AT", or "de_DE.UTF-8" (like $LANG)
+fn catalog(language: &str) -> Option<usize> {
+    let tag = language.split('.').next().unwrap_or("").replace('_', "-");
+    let language: LanguageIdentifier = tag.parse().ok()?;
+    CATALOGS
+        .iter()
+        .position(|(name, _)| *name == language.language.as_str())
+}
+
+///Check that ttjr speaks `language`, for the language option
+pub fn validate(language: &str) -> Result<(), TTError> {
+    match catalog(language) {
+        Some(_) => Ok(()),
+        None => Err(TTError::invalid(
+            "language",
+            language,
+            &format!(
+                "a language ttjr has been translated to ({})",
+                CATALOGS
+                    .iter()
+                    .map(|(name, _)| *name)
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            ),
+        )),
+    }
+}
+
+///Show text in `language` from now on
+pub fn set_language(language: &str) -> Result<(), TTError> {
+    validate(language)?;
+    LANGUAGE.store(catalog(language).unwrap_or(0), Ordering::Relaxed);
+    Ok(())
+}
+
+fn format(bundle: &Bundle, id: &str, args: &FluentArgs) -> Option<String> {
+    let pattern = bundle.get_message(id)?.value()?;
+    let mut errors = vec![];
+    Some(
+        bundle
+            .format_pattern(pattern, Some(args), &mut errors)
+            .to_string(),
+    )
+}
+
+fn text_in(language: usize, id: &str, args: &[(&str, FluentValue)]) -> String {
+    let mut fluent_args = FluentArgs::new();
+    for (name, value) in args {
+        fluent_args.set(*name, value.clone());
+    }
+    [language, 0]
+        .iter()
+        .find_map(|index| format(&BUNDLES[*index], id, &fluent_args))
+        .unwrap_or(id.to_string())
+}
+
+///The message `id` in the language in use, with its `{ $name }`s filled in from `args`
+pub fn text(id: &str, args: &[(&str, FluentValue)]) -> String {
+    text_in(LANGUAGE.load(Ordering::Relaxed), id, args)
+}
+
+///The default notification template `id` in the language in use, with its placeholders left for
+/// `notify::from_template` to fill in
+pub fn template(id: &str) -> String {
+    let placeholders = PLACEHOLDERS.map(|name| (name, FluentValue::from(format!("{{{}}}", name))));
+    text(id, &placeholders)
+}
+
+///An error as it's shown to people, in the language in use.  Errors without a message in the catalogs (like ones
+/// from the network or sqlite) are shown as they are.
+pub fn error(error: &TTError) -> String {
+    match error {
+        TTError::CategoryNotFound { category } => text(
+            "error-category-not-found",
+            &[("category", category.as_str().into())],
+        ),
+        TTError::TimeNotFound { id } => text("error-time-not-found", &[("id", (*id).into())]),
+        TTError::OverlappingTime { ids } => text(
+            "error-overlapping-time",
+            &[(
+                "ids",
+                ids.iter()
+                    .map(|id| id.to_string())
+                    .collect::<Vec<_>>()
+                    .join(", ")
+                    .into(),
+            )],
+        ),
+        TTError::ParseTime { input, reason } => text(
+            "error-parse-time",
+            &[
+                ("input", input.as_str().into()),
+                ("reason", reason.as_str().into()),
+            ],
+        ),
+        TTError::UnusualTime { time, reason } => text(
+            "error-unusual-time",
+            &[
+                ("time", time.as_str().into()),
+                ("reason", reason.as_str().into()),
+            ],
+        ),
+        TTError::DatabaseBusy { attempts } => {
+            text("error-database-busy", &[("attempts", (*attempts).into())])
+        }
+        _ => error.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_catalogs() {
+        assert_eq!(Some(1), catalog("de"));
+        assert_eq!(Some(1), catalog("de_DE.UTF-8"));
+        assert_eq!(Some(0), catalog("en-GB"));
+        assert_eq!(None, catalog("tlh"));
+        assert!(validate("klingon").is_err());
+
+        let minutes = |language, minutes: i64| {
+            text_in(language, "untracked-body", &[("minutes", minutes.into())])
+        };
+        assert_eq!(
+            "Nothing's been timed for a minute, what are you working on?",
+            minutes(0, 1)
+        );
+        assert_eq!(
+            "Seit 30 Minuten wird nichts erfasst, woran arbeitest du gerade?",
+            minutes(1, 30)
+        );
+        //anything missing from a catalog is in English, and anything missing from English is its id
+        assert_eq!("Keep going", text_in(0, "action-keep", &[]));
+        assert_eq!("no-such-message", text_in(1, "no-such-message", &[]));
+
+        //every translation is of a message that's in English
+        for (_, catalog) in &CATALOGS[1..] {
+            for line in catalog.lines() {
+                if let Some((id, _)) = line.split_once(" =") {
+                    assert!(BUNDLES[0].has_message(id), "{}", id);
+                }
+            }
+        }
+        assert_eq!(
+            "Gestartet: {category}",
+            text_in(
+                1,
+                "started-notification",
+                &[("category", "{category}".into())]
+            )
+        );
+    }
+}
@@ -0,0 +1,192 @@
+/*
+Copyright 2022 Luke Hospadaruk
+This file is part of Timetrack Jr.
+Timetrack Jr. is free software: you can redistribute it and/or modify it under the terms of the GNU General Public License as published by the Free Software Foundation, either version 3 of the License, or (at your option) any later version.
+Timetrack Jr. is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU General Public License for more details.
+You should have received a copy of the GNU General Public License along with Timetrack Jr. If not, see <https://www.gnu.org/licenses/>.
+*/
+#![allow(clippy::needless_return, clippy::ptr_arg)]
+#[cfg_attr(test, macro_use)]
+extern crate matches;
+use std::{
+    num::ParseIntError,
+    time::{Duration, SystemTimeError},
+};
+pub mod cli;
+pub mod client;
+pub mod commands;
+pub mod db;
+#[cfg(feature = "hotkeys")]
+pub mod keyboard;
+pub mod l10n;
+pub mod zone;
+
+pub use client::TtjrClient;
+
+pub type RusqliteError = rusqlite::Error;
+
+///Everything that can go wrong in Timetrack Jr.  Displaying an error gives a message fit to show the user.
+#[derive(Debug, thiserror::Error)]
+pub enum TTError {
+    #[error("Category '{category}' does not exist in the timetrack jr database, use `ttjr add-category` to add it")]
+    CategoryNotFound { category: String },
+    #[error("No time with id {id}")]
+    TimeNotFound { id: i64 },
+    ///A time that would overlap the already recorded times with these ids
+    #[error("Attempted to insert time that overlaps with other times! (overlapped IDs: {})", join_ids(.ids))]
+    OverlappingTime { ids: Vec<i64> },
+    ///A date, time, or time of day that couldn't be understood
+    #[error("Couldn't parse \"{input}\": {reason}")]
+    ParseTime { input: String, reason: String },
+    ///A time that's in the future or from before the earliest-time option, probably a typo
+    #[error("{time} is {reason}, add --allow-unusual-times if that's right")]
+    UnusualTime { time: String, reason: String },
+    #[error("{0}")]
+    Io(#[from] std::io::Error),
+    #[error("Database error: {0}")]
+    Sql(#[from] rusqlite::Error),
+    #[error("Unable to connect to DB: {0}")]
+    Pool(#[from] r2d2::Error),
+    #[error("The database is locked by another program (like another ttjr), gave up after {attempts} tries")]
+    DatabaseBusy { attempts: u32 },
+    #[error("The system clock is {0:?} before 1970")]
+    SystemTimeError(Duration),
+    #[error("{0}")]
+    ParseIntError(#[from] ParseIntError),
+    ///An option or argument given a value it can't take
+    #[error("{name} must be {expected}, got \"{value}\"")]
+    InvalidValue {
+        name: String,
+        value: String,
+        expected: String,
+    },
+    ///A command line (or API request) that doesn't make sense, like options that don't go together
+    #[error("{message}")]
+    InvalidArgument { message: String },
+    ///A pending time, auto-start, window rule, or webhook that doesn't exist
+    #[error("No {kind} with id {id}")]
+    NotFound { kind: &'static str, id: i64 },
+    #[error("{keypress} isn't bound to anything")]
+    HotkeyNotBound { keypress: String },
+    ///Something that has to be set up first, like an API token or a category option
+    #[error("{message}")]
+    NotConfigured { message: String },
+    ///No times (or nothing being timed) for a command to work with
+    #[error("{message}")]
+    NothingFound { message: String },
+    ///A request to another service (a sync service, calendar, MQTT broker, etc.) that failed or got a reply that
+    /// didn't make sense
+    #[error("{message}")]
+    Network { message: String },
+    ///Something the computer couldn't do, like telling how long you've been idle or running another program
+    #[error("{message}")]
+    System { message: String },
+    #[error("This ttjr was built without the {feature} feature, reinstall it with `cargo install timetrack_jr --features {feature}`")]
+    MissingFeature { feature: String },
+    #[error("{question} Not without --yes, there's no terminal to ask on")]
+    NeedsConfirmation { question: String },
+    #[error("Cancelled, nothing was changed")]
+    Cancelled,
+    ///An extension (`ttjr-<command>`) which exited with a failure
+    #[error("ttjr-{name} exited with {code}")]
+    ExtensionFailed { name: String, code: i32 },
+    #[error("Script failed: {message}")]
+    Script { message: String },
+    #[error("{0}")]
+    Json(#[from] serde_json::Error),
+    #[error("Couldn't show a notification: {message}")]
+    Notification { message: String },
+}
+
+fn join_ids(ids: &[i64]) -> String {
+    ids.iter()
+        .map(|id| id.to_string())
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+impl TTError {
+    ///A date/time argument (like --start-time) that couldn't be parsed
+    pub fn unparseable(input: &str, argument: &str) -> TTError {
+        TTError::ParseTime {
+            input: input.to_string(),
+            reason: format!(
+                "{} should be a date/time like \"2022-11-01 09:00\" or \"last monday\"",
+                argument
+            ),
+        }
+    }
+
+    ///An option or argument given a value it can't take, `expected` saying what it should be
+    pub fn invalid(name: &str, value: &str, expected: &str) -> TTError {
+        TTError::InvalidValue {
+            name: name.to_string(),
+            value: value.to_string(),
+            expected: expected.to_string(),
+        }
+    }
+
+    ///The exit code `ttjr` exits with after the error, so scripts can tell failures apart:
+    /// - 1: anything not listed below
+    /// - 2: the command line didn't make sense (like a time that couldn't be parsed, or is in the future), the same
+    ///   code clap uses
+    /// - 3: a category, time, or something else asked for by id (like a webhook) that doesn't exist
+    /// - 4: a time that would overlap others
+    /// - 5: the database couldn't be opened, read, or written
+    ///
+    /// A failed extension's exit code is passed along.
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            TTError::ParseTime { .. }
+            | TTError::UnusualTime { .. }
+            | TTError::ParseIntError(_)
+            | TTError::InvalidValue { .. }
+            | TTError::InvalidArgument { .. } => 2,
+            TTError::CategoryNotFound { .. }
+            | TTError::TimeNotFound { .. }
+            | TTError::NotFound { .. }
+            | TTError::HotkeyNotBound { .. }
+            | TTError::Sql(rusqlite::Error::QueryReturnedNoRows) => 3,
+            TTError::OverlappingTime { .. } => 4,
+            TTError::Sql(_) | TTError::Pool(_) | TTError::DatabaseBusy { .. } => 5,
+            TTError::ExtensionFailed { code, .. } => *code,
+            _ => 1,
+        }
+    }
+
+    ///Whether the error is worth a warning (rather than giving up) in long-running commands, i.e. it's down to what
+    /// was asked for or the outside world, rather than the database
+    pub fn recoverable(&self) -> bool {
+        !matches!(
+            self,
+            TTError::Sql(_)
+                | TTError::Pool(_)
+                | TTError::DatabaseBusy { .. }
+                | TTError::SystemTimeError(_)
+                | TTError::ParseIntError(_)
+        )
+    }
+}
+
+//io::Error can't be compared, so errors are equal when they're the same kind and say the same thing
+impl PartialEq for TTError {
+    fn eq(&self, other: &Self) -> bool {
+        std::mem::discriminant(self) == std::mem::discriminant(other)
+            && self.to_string() == other.to_string()
+    }
+}
+
+impl From<SystemTimeError> for TTError {
+    fn from(err: SystemTimeError) -> Self {
+        TTError::SystemTimeError(err.duration())
+    }
+}
+
+#[cfg(feature = "notifications")]
+impl From<notify_rust::error::Error> for TTError {
+    fn from(err: notify_rust::error::Error) -> Self {
+        TTError::Notification {
+            message: err.to_string(),
+        }
+    }
+}
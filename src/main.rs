@@ -5,92 +5,78 @@ Timetrack Jr. is free software: you can redistribute it and/or modify it under t
 Timetrack Jr. is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU General Public License for more details.
 You should have received a copy of the GNU General Public License along with Timetrack Jr. If not, see <https://www.gnu.org/licenses/>.
 */
-#[macro_use]
-extern crate matches;
-use clap::Parser;
-use std::{
-    num::ParseIntError,
-    process::exit,
-    time::{Duration, SystemTimeError},
+use clap::{error::ErrorKind, CommandFactory, Parser};
+use gag::Gag;
+use std::{fs::OpenOptions, io::Write, process::exit, sync::Mutex};
+use timetrack_jr::{
+    cli,
+    commands::{self, external},
+    db, l10n, TTError,
 };
-pub mod cli;
-pub mod commands;
-pub mod db;
+use tracing::Level;
+use tracing_subscriber::fmt::format::FmtSpan;
 
-pub type RusqliteError = rusqlite::Error;
-
-#[derive(Debug, PartialEq)]
-pub enum TTError {
-    SqlError(rusqlite::Error),
-    SystemTimeError(Duration),
-    ParseIntError(ParseIntError),
-    TTError { message: String },
-}
+fn main() {
+    let mut cli = cli::Cli::parse();
+    cli.propagate_json();
 
-impl From<serde_json::Error> for TTError {
-    fn from(err: serde_json::Error) -> Self {
-        TTError::TTError {
-            message: format!("{:?}", err),
+    //extensions get the terminal to themselves, and exit however they like
+    if let cli::Commands::External(args) = &cli.command {
+        match external::run(&cli, args) {
+            Ok(code) => exit(code),
+            Err(e) => cli::Cli::command()
+                .error(ErrorKind::InvalidSubcommand, e)
+                .exit(),
         }
     }
-}
 
-impl From<ParseIntError> for TTError {
-    fn from(err: ParseIntError) -> Self {
-        TTError::ParseIntError(err)
+    if let Err(e) = init_logging(&cli) {
+        println!("Couldn't open the log file: {}", e);
+        exit(e.exit_code());
     }
-}
-
-impl From<rusqlite::Error> for TTError {
-    fn from(err: rusqlite::Error) -> Self {
-        TTError::SqlError(err)
-    }
-}
-
-impl From<SystemTimeError> for TTError {
-    fn from(err: SystemTimeError) -> Self {
-        TTError::SystemTimeError(err.duration())
-    }
-}
+    //anything commands print is dropped, but errors are printed after it's released
+    let quiet = match cli.quiet {
+        true => Gag::stdout().ok(),
+        false => None,
+    };
 
-impl From<std::io::Error> for TTError {
-    fn from(err: std::io::Error) -> Self {
-        TTError::TTError {
-            message: format!("{:?}", err),
+    let result = run(&cli);
+    let _ = std::io::stdout().flush();
+    drop(quiet);
+    if let Err(e) = &result {
+        match cli.json {
+            true => println!("{}", serde_json::json!({ "error": e.to_string() })),
+            false if e.recoverable() => println!("{}", l10n::error(e)),
+            false => println!(
+                "{}",
+                l10n::text("error", &[("message", l10n::error(e).into())])
+            ),
         }
     }
+    exit(result.map_or_else(|e| e.exit_code(), |_| 0));
 }
 
-impl From<notify_rust::error::Error> for TTError {
-    fn from(err: notify_rust::error::Error) -> Self {
-        TTError::TTError {
-            message: format!("{:?}", err),
+///Log to stderr (or --log-file), with spans logged as they close so they show how long they took
+fn init_logging(cli: &cli::Cli) -> Result<(), TTError> {
+    let logger = tracing_subscriber::fmt()
+        .with_span_events(FmtSpan::CLOSE)
+        .with_max_level(match cli.verbose || cli.log_file.is_some() {
+            true => Level::DEBUG,
+            false => Level::WARN,
+        });
+    match &cli.log_file {
+        Some(path) => {
+            let file = OpenOptions::new().create(true).append(true).open(path)?;
+            logger.with_ansi(false).with_writer(Mutex::new(file)).init()
         }
+        None => logger.with_writer(std::io::stderr).init(),
     }
+    Ok(())
 }
 
-fn main() {
-    let cli = cli::Cli::parse();
-    let mut conn =
-        rusqlite::Connection::open(&cli.db_path.as_ref().unwrap()).expect("Couldn't open DB");
-
-    db::initialize_db(&mut conn).expect("failed to initialize DB");
-
-    let mut exit_code = 0;
-
-    match commands::execute(&cli, &mut conn) {
-        Err(TTError::TTError { message }) => {
-            println!("{}", message);
-            exit_code = 1;
-        }
-        Err(e) => {
-            println!("Error!: {:?}", e);
-            exit_code = 2;
-        }
-        _ => {}
-    };
-
-    conn.close().expect("Unable to close DB cleanly");
-
-    exit(exit_code);
+fn run(cli: &cli::Cli) -> Result<(), TTError> {
+    let mut conn = rusqlite::Connection::open(cli.db_path.as_ref().unwrap())?;
+    db::initialize_db(&mut conn)?;
+    commands::execute(cli, &mut conn)?;
+    conn.close().map_err(|(_, e)| TTError::from(e))
 }
@@ -0,0 +1,165 @@
+/*
+This file is part of Timetrack Jr.
+Timetrack Jr. is free software: you can redistribute it and/or modify it under the terms of the GNU General Public License as published by the Free Software Foundation, either version 3 of the License, or (at your option) any later version.
+Timetrack Jr. is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU General Public License for more details.
+You should have received a copy of the GNU General Public License along with Timetrack Jr. If not, see <https://www.gnu.org/licenses/>.
+*/
+use crate::cli;
+use chrono::{DateTime, Duration, Local, NaiveDate, NaiveDateTime, TimeZone, Utc};
+use chrono_tz::Tz;
+use std::str::FromStr;
+
+///The timezone reports use for day boundaries and for displaying times.
+/// Defaults to the machine's local zone, or can be any IANA zone like America/New_York
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum Zone {
+    #[default]
+    Local,
+    Named(Tz),
+}
+
+impl FromStr for Zone {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s.eq_ignore_ascii_case("local") {
+            return Ok(Zone::Local);
+        }
+        match s.parse::<Tz>() {
+            Ok(tz) => Ok(Zone::Named(tz)),
+            Err(_) => Err(format!(
+                "unknown timezone \"{}\", expected \"local\" or an IANA name like America/New_York or UTC",
+                s
+            )),
+        }
+    }
+}
+
+///Unix timestamp of the first moment of `day` in `tz`
+fn first_moment<T: TimeZone>(tz: &T, day: NaiveDate) -> i64 {
+    let midnight = day.and_hms_opt(0, 0, 0).unwrap();
+    //midnight doesn't exist on days where DST starts at midnight, so use the first hour that does
+    (0..24)
+        .find_map(|h| {
+            tz.from_local_datetime(&(midnight + Duration::hours(h)))
+                .earliest()
+        })
+        .map(|d| d.timestamp())
+        .unwrap_or(midnight.and_utc().timestamp())
+}
+
+impl Zone {
+    ///Convert a unix timestamp to this zone and hand it to whichever closure matches the zone
+    fn in_zone<T>(
+        &self,
+        tstamp: &i64,
+        local: impl Fn(DateTime<Local>) -> T,
+        named: impl Fn(DateTime<Tz>) -> T,
+    ) -> T {
+        let utc = DateTime::<Utc>::from_timestamp(*tstamp, 0).unwrap();
+        match self {
+            Zone::Local => local(utc.with_timezone(&Local)),
+            Zone::Named(tz) => named(utc.with_timezone(tz)),
+        }
+    }
+
+    ///The calendar date (in this zone) of a unix timestamp
+    pub fn date(&self, tstamp: &i64) -> NaiveDate {
+        self.in_zone(tstamp, |d| d.date_naive(), |d| d.date_naive())
+    }
+
+    ///Today's date in this zone
+    pub fn today(&self) -> NaiveDate {
+        self.date(&Utc::now().timestamp())
+    }
+
+    ///Unix timestamp of the start of `day` in this zone
+    pub fn midnight(&self, day: NaiveDate) -> i64 {
+        match self {
+            Zone::Local => first_moment(&Local, day),
+            Zone::Named(tz) => first_moment(tz, day),
+        }
+    }
+
+    ///Unix timestamp of a wall-clock time in this zone (the earlier one if it happens twice, None if it's skipped by DST)
+    pub fn timestamp(&self, datetime: &NaiveDateTime) -> Option<i64> {
+        match self {
+            Zone::Local => Local
+                .from_local_datetime(datetime)
+                .earliest()
+                .map(|d| d.timestamp()),
+            Zone::Named(tz) => tz
+                .from_local_datetime(datetime)
+                .earliest()
+                .map(|d| d.timestamp()),
+        }
+    }
+
+    ///Format a unix timestamp in this zone with a strftime-style format string
+    pub fn format(&self, tstamp: &i64, fmt: &str) -> String {
+        self.in_zone(
+            tstamp,
+            |d| d.format(fmt).to_string(),
+            |d| d.format(fmt).to_string(),
+        )
+    }
+
+    pub fn rfc2822(&self, tstamp: &i64) -> String {
+        self.in_zone(tstamp, |d| d.to_rfc2822(), |d| d.to_rfc2822())
+    }
+
+    pub fn rfc3339(&self, tstamp: &i64) -> String {
+        self.in_zone(tstamp, |d| d.to_rfc3339(), |d| d.to_rfc3339())
+    }
+
+    ///Parse a time string like those accepted by --start-time, relative to the current time in this zone
+    pub fn parse_time(&self, tstring: &Option<String>) -> Option<i64> {
+        match self {
+            Zone::Local => cli::time_string_to_tstamp_from(tstring, Local::now()),
+            Zone::Named(tz) => {
+                cli::time_string_to_tstamp_from(tstring, Utc::now().with_timezone(tz))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_zone() {
+        let zone: Zone = "America/New_York".parse().unwrap();
+        //2022-11-01T02:30:00Z is still Oct 31 in New York
+        assert_eq!(
+            NaiveDate::from_ymd_opt(2022, 10, 31).unwrap(),
+            zone.date(&1667269800)
+        );
+        assert_eq!(
+            NaiveDate::from_ymd_opt(2022, 11, 1).unwrap(),
+            Zone::Named(Tz::UTC).date(&1667269800)
+        );
+        //2022-10-31T04:00:00Z
+        assert_eq!(
+            1667188800,
+            zone.midnight(NaiveDate::from_ymd_opt(2022, 10, 31).unwrap())
+        );
+        //America/Havana starts DST at midnight, so the day starts at 1am
+        let havana: Zone = "America/Havana".parse().unwrap();
+        assert_eq!(
+            "2022-03-13T01:00:00-04:00",
+            havana.rfc3339(&havana.midnight(NaiveDate::from_ymd_opt(2022, 3, 13).unwrap()))
+        );
+        assert_eq!(
+            Some(1667188800),
+            zone.timestamp(
+                &NaiveDate::from_ymd_opt(2022, 10, 31)
+                    .unwrap()
+                    .and_hms_opt(0, 0, 0)
+                    .unwrap()
+            )
+        );
+        assert_eq!(Ok(Zone::Local), "local".parse());
+        assert!("Mars/Olympus_Mons".parse::<Zone>().is_err());
+    }
+}